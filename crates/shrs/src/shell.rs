@@ -4,12 +4,15 @@ use std::{
     cell::RefCell,
     io::{stdout, BufRead, BufWriter, Write},
     process::ExitStatus,
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 
 use log::{info, warn};
-use shrs_core::prelude::*;
-use shrs_job::JobManager;
+use shrs_core::{
+    history::{expand_history, hostname, session_id, HistoryReplacement},
+    prelude::*,
+};
+use shrs_job::{with_tty_fds, JobManager};
 use shrs_lang::PosixLang;
 use shrs_line::prelude::*;
 
@@ -41,6 +44,10 @@ pub struct ShellConfig {
     #[builder(default = "Env::new()")]
     pub env: Env,
 
+    /// Initial values of shell options, see [ShellOptions]
+    #[builder(default = "ShellOptions::default()")]
+    pub options: ShellOptions,
+
     // /// List of defined functions
     // #[builder(default = "HashMap::new()")]
     // pub functions: HashMap<String, Box<ast::Command>>,
@@ -58,6 +65,11 @@ pub struct ShellConfig {
     #[builder(setter(custom))]
     pub plugins: Vec<Box<dyn Plugin>>,
 
+    /// Command history, see [History]
+    #[builder(default = "Box::new(DefaultHistory::new())")]
+    #[builder(setter(custom))]
+    pub history: Box<dyn History<HistoryItem = String>>,
+
     /// Globally accessible state, see [State]
     #[builder(default = "State::new()")]
     #[builder(setter(custom))]
@@ -85,6 +97,10 @@ impl ShellBuilder {
         self.readline = Some(Box::new(line));
         self
     }
+    pub fn with_history(mut self, history: impl History<HistoryItem = String> + 'static) -> Self {
+        self.history = Some(Box::new(history));
+        self
+    }
 }
 
 impl ShellConfig {
@@ -124,6 +140,7 @@ impl ShellConfig {
             state: self.state,
             jobs: Jobs::new(),
             startup_time: Instant::now(),
+            history: self.history,
         };
         let mut rt = Runtime {
             env: self.env,
@@ -133,6 +150,8 @@ impl ShellConfig {
             // TODO currently unused (since we have not implemented functions etc)
             args: vec![],
             exit_status: 0,
+            pipe_status: vec![],
+            options: self.options,
             // functions: self.functions,
         };
         let sh = Shell {
@@ -171,11 +190,27 @@ fn run_shell(
     }
 
     loop {
-        let line = readline.read_line(sh, ctx, rt);
+        // the line editor uses the terminal even if stdin or stdout were redirected with exec
+        let mut raw_line = with_tty_fds(|| readline.read_line(sh, ctx, rt));
+
+        // expand references to earlier commands, the expanded line is shown and saved to history
+        if rt.options.histexpand {
+            match expand_history(&raw_line, ctx.history.as_ref()) {
+                Ok(Some(expanded)) => {
+                    ctx.out.println(&expanded)?;
+                    raw_line = expanded;
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    ctx.out.eprintln(e)?;
+                    continue;
+                },
+            }
+        }
 
         // attempt to expand alias
         // TODO IFS
-        let mut words = line
+        let mut words = raw_line
             .split(' ')
             .map(|s| s.trim_start_matches("\\\n").trim().to_string())
             .filter(|s| !s.is_empty())
@@ -197,9 +232,11 @@ fn run_shell(
 
         // TODO not sure if hook should run here (since not all vars are expanded yet)
         let hook_ctx = BeforeCommandCtx {
-            raw_command: line.clone(),
+            raw_command: raw_line.clone(),
             command: line.clone(),
         };
+        let started = (SystemTime::now(), Instant::now());
+        let started_in = rt.working_dir.clone();
         sh.hooks.run::<BeforeCommandCtx>(sh, ctx, rt, hook_ctx)?;
 
         // Retrieve command name or return immediately (empty command)
@@ -215,6 +252,8 @@ fn run_shell(
             .map(|(_, builtin_cmd)| builtin_cmd);
 
         let mut cmd_output: CmdOutput = CmdOutput::error();
+        // the command language is responsible for filling in the status of each pipeline stage
+        rt.pipe_status.clear();
         ctx.out.begin_collecting();
         if let Some(builtin_cmd) = builtin_cmd {
             let output = builtin_cmd.run(sh, ctx, rt, &words);
@@ -231,6 +270,12 @@ fn run_shell(
         }
         let (out, err) = ctx.out.end_collecting();
         cmd_output.set_output(out, err);
+
+        rt.exit_status = cmd_output.exit_code();
+        if rt.pipe_status.is_empty() {
+            rt.set_pipe_status(vec![rt.exit_status]);
+        }
+
         let _ = sh.hooks.run(
             sh,
             ctx,
@@ -241,6 +286,22 @@ fn run_shell(
             },
         );
 
+        // record the command as it was typed, before alias expansion, or the command that a
+        // builtin such as fc ran in its place
+        let raw_line = ctx
+            .state
+            .remove::<HistoryReplacement>()
+            .map_or(raw_line, |replacement| replacement.0);
+        let metadata = HistoryMetadata {
+            timestamp: Some(started.0),
+            duration: Some(started.1.elapsed()),
+            exit_status: Some(rt.exit_status),
+            cwd: Some(started_in),
+            hostname: hostname(),
+            session_id: Some(session_id().to_string()),
+        };
+        ctx.history.add_with_metadata(raw_line, metadata);
+
         // check up on running jobs
         let mut exit_statuses = vec![];
         ctx.jobs.retain(|status: ExitStatus| {
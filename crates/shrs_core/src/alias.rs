@@ -85,6 +85,11 @@ impl Alias {
             .collect::<Vec<_>>()
     }
 
+    /// Names of all defined aliases
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.aliases.keys()
+    }
+
     /// Set an alias
     pub fn set(&mut self, alias_name: &str, alias_info: AliasInfo) {
         self.aliases.insert(alias_name.into(), alias_info);
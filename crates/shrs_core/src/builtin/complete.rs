@@ -0,0 +1,50 @@
+//! Define completions at runtime, following fish's `complete`
+
+use super::BuiltinCmd;
+use crate::{
+    complete::{split_words, CompletionSpecs},
+    prelude::CmdOutput,
+    shell::{Context, Runtime, Shell},
+};
+
+#[derive(Default)]
+pub struct CompleteBuiltin {}
+
+impl BuiltinCmd for CompleteBuiltin {
+    fn run(
+        &self,
+        _sh: &Shell,
+        ctx: &mut Context,
+        _rt: &mut Runtime,
+        args: &Vec<String>,
+    ) -> anyhow::Result<CmdOutput> {
+        let specs = ctx.state.get_mut_or_default::<CompletionSpecs>();
+
+        // without arguments list every spec as a complete command that recreates it
+        if args.len() <= 1 {
+            let mut lines = vec![];
+            for (cmd, spec) in specs.iter() {
+                if let Some(external) = spec.external {
+                    lines.push(format!("complete -c {cmd} --external {external}"));
+                }
+                for spec in &spec.specs {
+                    lines.push(format!("complete -c {cmd}{spec}"));
+                }
+            }
+            lines.sort();
+            for line in lines {
+                ctx.out.println(line)?;
+            }
+            return Ok(CmdOutput::success());
+        }
+
+        // TODO builtins are passed words split on spaces, so split them again to handle quoted
+        // descriptions
+        let args = split_words(&args[1..].join(" "));
+        if let Err(e) = specs.complete(&args) {
+            ctx.out.eprintln(e)?;
+            return Ok(CmdOutput::error());
+        }
+        Ok(CmdOutput::success())
+    }
+}
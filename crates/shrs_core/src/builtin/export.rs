@@ -43,7 +43,7 @@ impl BuiltinCmd for ExportBuiltin {
 
         // print all env vars
         if cli.p {
-            for (var, val) in rt.env.iter() {
+            for (var, val) in rt.env.exported() {
                 let s = format!("export {:?}={:?}", var, val);
                 ctx.out.println(s)?;
             }
@@ -0,0 +1,246 @@
+//! Edit, list and re-run commands from history
+//!
+//! ```sh
+//! fc              # edit the previous command in $EDITOR and run it
+//! fc -e nano 12   # edit command number 12 in nano
+//! fc 10 14        # edit commands 10 to 14 and run them
+//! fc -l -5        # list the last five commands
+//! fc -s git       # run the last command starting with git again
+//! fc -s old=new   # run the previous command again, replacing old with new
+//! ```
+//!
+//! Commands can be given as their number in the output of `history`, as a negative offset from
+//! the previous command or as the prefix of a command.
+
+use clap::Parser;
+
+use super::{rerun_line, BuiltinCmd};
+use crate::{
+    editor::{edit_text, editor, env_var},
+    history::History,
+    prelude::CmdOutput,
+    shell::{Context, Runtime, Shell},
+};
+
+#[derive(Parser)]
+#[command(allow_negative_numbers = true)]
+struct Cli {
+    /// Editor to use instead of $FCEDIT, $VISUAL or $EDITOR, `-` runs the commands without
+    /// editing them
+    #[arg(short)]
+    editor: Option<String>,
+    /// List the commands instead of editing them
+    #[arg(short)]
+    list: bool,
+    /// Don't number the listed commands
+    #[arg(short = 'n')]
+    no_numbers: bool,
+    /// Reverse the order of the commands
+    #[arg(short)]
+    reverse: bool,
+    /// Run a command again without editing it, replacing OLD with NEW if given
+    #[arg(short)]
+    substitute: bool,
+    /// First and last command, or OLD=NEW followed by the command with -s
+    args: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct FcBuiltin {}
+
+impl BuiltinCmd for FcBuiltin {
+    fn run(
+        &self,
+        sh: &Shell,
+        ctx: &mut Context,
+        rt: &mut Runtime,
+        args: &Vec<String>,
+    ) -> anyhow::Result<CmdOutput> {
+        let cli = Cli::try_parse_from(args)?;
+
+        let substitute = cli.substitute || cli.editor.as_deref() == Some("-");
+        if substitute {
+            return match substitute_command(ctx.history.as_ref(), &cli.args) {
+                Ok(cmd) => rerun_line(sh, ctx, rt, cmd),
+                Err(spec) => not_found(ctx, spec),
+            };
+        }
+
+        // list the last 16 commands by default, or edit the previous one
+        let default_first = if cli.list { "-16" } else { "-1" };
+        let first_spec = cli.args.first().map_or(default_first, |s| s.as_str());
+        let last_spec = cli
+            .args
+            .get(1)
+            .map_or(if cli.list { "-1" } else { first_spec }, |s| s.as_str());
+        let commands = match command_range(ctx.history.as_ref(), first_spec, last_spec, cli.reverse)
+        {
+            Ok(commands) => commands,
+            Err(spec) => return not_found(ctx, spec),
+        };
+
+        if cli.list {
+            for (number, cmd) in commands {
+                if cli.no_numbers {
+                    ctx.out.println(format!("       {cmd}"))?;
+                } else {
+                    ctx.out.println(format!("{number:>5}  {cmd}"))?;
+                }
+            }
+            return Ok(CmdOutput::success());
+        }
+
+        let editor = cli
+            .editor
+            .or_else(|| env_var(rt, "FCEDIT"))
+            .unwrap_or_else(|| editor(rt));
+        let text = commands
+            .into_iter()
+            .map(|(_, cmd)| cmd)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let edited = match edit_text(rt, &editor, &text) {
+            Ok(edited) => edited,
+            Err(e) => {
+                ctx.out.eprintln(format!("fc: {e}"))?;
+                return Ok(CmdOutput::error());
+            },
+        };
+        if edited.trim().is_empty() {
+            return Ok(CmdOutput::success());
+        }
+        rerun_line(sh, ctx, rt, edited)
+    }
+}
+
+fn not_found(ctx: &mut Context, spec: &str) -> anyhow::Result<CmdOutput> {
+    ctx.out.eprintln(format!("fc: {spec}: no such command"))?;
+    Ok(CmdOutput::error())
+}
+
+/// Number of a command in history, where the oldest command is 1
+fn resolve_number(history: &dyn History<HistoryItem = String>, spec: &str) -> Option<usize> {
+    let len = history.len();
+    let index = match spec.parse::<isize>() {
+        Ok(n) if n > 0 => len.checked_sub(n as usize)?,
+        Ok(n) if n < 0 => (n.unsigned_abs() - 1).min(len.checked_sub(1)?),
+        Ok(_) => return None,
+        Err(_) => (0..len).find(|i| history.get(*i).is_some_and(|cmd| cmd.starts_with(spec)))?,
+    };
+    Some(len - index)
+}
+
+/// Commands from `first` to `last` along with their numbers, returning the spec that doesn't
+/// match a command otherwise
+fn command_range<'a>(
+    history: &dyn History<HistoryItem = String>,
+    first: &'a str,
+    last: &'a str,
+    reverse: bool,
+) -> Result<Vec<(usize, String)>, &'a str> {
+    let first_number = resolve_number(history, first).ok_or(first)?;
+    let last_number = resolve_number(history, last).ok_or(last)?;
+
+    let mut numbers =
+        (first_number.min(last_number)..=first_number.max(last_number)).collect::<Vec<_>>();
+    if (first_number > last_number) != reverse {
+        numbers.reverse();
+    }
+    let len = history.len();
+    Ok(numbers
+        .into_iter()
+        .filter_map(|number| Some((number, history.get(len - number)?.clone())))
+        .collect())
+}
+
+/// Command to run for `fc -s [OLD=NEW] [COMMAND]`, returning the spec that doesn't match a
+/// command otherwise
+fn substitute_command<'a>(
+    history: &dyn History<HistoryItem = String>,
+    args: &'a [String],
+) -> Result<String, &'a str> {
+    let (replace, spec) = match args.split_first() {
+        Some((first, rest)) if first.contains('=') => (first.split_once('='), rest.first()),
+        _ => (None, args.first()),
+    };
+    let spec = spec.map_or("-1", |s| s.as_str());
+    let number = resolve_number(history, spec).ok_or(spec)?;
+    let cmd = history.get(history.len() - number).ok_or(spec)?;
+    Ok(match replace {
+        Some((old, new)) => cmd.replace(old, new),
+        None => cmd.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command_range, resolve_number, substitute_command};
+    use crate::history::{DefaultHistory, History};
+
+    fn history() -> DefaultHistory {
+        let mut hist = DefaultHistory::new();
+        for cmd in ["ls", "git status", "cargo build", "git commit"] {
+            hist.add(cmd.into());
+        }
+        hist
+    }
+
+    #[test]
+    fn numbers() {
+        let hist = history();
+
+        assert_eq!(resolve_number(&hist, "1"), Some(1));
+        assert_eq!(resolve_number(&hist, "4"), Some(4));
+        assert_eq!(resolve_number(&hist, "-1"), Some(4));
+        assert_eq!(resolve_number(&hist, "-3"), Some(2));
+        // offsets past the oldest command stop at it
+        assert_eq!(resolve_number(&hist, "-10"), Some(1));
+        assert_eq!(resolve_number(&hist, "git"), Some(4));
+        assert_eq!(resolve_number(&hist, "car"), Some(3));
+
+        assert_eq!(resolve_number(&hist, "5"), None);
+        assert_eq!(resolve_number(&hist, "0"), None);
+        assert_eq!(resolve_number(&hist, "rm"), None);
+        assert_eq!(resolve_number(&DefaultHistory::new(), "-1"), None);
+    }
+
+    #[test]
+    fn ranges() {
+        let hist = history();
+
+        assert_eq!(
+            command_range(&hist, "2", "3", false),
+            Ok(vec![(2, "git status".into()), (3, "cargo build".into())])
+        );
+        assert_eq!(
+            command_range(&hist, "-1", "-2", false),
+            Ok(vec![(4, "git commit".into()), (3, "cargo build".into())])
+        );
+        assert_eq!(
+            command_range(&hist, "3", "2", true),
+            Ok(vec![(2, "git status".into()), (3, "cargo build".into())])
+        );
+        assert_eq!(command_range(&hist, "1", "9", false), Err("9"));
+    }
+
+    #[test]
+    fn substitute() {
+        let hist = history();
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(substitute_command(&hist, &[]), Ok("git commit".into()));
+        assert_eq!(
+            substitute_command(&hist, &args(&["commit=push"])),
+            Ok("git push".into())
+        );
+        assert_eq!(
+            substitute_command(&hist, &args(&["git=jj", "git s"])),
+            Ok("jj status".into())
+        );
+        assert_eq!(
+            substitute_command(&hist, &args(&["build=test", "cargo"])),
+            Ok("cargo test".into())
+        );
+        assert_eq!(substitute_command(&hist, &args(&["rm"])), Err("rm"));
+    }
+}
@@ -2,12 +2,13 @@
 
 // debatable if crate::history should be moved to crate::builtin::history
 
-use std::io::{stdout, Write};
+use std::time::{Duration, SystemTime};
 
 use clap::{Parser, Subcommand};
 
-use super::BuiltinCmd;
+use super::{rerun_line, BuiltinCmd};
 use crate::{
+    history::HistoryFilter,
     prelude::CmdOutput,
     shell::{Context, Runtime, Shell},
 };
@@ -16,13 +17,27 @@ use crate::{
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Only list commands run in the current directory
+    #[arg(long)]
+    cwd: bool,
+    /// Only list commands that exited with a non-zero status
+    #[arg(long)]
+    failed: bool,
+    /// Only list commands run within a duration, such as 30m, 2h, 1d or 1w
+    #[arg(long, value_parser = parse_duration)]
+    since: Option<Duration>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Remove all history entries
     Clear,
-    Run { index: u32 },
+    /// Run the command with the given number again
+    Run { number: usize },
+    /// List commands containing a string
     Search { query: String },
+    /// Remove the command with the given number
+    Delete { number: usize },
 }
 
 #[derive(Default)]
@@ -41,23 +56,107 @@ impl BuiltinCmd for HistoryBuiltin {
 
         match &cli.command {
             None => {
-                // let history = ctx.history.all();
-                // for (i, h) in history.iter().enumerate() {
-                //     print!("{} {}", i, h);
-                // }
-                // stdout().flush()?;
+                let filter = HistoryFilter {
+                    cwd: cli.cwd.then(|| rt.working_dir.clone()),
+                    failed: cli.failed,
+                    since: cli
+                        .since
+                        .and_then(|since| SystemTime::now().checked_sub(since)),
+                    until: None,
+                };
+                // entries are numbered from the oldest one
+                let len = ctx.history.len();
+                for (i, entry) in ctx.history.filter(&filter).into_iter().rev() {
+                    ctx.out
+                        .println(format!("{:>5}  {}", len - i, entry.command))?;
+                }
             },
             Some(Commands::Clear) => {
-                // ctx.history.clear();
+                ctx.history.clear();
             },
-            Some(Commands::Run { index }) => {
-                todo!()
+            Some(Commands::Run { number }) => {
+                let cmd = numbered_index(ctx, *number).and_then(|i| ctx.history.get(i).cloned());
+                let Some(cmd) = cmd else {
+                    ctx.out
+                        .eprintln(format!("history: {number}: no such entry"))?;
+                    return Ok(CmdOutput::error());
+                };
+                return rerun_line(sh, ctx, rt, cmd);
             },
             Some(Commands::Search { query }) => {
-                todo!()
+                let len = ctx.history.len();
+                let matches = (0..len)
+                    .rev()
+                    .filter_map(|i| Some((i, ctx.history.get(i)?.clone())))
+                    .filter(|(_, cmd)| cmd.contains(query.as_str()))
+                    .collect::<Vec<_>>();
+                if matches.is_empty() {
+                    return Ok(CmdOutput::error());
+                }
+                for (i, cmd) in matches {
+                    ctx.out.println(format!("{:>5}  {}", len - i, cmd))?;
+                }
+            },
+            Some(Commands::Delete { number }) => {
+                let removed = numbered_index(ctx, *number).and_then(|i| ctx.history.remove(i));
+                if removed.is_none() {
+                    ctx.out
+                        .eprintln(format!("history: {number}: no such entry"))?;
+                    return Ok(CmdOutput::error());
+                }
             },
         }
 
         Ok(CmdOutput::success())
     }
 }
+
+/// Index of the history entry listed with a number, where the oldest entry is 1
+fn numbered_index(ctx: &Context, number: usize) -> Option<usize> {
+    let len = ctx.history.len();
+    (1..=len).contains(&number).then(|| len - number)
+}
+
+/// Parse a duration made of a number and a unit of s, m, h, d or w, defaulting to seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration '{s}'"))?;
+    let secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return Err(format!("invalid duration unit '{unit}'")),
+    };
+    amount
+        .checked_mul(secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration '{s}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_duration;
+
+    #[test]
+    fn duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(
+            parse_duration("1w"),
+            Ok(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("h").is_err());
+        assert_eq!(
+            parse_duration("100000000000000000w"),
+            Err(String::from("invalid duration '100000000000000000w'"))
+        );
+    }
+}
@@ -7,23 +7,31 @@
 
 mod alias;
 mod cd;
+mod complete;
 mod debug;
 mod exit;
 mod export;
+mod fc;
 mod help;
 mod history;
 mod jobs;
+mod set;
 mod source;
+mod times;
+mod ulimit;
+mod umask;
 mod unalias;
 
 use std::collections::{hash_map::Iter, HashMap};
 
 use self::{
-    alias::AliasBuiltin, cd::CdBuiltin, debug::DebugBuiltin, exit::ExitBuiltin,
-    export::ExportBuiltin, help::HelpBuiltin, history::HistoryBuiltin, jobs::JobsBuiltin,
-    source::SourceBuiltin, unalias::UnaliasBuiltin,
+    alias::AliasBuiltin, cd::CdBuiltin, complete::CompleteBuiltin, debug::DebugBuiltin,
+    exit::ExitBuiltin, export::ExportBuiltin, fc::FcBuiltin, help::HelpBuiltin,
+    history::HistoryBuiltin, jobs::JobsBuiltin, set::SetBuiltin, source::SourceBuiltin,
+    times::TimesBuiltin, ulimit::UlimitBuiltin, umask::UmaskBuiltin, unalias::UnaliasBuiltin,
 };
 use crate::{
+    history::HistoryReplacement,
     prelude::CmdOutput,
     shell::{Context, Runtime, Shell},
 };
@@ -81,11 +89,16 @@ impl Default for Builtins {
                     "history",
                     Box::new(HistoryBuiltin::default()) as Box<dyn BuiltinCmd>,
                 ),
+                ("fc", Box::new(FcBuiltin::default()) as Box<dyn BuiltinCmd>),
                 (
                     "exit",
                     Box::new(ExitBuiltin::default()) as Box<dyn BuiltinCmd>,
                 ),
                 ("cd", Box::new(CdBuiltin::default()) as Box<dyn BuiltinCmd>),
+                (
+                    "complete",
+                    Box::new(CompleteBuiltin::default()) as Box<dyn BuiltinCmd>,
+                ),
                 (
                     "debug",
                     Box::new(DebugBuiltin::default()) as Box<dyn BuiltinCmd>,
@@ -114,6 +127,22 @@ impl Default for Builtins {
                     "help",
                     Box::new(HelpBuiltin::default()) as Box<dyn BuiltinCmd>,
                 ),
+                (
+                    "set",
+                    Box::new(SetBuiltin::default()) as Box<dyn BuiltinCmd>,
+                ),
+                (
+                    "ulimit",
+                    Box::new(UlimitBuiltin::default()) as Box<dyn BuiltinCmd>,
+                ),
+                (
+                    "umask",
+                    Box::new(UmaskBuiltin::default()) as Box<dyn BuiltinCmd>,
+                ),
+                (
+                    "times",
+                    Box::new(TimesBuiltin::default()) as Box<dyn BuiltinCmd>,
+                ),
             ]),
         }
     }
@@ -129,3 +158,44 @@ pub trait BuiltinCmd {
         args: &Vec<String>,
     ) -> anyhow::Result<CmdOutput>;
 }
+
+/// Run a command line from a builtin, such as a command from history
+///
+/// Like the shell's own loop, a single line starting with the name of a builtin runs the builtin
+/// directly and anything else is evaluated by the shell language.
+pub(crate) fn run_line(
+    sh: &Shell,
+    ctx: &mut Context,
+    rt: &mut Runtime,
+    line: String,
+) -> anyhow::Result<CmdOutput> {
+    // TODO IFS
+    let words = line
+        .split(' ')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    if !line.contains('\n') {
+        let builtin = words
+            .first()
+            .and_then(|name| sh.builtins.iter().find(|(builtin, _)| **builtin == name));
+        if let Some((_, builtin)) = builtin {
+            return builtin.run(sh, ctx, rt, &words);
+        }
+    }
+    sh.lang.eval(sh, ctx, rt, line)
+}
+
+/// Echo a command from history and run it again
+///
+/// The command is saved to history in place of the line that ran the builtin.
+pub(crate) fn rerun_line(
+    sh: &Shell,
+    ctx: &mut Context,
+    rt: &mut Runtime,
+    line: String,
+) -> anyhow::Result<CmdOutput> {
+    ctx.out.println(&line)?;
+    ctx.state.insert(HistoryReplacement(line.clone()));
+    run_line(sh, ctx, rt, line)
+}
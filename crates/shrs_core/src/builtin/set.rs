@@ -0,0 +1,65 @@
+//! Toggle shell options
+//!
+//! ```sh
+//! set -o pipefail  # enable option
+//! set +o pipefail  # disable option
+//! set -o emacs     # use emacs keybindings in the line editor
+//! set -o           # list all options
+//! ```
+
+use super::BuiltinCmd;
+use crate::{
+    prelude::CmdOutput,
+    shell::{Context, Runtime, Shell},
+};
+
+#[derive(Default)]
+pub struct SetBuiltin {}
+
+impl BuiltinCmd for SetBuiltin {
+    fn run(
+        &self,
+        _sh: &Shell,
+        ctx: &mut Context,
+        rt: &mut Runtime,
+        args: &Vec<String>,
+    ) -> anyhow::Result<CmdOutput> {
+        // clap does not support flags prefixed with '+', so args are parsed by hand
+        let mut it = args.iter().skip(1);
+        while let Some(arg) = it.next() {
+            let enable = match arg.as_str() {
+                "-o" => true,
+                "+o" => false,
+                _ => {
+                    ctx.out.eprintln(format!("set: invalid argument {arg}"))?;
+                    return Ok(CmdOutput::error());
+                },
+            };
+            match it.next() {
+                Some(name) => {
+                    if let Err(e) = rt.options.set(name, enable) {
+                        ctx.out.eprintln(format!("set: {e}"))?;
+                        return Ok(CmdOutput::error());
+                    }
+                },
+                None => {
+                    // `set -o` without an option name lists all options
+                    for (name, val) in rt.options.iter() {
+                        let state = if val { "on" } else { "off" };
+                        ctx.out.println(format!("{name:<15}{state}"))?;
+                    }
+                },
+            }
+        }
+
+        // plain `set` prints the options in a form that can be reused as input
+        if args.len() <= 1 {
+            for (name, val) in rt.options.iter() {
+                let sign = if val { '-' } else { '+' };
+                ctx.out.println(format!("set {sign}o {name}"))?;
+            }
+        }
+
+        Ok(CmdOutput::success())
+    }
+}
@@ -0,0 +1,35 @@
+//! Print the accumulated CPU time of the shell and of its children
+//!
+//! The first line contains the user and system time used by the shell itself, the second line
+//! the times used by all child processes that have been waited on.
+
+use shrs_job::{format_duration, CpuTime};
+
+use super::BuiltinCmd;
+use crate::{
+    prelude::CmdOutput,
+    shell::{Context, Runtime, Shell},
+};
+
+#[derive(Default)]
+pub struct TimesBuiltin {}
+
+impl BuiltinCmd for TimesBuiltin {
+    fn run(
+        &self,
+        _sh: &Shell,
+        ctx: &mut Context,
+        _rt: &mut Runtime,
+        _args: &Vec<String>,
+    ) -> anyhow::Result<CmdOutput> {
+        for times in [CpuTime::shell()?, CpuTime::children()?] {
+            ctx.out.println(format!(
+                "{} {}",
+                format_duration(times.user),
+                format_duration(times.sys)
+            ))?;
+        }
+
+        Ok(CmdOutput::success())
+    }
+}
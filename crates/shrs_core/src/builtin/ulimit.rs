@@ -0,0 +1,210 @@
+//! Query and set resource limits of the shell
+//!
+//! Limits are set on the shell process itself, so they are inherited by every process the shell
+//! spawns afterwards.
+//!
+//! ```sh
+//! ulimit -a          # print all soft limits
+//! ulimit -Hn         # print hard limit of open files
+//! ulimit -n 1024     # set both soft and hard limit of open files
+//! ulimit -Sc unlimited
+//! ```
+
+use clap::Parser;
+use nix::{
+    libc::RLIM_INFINITY,
+    sys::resource::{getrlimit, setrlimit, Resource},
+};
+
+use super::BuiltinCmd;
+use crate::{
+    prelude::CmdOutput,
+    shell::{Context, Runtime, Shell},
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// Use the soft limit
+    #[arg(short = 'S')]
+    soft: bool,
+    /// Use the hard limit
+    #[arg(short = 'H')]
+    hard: bool,
+    /// Print all limits
+    #[arg(short = 'a')]
+    all: bool,
+    /// Maximum size of core files created
+    #[arg(short = 'c')]
+    core: bool,
+    /// Maximum size of files written by the shell and its children
+    #[arg(short = 'f')]
+    file_size: bool,
+    /// Maximum number of open file descriptors
+    #[arg(short = 'n')]
+    open_files: bool,
+    /// Maximum stack size
+    #[arg(short = 's')]
+    stack: bool,
+    /// Maximum amount of cpu time in seconds
+    #[arg(short = 't')]
+    cpu_time: bool,
+    /// Maximum amount of virtual memory
+    #[arg(short = 'v')]
+    virtual_memory: bool,
+    /// New limit, either a number, `unlimited`, `soft` or `hard`
+    limit: Option<String>,
+}
+
+/// Description of a limit that can be queried or set
+struct Limit {
+    flag: char,
+    resource: Resource,
+    description: &'static str,
+    unit: &'static str,
+    /// Number of bytes (or other base unit) that a unit of the limit represents
+    factor: u64,
+}
+
+const LIMITS: [Limit; 6] = [
+    Limit {
+        flag: 'c',
+        resource: Resource::RLIMIT_CORE,
+        description: "core file size",
+        unit: "blocks",
+        factor: 1024,
+    },
+    Limit {
+        flag: 'f',
+        resource: Resource::RLIMIT_FSIZE,
+        description: "file size",
+        unit: "blocks",
+        factor: 1024,
+    },
+    Limit {
+        flag: 'n',
+        resource: Resource::RLIMIT_NOFILE,
+        description: "open files",
+        unit: "",
+        factor: 1,
+    },
+    Limit {
+        flag: 's',
+        resource: Resource::RLIMIT_STACK,
+        description: "stack size",
+        unit: "kbytes",
+        factor: 1024,
+    },
+    Limit {
+        flag: 't',
+        resource: Resource::RLIMIT_CPU,
+        description: "cpu time",
+        unit: "seconds",
+        factor: 1,
+    },
+    Limit {
+        flag: 'v',
+        resource: Resource::RLIMIT_AS,
+        description: "virtual memory",
+        unit: "kbytes",
+        factor: 1024,
+    },
+];
+
+#[derive(Default)]
+pub struct UlimitBuiltin {}
+
+impl BuiltinCmd for UlimitBuiltin {
+    fn run(
+        &self,
+        _sh: &Shell,
+        ctx: &mut Context,
+        _rt: &mut Runtime,
+        args: &Vec<String>,
+    ) -> anyhow::Result<CmdOutput> {
+        let cli = Cli::try_parse_from(args)?;
+
+        let selected = [
+            cli.core,
+            cli.file_size,
+            cli.open_files,
+            cli.stack,
+            cli.cpu_time,
+            cli.virtual_memory,
+        ];
+        let mut limits: Vec<&Limit> = LIMITS
+            .iter()
+            .zip(selected)
+            .filter(|(_, selected)| cli.all || *selected)
+            .map(|(limit, _)| limit)
+            .collect();
+        // file size is the default limit, like in other shells
+        if limits.is_empty() {
+            limits.push(&LIMITS[1]);
+        }
+
+        let Some(value) = cli.limit else {
+            let verbose = limits.len() > 1;
+            for limit in limits {
+                let (soft, hard) = getrlimit(limit.resource)?;
+                let value = display_value(if cli.hard { hard } else { soft }, limit.factor);
+                if verbose {
+                    let unit = if limit.unit.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{}, ", limit.unit)
+                    };
+                    let label = format!("{} ({unit}-{})", limit.description, limit.flag);
+                    ctx.out.println(format!("{label:<32}{value}"))?;
+                } else {
+                    ctx.out.println(value)?;
+                }
+            }
+            return Ok(CmdOutput::success());
+        };
+
+        if limits.len() != 1 {
+            ctx.out
+                .eprintln("ulimit: only a single limit can be set at a time")?;
+            return Ok(CmdOutput::error());
+        }
+        let limit = limits[0];
+
+        let (cur_soft, cur_hard) = getrlimit(limit.resource)?;
+        let new = match value.as_str() {
+            "unlimited" => RLIM_INFINITY,
+            "soft" => cur_soft,
+            "hard" => cur_hard,
+            value => match value.parse::<u64>() {
+                Ok(value) => value.saturating_mul(limit.factor),
+                Err(_) => {
+                    ctx.out
+                        .eprintln(format!("ulimit: {value}: invalid number"))?;
+                    return Ok(CmdOutput::error());
+                },
+            },
+        };
+
+        // without -S or -H both limits are set
+        let both = !cli.soft && !cli.hard;
+        let soft = if cli.soft || both { new } else { cur_soft };
+        let hard = if cli.hard || both { new } else { cur_hard };
+
+        if let Err(e) = setrlimit(limit.resource, soft, hard) {
+            ctx.out.eprintln(format!(
+                "ulimit: {}: cannot modify limit: {e}",
+                limit.description
+            ))?;
+            return Ok(CmdOutput::error());
+        }
+
+        Ok(CmdOutput::success())
+    }
+}
+
+fn display_value(value: u64, factor: u64) -> String {
+    if value == RLIM_INFINITY {
+        String::from("unlimited")
+    } else {
+        (value / factor).to_string()
+    }
+}
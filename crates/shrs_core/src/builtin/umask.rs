@@ -0,0 +1,187 @@
+//! Query and set the file mode creation mask
+//!
+//! ```sh
+//! umask             # print mask in octal, e.g. 0022
+//! umask -S          # print mask in symbolic form, e.g. u=rwx,g=rx,o=rx
+//! umask 027         # set mask from octal
+//! umask u=rwx,g=rx  # set mask from symbolic mode
+//! umask g-w,o+r     # modify the permissions allowed by the current mask
+//! ```
+
+use clap::Parser;
+use nix::sys::stat::{umask, Mode};
+use thiserror::Error;
+
+use super::BuiltinCmd;
+use crate::{
+    prelude::CmdOutput,
+    shell::{Context, Runtime, Shell},
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// Print the mask in symbolic form
+    #[arg(short = 'S')]
+    symbolic: bool,
+    /// Octal or symbolic mode to set the mask to
+    mode: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum UmaskError {
+    #[error("{0}: octal number out of range")]
+    OutOfRange(String),
+    #[error("{0}: invalid symbolic mode")]
+    InvalidSymbolic(String),
+}
+
+#[derive(Default)]
+pub struct UmaskBuiltin {}
+
+impl BuiltinCmd for UmaskBuiltin {
+    fn run(
+        &self,
+        _sh: &Shell,
+        ctx: &mut Context,
+        _rt: &mut Runtime,
+        args: &Vec<String>,
+    ) -> anyhow::Result<CmdOutput> {
+        let cli = Cli::try_parse_from(args)?;
+
+        let current = current_umask();
+
+        let Some(mode) = cli.mode else {
+            if cli.symbolic {
+                ctx.out.println(to_symbolic(current))?;
+            } else {
+                ctx.out.println(format!("{current:04o}"))?;
+            }
+            return Ok(CmdOutput::success());
+        };
+
+        match parse_mask(current, &mode) {
+            Ok(mask) => {
+                umask(Mode::from_bits_truncate(mask));
+                Ok(CmdOutput::success())
+            },
+            Err(e) => {
+                ctx.out.eprintln(format!("umask: {e}"))?;
+                Ok(CmdOutput::error())
+            },
+        }
+    }
+}
+
+/// Read the mask of the shell process
+///
+/// There is no way to read the mask without setting it, so it is restored right away.
+fn current_umask() -> u32 {
+    let mask = umask(Mode::empty());
+    umask(mask);
+    mask.bits() as u32
+}
+
+/// Display mask as the permissions it allows
+fn to_symbolic(mask: u32) -> String {
+    let allowed = !mask & 0o777;
+    ["u", "g", "o"]
+        .iter()
+        .enumerate()
+        .map(|(i, who)| {
+            let bits = (allowed >> (6 - 3 * i)) & 0o7;
+            let perms: String = [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')]
+                .iter()
+                .filter(|(bit, _)| bits & bit != 0)
+                .map(|(_, c)| c)
+                .collect();
+            format!("{who}={perms}")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Compute new mask from either an octal number or a symbolic mode applied to the current mask
+fn parse_mask(current: u32, mode: &str) -> Result<u32, UmaskError> {
+    if mode.starts_with(|c: char| c.is_ascii_digit()) {
+        return match u32::from_str_radix(mode, 8) {
+            Ok(mask) if mask <= 0o777 => Ok(mask),
+            _ => Err(UmaskError::OutOfRange(mode.into())),
+        };
+    }
+
+    // symbolic modes describe the permissions that are allowed, which is the inverse of the mask
+    let invalid = || UmaskError::InvalidSymbolic(mode.into());
+    let mut allowed = !current & 0o777;
+    for clause in mode.split(',') {
+        let op_pos = clause.find(['+', '-', '=']).ok_or_else(invalid)?;
+        let (who, rest) = clause.split_at(op_pos);
+
+        let mut who_bits = 0;
+        for c in who.chars() {
+            who_bits |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return Err(invalid()),
+            };
+        }
+        if who.is_empty() {
+            who_bits = 0o777;
+        }
+
+        let mut chars = rest.chars();
+        let op = chars.next().ok_or_else(invalid)?;
+        let mut perm_bits = 0;
+        for c in chars {
+            perm_bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                _ => return Err(invalid()),
+            };
+        }
+        let bits = who_bits & perm_bits;
+
+        match op {
+            '+' => allowed |= bits,
+            '-' => allowed &= !bits,
+            '=' => allowed = (allowed & !who_bits) | bits,
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(!allowed & 0o777)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mask, to_symbolic, UmaskError};
+
+    #[test]
+    fn octal() {
+        assert_eq!(parse_mask(0o022, "027"), Ok(0o027));
+        assert_eq!(
+            parse_mask(0o022, "1000"),
+            Err(UmaskError::OutOfRange("1000".into()))
+        );
+        assert!(parse_mask(0o022, "089").is_err());
+    }
+
+    #[test]
+    fn symbolic() {
+        assert_eq!(parse_mask(0o022, "u=rwx,g=rx,o="), Ok(0o027));
+        assert_eq!(parse_mask(0o022, "g+w"), Ok(0o002));
+        assert_eq!(parse_mask(0o022, "o-rx"), Ok(0o027));
+        assert_eq!(parse_mask(0o022, "a=r"), Ok(0o333));
+        assert_eq!(parse_mask(0o022, "+w"), Ok(0o000));
+        assert!(parse_mask(0o022, "z+w").is_err());
+        assert!(parse_mask(0o022, "u").is_err());
+    }
+
+    #[test]
+    fn display_symbolic() {
+        assert_eq!(to_symbolic(0o022), "u=rwx,g=rx,o=rx");
+        assert_eq!(to_symbolic(0o077), "u=rwx,g=,o=");
+    }
+}
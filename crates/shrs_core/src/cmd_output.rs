@@ -15,11 +15,17 @@ pub struct CmdOutput {
     pub status: ExitStatus,
 }
 impl CmdOutput {
+    /// Construct output with the given exit code
     pub fn new(status: i32) -> Self {
+        // exit codes are stored in the second byte of the raw wait status
+        CmdOutput::from_status(ExitStatus::from_raw((status & 0xff) << 8))
+    }
+    /// Construct output from the exit status of a process
+    pub fn from_status(status: ExitStatus) -> Self {
         CmdOutput {
             stdout: String::new(),
             stderr: String::new(),
-            status: ExitStatus::from_raw(status),
+            status,
         }
     }
     pub fn success() -> Self {
@@ -32,4 +38,8 @@ impl CmdOutput {
         self.stdout = out;
         self.stderr = err;
     }
+    /// Numeric exit code, processes killed by a signal are reported as `128+N`
+    pub fn exit_code(&self) -> i32 {
+        shrs_job::exit_code(self.status)
+    }
 }
@@ -0,0 +1,538 @@
+//! Completion specs in the style of fish's `complete` command
+//!
+//! Specs are defined at runtime with the `complete` builtin, or loaded from files of `complete`
+//! commands such as the ones shipped with fish. A file named `<cmd>.fish` in one of the search
+//! paths is loaded the first time `cmd` is completed.
+//! ```ignore
+//! complete -c git -l version -d 'Print the git suite version'
+//! complete -c git -n __fish_use_subcommand -f -a 'checkout\tSwitch branches'
+//! complete -c kubectl --external cobra
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CompleteError {
+    #[error("No command given")]
+    MissingCommand,
+    #[error("Invalid arguments: {0}")]
+    InvalidArgs(#[from] clap::Error),
+    #[error("Failed to read spec file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Convention a program follows to complete its own arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExternalCompleter {
+    /// `COMPLETE=fish cmd -- <words>` as used by clap_complete
+    Clap,
+    /// `cmd __complete <args>` as used by cobra
+    Cobra,
+}
+
+impl Display for ExternalCompleter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExternalCompleter::Clap => write!(f, "clap"),
+            ExternalCompleter::Cobra => write!(f, "cobra"),
+        }
+    }
+}
+
+/// Argument offered by a spec, along with its description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecArgument {
+    pub value: String,
+    pub description: Option<String>,
+}
+
+/// Single `complete` definition, describing an option and/or a list of arguments
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompleteSpec {
+    /// Short options such as `-h`
+    pub short: Vec<char>,
+    /// Long options such as `--help`
+    pub long: Vec<String>,
+    /// Old style long options with a single dash such as `-name`
+    pub old: Vec<String>,
+    pub description: Option<String>,
+    /// Arguments that are offered for the option, or as positional arguments if there is no option
+    pub arguments: Vec<SpecArgument>,
+    /// Don't offer files along with the arguments
+    pub no_files: bool,
+    /// Offer files even if other specs said not to
+    pub force_files: bool,
+    /// The option takes a parameter
+    pub requires_param: bool,
+    /// Only use the spec if the condition holds, see [Condition]
+    pub condition: Option<Condition>,
+}
+
+impl CompleteSpec {
+    /// Check if the spec defines any options
+    pub fn has_options(&self) -> bool {
+        !self.short.is_empty() || !self.long.is_empty() || !self.old.is_empty()
+    }
+
+    /// Check if a word is one of the options of the spec
+    pub fn matches_option(&self, word: &str) -> bool {
+        if let Some(long) = word.strip_prefix("--") {
+            self.long.iter().any(|l| l == long)
+        } else if let Some(short) = word.strip_prefix('-') {
+            self.old.iter().any(|o| o == short)
+                || short.chars().count() == 1 && self.short.iter().any(|s| short.starts_with(*s))
+        } else {
+            false
+        }
+    }
+}
+
+impl Display for CompleteSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for short in &self.short {
+            write!(f, " -s {short}")?;
+        }
+        for long in &self.long {
+            write!(f, " -l {long}")?;
+        }
+        for old in &self.old {
+            write!(f, " -o {old}")?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " -n {}", quote(&condition.to_string()))?;
+        }
+        if self.no_files {
+            write!(f, " -f")?;
+        }
+        if self.force_files {
+            write!(f, " -F")?;
+        }
+        if self.requires_param {
+            write!(f, " -r")?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, " -d {}", quote(description))?;
+        }
+        if !self.arguments.is_empty() {
+            let arguments = self
+                .arguments
+                .iter()
+                .map(|arg| match &arg.description {
+                    Some(description) => {
+                        format!("{}\\t{}", escape(&arg.value), escape(description))
+                    },
+                    None => escape(&arg.value),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            write!(f, " -a {}", quote(&arguments))?;
+        }
+        Ok(())
+    }
+}
+
+/// Conditions of fish's completion helpers that are understood
+///
+/// Specs with any other condition are skipped, since showing them everywhere would do more harm
+/// than good.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// `__fish_use_subcommand`, no subcommand has been given yet
+    UseSubcommand,
+    /// `__fish_seen_subcommand_from a b`, one of the subcommands has been given
+    SeenSubcommandFrom(Vec<String>),
+}
+
+impl Condition {
+    fn parse(condition: &str) -> Option<Self> {
+        let words = split_words(condition);
+        match words.first().map(String::as_str) {
+            Some("__fish_use_subcommand") => Some(Condition::UseSubcommand),
+            Some("__fish_seen_subcommand_from") => {
+                Some(Condition::SeenSubcommandFrom(words[1..].to_vec()))
+            },
+            _ => None,
+        }
+    }
+
+    /// Check the condition against the arguments before the one being completed, not including
+    /// the command name
+    pub fn test(&self, args: &[String]) -> bool {
+        let mut positional = args.iter().filter(|arg| !arg.starts_with('-'));
+        match self {
+            Condition::UseSubcommand => positional.next().is_none(),
+            Condition::SeenSubcommandFrom(subcommands) => {
+                positional.any(|arg| subcommands.contains(arg))
+            },
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::UseSubcommand => write!(f, "__fish_use_subcommand"),
+            Condition::SeenSubcommandFrom(subcommands) => {
+                write!(f, "__fish_seen_subcommand_from {}", subcommands.join(" "))
+            },
+        }
+    }
+}
+
+/// Everything known about completing a single command
+#[derive(Debug, Clone, Default)]
+pub struct CommandSpec {
+    pub specs: Vec<CompleteSpec>,
+    /// Ask the program itself for completions instead of using the specs
+    pub external: Option<ExternalCompleter>,
+}
+
+/// Store of completion specs, kept in the shell's [State](crate::state::State)
+#[derive(Debug, Default)]
+pub struct CompletionSpecs {
+    commands: HashMap<String, CommandSpec>,
+    /// Directories searched for spec files
+    paths: Vec<PathBuf>,
+    /// Commands that spec files have already been searched for
+    loaded: HashSet<String>,
+}
+
+impl CompletionSpecs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Search a directory for `<cmd>.fish` spec files
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Get the specs of a command, loading its spec file if there is one
+    pub fn get(&mut self, cmd: &str) -> Option<&CommandSpec> {
+        if self.loaded.insert(cmd.to_string()) {
+            let file = self
+                .paths
+                .iter()
+                .map(|path| path.join(format!("{cmd}.fish")))
+                .find(|file| file.is_file());
+            if let Some(file) = file {
+                // spec files are best effort, lines that fail to parse are skipped
+                let _ = self.load_file(&file);
+            }
+        }
+        self.commands.get(cmd)
+    }
+
+    /// Add a spec for a command
+    pub fn insert(&mut self, cmd: &str, spec: CompleteSpec) {
+        self.commands
+            .entry(cmd.to_string())
+            .or_default()
+            .specs
+            .push(spec);
+    }
+
+    /// Complete a command by running it, see [ExternalCompleter]
+    pub fn set_external(&mut self, cmd: &str, external: ExternalCompleter) {
+        self.commands.entry(cmd.to_string()).or_default().external = Some(external);
+    }
+
+    /// Remove all specs of a command
+    pub fn remove(&mut self, cmd: &str) {
+        self.commands.remove(cmd);
+    }
+
+    /// Iterate over the specs of all commands
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &CommandSpec)> {
+        self.commands.iter()
+    }
+
+    /// Run a `complete` command given its arguments, not including the name `complete` itself
+    pub fn complete(&mut self, args: &[String]) -> Result<(), CompleteError> {
+        let cli = CompleteArgs::try_parse_from(
+            std::iter::once("complete").chain(args.iter().map(String::as_str)),
+        )?;
+        cli.apply(self)
+    }
+
+    /// Load a file of `complete` commands, lines that are not `complete` commands are ignored
+    pub fn load_file(&mut self, path: &Path) -> Result<(), CompleteError> {
+        let contents = std::fs::read_to_string(path)?;
+        for line in contents.lines() {
+            let words = split_words(line);
+            if words.first().map(String::as_str) != Some("complete") {
+                continue;
+            }
+            // keep going so one unsupported line doesn't lose the whole file
+            let _ = self.complete(&words[1..]);
+        }
+        Ok(())
+    }
+}
+
+/// Arguments of the `complete` command, following fish
+#[derive(Parser)]
+#[command(name = "complete", disable_help_flag = true)]
+pub(crate) struct CompleteArgs {
+    /// Command to add completions to
+    #[arg(short = 'c', long = "command")]
+    pub command: Vec<String>,
+    /// Short option such as `-h`
+    #[arg(short = 's', long = "short-option")]
+    pub short: Vec<char>,
+    /// Long option such as `--help`
+    #[arg(short = 'l', long = "long-option", allow_hyphen_values = true)]
+    pub long: Vec<String>,
+    /// Old style option such as `-name`
+    #[arg(short = 'o', long = "old-option", allow_hyphen_values = true)]
+    pub old: Vec<String>,
+    /// Description of the option or arguments
+    #[arg(short = 'd', long, allow_hyphen_values = true)]
+    pub description: Option<String>,
+    /// Space separated arguments, each may be followed by a tab and its description
+    #[arg(short = 'a', long, allow_hyphen_values = true)]
+    pub arguments: Option<String>,
+    /// Don't complete files
+    #[arg(short = 'f', long)]
+    pub no_files: bool,
+    /// Complete files even if other specs say not to
+    #[arg(short = 'F', long)]
+    pub force_files: bool,
+    /// The option requires a parameter
+    #[arg(short = 'r', long)]
+    pub require_parameter: bool,
+    /// Same as both -r and -f
+    #[arg(short = 'x', long)]
+    pub exclusive: bool,
+    /// Only use the completion if the condition holds
+    #[arg(short = 'n', long, allow_hyphen_values = true)]
+    pub condition: Option<String>,
+    /// Keep the order of the arguments, accepted for compatibility
+    #[arg(short = 'k', long)]
+    pub keep_order: bool,
+    /// Remove all completions of the command
+    #[arg(short = 'e', long)]
+    pub erase: bool,
+    /// Ask the command itself for completions
+    #[arg(long, value_enum)]
+    pub external: Option<ExternalCompleter>,
+    /// Show help
+    #[arg(short = 'h', long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+}
+
+impl CompleteArgs {
+    pub(crate) fn apply(self, specs: &mut CompletionSpecs) -> Result<(), CompleteError> {
+        if self.command.is_empty() {
+            return Err(CompleteError::MissingCommand);
+        }
+
+        let condition = match &self.condition {
+            Some(condition) => match Condition::parse(condition) {
+                Some(condition) => Some(condition),
+                // not understood, skip the spec
+                None => return Ok(()),
+            },
+            None => None,
+        };
+        let spec = CompleteSpec {
+            short: self.short,
+            long: self.long,
+            old: self.old,
+            description: self.description,
+            arguments: self
+                .arguments
+                .as_deref()
+                .map(parse_arguments)
+                .unwrap_or_default(),
+            no_files: self.no_files || self.exclusive,
+            force_files: self.force_files,
+            requires_param: self.require_parameter || self.exclusive,
+            condition,
+        };
+
+        for cmd in &self.command {
+            if self.erase {
+                specs.remove(cmd);
+                continue;
+            }
+            if let Some(external) = self.external {
+                specs.set_external(cmd, external);
+            }
+            if spec != CompleteSpec::default() {
+                specs.insert(cmd, spec.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split the arguments of `-a` into values and their descriptions
+fn parse_arguments(arguments: &str) -> Vec<SpecArgument> {
+    split_words(arguments)
+        .into_iter()
+        // command substitutions can't be run here
+        .filter(|word| !word.starts_with('('))
+        .map(|word| match word.split_once('\t') {
+            Some((value, description)) => SpecArgument {
+                value: value.to_string(),
+                description: Some(description.to_string()),
+            },
+            None => SpecArgument {
+                value: word,
+                description: None,
+            },
+        })
+        .collect()
+}
+
+/// Split a line into words, handling quotes and backslash escapes like a shell
+///
+/// Inside double quotes and outside of quotes `\t` is a tab, as fish uses it to separate values
+/// from their descriptions. Inside single quotes only `\'` and `\\` are escapes.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (None, '#') if word.is_none() => break,
+            (None, ch) if ch.is_whitespace() => words.extend(word.take()),
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                word.get_or_insert_with(String::new);
+            },
+            (Some(q), ch) if q == ch => quote = None,
+            (Some('\''), '\\') => {
+                let word = word.get_or_insert_with(String::new);
+                match chars.peek() {
+                    Some('\'' | '\\') => word.extend(chars.next()),
+                    _ => word.push('\\'),
+                }
+            },
+            (None | Some('"'), '\\') => {
+                let escaped = match chars.next() {
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some(ch) => ch,
+                    None => break,
+                };
+                word.get_or_insert_with(String::new).push(escaped);
+            },
+            (_, ch) => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Quote a word so that [split_words] gives it back
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Escape the whitespace and backslashes of a word
+fn escape(word: &str) -> String {
+    word.replace('\\', "\\\\")
+        .replace(' ', "\\ ")
+        .replace('\t', "\\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_words, CompletionSpecs, Condition, ExternalCompleter, SpecArgument};
+
+    fn words(line: &str) -> Vec<String> {
+        split_words(line)
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            words("a 'b c' \"d\\\"e\" f\\ g # h"),
+            vec!["a", "b c", "d\"e", "f g"]
+        );
+        assert_eq!(words("x\\ty 'x\\ty'"), vec!["x\ty", "x\\ty"]);
+        assert_eq!(words("''"), vec![""]);
+        assert_eq!(words(r"'a\'b\\c\d'"), vec![r"a'b\c\d"]);
+    }
+
+    #[test]
+    fn complete_commands() {
+        let mut specs = CompletionSpecs::new();
+        specs
+            .complete(&words("-c git -s v -l version -d 'Print the version'"))
+            .unwrap();
+        specs
+            .complete(&words(
+                "-c git -n __fish_use_subcommand -f -a 'checkout\\tSwitch\\ branches add'",
+            ))
+            .unwrap();
+        // unsupported conditions are skipped
+        specs
+            .complete(&words(
+                "-c git -n '__fish_git_using_command log' -l oneline",
+            ))
+            .unwrap();
+        specs
+            .complete(&words("-c kubectl --external cobra"))
+            .unwrap();
+        assert!(specs.complete(&words("-l version")).is_err());
+
+        let git = specs.get("git").unwrap();
+        assert_eq!(git.specs.len(), 2);
+        assert!(git.specs[0].matches_option("--version"));
+        assert!(git.specs[0].matches_option("-v"));
+        assert_eq!(git.specs[1].condition, Some(Condition::UseSubcommand));
+        assert!(git.specs[1].no_files);
+        assert_eq!(
+            git.specs[1].arguments,
+            vec![
+                SpecArgument {
+                    value: "checkout".into(),
+                    description: Some("Switch branches".into())
+                },
+                SpecArgument {
+                    value: "add".into(),
+                    description: None
+                },
+            ]
+        );
+        assert_eq!(
+            specs.get("kubectl").unwrap().external,
+            Some(ExternalCompleter::Cobra)
+        );
+
+        // printed specs can be read back
+        let printed = format!("-c git{}", specs.get("git").unwrap().specs[1]);
+        let mut reread = CompletionSpecs::new();
+        reread.complete(&words(&printed)).unwrap();
+        assert_eq!(
+            reread.get("git").unwrap().specs[0],
+            specs.get("git").unwrap().specs[1]
+        );
+
+        specs.complete(&words("-c git -e")).unwrap();
+        assert!(specs.get("git").is_none());
+    }
+
+    #[test]
+    fn conditions() {
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(Condition::UseSubcommand.test(&args(&["-v"])));
+        assert!(!Condition::UseSubcommand.test(&args(&["add"])));
+        let seen = Condition::SeenSubcommandFrom(vec!["add".into(), "rm".into()]);
+        assert!(seen.test(&args(&["-v", "rm", "file"])));
+        assert!(!seen.test(&args(&["commit"])));
+    }
+}
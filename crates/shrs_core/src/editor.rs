@@ -0,0 +1,150 @@
+//! Edit text in the user's editor
+//!
+//! Used to edit the command being typed as well as commands from history with the `fc` builtin.
+//! The editor is taken from `$VISUAL`, then `$EDITOR`, falling back to `vi`.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command, ExitStatus},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+use crate::shell::Runtime;
+
+#[derive(Error, Debug)]
+pub enum EditorError {
+    #[error("failed to write temporary file {0}: {1}")]
+    TempFile(PathBuf, std::io::Error),
+    #[error("failed to run editor {0}: {1}")]
+    Spawn(String, std::io::Error),
+    #[error("editor {0} exited with {1}")]
+    Failed(String, ExitStatus),
+}
+
+/// Editor command set by the user
+pub fn editor(rt: &Runtime) -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| env_var(rt, var))
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Non-empty value of a variable set in the shell or inherited by it
+pub(crate) fn env_var(rt: &Runtime, var: &str) -> Option<String> {
+    rt.env
+        .get(var)
+        .ok()
+        .cloned()
+        .or_else(|| std::env::var(var).ok())
+        .filter(|val| !val.trim().is_empty())
+}
+
+/// Open text in an editor and return the edited text once the editor exits
+///
+/// The editor command may contain arguments, such as `code --wait`, and is run in the foreground
+/// with the file to edit as its last argument. The trailing newline that most editors add is
+/// removed. Raw mode should be disabled before calling this.
+pub fn edit_text(rt: &Runtime, editor: &str, text: &str) -> Result<String, EditorError> {
+    let path = create_temp_file(text)?;
+
+    let edited = run_editor(rt, editor, &path).and_then(|_| {
+        fs::read_to_string(&path).map_err(|e| EditorError::TempFile(path.clone(), e))
+    });
+    let _ = fs::remove_file(&path);
+
+    let edited = edited?;
+    Ok(edited.strip_suffix('\n').unwrap_or(&edited).to_string())
+}
+
+/// Create a new file only the user can access in the temporary directory, holding the text
+///
+/// The file is never opened if it already exists, so it can't be swapped for a symlink to
+/// another file by someone else.
+fn create_temp_file(text: &str) -> Result<PathBuf, EditorError> {
+    let mut attempt = 0;
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        // editors can use the extension to pick syntax highlighting
+        let path = std::env::temp_dir().join(format!("shrs-edit-{}-{nanos}.sh", process::id()));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path);
+        match file {
+            Ok(mut file) => {
+                if let Err(e) = writeln!(file, "{text}") {
+                    let _ = fs::remove_file(&path);
+                    return Err(EditorError::TempFile(path, e));
+                }
+                return Ok(path);
+            },
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < 10 => attempt += 1,
+            Err(e) => return Err(EditorError::TempFile(path, e)),
+        }
+    }
+}
+
+fn run_editor(rt: &Runtime, editor: &str, path: &Path) -> Result<(), EditorError> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .envs(rt.env.exported())
+        .current_dir(&rt.working_dir)
+        .status()
+        .map_err(|e| EditorError::Spawn(editor.to_string(), e))?;
+
+    if !status.success() {
+        return Err(EditorError::Failed(editor.to_string(), status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::{create_temp_file, edit_text, EditorError};
+    use crate::{env::Env, options::ShellOptions, shell::Runtime};
+
+    #[test]
+    fn edit() {
+        let rt = Runtime {
+            working_dir: std::env::temp_dir(),
+            env: Env::new(),
+            name: "shrs".into(),
+            args: vec![],
+            exit_status: 0,
+            pipe_status: vec![],
+            options: ShellOptions::default(),
+        };
+
+        // the file to edit is passed after the arguments of the editor
+        let edited = edit_text(&rt, "sed -i s/old/new/", "echo old\nls").unwrap();
+        assert_eq!(edited, "echo new\nls");
+
+        assert!(matches!(
+            edit_text(&rt, "false", "ls"),
+            Err(EditorError::Failed(..))
+        ));
+    }
+
+    #[test]
+    fn temp_file() {
+        let path = create_temp_file("ls").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ls\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,6 +1,10 @@
 //! Environment variables
 
-use std::{collections::HashMap, env, ffi::OsString};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+};
 
 use shrs_utils::warn_if_err;
 use thiserror::Error;
@@ -35,6 +39,9 @@ pub enum EnvError {
 #[derive(Debug, Clone)]
 pub struct Env {
     var_table: HashMap<String, String>,
+    /// Shell variables that are not exported to child processes
+    #[cfg_attr(feature = "serde", serde(default))]
+    unexported: HashSet<String>,
 }
 
 impl Env {
@@ -42,6 +49,7 @@ impl Env {
     pub fn new() -> Self {
         Env {
             var_table: HashMap::new(),
+            unexported: HashSet::new(),
         }
     }
 
@@ -84,15 +92,36 @@ impl Env {
 
         env::set_var(var, val);
         self.var_table.insert(var.into(), val.into());
+        self.unexported.remove(var);
         Ok(())
     }
 
-    /// Obtain an iterator of all the environment variables
+    /// Set a shell variable, which unlike an environment variable is not exported to child
+    /// processes
+    pub fn set_unexported(&mut self, var: &str, val: &str) -> Result<(), EnvError> {
+        if key_sanitation(var) {
+            return Err(EnvError::InvalidKey(var.into()));
+        }
+
+        env::remove_var(var);
+        self.var_table.insert(var.into(), val.into());
+        self.unexported.insert(var.into());
+        Ok(())
+    }
+
+    /// Obtain an iterator of all the environment variables, including unexported shell variables
     pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
         // env::vars_os()
         self.var_table.iter()
     }
 
+    /// Obtain an iterator of the environment variables that are exported to child processes
+    pub fn exported(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.var_table
+            .iter()
+            .filter(|(var, _)| !self.unexported.contains(*var))
+    }
+
     /// Unset an environment variable
     ///
     /// If the environment variable was already not set, it is a NOOP
@@ -102,6 +131,7 @@ impl Env {
         }
         env::remove_var(var);
         self.var_table.remove(var);
+        self.unexported.remove(var);
         Ok(())
     }
 
@@ -128,6 +158,27 @@ impl<S: ToString> FromIterator<(S, S)> for Env {
                 iter.into_iter()
                     .map(|(k, v)| (k.to_string(), v.to_string())),
             ),
+            unexported: HashSet::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Env;
+
+    #[test]
+    fn unexported() {
+        let mut env = Env::new();
+        env.set_unexported("SHRS_TEST_SHELL_VAR", "1").unwrap();
+        assert_eq!(env.get("SHRS_TEST_SHELL_VAR").unwrap(), "1");
+        assert!(std::env::var("SHRS_TEST_SHELL_VAR").is_err());
+        assert_eq!(env.exported().count(), 0);
+
+        // exporting the variable makes it visible to child processes again
+        env.set("SHRS_TEST_SHELL_VAR", "2").unwrap();
+        assert_eq!(std::env::var("SHRS_TEST_SHELL_VAR").unwrap(), "2");
+        assert_eq!(env.exported().count(), 1);
+        env.remove("SHRS_TEST_SHELL_VAR").unwrap();
+    }
+}
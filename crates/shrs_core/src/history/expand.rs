@@ -0,0 +1,211 @@
+//! Bash style history expansion
+//!
+//! Supported forms are
+//! - `!!` the previous command
+//! - `!$` the last word of the previous command
+//! - `!N` command number N, and `!-N` the command N entries back
+//! - `!prefix` the most recent command starting with prefix
+//! - `!?text?` the most recent command containing text
+//! - `^old^new^` the previous command with the first occurrence of old replaced by new, only at the
+//!   start of the line
+//!
+//! Commands are numbered from the oldest one, the same as in the output of the `history` builtin.
+//! Nothing is expanded inside single quotes or after a backslash, and a `!` followed by whitespace,
+//! `=` or `(` is left as is.
+
+use thiserror::Error;
+
+use super::History;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HistoryExpansionError {
+    #[error("{0}: event not found")]
+    EventNotFound(String),
+    #[error("{0}: substitution failed")]
+    SubstitutionFailed(String),
+}
+
+/// Expand history references in a line, returns `None` if the line doesn't contain any
+pub fn expand_history(
+    line: &str,
+    history: &dyn History<HistoryItem = String>,
+) -> Result<Option<String>, HistoryExpansionError> {
+    if let Some(rest) = line.strip_prefix('^') {
+        return quick_substitution(rest, history).map(Some);
+    }
+
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut in_single_quote = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            '\'' => in_single_quote = !in_single_quote,
+            '\\' if !in_single_quote => {
+                expanded.push(ch);
+                if let Some(next) = chars.get(i + 1) {
+                    expanded.push(*next);
+                }
+                i += 2;
+                continue;
+            },
+            '!' if !in_single_quote => {
+                if let Some((event, len)) = parse_event(&chars[i + 1..]) {
+                    expanded.push_str(&resolve_event(&event, history)?);
+                    changed = true;
+                    i += len + 1;
+                    continue;
+                }
+            },
+            _ => {},
+        }
+        expanded.push(ch);
+        i += 1;
+    }
+
+    Ok(changed.then_some(expanded))
+}
+
+/// Reference to a history entry
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    /// Entry with this number
+    Number(usize),
+    /// Entry this many entries back
+    Relative(usize),
+    Prefix(String),
+    Contains(String),
+    /// Last word of the previous entry
+    LastWord,
+}
+
+/// Parse the event after a `!`, returning it and the number of characters it takes up
+fn parse_event(chars: &[char]) -> Option<(Event, usize)> {
+    let word_len = chars
+        .iter()
+        .position(|ch| ch.is_whitespace() || ";&|<>()'\"`".contains(*ch))
+        .unwrap_or(chars.len());
+    let digits = |chars: &[char]| chars.iter().take_while(|ch| ch.is_ascii_digit()).count();
+
+    match chars.first()? {
+        '!' => Some((Event::Relative(1), 1)),
+        '$' => Some((Event::LastWord, 1)),
+        ch if ch.is_whitespace() || "=(".contains(*ch) => None,
+        '-' => {
+            let len = digits(&chars[1..]);
+            let n = chars[1..len + 1].iter().collect::<String>().parse().ok()?;
+            Some((Event::Relative(n), len + 1))
+        },
+        '?' => {
+            let end = chars[1..].iter().position(|ch| *ch == '?');
+            let text = chars[1..end.map_or(chars.len(), |end| end + 1)]
+                .iter()
+                .collect::<String>();
+            let len = end.map_or(chars.len(), |end| end + 2);
+            (!text.is_empty()).then_some((Event::Contains(text), len))
+        },
+        ch if ch.is_ascii_digit() => {
+            let len = digits(chars);
+            let n = chars[..len].iter().collect::<String>().parse().ok()?;
+            Some((Event::Number(n), len))
+        },
+        _ if word_len > 0 => {
+            let prefix = chars[..word_len].iter().collect::<String>();
+            Some((Event::Prefix(prefix), word_len))
+        },
+        _ => None,
+    }
+}
+
+fn resolve_event(
+    event: &Event,
+    history: &dyn History<HistoryItem = String>,
+) -> Result<String, HistoryExpansionError> {
+    let len = history.len();
+    let found = match event {
+        Event::Number(n) => (1..=len)
+            .contains(n)
+            .then(|| history.get(len - n))
+            .flatten()
+            .cloned(),
+        Event::Relative(n) => n.checked_sub(1).and_then(|i| history.get(i)).cloned(),
+        Event::Prefix(prefix) => (0..len)
+            .filter_map(|i| history.get(i))
+            .find(|cmd| cmd.starts_with(prefix.as_str()))
+            .cloned(),
+        Event::Contains(text) => history.search(text).cloned(),
+        Event::LastWord => history
+            .get(0)
+            .and_then(|cmd| cmd.split_whitespace().last())
+            .map(String::from),
+    };
+    found.ok_or_else(|| {
+        let name = match event {
+            Event::Number(n) => format!("!{n}"),
+            Event::Relative(1) => String::from("!!"),
+            Event::Relative(n) => format!("!-{n}"),
+            Event::Prefix(prefix) => format!("!{prefix}"),
+            Event::Contains(text) => format!("!?{text}"),
+            Event::LastWord => String::from("!$"),
+        };
+        HistoryExpansionError::EventNotFound(name)
+    })
+}
+
+/// Expand `^old^new^`, with the leading `^` already removed
+fn quick_substitution(
+    rest: &str,
+    history: &dyn History<HistoryItem = String>,
+) -> Result<String, HistoryExpansionError> {
+    let mut parts = rest.splitn(3, '^');
+    let old = parts.next().unwrap_or_default();
+    let new = parts.next().unwrap_or_default();
+    // anything after the closing caret is appended to the command
+    let suffix = parts.next().unwrap_or_default();
+
+    let prev = resolve_event(&Event::Relative(1), history)?;
+    if old.is_empty() || !prev.contains(old) {
+        return Err(HistoryExpansionError::SubstitutionFailed(format!(
+            "^{old}^{new}"
+        )));
+    }
+    Ok(format!("{}{suffix}", prev.replacen(old, new, 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_history, HistoryExpansionError};
+    use crate::history::{DefaultHistory, History};
+
+    #[test]
+    fn expansion() {
+        let mut hist = DefaultHistory::new();
+        for cmd in ["cargo build", "ls -la", "echo hello", "git status"] {
+            hist.add(cmd.into());
+        }
+        let expand = |line| expand_history(line, &hist);
+
+        assert_eq!(expand("ls"), Ok(None));
+        assert_eq!(expand("sudo !!"), Ok(Some("sudo git status".into())));
+        assert_eq!(
+            expand("!1 --release"),
+            Ok(Some("cargo build --release".into()))
+        );
+        assert_eq!(expand("!-2"), Ok(Some("echo hello".into())));
+        assert_eq!(expand("!ls"), Ok(Some("ls -la".into())));
+        assert_eq!(expand("!?hell?"), Ok(Some("echo hello".into())));
+        assert_eq!(expand("cd !$"), Ok(Some("cd status".into())));
+        assert_eq!(expand("^status^diff"), Ok(Some("git diff".into())));
+
+        // literal exclamation marks
+        assert_eq!(expand("echo 'hi!!' \\!! ! x!=y"), Ok(None));
+        assert_eq!(
+            expand("!vim"),
+            Err(HistoryExpansionError::EventNotFound("!vim".into()))
+        );
+        assert!(expand("^foo^bar").is_err());
+    }
+}
@@ -0,0 +1,666 @@
+//! History stored in a plain file on disk
+//!
+//! The history file is append-only so that multiple sessions can write to it at once without
+//! clobbering each other's entries. Each entry is a single line of the form
+//! ```text
+//! : <timestamp>\t<duration>\t<exit status>\t<cwd>\t<hostname>\t<session id>\t<command>
+//! ```
+//! where times are in milliseconds, empty fields are unknown, and backslashes, tabs and newlines
+//! are escaped. Lines without the leading `: ` are read as a bare command, which is what older
+//! versions of shrs wrote.
+//!
+//! Every access to the file holds an advisory lock on it, and the file is only rewritten as a
+//! whole when it grows past twice the configured max length.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    os::fd::AsRawFd,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use nix::fcntl::{flock, FlockArg};
+use shrs_utils::glob::glob_match;
+use thiserror::Error;
+
+use super::{History, HistoryMetadata};
+
+const RECORD_PREFIX: &str = ": ";
+
+/// How duplicate entries are handled when they are added to history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryDedup {
+    /// Keep every entry
+    None,
+    /// Don't add an entry that is the same as the most recent one
+    Consecutive,
+    /// Remove older copies of an entry when it is added again
+    #[default]
+    All,
+}
+
+#[derive(Debug, Error)]
+pub enum FileBackedHistoryError {
+    #[error("error when opening history file {0}")]
+    OpeningHistFile(std::io::Error),
+    #[error("error locking history file {0}")]
+    Lock(nix::Error),
+    #[error("error reading history file {0}")]
+    Read(std::io::Error),
+    #[error("error writing history to disk {0}")]
+    Flush(std::io::Error),
+}
+
+/// Store the history persistently in a file on disk
+///
+/// ```ignore
+/// let history = FileBackedHistory::new(hist_file)?
+///     .with_max_length(5000)
+///     .with_dedup(HistoryDedup::Consecutive)
+///     .with_ignore("ls:cd *")
+///     .with_share(true);
+/// ```
+// TODO potential options
+// - only use valid commands
+// - resolve alias
+pub struct FileBackedHistory {
+    /// Entries, most recent first
+    hist: Vec<String>,
+    metadata: Vec<HistoryMetadata>,
+    hist_file: PathBuf,
+    /// Number of bytes of the history file that have been read
+    offset: u64,
+    /// Number of entries in the history file, including ones no longer kept in memory
+    file_len: usize,
+
+    // config options
+    /// Max length of history to keep
+    max_length: usize,
+    /// How to handle duplicate history values
+    dedup: HistoryDedup,
+    /// Don't save commands that start with a space
+    ignore_space: bool,
+    /// Patterns of commands that are not saved
+    ignore: Vec<String>,
+    /// Pick up entries written by other sessions
+    share: bool,
+}
+
+impl FileBackedHistory {
+    pub fn new(hist_file: PathBuf) -> Result<Self, FileBackedHistoryError> {
+        let mut history = FileBackedHistory {
+            hist: vec![],
+            metadata: vec![],
+            hist_file,
+            offset: 0,
+            file_len: 0,
+            max_length: 10000,
+            dedup: HistoryDedup::default(),
+            ignore_space: true,
+            ignore: vec![],
+            share: false,
+        };
+        history.load()?;
+        Ok(history)
+    }
+
+    /// Set max number of entries to keep, older entries are dropped
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self.truncate();
+        self
+    }
+
+    /// Set how duplicate entries are handled
+    pub fn with_dedup(mut self, dedup: HistoryDedup) -> Self {
+        self.dedup = dedup;
+        self.dedup_all();
+        self
+    }
+
+    /// Set whether commands starting with a space are left out of history
+    pub fn with_ignore_space(mut self, ignore_space: bool) -> Self {
+        self.ignore_space = ignore_space;
+        self
+    }
+
+    /// Leave out commands matching any of a colon separated list of patterns, in the same format
+    /// as `HISTIGNORE`
+    ///
+    /// Patterns must match the entire command, for example `ls:cd *` ignores `ls` and any `cd`
+    /// with arguments but still saves `ls -la`.
+    pub fn with_ignore(mut self, patterns: &str) -> Self {
+        self.ignore = patterns
+            .split(':')
+            .filter(|pattern| !pattern.is_empty())
+            .map(String::from)
+            .collect();
+        self
+    }
+
+    /// Set whether entries written by other sessions show up in history
+    ///
+    /// New entries are picked up when browsing history starts and when a command is added. When
+    /// disabled, only entries from before this session started and entries from this session are
+    /// available, although entries from all sessions are still written to the same file.
+    pub fn with_share(mut self, share: bool) -> Self {
+        self.share = share;
+        self
+    }
+
+    fn is_ignored(&self, cmd: &str) -> bool {
+        cmd.trim().is_empty()
+            || (self.ignore_space && cmd.starts_with(' '))
+            || self.ignore.iter().any(|pattern| glob_match(pattern, cmd))
+    }
+
+    /// Read the entire history file
+    fn load(&mut self) -> Result<(), FileBackedHistoryError> {
+        let mut file = LockedFile::open(&self.hist_file, FlockArg::LockExclusive)?;
+        let contents = file.read_from(0)?;
+
+        // convert history written by older versions, which keep the most recent entry first
+        let legacy =
+            !contents.is_empty() && !contents.lines().any(|line| line.starts_with(RECORD_PREFIX));
+        let mut entries = contents.lines().map(parse_record).collect::<Vec<_>>();
+        if legacy {
+            entries.reverse();
+            file.rewrite(&entries)?;
+        }
+
+        self.hist.clear();
+        self.metadata.clear();
+        self.file_len = 0;
+        self.offset = file.len()?;
+        self.extend(entries);
+        Ok(())
+    }
+
+    /// Read entries that other sessions appended since the file was last read
+    fn read_new(&mut self, file: &mut LockedFile) -> Result<(), FileBackedHistoryError> {
+        // the file was compacted by another session so its contents have moved
+        if file.len()? < self.offset {
+            self.offset = 0;
+            self.hist.clear();
+            self.metadata.clear();
+            self.file_len = 0;
+        }
+
+        let contents = file.read_from(self.offset)?;
+        // a well behaved writer never leaves a partial line, but don't consume one if it does
+        let complete = contents.rfind('\n').map_or(0, |i| i + 1);
+        self.offset += complete as u64;
+        let entries = contents[..complete].lines().map(parse_record).collect();
+        self.extend(entries);
+        Ok(())
+    }
+
+    /// Add entries from the history file, oldest first
+    fn extend(&mut self, entries: Vec<(String, HistoryMetadata)>) {
+        self.file_len += entries.len();
+        if self.dedup != HistoryDedup::All {
+            for (cmd, metadata) in entries {
+                self.push(cmd, metadata);
+            }
+            self.truncate();
+            return;
+        }
+
+        // keep only the most recent copy of each command without searching history for each one
+        let mut seen = HashSet::new();
+        let newest = entries
+            .into_iter()
+            .rev()
+            .filter(|(cmd, _)| seen.insert(cmd.clone()))
+            .collect::<Vec<_>>();
+        let older = self
+            .hist
+            .drain(..)
+            .zip(self.metadata.drain(..))
+            .filter(|(cmd, _)| !seen.contains(cmd))
+            .collect::<Vec<_>>();
+        for (cmd, metadata) in newest.into_iter().chain(older) {
+            self.hist.push(cmd);
+            self.metadata.push(metadata);
+        }
+        self.truncate();
+    }
+
+    /// Whether an entry is dropped because it repeats the most recent one
+    fn is_consecutive_dup(&self, cmd: &str) -> bool {
+        self.dedup == HistoryDedup::Consecutive
+            && self.hist.first().map(|s| s.as_str()) == Some(cmd)
+    }
+
+    /// Add an entry to memory as the most recent one
+    fn push(&mut self, cmd: String, metadata: HistoryMetadata) {
+        if self.is_consecutive_dup(&cmd) {
+            return;
+        }
+        if self.dedup == HistoryDedup::All {
+            if let Some(i) = self.hist.iter().position(|item| *item == cmd) {
+                self.hist.remove(i);
+                self.metadata.remove(i);
+            }
+        }
+        self.hist.insert(0, cmd);
+        self.metadata.insert(0, metadata);
+    }
+
+    /// Append an entry to the history file, compacting it if it has grown too large
+    fn write(
+        &mut self,
+        cmd: &str,
+        metadata: &HistoryMetadata,
+    ) -> Result<(), FileBackedHistoryError> {
+        let mut file = LockedFile::open(&self.hist_file, FlockArg::LockExclusive)?;
+        if self.share {
+            self.read_new(&mut file)?;
+        }
+
+        file.append(&format_record(cmd, metadata))?;
+        self.file_len += 1;
+        if self.share {
+            self.offset = file.len()?;
+        }
+
+        if self.file_len > self.max_length.saturating_mul(2) {
+            self.compact(&mut file)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the history file with only the entries that are kept, including the ones written
+    /// by other sessions
+    fn compact(&mut self, file: &mut LockedFile) -> Result<(), FileBackedHistoryError> {
+        let contents = file.read_from(0)?;
+        let mut kept = FileBackedHistory {
+            hist: vec![],
+            metadata: vec![],
+            hist_file: self.hist_file.clone(),
+            offset: 0,
+            file_len: 0,
+            ignore: vec![],
+            ..*self
+        };
+        kept.extend(contents.lines().map(parse_record).collect());
+
+        let entries = kept
+            .hist
+            .into_iter()
+            .zip(kept.metadata)
+            .rev()
+            .collect::<Vec<_>>();
+        file.rewrite(&entries)?;
+        self.file_len = entries.len();
+        self.offset = file.len()?;
+        Ok(())
+    }
+
+    fn truncate(&mut self) {
+        self.hist.truncate(self.max_length);
+        self.metadata.truncate(self.max_length);
+    }
+
+    fn dedup_all(&mut self) {
+        let entries = self
+            .hist
+            .drain(..)
+            .zip(self.metadata.drain(..))
+            .rev()
+            .collect::<Vec<_>>();
+        let file_len = self.file_len;
+        self.extend(entries);
+        self.file_len = file_len;
+    }
+}
+
+impl History for FileBackedHistory {
+    type HistoryItem = String;
+
+    fn add(&mut self, item: Self::HistoryItem) {
+        let metadata = HistoryMetadata {
+            timestamp: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        self.add_with_metadata(item, metadata);
+    }
+
+    fn add_with_metadata(&mut self, item: Self::HistoryItem, metadata: HistoryMetadata) {
+        if self.is_ignored(&item) || self.is_consecutive_dup(&item) {
+            return;
+        }
+        if let Err(e) = self.write(&item, &metadata) {
+            warn!("failed to write history entry: {e}");
+        }
+        self.push(item, metadata);
+        self.truncate();
+    }
+
+    fn remove(&mut self, i: usize) -> Option<Self::HistoryItem> {
+        let cmd = self.hist.get(i)?.clone();
+
+        // every copy of the command is removed, otherwise older ones would show up again once the
+        // history is loaded from the file
+        while let Some(i) = self.hist.iter().position(|item| *item == cmd) {
+            self.hist.remove(i);
+            self.metadata.remove(i);
+        }
+        let res =
+            LockedFile::open(&self.hist_file, FlockArg::LockExclusive).and_then(|mut file| {
+                let contents = file.read_from(0)?;
+                let entries = contents
+                    .lines()
+                    .map(parse_record)
+                    .filter(|(item, _)| *item != cmd)
+                    .collect::<Vec<_>>();
+                file.rewrite(&entries)?;
+                self.file_len = entries.len();
+                self.offset = file.len()?;
+                Ok(())
+            });
+        if let Err(e) = res {
+            warn!("failed to remove entry from history file: {e}");
+        }
+        Some(cmd)
+    }
+
+    fn clear(&mut self) {
+        self.hist.clear();
+        self.metadata.clear();
+        self.file_len = 0;
+        self.offset = 0;
+        let res = LockedFile::open(&self.hist_file, FlockArg::LockExclusive)
+            .and_then(|mut file| file.rewrite(&[]));
+        if let Err(e) = res {
+            warn!("failed to clear history file: {e}");
+        }
+    }
+
+    fn sync(&mut self) {
+        if !self.share {
+            return;
+        }
+        let res = LockedFile::open(&self.hist_file, FlockArg::LockShared)
+            .and_then(|mut file| self.read_new(&mut file));
+        if let Err(e) = res {
+            warn!("failed to read history file: {e}");
+        }
+    }
+
+    fn search(&self, query: &str) -> Option<&Self::HistoryItem> {
+        self.hist.iter().find(|item| item.contains(query))
+    }
+
+    fn len(&self) -> usize {
+        self.hist.len()
+    }
+
+    fn get(&self, i: usize) -> Option<&Self::HistoryItem> {
+        self.hist.get(i)
+    }
+
+    fn metadata(&self, i: usize) -> Option<HistoryMetadata> {
+        self.metadata.get(i).cloned()
+    }
+}
+
+/// History file that is locked for as long as it is open
+struct LockedFile {
+    file: File,
+}
+
+impl LockedFile {
+    fn open(path: &PathBuf, lock: FlockArg) -> Result<Self, FileBackedHistoryError> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(FileBackedHistoryError::OpeningHistFile)?;
+        flock(file.as_raw_fd(), lock).map_err(FileBackedHistoryError::Lock)?;
+        Ok(LockedFile { file })
+    }
+
+    fn len(&self) -> Result<u64, FileBackedHistoryError> {
+        Ok(self
+            .file
+            .metadata()
+            .map_err(FileBackedHistoryError::Read)?
+            .len())
+    }
+
+    fn read_from(&mut self, offset: u64) -> Result<String, FileBackedHistoryError> {
+        let mut contents = String::new();
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_to_string(&mut contents))
+            .map_err(FileBackedHistoryError::Read)?;
+        Ok(contents)
+    }
+
+    fn append(&mut self, record: &str) -> Result<(), FileBackedHistoryError> {
+        self.file
+            .seek(SeekFrom::End(0))
+            .and_then(|_| self.file.write_all(record.as_bytes()))
+            .map_err(FileBackedHistoryError::Flush)
+    }
+
+    /// Replace the contents of the file with entries, oldest first
+    fn rewrite(
+        &mut self,
+        entries: &[(String, HistoryMetadata)],
+    ) -> Result<(), FileBackedHistoryError> {
+        let contents = entries
+            .iter()
+            .map(|(cmd, metadata)| format_record(cmd, metadata))
+            .collect::<String>();
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| self.file.write_all(contents.as_bytes()))
+            .map_err(FileBackedHistoryError::Flush)
+    }
+}
+
+impl Drop for LockedFile {
+    fn drop(&mut self) {
+        let _ = flock(self.file.as_raw_fd(), FlockArg::Unlock);
+    }
+}
+
+fn format_record(cmd: &str, metadata: &HistoryMetadata) -> String {
+    let fields = [
+        metadata.timestamp.map(|t| to_millis(t).to_string()),
+        metadata.duration.map(|d| d.as_millis().to_string()),
+        metadata.exit_status.map(|status| status.to_string()),
+        metadata
+            .cwd
+            .as_ref()
+            .map(|cwd| cwd.to_string_lossy().to_string()),
+        metadata.hostname.clone(),
+        metadata.session_id.clone(),
+        Some(cmd.to_string()),
+    ];
+    let fields = fields
+        .iter()
+        .map(|field| escape(field.as_deref().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    format!("{RECORD_PREFIX}{}\n", fields.join("\t"))
+}
+
+fn parse_record(line: &str) -> (String, HistoryMetadata) {
+    let Some(record) = line.strip_prefix(RECORD_PREFIX) else {
+        return (line.to_string(), HistoryMetadata::default());
+    };
+
+    let mut fields = record.splitn(7, '\t').map(unescape);
+    let mut next = || fields.next().filter(|field| !field.is_empty());
+    let metadata = HistoryMetadata {
+        timestamp: next()
+            .and_then(|t| t.parse().ok())
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis)),
+        duration: next()
+            .and_then(|d| d.parse().ok())
+            .map(Duration::from_millis),
+        exit_status: next().and_then(|status| status.parse().ok()),
+        cwd: next().map(PathBuf::from),
+        hostname: next(),
+        session_id: next(),
+    };
+    (next().unwrap_or_default(), metadata)
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(ch) => unescaped.push(ch),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::SystemTime};
+
+    use super::{FileBackedHistory, HistoryDedup};
+    use crate::history::{History, HistoryMetadata};
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("shrs_history_{}_{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn commands(hist: &FileBackedHistory) -> Vec<&str> {
+        (0..hist.len())
+            .filter_map(|i| hist.get(i))
+            .map(|s| s.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn shared_sessions() {
+        let path = temp_file("shared");
+        let mut a = FileBackedHistory::new(path.clone())
+            .unwrap()
+            .with_share(true);
+        let mut b = FileBackedHistory::new(path.clone()).unwrap();
+
+        a.add("echo a".into());
+        b.add_with_metadata(
+            "echo\tb\nc".into(),
+            HistoryMetadata {
+                timestamp: Some(SystemTime::now()),
+                exit_status: Some(1),
+                cwd: Some(PathBuf::from("/tmp")),
+                ..Default::default()
+            },
+        );
+
+        // only the sharing session sees entries from the other one
+        assert_eq!(commands(&b), vec!["echo\tb\nc"]);
+        a.sync();
+        assert_eq!(commands(&a), vec!["echo\tb\nc", "echo a"]);
+        assert_eq!(a.metadata(0).unwrap().exit_status, Some(1));
+
+        // both entries were appended to the file
+        let c = FileBackedHistory::new(path.clone()).unwrap();
+        assert_eq!(commands(&c), vec!["echo\tb\nc", "echo a"]);
+        assert_eq!(
+            c.metadata(0).unwrap().cwd.as_deref(),
+            Some(PathBuf::from("/tmp").as_path())
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn options() {
+        let path = temp_file("options");
+        let mut hist = FileBackedHistory::new(path.clone())
+            .unwrap()
+            .with_max_length(3)
+            .with_dedup(HistoryDedup::Consecutive)
+            .with_ignore("ls:cd *");
+
+        for cmd in [
+            "make", "make", "ls", "ls -la", "cd src", " secret", "make", "vim",
+        ] {
+            hist.add(cmd.into());
+        }
+        assert_eq!(commands(&hist), vec!["vim", "make", "ls -la"]);
+
+        // the file is compacted once it grows past twice the max length
+        for cmd in ["git status", "git push"] {
+            hist.add(cmd.into());
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 6);
+        hist.add("git log".into());
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn legacy_format() {
+        let path = temp_file("legacy");
+        fs::write(&path, "newest\nolder\noldest").unwrap();
+
+        let hist = FileBackedHistory::new(path.clone()).unwrap();
+        assert_eq!(commands(&hist), vec!["newest", "older", "oldest"]);
+        assert!(fs::read_to_string(&path).unwrap().starts_with(": "));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn remove_duplicates() {
+        let path = temp_file("remove");
+        let mut hist = FileBackedHistory::new(path.clone()).unwrap();
+        for cmd in ["make", "ls", "make"] {
+            hist.add(cmd.into());
+        }
+
+        assert_eq!(hist.remove(0).as_deref(), Some("make"));
+        assert_eq!(commands(&hist), vec!["ls"]);
+        let hist = FileBackedHistory::new(path.clone()).unwrap();
+        assert_eq!(commands(&hist), vec!["ls"]);
+
+        let _ = fs::remove_file(path);
+    }
+}
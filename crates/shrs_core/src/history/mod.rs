@@ -0,0 +1,329 @@
+//! Shell history
+//!
+//! History is owned by the shell [Context] so that both the line editor and builtins have access
+//! to it. The shell records each command it runs along with [HistoryMetadata] describing how the
+//! command was run, gathered around the [BeforeCommandCtx] and [AfterCommandCtx] hooks.
+//!
+//! [Context]: crate::shell::Context
+//! [BeforeCommandCtx]: crate::hooks::BeforeCommandCtx
+//! [AfterCommandCtx]: crate::hooks::AfterCommandCtx
+
+mod expand;
+mod file;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub use expand::{expand_history, HistoryExpansionError};
+pub use file::{FileBackedHistory, FileBackedHistoryError, HistoryDedup};
+use lazy_static::lazy_static;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{SqliteHistory, SqliteHistoryError};
+
+/// Trait to implement for shell history
+pub trait History {
+    type HistoryItem;
+
+    /// Insert item into shell history
+    fn add(&mut self, cmd: Self::HistoryItem);
+    /// Insert item into shell history along with information about how it was run
+    fn add_with_metadata(&mut self, cmd: Self::HistoryItem, _metadata: HistoryMetadata) {
+        self.add(cmd);
+    }
+    /// Remove a history entry by index, returning it if it exists
+    ///
+    /// Histories that don't support removing entries return [None].
+    fn remove(&mut self, _i: usize) -> Option<Self::HistoryItem> {
+        None
+    }
+    /// Remove all history entries
+    fn clear(&mut self);
+    /// Pick up entries that were added by other shell sessions, if the history is shared
+    fn sync(&mut self) {}
+    /// Query for the most recent history entry containing the query
+    fn search(&self, query: &str) -> Option<&Self::HistoryItem>;
+    /// Get number of history entries
+    fn len(&self) -> usize;
+    /// Check if there are no history entries
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Get a history entry by index
+    fn get(&self, i: usize) -> Option<&Self::HistoryItem>;
+    /// Get extra information recorded about a history entry, if the history keeps track of it
+    fn metadata(&self, _i: usize) -> Option<HistoryMetadata> {
+        None
+    }
+
+    /// Find the index of the closest entry containing the query, beginning at index `start`
+    /// (inclusive) and moving in the given direction
+    fn search_index(&self, query: &str, start: usize, direction: SearchDirection) -> Option<usize>
+    where
+        Self::HistoryItem: AsRef<str>,
+    {
+        let matches =
+            |i: &usize| matches!(self.get(*i), Some(item) if item.as_ref().contains(query));
+        match direction {
+            SearchDirection::Backward => (start..self.len()).find(matches),
+            SearchDirection::Forward => (0..=start.min(self.len())).rev().find(matches),
+        }
+    }
+
+    /// Find all entries selected by a filter along with their index, most recent first
+    fn filter(&self, filter: &HistoryFilter) -> Vec<(usize, HistoryEntry)>
+    where
+        Self::HistoryItem: AsRef<str>,
+    {
+        (0..self.len())
+            .filter_map(|i| {
+                let entry = HistoryEntry {
+                    command: self.get(i)?.as_ref().to_string(),
+                    metadata: self.metadata(i).unwrap_or_default(),
+                };
+                filter.matches(&entry).then_some((i, entry))
+            })
+            .collect()
+    }
+}
+
+/// Information recorded about when and how a history entry was run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryMetadata {
+    /// Time the command was started
+    pub timestamp: Option<SystemTime>,
+    /// How long the command took to run
+    pub duration: Option<Duration>,
+    /// Exit status of the command
+    pub exit_status: Option<i32>,
+    /// Working directory the command was run in
+    pub cwd: Option<PathBuf>,
+    /// Host the shell was running on
+    pub hostname: Option<String>,
+    /// Shell session the command was run in, see [session_id]
+    pub session_id: Option<String>,
+}
+
+/// Command to save to history instead of the line that was typed
+///
+/// Builtins that run a command from history, such as `fc`, put this in the shell's [State] so
+/// that the command that ran is saved in place of the line that ran it, like in bash.
+///
+/// [State]: crate::state::State
+pub struct HistoryReplacement(pub String);
+
+/// Command in history along with information about how it was run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub metadata: HistoryMetadata,
+}
+
+/// Criteria to select history entries by, see [History::filter]
+///
+/// Entries that are missing the metadata a criteria depends on are not selected.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Only select commands run in this directory
+    pub cwd: Option<PathBuf>,
+    /// Only select commands that exited with a non-zero status
+    pub failed: bool,
+    /// Only select commands started at or after this time
+    pub since: Option<SystemTime>,
+    /// Only select commands started before this time
+    pub until: Option<SystemTime>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        let metadata = &entry.metadata;
+        if self.cwd.is_some() && metadata.cwd != self.cwd {
+            return false;
+        }
+        if self.failed && !matches!(metadata.exit_status, Some(status) if status != 0) {
+            return false;
+        }
+        match (self.since, self.until, metadata.timestamp) {
+            (None, None, _) => true,
+            (_, _, None) => false,
+            (since, until, Some(timestamp)) => {
+                since.is_none_or(|since| timestamp >= since)
+                    && until.is_none_or(|until| timestamp < until)
+            },
+        }
+    }
+}
+
+lazy_static! {
+    static ref SESSION_ID: String = {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        format!("{:x}-{:x}", std::process::id(), started)
+    };
+}
+
+/// Identifier of the running shell, used to tell apart entries written by concurrent sessions
+pub fn session_id() -> &'static str {
+    &SESSION_ID
+}
+
+/// Name of the host the shell is running on
+pub fn hostname() -> Option<String> {
+    nix::unistd::gethostname()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+}
+
+/// Direction to search history in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SearchDirection {
+    /// Towards older entries (`Ctrl-R`)
+    Backward,
+    /// Towards more recent entries (`Ctrl-S`)
+    Forward,
+}
+
+/// Default implementation of [History] that saves history in process memory
+pub struct DefaultHistory {
+    hist: Vec<String>,
+    metadata: Vec<HistoryMetadata>,
+}
+
+impl DefaultHistory {
+    pub fn new() -> Self {
+        DefaultHistory {
+            hist: vec![],
+            metadata: vec![],
+        }
+    }
+}
+
+impl Default for DefaultHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History for DefaultHistory {
+    type HistoryItem = String;
+
+    fn add(&mut self, item: Self::HistoryItem) {
+        let metadata = HistoryMetadata {
+            timestamp: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        self.add_with_metadata(item, metadata);
+    }
+
+    fn add_with_metadata(&mut self, item: Self::HistoryItem, metadata: HistoryMetadata) {
+        self.hist.insert(0, item);
+        self.metadata.insert(0, metadata);
+    }
+
+    fn remove(&mut self, i: usize) -> Option<Self::HistoryItem> {
+        if i >= self.hist.len() {
+            return None;
+        }
+        self.metadata.remove(i);
+        Some(self.hist.remove(i))
+    }
+
+    fn clear(&mut self) {
+        self.hist.clear();
+        self.metadata.clear();
+    }
+
+    fn search(&self, query: &str) -> Option<&Self::HistoryItem> {
+        self.hist.iter().find(|item| item.contains(query))
+    }
+
+    fn len(&self) -> usize {
+        self.hist.len()
+    }
+
+    /// Get index starts at most recent (index zero is previous command)
+    fn get(&self, i: usize) -> Option<&Self::HistoryItem> {
+        self.hist.get(i)
+    }
+
+    fn metadata(&self, i: usize) -> Option<HistoryMetadata> {
+        self.metadata.get(i).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::SystemTime};
+
+    use super::{DefaultHistory, History, HistoryFilter, HistoryMetadata, SearchDirection};
+
+    #[test]
+    fn search() {
+        let mut hist = DefaultHistory::new();
+        for cmd in ["cargo build", "ls", "cargo test", "cd .."] {
+            hist.add(cmd.into());
+        }
+
+        assert_eq!(hist.search("cargo").map(|s| s.as_str()), Some("cargo test"));
+        assert_eq!(hist.search("rm"), None);
+
+        assert_eq!(
+            hist.search_index("cargo", 0, SearchDirection::Backward),
+            Some(1)
+        );
+        assert_eq!(
+            hist.search_index("cargo", 2, SearchDirection::Backward),
+            Some(3)
+        );
+        assert_eq!(
+            hist.search_index("cargo", 2, SearchDirection::Forward),
+            Some(1)
+        );
+        assert_eq!(
+            hist.search_index("cargo", 0, SearchDirection::Forward),
+            None
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let mut hist = DefaultHistory::new();
+        let metadata = |status, cwd: &str| HistoryMetadata {
+            timestamp: Some(SystemTime::now()),
+            exit_status: Some(status),
+            cwd: Some(PathBuf::from(cwd)),
+            ..Default::default()
+        };
+        hist.add_with_metadata("make".into(), metadata(2, "/src"));
+        hist.add_with_metadata("ls".into(), metadata(0, "/"));
+        hist.add_with_metadata("make test".into(), metadata(0, "/src"));
+        hist.add("cd".into());
+
+        let commands = |filter: HistoryFilter| {
+            hist.filter(&filter)
+                .into_iter()
+                .map(|(_, entry)| entry.command)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            commands(HistoryFilter {
+                cwd: Some(PathBuf::from("/src")),
+                ..Default::default()
+            }),
+            vec!["make test", "make"]
+        );
+        assert_eq!(
+            commands(HistoryFilter {
+                failed: true,
+                ..Default::default()
+            }),
+            vec!["make"]
+        );
+        assert_eq!(commands(HistoryFilter::default()).len(), 4);
+    }
+}
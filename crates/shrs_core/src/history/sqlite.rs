@@ -0,0 +1,323 @@
+//! History backed by a SQLite database
+//!
+//! Every entry is stored as a row along with its [HistoryMetadata], which allows history to be
+//! searched and filtered with indexed queries. The database is opened in WAL mode so that multiple
+//! shell sessions can write to the same history at once.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use rusqlite::{params, params_from_iter, types::Value, Connection, Row};
+use thiserror::Error;
+
+use super::{History, HistoryEntry, HistoryFilter, HistoryMetadata};
+
+/// How long to wait for another session to release a lock on the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    command TEXT NOT NULL,
+    timestamp INTEGER,
+    duration INTEGER,
+    exit_status INTEGER,
+    cwd TEXT,
+    hostname TEXT,
+    session_id TEXT
+);
+CREATE INDEX IF NOT EXISTS history_timestamp ON history(timestamp);
+CREATE INDEX IF NOT EXISTS history_cwd ON history(cwd);
+CREATE INDEX IF NOT EXISTS history_exit_status ON history(exit_status);
+";
+
+const COLUMNS: &str = "id, command, timestamp, duration, exit_status, cwd, hostname, session_id";
+
+#[derive(Debug, Error)]
+pub enum SqliteHistoryError {
+    #[error("error opening history database {0}")]
+    Open(rusqlite::Error),
+    #[error("error querying history database {0}")]
+    Query(#[from] rusqlite::Error),
+}
+
+/// Store the history in a SQLite database
+///
+/// Entries are cached in memory so that they can be handed out by reference, the cache is brought
+/// up to date with the database whenever an entry is added or history browsing starts. This means
+/// that commands run in other sessions show up the next time history is browsed.
+pub struct SqliteHistory {
+    conn: Connection,
+    /// Row ids of the cached entries, oldest first
+    ids: Vec<i64>,
+    /// Cached commands, most recent first
+    commands: Vec<String>,
+    /// Cached metadata, most recent first
+    metadata: Vec<HistoryMetadata>,
+}
+
+impl SqliteHistory {
+    /// Open or create a history database at the given path
+    pub fn new(db_file: PathBuf) -> Result<Self, SqliteHistoryError> {
+        let conn = Connection::open(&db_file).map_err(SqliteHistoryError::Open)?;
+        Self::from_connection(conn)
+    }
+
+    /// History that is only kept in memory, mostly useful for testing
+    pub fn in_memory() -> Result<Self, SqliteHistoryError> {
+        let conn = Connection::open_in_memory().map_err(SqliteHistoryError::Open)?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, SqliteHistoryError> {
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .map_err(SqliteHistoryError::Open)?;
+        // in memory databases don't support WAL and report back a different mode, which is fine
+        let _: String = conn
+            .query_row("PRAGMA journal_mode=WAL", [], |row| row.get(0))
+            .map_err(SqliteHistoryError::Open)?;
+        conn.execute_batch(SCHEMA)
+            .map_err(SqliteHistoryError::Open)?;
+
+        let mut history = SqliteHistory {
+            conn,
+            ids: vec![],
+            commands: vec![],
+            metadata: vec![],
+        };
+        history.load_new()?;
+        Ok(history)
+    }
+
+    /// Load entries written since the cache was last updated, including ones written by other
+    /// sessions
+    fn load_new(&mut self) -> Result<(), SqliteHistoryError> {
+        let last_id = self.ids.last().copied().unwrap_or(0);
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {COLUMNS} FROM history WHERE id > ?1 ORDER BY id"
+        ))?;
+        let rows = stmt
+            .query_map([last_id], row_to_entry)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut commands = Vec::with_capacity(rows.len());
+        let mut metadata = Vec::with_capacity(rows.len());
+        for (id, entry) in rows {
+            self.ids.push(id);
+            commands.push(entry.command);
+            metadata.push(entry.metadata);
+        }
+        self.commands.splice(0..0, commands.into_iter().rev());
+        self.metadata.splice(0..0, metadata.into_iter().rev());
+        Ok(())
+    }
+
+    fn insert(&mut self, cmd: &str, metadata: &HistoryMetadata) -> Result<(), SqliteHistoryError> {
+        self.conn.execute(
+            "INSERT INTO history (command, timestamp, duration, exit_status, cwd, hostname, session_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                cmd,
+                metadata.timestamp.map(to_millis),
+                metadata.duration.map(|d| d.as_millis() as i64),
+                metadata.exit_status,
+                metadata.cwd.as_ref().map(|cwd| cwd.to_string_lossy().to_string()),
+                metadata.hostname,
+                metadata.session_id,
+            ],
+        )?;
+        self.load_new()
+    }
+
+    fn query(
+        &self,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<(i64, HistoryEntry)>, SqliteHistoryError> {
+        let mut clauses = vec![];
+        let mut values = vec![];
+        if let Some(cwd) = &filter.cwd {
+            clauses.push("cwd = ?");
+            values.push(Value::Text(cwd.to_string_lossy().to_string()));
+        }
+        if filter.failed {
+            clauses.push("exit_status != 0");
+        }
+        if let Some(since) = filter.since {
+            clauses.push("timestamp >= ?");
+            values.push(Value::Integer(to_millis(since)));
+        }
+        if let Some(until) = filter.until {
+            clauses.push("timestamp < ?");
+            values.push(Value::Integer(to_millis(until)));
+        }
+        let condition = if clauses.is_empty() {
+            String::from("1")
+        } else {
+            clauses.join(" AND ")
+        };
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM history WHERE {condition} ORDER BY id DESC"
+        ))?;
+        let rows = stmt
+            .query_map(params_from_iter(values), row_to_entry)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Index into the cached entries of a row
+    fn index_of(&self, id: i64) -> Option<usize> {
+        let pos = self.ids.binary_search(&id).ok()?;
+        Some(self.ids.len() - 1 - pos)
+    }
+}
+
+impl History for SqliteHistory {
+    type HistoryItem = String;
+
+    fn add(&mut self, cmd: Self::HistoryItem) {
+        let metadata = HistoryMetadata {
+            timestamp: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        self.add_with_metadata(cmd, metadata);
+    }
+
+    fn add_with_metadata(&mut self, cmd: Self::HistoryItem, metadata: HistoryMetadata) {
+        if let Err(e) = self.insert(&cmd, &metadata) {
+            warn!("failed to write history entry: {e}");
+        }
+    }
+
+    fn remove(&mut self, i: usize) -> Option<Self::HistoryItem> {
+        let pos = self.ids.len().checked_sub(i + 1)?;
+        if let Err(e) = self
+            .conn
+            .execute("DELETE FROM history WHERE id = ?1", [self.ids[pos]])
+        {
+            warn!("failed to remove history entry: {e}");
+            return None;
+        }
+        self.ids.remove(pos);
+        self.metadata.remove(i);
+        Some(self.commands.remove(i))
+    }
+
+    fn clear(&mut self) {
+        if let Err(e) = self.conn.execute("DELETE FROM history", []) {
+            warn!("failed to clear history: {e}");
+            return;
+        }
+        self.ids.clear();
+        self.commands.clear();
+        self.metadata.clear();
+    }
+
+    fn sync(&mut self) {
+        if let Err(e) = self.load_new() {
+            warn!("failed to read history database: {e}");
+        }
+    }
+
+    fn search(&self, query: &str) -> Option<&Self::HistoryItem> {
+        self.commands.iter().find(|item| item.contains(query))
+    }
+
+    fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    fn get(&self, i: usize) -> Option<&Self::HistoryItem> {
+        self.commands.get(i)
+    }
+
+    fn metadata(&self, i: usize) -> Option<HistoryMetadata> {
+        self.metadata.get(i).cloned()
+    }
+
+    fn filter(&self, filter: &HistoryFilter) -> Vec<(usize, HistoryEntry)> {
+        match self.query(filter) {
+            Ok(rows) => rows
+                .into_iter()
+                .filter_map(|(id, entry)| Some((self.index_of(id)?, entry)))
+                .collect(),
+            Err(e) => {
+                warn!("failed to query history: {e}");
+                vec![]
+            },
+        }
+    }
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn row_to_entry(row: &Row) -> rusqlite::Result<(i64, HistoryEntry)> {
+    let metadata = HistoryMetadata {
+        timestamp: row.get::<_, Option<i64>>(2)?.map(from_millis),
+        duration: row
+            .get::<_, Option<i64>>(3)?
+            .map(|millis| Duration::from_millis(millis.max(0) as u64)),
+        exit_status: row.get(4)?,
+        cwd: row.get::<_, Option<String>>(5)?.map(PathBuf::from),
+        hostname: row.get(6)?,
+        session_id: row.get(7)?,
+    };
+    let entry = HistoryEntry {
+        command: row.get(1)?,
+        metadata,
+    };
+    Ok((row.get(0)?, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::SystemTime};
+
+    use super::SqliteHistory;
+    use crate::history::{History, HistoryFilter, HistoryMetadata};
+
+    #[test]
+    fn filter() {
+        let mut hist = SqliteHistory::in_memory().unwrap();
+        let metadata = |status, cwd: &str| HistoryMetadata {
+            timestamp: Some(SystemTime::now()),
+            exit_status: Some(status),
+            cwd: Some(PathBuf::from(cwd)),
+            ..Default::default()
+        };
+        hist.add_with_metadata("make".into(), metadata(2, "/src"));
+        hist.add_with_metadata("ls".into(), metadata(0, "/"));
+        hist.add_with_metadata("make test".into(), metadata(0, "/src"));
+
+        assert_eq!(hist.len(), 3);
+        assert_eq!(hist.get(0).map(|s| s.as_str()), Some("make test"));
+
+        let failed = hist.filter(&HistoryFilter {
+            failed: true,
+            ..Default::default()
+        });
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 2);
+        assert_eq!(failed[0].1.metadata.exit_status, Some(2));
+
+        let in_src = hist.filter(&HistoryFilter {
+            cwd: Some(PathBuf::from("/src")),
+            ..Default::default()
+        });
+        assert_eq!(
+            in_src.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+    }
+}
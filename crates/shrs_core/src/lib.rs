@@ -9,10 +9,14 @@ extern crate lazy_static;
 pub mod alias;
 pub mod builtin;
 pub mod cmd_output;
+pub mod complete;
+pub mod editor;
 pub mod env;
+pub mod history;
 pub mod hooks;
 pub mod jobs;
 pub mod lang;
+pub mod options;
 pub mod output_writer;
 pub mod prompt;
 pub mod shell;
@@ -23,14 +27,22 @@ pub mod theme;
 pub mod prelude {
     //! Conveniently import commonly used types
 
+    #[cfg(feature = "sqlite")]
+    pub use crate::history::SqliteHistory;
     pub use crate::{
         alias::{Alias, AliasInfo, AliasRule, AliasRuleCtx},
         builtin::{BuiltinCmd, Builtins},
         cmd_output::CmdOutput,
+        complete::CompletionSpecs,
         env::Env,
+        history::{
+            DefaultHistory, FileBackedHistory, History, HistoryDedup, HistoryEntry, HistoryFilter,
+            HistoryMetadata,
+        },
         hooks::{Hook, HookFn, Hooks, *},
         jobs::{JobId, JobInfo, Jobs},
         lang::Lang,
+        options::{EditMode, ShellOptions},
         output_writer::OutputWriter,
         prompt::*,
         shell::{Context, Runtime, Shell},
@@ -0,0 +1,85 @@
+//! Shell options that can be toggled at runtime
+//!
+//! Options are modified using the `set` builtin, for example `set -o pipefail`.
+//! The editing mode of the line editor is also an option, `set -o vi` and `set -o emacs` switch
+//! between the two.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OptionsError {
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+}
+
+/// Keybinding style used by the line editor
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EditMode {
+    /// Modal vi style editing
+    #[default]
+    Vi,
+    /// Readline style emacs editing
+    Emacs,
+}
+
+/// Collection of toggleable shell options
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ShellOptions {
+    /// Use the status of the last pipeline stage to exit with a non-zero status as the status of
+    /// the whole pipeline, instead of the status of the last stage
+    pub pipefail: bool,
+    /// Editing mode of the line editor, `None` until the line editor has picked its default
+    pub edit_mode: Option<EditMode>,
+    /// Expand `!` references to earlier commands in the entered line, see
+    /// [crate::history::expand_history]
+    pub histexpand: bool,
+}
+
+impl Default for ShellOptions {
+    fn default() -> Self {
+        ShellOptions {
+            pipefail: false,
+            edit_mode: None,
+            histexpand: true,
+        }
+    }
+}
+
+impl ShellOptions {
+    /// Set an option by name
+    pub fn set(&mut self, name: &str, val: bool) -> Result<(), OptionsError> {
+        match name {
+            "pipefail" => self.pipefail = val,
+            "histexpand" => self.histexpand = val,
+            // the two editing modes are mutually exclusive, so turning one off turns the other on
+            "vi" => self.edit_mode = Some(if val { EditMode::Vi } else { EditMode::Emacs }),
+            "emacs" => self.edit_mode = Some(if val { EditMode::Emacs } else { EditMode::Vi }),
+            _ => return Err(OptionsError::UnknownOption(name.into())),
+        }
+        Ok(())
+    }
+
+    /// Query an option by name
+    pub fn get(&self, name: &str) -> Result<bool, OptionsError> {
+        match name {
+            "pipefail" => Ok(self.pipefail),
+            "histexpand" => Ok(self.histexpand),
+            "vi" => Ok(self.edit_mode == Some(EditMode::Vi)),
+            "emacs" => Ok(self.edit_mode == Some(EditMode::Emacs)),
+            _ => Err(OptionsError::UnknownOption(name.into())),
+        }
+    }
+
+    /// Names and values of all options
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("emacs", self.edit_mode == Some(EditMode::Emacs)),
+            ("histexpand", self.histexpand),
+            ("pipefail", self.pipefail),
+            ("vi", self.edit_mode == Some(EditMode::Vi)),
+        ]
+        .into_iter()
+    }
+}
@@ -24,9 +24,11 @@ use crate::{
     alias::Alias,
     builtin::Builtins,
     env::Env,
+    history::History,
     hooks::{AfterCommandCtx, BeforeCommandCtx, ChangeDirCtx, Hooks, JobExitCtx, StartupCtx},
     jobs::Jobs,
     lang::Lang,
+    options::ShellOptions,
     output_writer::OutputWriter,
     signal::Signals,
     state::State,
@@ -60,6 +62,8 @@ pub struct Context {
     pub jobs: Jobs,
     pub startup_time: Instant,
     pub alias: Alias,
+    /// Command history, see [History]
+    pub history: Box<dyn History<HistoryItem = String>>,
 }
 
 /// Runtime context for the shell
@@ -78,11 +82,41 @@ pub struct Runtime {
     /// Arguments this shell was called with
     pub args: Vec<String>,
     /// Exit status of most recent pipeline
+    ///
+    /// Processes that were terminated by a signal are reported as `128+N`
     pub exit_status: i32,
+    /// Exit status of each stage of the most recent pipeline, like `PIPESTATUS` in bash
+    ///
+    /// Use [Runtime::set_pipe_status] to keep the `PIPESTATUS` variable in sync.
+    pub pipe_status: Vec<i32>,
+    /// Options that are toggled using the `set` builtin
+    pub options: ShellOptions,
     // /// List of defined functions
     // pub functions: HashMap<String, Box<ast::Command>>,
 }
 
+impl Runtime {
+    /// Set the exit status of each stage of the most recent pipeline
+    ///
+    /// They are also exposed to the shell as `PIPESTATUS[N]`, which is not exported.
+    pub fn set_pipe_status(&mut self, codes: Vec<i32>) {
+        for (i, code) in codes.iter().enumerate() {
+            let _ = self
+                .env
+                .set_unexported(&format!("PIPESTATUS[{i}]"), &code.to_string());
+        }
+        // stages left over from a longer pipeline
+        for i in codes.len().. {
+            let var = format!("PIPESTATUS[{i}]");
+            if self.env.get(&var).is_err() {
+                break;
+            }
+            let _ = self.env.remove(&var);
+        }
+        self.pipe_status = codes;
+    }
+}
+
 /// Set the current working directory
 pub fn set_working_dir(
     sh: &Shell,
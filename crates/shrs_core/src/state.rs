@@ -25,6 +25,10 @@ impl State {
         self.store.get_mut::<T>()
     }
 
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.store.remove::<T>()
+    }
+
     /// Get data or return default if not exist
     ///
     /// Also inserts default into state store to ensure future gets don't fail
@@ -1,6 +1,6 @@
 //! Configuration for common color values bundled into a theme
 
-use crossterm::style::Color;
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
 
 pub struct Theme {
     pub black: Color,
@@ -19,6 +19,14 @@ pub struct Theme {
     pub dark_cyan: Color,
     pub white: Color,
     pub light_grey: Color,
+    /// Selected entry of menus
+    pub menu_selected: ContentStyle,
+    /// Characters of menu entries matched by what was typed
+    pub menu_matched: ContentStyle,
+    /// Descriptions next to menu entries
+    pub menu_comment: ContentStyle,
+    /// Group headers and the count of entries that didn't fit in menus
+    pub menu_header: ContentStyle,
 }
 
 impl Default for Theme {
@@ -40,6 +48,14 @@ impl Default for Theme {
             dark_cyan: Color::DarkCyan,
             white: Color::White,
             light_grey: Color::Grey,
+            menu_selected: ContentStyle::new().with(Color::Black).on(Color::White),
+            menu_matched: ContentStyle::new()
+                .with(Color::DarkGreen)
+                .attribute(Attribute::Bold),
+            menu_comment: ContentStyle::new().with(Color::Yellow),
+            menu_header: ContentStyle::new()
+                .with(Color::DarkGrey)
+                .attribute(Attribute::Underlined),
         }
     }
 }
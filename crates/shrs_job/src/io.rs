@@ -2,11 +2,28 @@
 use std::os::unix::io::RawFd;
 use std::{
     fs::File,
-    os::fd::AsRawFd,
+    os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
     process::{ChildStdout, Stdio},
+    sync::OnceLock,
 };
 
-use nix::libc::STDIN_FILENO;
+use log::error;
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, FdFlag, OFlag},
+    libc::{STDERR_FILENO, STDIN_FILENO},
+    sys::stat::{fstat, SFlag},
+    unistd,
+};
+
+/// Lowest file descriptor used for the files the shell holds on to itself
+///
+/// Like in bash, file descriptors from here on are reserved for the shell, redirections can only
+/// use the ones below.
+pub const SHELL_FD_MIN: RawFd = 255;
+
+/// Copies of the stdin, stdout and stderr the shell was started with, see [save_tty_fds]
+static TTY_FDS: OnceLock<[RawFd; 3]> = OnceLock::new();
 
 #[derive(Debug)]
 pub enum Stdin {
@@ -35,7 +52,7 @@ impl From<Stdin> for Stdio {
         match stdin {
             Stdin::Inherit => Self::inherit(),
             Stdin::File(file) => file.into(),
-            Stdin::FileDescriptor(_) => panic!("must occur after fork(2)"),
+            Stdin::FileDescriptor(fd) => dup_to_stdio(fd),
             Stdin::Child(child) => child.into(),
         }
     }
@@ -64,8 +81,210 @@ impl From<Output> for Stdio {
         match stdout {
             Output::Inherit => Self::inherit(),
             Output::File(file) => file.into(),
-            Output::FileDescriptor(_fd) => panic!("must occur after fork(2)"),
+            Output::FileDescriptor(fd) => dup_to_stdio(fd),
             Output::CreatePipe => Self::piped(),
         }
     }
 }
+
+/// Duplicate a file descriptor that is owned elsewhere, so it can be handed to [Stdio]
+fn dup_to_stdio(fd: RawFd) -> Stdio {
+    // SAFETY: whoever constructed the Stdin or Output keeps the file descriptor open
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    match fd.try_clone_to_owned() {
+        Ok(fd) => fd.into(),
+        Err(e) => {
+            error!(
+                "failed to duplicate file descriptor {}: {e}",
+                fd.as_raw_fd()
+            );
+            Stdio::null()
+        },
+    }
+}
+
+/// Change made to the file descriptors of a process, used to implement redirections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdAction {
+    /// Make `fd` a duplicate of `src`, like `fd>&src`
+    Dup { fd: RawFd, src: RawFd },
+    /// Close `fd`, like `fd>&-`
+    Close(RawFd),
+}
+
+impl FdAction {
+    /// File descriptor that is changed by the action
+    pub fn fd(&self) -> RawFd {
+        match *self {
+            FdAction::Dup { fd, .. } | FdAction::Close(fd) => fd,
+        }
+    }
+
+    /// Apply the action to the file descriptors of the current process
+    ///
+    /// This runs in child processes right before they execute, but also in the shell itself for
+    /// `exec` without a command.
+    pub fn apply(&self) -> nix::Result<()> {
+        match *self {
+            // dup2 does nothing if both are the same, but the file descriptor should still be
+            // inherited
+            FdAction::Dup { fd, src } if fd == src => {
+                fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+            },
+            FdAction::Dup { fd, src } => {
+                unistd::dup2(src, fd)?;
+            },
+            // closing a file descriptor that is not open is not an error
+            FdAction::Close(fd) => match unistd::close(fd) {
+                Ok(()) | Err(Errno::EBADF) => {},
+                Err(e) => return Err(e),
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Create a pipe, returning the read and write ends
+///
+/// Both ends are closed on exec, so they are only passed to the child processes they are
+/// explicitly given to as [Stdin::FileDescriptor] or [Output::FileDescriptor], or that they are
+/// exposed to using [inherit_fd].
+pub fn pipe() -> anyhow::Result<(OwnedFd, OwnedFd)> {
+    let (read, write) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+    // SAFETY: both file descriptors were just created and are not owned by anything else
+    unsafe { Ok((OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write))) }
+}
+
+/// Let a file descriptor be inherited by the child processes that are spawned from now on
+///
+/// Returns the path that child processes can use to refer to the file descriptor, of the form
+/// `/dev/fd/N`. This is used to implement process substitution.
+pub fn inherit_fd(fd: &OwnedFd) -> anyhow::Result<String> {
+    let raw_fd = fd.as_raw_fd();
+    fcntl(raw_fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+    Ok(format!("/dev/fd/{raw_fd}"))
+}
+
+/// Duplicate a file descriptor to one numbered `min` or higher, which is closed on exec
+///
+/// Useful for file descriptors that the shell holds on to for a long time, so they don't get in
+/// the way of low numbered file descriptors used in redirections.
+pub fn dup_fd_above(fd: &OwnedFd, min: RawFd) -> anyhow::Result<OwnedFd> {
+    let new_fd = fcntl(fd.as_raw_fd(), FcntlArg::F_DUPFD_CLOEXEC(min))?;
+    // SAFETY: the file descriptor was just created and is not owned by anything else
+    unsafe { Ok(OwnedFd::from_raw_fd(new_fd)) }
+}
+
+/// Close the pipes above stderr that would be closed on exec
+///
+/// Used in forked copies of the shell, so that they don't keep the pipes of other processes open.
+/// File descriptors that programs inherit, such as the ones opened with `exec 3>file`, and other
+/// files the shell holds, such as its log file, are kept.
+pub(crate) fn close_cloexec_fds() {
+    let Ok(entries) = std::fs::read_dir("/dev/fd") else {
+        return;
+    };
+    let fds = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<RawFd>().ok())
+        .collect::<Vec<_>>();
+    // the file descriptor used to read the directory is already closed, and is skipped here
+    for fd in fds.into_iter().filter(|fd| *fd > STDERR_FILENO) {
+        let Ok(flags) = fcntl(fd, FcntlArg::F_GETFD) else {
+            continue;
+        };
+        let is_pipe = fstat(fd).is_ok_and(|stat| {
+            SFlag::from_bits_truncate(stat.st_mode & SFlag::S_IFMT.bits()) == SFlag::S_IFIFO
+        });
+        if is_pipe && FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC) {
+            let _ = unistd::close(fd);
+        }
+    }
+}
+
+/// Keep a copy of the stdin, stdout and stderr the shell was started with
+///
+/// Needs to be called before redirecting the file descriptors of the shell itself with `exec`,
+/// so that the line editor and job control can keep using the terminal. Only the first call
+/// saves anything.
+pub fn save_tty_fds() -> anyhow::Result<()> {
+    if TTY_FDS.get().is_some() {
+        return Ok(());
+    }
+    let mut fds = [0; 3];
+    for (fd, saved) in fds.iter_mut().enumerate() {
+        *saved = fcntl(fd as RawFd, FcntlArg::F_DUPFD_CLOEXEC(SHELL_FD_MIN))?;
+    }
+    let _ = TTY_FDS.set(fds);
+    Ok(())
+}
+
+/// Stdin, stdout or stderr the shell was started with, even if it was redirected since
+pub fn tty_fd(fd: RawFd) -> RawFd {
+    match TTY_FDS.get() {
+        Some(fds) if (STDIN_FILENO..=STDERR_FILENO).contains(&fd) => fds[fd as usize],
+        _ => fd,
+    }
+}
+
+/// Run `f` with the stdin, stdout and stderr the shell was started with
+///
+/// Redirections done with `exec` are put back afterwards. Does nothing special if they were
+/// never saved with [save_tty_fds].
+pub fn with_tty_fds<T>(f: impl FnOnce() -> T) -> T {
+    let Some(tty_fds) = TTY_FDS.get() else {
+        return f();
+    };
+
+    let current = SavedFds::save(STDIN_FILENO..=STDERR_FILENO);
+    for (fd, tty_fd) in tty_fds.iter().enumerate() {
+        if let Err(e) = unistd::dup2(*tty_fd, fd as RawFd) {
+            error!("failed to restore file descriptor {fd}: {e}");
+        }
+    }
+
+    let result = f();
+
+    current.restore();
+    result
+}
+
+/// Copies of file descriptors of the shell, taken before they are redirected
+pub struct SavedFds {
+    fds: Vec<(RawFd, Option<OwnedFd>)>,
+}
+
+impl SavedFds {
+    /// Keep a copy of each of the file descriptors
+    pub fn save(fds: impl IntoIterator<Item = RawFd>) -> Self {
+        let fds = fds
+            .into_iter()
+            .map(|fd| {
+                let held = match fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(SHELL_FD_MIN)) {
+                    // SAFETY: the file descriptor was just created and is not owned by anything else
+                    Ok(held) => Some(unsafe { OwnedFd::from_raw_fd(held) }),
+                    Err(_) => None,
+                };
+                (fd, held)
+            })
+            .collect();
+        Self { fds }
+    }
+
+    /// Put the file descriptors back the way they were when they were saved
+    ///
+    /// A file descriptor that was not open is closed again.
+    pub fn restore(self) {
+        for (fd, held) in self.fds {
+            let action = match held {
+                Some(ref held) => FdAction::Dup {
+                    fd,
+                    src: held.as_raw_fd(),
+                },
+                None => FdAction::Close(fd),
+            };
+            if let Err(e) = action.apply() {
+                error!("failed to redirect file descriptor {fd}: {e}");
+            }
+        }
+    }
+}
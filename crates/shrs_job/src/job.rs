@@ -67,9 +67,22 @@ pub struct JobManager {
     jobs: Vec<JobImpl>,
     job_count: u32,
     current_job: Option<JobId>,
+    /// Jobs are run by a subshell, which leaves the terminal to the shell that started it
+    subshell: bool,
 }
 
 impl JobManager {
+    /// Job manager of a subshell, which doesn't do job control
+    ///
+    /// The subshell is itself part of a job of the shell that started it, so it doesn't give the
+    /// jobs it runs control of the terminal.
+    pub fn subshell() -> Self {
+        Self {
+            subshell: true,
+            ..Default::default()
+        }
+    }
+
     pub fn create_job(&mut self, input: &str, process_group: ProcessGroup) -> JobId {
         let job_id = self.get_next_job_id();
         self.jobs.push(JobImpl::new(
@@ -77,6 +90,7 @@ impl JobManager {
             input,
             process_group.id.map(|pgid| pgid as pid_t),
             process_group.processes,
+            process_group.substitutions,
         ));
         job_id
     }
@@ -89,6 +103,11 @@ impl JobManager {
         self.jobs.iter().map(|j| j.as_job()).collect()
     }
 
+    /// Find a job by its id
+    pub fn get_job(&self, job_id: JobId) -> Option<&dyn Job> {
+        self.find_job(job_id).map(|i| self.jobs[i].as_job())
+    }
+
     /// Waits for job to stop or complete.
     ///
     /// This function also updates the statuses of other jobs if we receive
@@ -121,7 +140,9 @@ impl JobManager {
             self.jobs[job_index].set_last_running_in_foreground(true);
             let job_pgid = self.jobs[job_index].pgid();
             let job_tmodes = self.jobs[job_index].tmodes().clone();
-            let _terminal_state = job_pgid.map(|pgid| TerminalState::new(Pid::from_raw(pgid)));
+            let _terminal_state = job_pgid
+                .filter(|_| !self.subshell)
+                .map(|pgid| TerminalState::new(Pid::from_raw(pgid)));
 
             // Send the job a continue signal if necessary
             if cont {
@@ -257,6 +278,7 @@ pub struct JobImpl {
     input: String,
     pgid: Option<pid_t>,
     processes: Vec<Box<dyn Process>>,
+    substitutions: Vec<Box<dyn Process>>,
     last_status_code: Option<ExitStatus>,
     last_running_in_foreground: bool,
     notified_stopped_job: bool,
@@ -269,6 +291,7 @@ impl JobImpl {
         input: &str,
         pgid: Option<pid_t>,
         processes: Vec<Box<dyn Process>>,
+        substitutions: Vec<Box<dyn Process>>,
     ) -> Self {
         // Initialize last_status_code if possible; this prevents a completed
         // job from having a None last_status_code if all processes have
@@ -280,6 +303,7 @@ impl JobImpl {
             input: input.to_string(),
             pgid,
             processes,
+            substitutions,
             last_status_code,
             last_running_in_foreground: true,
             notified_stopped_job: false,
@@ -303,8 +327,19 @@ impl JobImpl {
         self.last_running_in_foreground = last_running_in_foreground;
     }
 
+    /// Both the stages of the pipeline and the processes substituted into it
+    fn all_processes(&self) -> impl Iterator<Item = &Box<dyn Process>> {
+        self.processes.iter().chain(self.substitutions.iter())
+    }
+
+    fn all_processes_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Process>> {
+        self.processes
+            .iter_mut()
+            .chain(self.substitutions.iter_mut())
+    }
+
     fn kill(&mut self) -> anyhow::Result<()> {
-        for process in &mut self.processes {
+        for process in self.all_processes_mut() {
             process.kill()?;
         }
 
@@ -312,12 +347,14 @@ impl JobImpl {
     }
 
     fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
-        for process in &mut self.processes {
-            if let Some(exit_status) = process.try_wait()? {
-                // BUG: this is not actually the most recently exited process,
-                // but instead the latest process in the job that has exited
-                self.last_status_code = Some(exit_status);
-            }
+        for process in self.all_processes_mut() {
+            process.try_wait()?;
+        }
+
+        // The status of a pipeline is the status of its last stage, regardless of the order in
+        // which the stages exited
+        if let Some(exit_status) = self.processes.last().and_then(|p| p.status_code()) {
+            self.last_status_code = Some(exit_status);
         }
 
         Ok(self.last_status_code)
@@ -332,14 +369,12 @@ impl JobImpl {
     }
 
     fn is_stopped(&self) -> bool {
-        self.processes
-            .iter()
+        self.all_processes()
             .all(|p| p.status() == ProcessStatus::Stopped)
     }
 
     fn is_completed(&self) -> bool {
-        self.processes
-            .iter()
+        self.all_processes()
             .all(|p| p.status() == ProcessStatus::Completed)
     }
 }
@@ -9,5 +9,8 @@ pub use job::*;
 mod process;
 pub use process::*;
 
+mod rusage;
+pub use rusage::*;
+
 mod util;
 pub use util::*;
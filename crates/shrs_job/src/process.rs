@@ -1,14 +1,23 @@
 use std::{
-    ffi::OsStr,
-    fmt, iter,
-    os::fd::AsRawFd,
+    ffi::{CStr, OsStr},
+    fmt,
+    io::Write,
+    iter,
+    os::{fd::AsRawFd, unix::process::ExitStatusExt},
     process::{Child, Command, ExitStatus},
 };
 
 use log::*;
-use nix::libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::{
+    libc::{self, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO},
+    sys::signal::{self, Signal},
+    unistd::{self, ForkResult, Pid},
+};
 
-use super::{io::Stdin, pid_t, util, Output};
+use super::{
+    io::{close_cloexec_fds, Stdin},
+    pid_t, pipe, util, CpuTime, FdAction, Output,
+};
 use crate::log_if_err;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -36,6 +45,10 @@ pub trait Process {
     fn kill(&mut self) -> anyhow::Result<()>;
     fn wait(&mut self) -> anyhow::Result<ExitStatus>;
     fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>>;
+    /// CPU time used by the process, known once it has been waited on
+    fn cpu_time(&self) -> Option<CpuTime> {
+        None
+    }
 }
 
 impl fmt::Debug for dyn Process {
@@ -50,10 +63,52 @@ impl fmt::Debug for dyn Process {
     }
 }
 
+/// Convert an [ExitStatus] to the numeric status reported by the shell
+///
+/// Processes that were terminated or stopped by a signal are reported as `128+N`, where `N` is the
+/// signal number.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => status.stopped_signal().map_or(1, |signal| 128 + signal),
+    }
+}
+
+/// Status of a process that was stopped by a signal, such as `SIGTSTP` from Ctrl-Z
+pub fn stopped_status(signal: i32) -> ExitStatus {
+    ExitStatus::from_raw((signal << 8) | 0x7f)
+}
+
+/// Human readable message describing how a process was terminated by a signal
+///
+/// Returns [None] if the process was not terminated by a signal.
+pub fn signal_message(status: ExitStatus) -> Option<String> {
+    let signal = status.signal()?;
+    // strsignal returns a pointer to a static (or thread local) string that is valid until the
+    // next call
+    let desc = unsafe {
+        let ptr = libc::strsignal(signal);
+        if ptr.is_null() {
+            format!("Unknown signal {signal}")
+        } else {
+            CStr::from_ptr(ptr).to_string_lossy().into_owned()
+        }
+    };
+    if status.core_dumped() {
+        Some(format!("{desc} (core dumped)"))
+    } else {
+        Some(desc)
+    }
+}
+
 #[derive(Debug)]
 pub struct ProcessGroup {
     pub id: Option<u32>,
     pub processes: Vec<Box<dyn Process>>,
+    /// Processes spawned for process substitution, which are waited on and killed along with the
+    /// rest of the group, but do not contribute to its exit status
+    pub substitutions: Vec<Box<dyn Process>>,
     pub foreground: bool,
 }
 
@@ -124,6 +179,7 @@ struct ExternalProcess {
     child: Child,
     status: ProcessStatus,
     status_code: Option<ExitStatus>,
+    cpu_time: Option<CpuTime>,
 }
 
 impl ExternalProcess {
@@ -140,8 +196,48 @@ impl ExternalProcess {
             child,
             status: ProcessStatus::Running,
             status_code: None,
+            cpu_time: None,
         }
     }
+
+    /// Reap the process with [wait4]
+    ///
+    /// The child is reaped behind the back of [Child], so it must not be waited on through it.
+    fn wait4(&mut self, block: bool) -> anyhow::Result<Option<ExitStatus>> {
+        if self.status_code.is_none() {
+            if let Some((exit_status, cpu_time)) = wait4(self.child.id() as libc::pid_t, block)? {
+                self.status = ProcessStatus::Completed;
+                self.status_code = Some(exit_status);
+                self.cpu_time = Some(cpu_time);
+            }
+        }
+        Ok(self.status_code)
+    }
+}
+
+/// Reap a child process with `wait4`, which also reports the resources it used
+///
+/// Returns [None] if the process has not exited yet and `block` is false.
+fn wait4(pid: libc::pid_t, block: bool) -> anyhow::Result<Option<(ExitStatus, CpuTime)>> {
+    let flags = if block { 0 } else { libc::WNOHANG };
+    let mut status = 0;
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        match unsafe { libc::wait4(pid, &mut status, flags, &mut usage) } {
+            0 => return Ok(None),
+            -1 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err.into());
+                }
+            },
+            _ => break,
+        }
+    }
+    Ok(Some((
+        ExitStatus::from_raw(status),
+        CpuTime::from_rusage(&usage),
+    )))
 }
 
 impl Process for ExternalProcess {
@@ -166,35 +262,41 @@ impl Process for ExternalProcess {
     }
 
     fn kill(&mut self) -> anyhow::Result<()> {
-        self.child.kill()?;
+        // the pid of a process that was reaped may have been reused
+        if self.status_code.is_none() {
+            self.child.kill()?;
+        }
         Ok(())
     }
 
     fn wait(&mut self) -> anyhow::Result<ExitStatus> {
-        let exit_status = self.child.wait()?;
-        self.status = ProcessStatus::Completed;
-        self.status_code = Some(exit_status);
-        Ok(exit_status)
+        Ok(self.wait4(true)?.expect("blocking wait returned no status"))
     }
 
     fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
-        if let Some(exit_status) = self.child.try_wait()? {
-            self.status = ProcessStatus::Completed;
-            self.status_code = Some(exit_status);
-            Ok(Some(exit_status))
-        } else {
-            Ok(None)
-        }
+        self.wait4(false)
+    }
+
+    fn cpu_time(&self) -> Option<CpuTime> {
+        self.cpu_time
     }
 }
 
+/// Spawn an external program in the process group `pgid`
+///
+/// Only processes of foreground jobs are given control of the terminal, background jobs leave it
+/// to the shell. The `fd_actions` are applied in order after stdin, stdout and stderr have been
+/// set up, so redirections take precedence over pipes.
+#[allow(clippy::too_many_arguments)]
 pub fn run_external_command<S1, S2>(
     program: S1,
     args: &[S2],
     stdin: Stdin,
     stdout: Output,
     stderr: Output,
+    fd_actions: Vec<FdAction>,
     pgid: Option<u32>,
+    foreground: bool,
 ) -> anyhow::Result<(Box<dyn Process>, Option<u32>)>
 where
     S1: AsRef<str>,
@@ -202,11 +304,6 @@ where
 {
     use std::os::unix::process::CommandExt;
 
-    use nix::{
-        sys::signal::{self, SigHandler, Signal},
-        unistd::{self, Pid},
-    };
-
     let mut command = Command::new(OsStr::new(program.as_ref()));
     command.args(args.iter().map(AsRef::as_ref).map(OsStr::new));
 
@@ -255,24 +352,16 @@ where
                 //     pipeline, as Command::stdin configures stdin *before*
                 //     before_exec runs.
                 // 3) incorrect permissions
-                unistd::tcsetpgrp(shell_terminal, pgid).expect("tcsetpgrp failed");
+                if foreground {
+                    unistd::tcsetpgrp(shell_terminal, pgid).expect("tcsetpgrp failed");
+                }
 
                 // Reset job control signal handling back to default
                 // signal(3) failing represents programmer error, e.g.
                 // 1) signal argument is not a valid signal number
                 // 2) an attempt is made to supply a signal handler for a
                 //    signal that cannot have a custom signal handler
-                for signal in [
-                    Signal::SIGINT,
-                    Signal::SIGQUIT,
-                    Signal::SIGTSTP,
-                    Signal::SIGTTIN,
-                    Signal::SIGTTOU,
-                    Signal::SIGCHLD,
-                ] {
-                    signal::signal(signal, SigHandler::SigDfl)
-                        .expect("failed to reset signal handler");
-                }
+                util::reset_job_control_signals().expect("failed to reset signal handler");
             }
 
             // See comment at the top of this function on why we are configuring
@@ -298,6 +387,10 @@ where
                 }
             }
 
+            for action in fd_actions.iter() {
+                action.apply()?;
+            }
+
             Ok(())
         });
     }
@@ -305,7 +398,7 @@ where
     let child = match command.spawn() {
         Ok(child) => child,
         Err(e) => {
-            if job_control_is_enabled {
+            if job_control_is_enabled && foreground {
                 warn!("failed to spawn child, resetting terminal's pgrp");
                 // see above comment for tcsetpgrp(2) failing being programmer
                 // error
@@ -337,6 +430,173 @@ where
     ))
 }
 
+/// Copy of the shell that was forked to evaluate a command, see [run_subshell]
+struct SubshellProcess {
+    pid: libc::pid_t,
+    stdout: Option<Stdin>,
+    status: ProcessStatus,
+    status_code: Option<ExitStatus>,
+    cpu_time: Option<CpuTime>,
+}
+
+impl SubshellProcess {
+    fn wait4(&mut self, block: bool) -> anyhow::Result<Option<ExitStatus>> {
+        if self.status_code.is_none() {
+            if let Some((exit_status, cpu_time)) = wait4(self.pid, block)? {
+                self.status = ProcessStatus::Completed;
+                self.status_code = Some(exit_status);
+                self.cpu_time = Some(cpu_time);
+            }
+        }
+        Ok(self.status_code)
+    }
+}
+
+impl Process for SubshellProcess {
+    fn id(&self) -> Option<ProcessId> {
+        Some((self.pid as u32).into())
+    }
+
+    fn argv(&self) -> String {
+        String::from("(subshell)")
+    }
+
+    fn status(&self) -> ProcessStatus {
+        self.status
+    }
+
+    fn status_code(&self) -> Option<ExitStatus> {
+        self.status_code
+    }
+
+    fn stdout(&mut self) -> Option<Stdin> {
+        self.stdout.take()
+    }
+
+    fn kill(&mut self) -> anyhow::Result<()> {
+        // the pid of a process that was reaped may have been reused
+        if self.status_code.is_none() {
+            signal::kill(Pid::from_raw(self.pid), Signal::SIGKILL)?;
+        }
+        Ok(())
+    }
+
+    fn wait(&mut self) -> anyhow::Result<ExitStatus> {
+        Ok(self.wait4(true)?.expect("blocking wait returned no status"))
+    }
+
+    fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
+        self.wait4(false)
+    }
+
+    fn cpu_time(&self) -> Option<CpuTime> {
+        self.cpu_time
+    }
+}
+
+/// Fork the shell and run `f` in the child, in the process group `pgid`
+///
+/// Used for commands the shell has to evaluate itself, such as subshells or a list of commands
+/// in a pipeline. The child exits with the status returned by `f`. Like programs, it gets the
+/// default handlers for the job control signals, and it closes the file descriptors the shell
+/// keeps to itself, so it doesn't hold on to pipes of other processes.
+pub fn run_subshell(
+    f: impl FnOnce() -> i32,
+    stdin: Stdin,
+    stdout: Output,
+    pgid: Option<u32>,
+    foreground: bool,
+) -> anyhow::Result<(Box<dyn Process>, Option<u32>)> {
+    // the parent's copy of the write end is closed when `stdout_pipe` is dropped
+    let mut stdout_pipe = None;
+    let mut subshell_stdout = None;
+    let stdout_fd = match &stdout {
+        Output::Inherit => None,
+        Output::File(file) => Some(file.as_raw_fd()),
+        Output::FileDescriptor(fd) => Some(*fd),
+        Output::CreatePipe => {
+            let (read, write) = pipe()?;
+            subshell_stdout = Some(Stdin::File(read.into()));
+            let fd = write.as_raw_fd();
+            stdout_pipe = Some(write);
+            Some(fd)
+        },
+    };
+    let stdin_fd = stdin.as_raw_fd();
+    let shell_terminal = util::get_terminal();
+
+    // SAFETY: the child only evaluates the command, then exits without returning
+    match unsafe { unistd::fork()? } {
+        ForkResult::Child => {
+            let pid = unistd::getpid();
+            let pgid = pgid.map(|pgid| Pid::from_raw(pgid as pid_t)).unwrap_or(pid);
+            let _ = unistd::setpgid(pid, pgid);
+            if foreground {
+                let _ = unistd::tcsetpgrp(shell_terminal, pgid);
+            }
+            let _ = util::reset_job_control_signals();
+
+            if stdin_fd != STDIN_FILENO {
+                let _ = unistd::dup2(stdin_fd, STDIN_FILENO);
+            }
+            if let Some(fd) = stdout_fd.filter(|fd| *fd != STDOUT_FILENO) {
+                let _ = unistd::dup2(fd, STDOUT_FILENO);
+            }
+            close_cloexec_fds();
+
+            let code = f();
+            let _ = std::io::stdout().flush();
+            let _ = std::io::stderr().flush();
+            // SAFETY: exiting right away, without running destructors that belong to the parent
+            unsafe { libc::_exit(code) }
+        },
+        ForkResult::Parent { child } => {
+            drop(stdout_pipe);
+            let pgid = pgid.unwrap_or(child.as_raw() as u32);
+            log_if_err!(
+                unistd::setpgid(child, Pid::from_raw(pgid as pid_t)),
+                "failed to set pgid ({}) for pid ({})",
+                pgid,
+                child
+            );
+            let process = SubshellProcess {
+                pid: child.as_raw(),
+                stdout: subshell_stdout,
+                status: ProcessStatus::Running,
+                status_code: None,
+                cpu_time: None,
+            };
+            Ok((Box::new(process), Some(pgid)))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    use super::{exit_code, signal_message, stopped_status};
+
+    #[test]
+    fn exit_code_from_status() {
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        // killed by SIGSEGV
+        assert_eq!(exit_code(ExitStatus::from_raw(11)), 139);
+        // stopped by SIGTSTP
+        assert_eq!(exit_code(stopped_status(20)), 148);
+    }
+
+    #[test]
+    fn signal_message_from_status() {
+        assert_eq!(signal_message(ExitStatus::from_raw(1 << 8)), None);
+        assert_eq!(
+            signal_message(ExitStatus::from_raw(11 | 0x80)),
+            Some(String::from("Segmentation fault (core dumped)"))
+        );
+    }
+}
+
 /*
 fn run_builtin_command<S1, S2>(
     shell: &mut dyn Shell,
@@ -0,0 +1,102 @@
+//! Measuring the CPU time used by the shell and the processes it has waited on
+
+use std::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+use nix::{
+    libc,
+    sys::{
+        resource::{getrusage, UsageWho},
+        time::TimeVal,
+    },
+};
+
+/// CPU time spent in user and kernel mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTime {
+    pub user: Duration,
+    pub sys: Duration,
+}
+
+impl CpuTime {
+    /// CPU time used by the shell process itself
+    pub fn shell() -> anyhow::Result<Self> {
+        Self::query(UsageWho::RUSAGE_SELF)
+    }
+
+    /// CPU time used by all child processes that have terminated and been waited on
+    ///
+    /// Taking the difference of this before and after a job has been waited on gives the CPU
+    /// time used by the processes of that job.
+    pub fn children() -> anyhow::Result<Self> {
+        Self::query(UsageWho::RUSAGE_CHILDREN)
+    }
+
+    /// CPU time in the usage reported by `wait4` for a single process
+    pub(crate) fn from_rusage(usage: &libc::rusage) -> Self {
+        Self {
+            user: timeval_to_duration(TimeVal::from(usage.ru_utime)),
+            sys: timeval_to_duration(TimeVal::from(usage.ru_stime)),
+        }
+    }
+
+    fn query(who: UsageWho) -> anyhow::Result<Self> {
+        let usage = getrusage(who)?;
+        Ok(Self {
+            user: timeval_to_duration(usage.user_time()),
+            sys: timeval_to_duration(usage.system_time()),
+        })
+    }
+}
+
+impl Add for CpuTime {
+    type Output = CpuTime;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        CpuTime {
+            user: self.user + rhs.user,
+            sys: self.sys + rhs.sys,
+        }
+    }
+}
+
+impl Sub for CpuTime {
+    type Output = CpuTime;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        CpuTime {
+            user: self.user.saturating_sub(rhs.user),
+            sys: self.sys.saturating_sub(rhs.sys),
+        }
+    }
+}
+
+fn timeval_to_duration(tv: TimeVal) -> Duration {
+    Duration::from_secs(tv.tv_sec() as u64) + Duration::from_micros(tv.tv_usec() as u64)
+}
+
+/// Format a duration in the same way as bash's `time` and `times`, for example `1m2.345s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{}m{}.{:03}s",
+        secs / 60,
+        secs % 60,
+        duration.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::format_duration;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_duration(Duration::from_millis(3)), "0m0.003s");
+        assert_eq!(format_duration(Duration::from_millis(62_345)), "1m2.345s");
+    }
+}
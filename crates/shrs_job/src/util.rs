@@ -5,7 +5,7 @@ use nix::{
     unistd::{self, Pid},
 };
 
-use super::job::pid_t;
+use super::{io::tty_fd, job::pid_t};
 
 #[macro_export]
 macro_rules! log_if_err {
@@ -26,8 +26,47 @@ macro_rules! log_if_err {
     }};
 }
 
+/// Signals that the shell ignores for job control, which programs it runs handle as usual
+pub const JOB_CONTROL_SIGNALS: [Signal; 6] = [
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGTTIN,
+    Signal::SIGTTOU,
+    Signal::SIGCHLD,
+];
+
+/// Reset the [JOB_CONTROL_SIGNALS] to their default handlers
+///
+/// Returns the handlers that were replaced, which can be put back with
+/// [restore_signal_handlers].
+pub fn reset_job_control_signals() -> nix::Result<Vec<(Signal, SigHandler)>> {
+    let mut previous = vec![];
+    for signal in JOB_CONTROL_SIGNALS {
+        let handler = unsafe { signal::signal(signal, SigHandler::SigDfl) };
+        match handler {
+            Ok(handler) => previous.push((signal, handler)),
+            Err(e) => {
+                restore_signal_handlers(previous)?;
+                return Err(e);
+            },
+        }
+    }
+    Ok(previous)
+}
+
+/// Put back signal handlers returned by [reset_job_control_signals]
+pub fn restore_signal_handlers(handlers: Vec<(Signal, SigHandler)>) -> nix::Result<()> {
+    for (signal, handler) in handlers {
+        unsafe { signal::signal(signal, handler)? };
+    }
+    Ok(())
+}
+
+/// File descriptor of the terminal the shell runs in, which stays the same when stdin is
+/// redirected with `exec`
 pub fn get_terminal() -> RawFd {
-    std::io::stdin().as_raw_fd()
+    tty_fd(std::io::stdin().as_raw_fd())
 }
 
 pub fn initialize_job_control() -> anyhow::Result<()> {
@@ -3,21 +3,34 @@
 /// File redirection
 #[derive(Debug, Clone)]
 pub struct Redirect {
+    /// File descriptor being redirected, defaults to stdin or stdout depending on the mode
     pub n: Option<usize>,
+    /// File, or for duplication a file descriptor number or `-`
     pub file: String,
     pub mode: RedirectMode,
 }
 
 /// File redirection modes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RedirectMode {
+    /// `<file`
     Read,
+    /// `>file`
     Write,
+    /// `<<word`
     ReadAppend,
+    /// `>>file`
     WriteAppend,
+    /// `<&fd`, or `<&-` to close
     ReadDup,
+    /// `>&fd`, or `>&-` to close
     WriteDup,
+    /// `<>file`
     ReadWrite,
+    /// `&>file`, redirects both stdout and stderr
+    WriteBoth,
+    /// `&>>file`, appends both stdout and stderr
+    WriteBothAppend,
 }
 
 /// Assignment
@@ -27,6 +40,31 @@ pub struct Assign {
     pub val: String,
 }
 
+/// Argument to a simple command
+#[derive(Debug, Clone)]
+pub enum Arg {
+    /// Plain word
+    Word(String),
+    /// Process substitution, which is replaced with a path to a pipe connected to the command
+    ///
+    /// ```sh
+    /// diff <(ls dir1) <(ls dir2)
+    /// ```
+    ProcessSubst {
+        cmd: Box<Command>,
+        mode: ProcessSubstMode,
+    },
+}
+
+/// Direction of the pipe used in process substitution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessSubstMode {
+    /// `<(cmd)`, the output of the command can be read from the pipe
+    Read,
+    /// `>(cmd)`, data written to the pipe is used as the input of the command
+    Write,
+}
+
 /// Separator character between commands
 #[derive(Debug, Clone)]
 pub enum SeparatorOp {
@@ -46,7 +84,7 @@ pub enum Command {
     Simple {
         assigns: Vec<Assign>,
         redirects: Vec<Redirect>,
-        args: Vec<String>,
+        args: Vec<Arg>,
     },
 
     /// Two commands joined by a pipe
@@ -65,6 +103,23 @@ pub enum Command {
     /// Negate the exit code of command
     Not(Box<Command>),
 
+    /// Run command in the background with its input and output connected to the shell
+    ///
+    /// ```sh
+    /// coproc NAME { cat; }
+    /// ```
+    Coproc {
+        name: Option<String>,
+        cmd: Box<Command>,
+    },
+
+    /// Report the real, user and system time taken by a pipeline
+    ///
+    /// ```sh
+    /// time cat .bashrc | wc -l
+    /// ```
+    Time(Box<Command>),
+
     /// Asynchronous list of commands
     ///
     /// ```sh
@@ -0,0 +1,94 @@
+//! Coprocesses started with `coproc`
+//!
+//! A coprocess is a background job whose input and output are connected to the shell with pipes.
+//! The shell's ends of the pipes are exposed as variables, `NAME[0]` for reading the output of
+//! the coprocess and `NAME[1]` for writing to its input, which can be used in redirections.
+//!
+//! ```sh
+//! coproc cat
+//! echo hello >&${COPROC[1]}
+//! head -n1 <&${COPROC[0]}
+//! ```
+
+use std::os::fd::{AsRawFd, OwnedFd};
+
+use shrs_core::shell::Runtime;
+use shrs_job::{JobId, JobManager, ProcessStatus};
+
+/// Name of a coprocess that was not given one explicitly
+pub const DEFAULT_COPROC_NAME: &str = "COPROC";
+
+/// Coprocess running in the background
+pub struct Coproc {
+    pub name: String,
+    pub job_id: JobId,
+    /// Process id of the coprocess, exposed as `NAME_PID`
+    pub pid: Option<u32>,
+    /// Shell's end of the pipe connected to the output of the coprocess
+    pub read: OwnedFd,
+    /// Shell's end of the pipe connected to the input of the coprocess
+    pub write: OwnedFd,
+}
+
+/// All coprocesses that are currently running
+#[derive(Default)]
+pub struct Coprocs {
+    coprocs: Vec<Coproc>,
+}
+
+impl Coprocs {
+    /// Check if a coprocess with the given name is still running
+    pub fn is_active(&self, name: &str) -> bool {
+        self.coprocs.iter().any(|c| c.name == name)
+    }
+
+    /// Keep track of a new coprocess and set the variables referring to it
+    ///
+    /// The variables are only visible to the shell, they are not exported to child processes.
+    pub fn insert(&mut self, coproc: Coproc, rt: &mut Runtime) -> anyhow::Result<()> {
+        let name = &coproc.name;
+        rt.env
+            .set_unexported(&format!("{name}[0]"), &coproc.read.as_raw_fd().to_string())?;
+        rt.env
+            .set_unexported(&format!("{name}[1]"), &coproc.write.as_raw_fd().to_string())?;
+        if let Some(pid) = coproc.pid {
+            rt.env
+                .set_unexported(&format!("{name}_PID"), &pid.to_string())?;
+        }
+        self.coprocs.push(coproc);
+        Ok(())
+    }
+
+    /// Close the pipes of coprocesses that have exited and unset their variables
+    pub fn reap(&mut self, job_manager: &mut JobManager, rt: &mut Runtime) -> anyhow::Result<()> {
+        if self.coprocs.is_empty() {
+            return Ok(());
+        }
+        job_manager.update_job_statues()?;
+
+        let mut exited = vec![];
+        self.coprocs.retain(|coproc| {
+            let running = job_manager.get_job(coproc.job_id).is_some_and(|job| {
+                job.processes()
+                    .iter()
+                    .any(|p| p.status() != ProcessStatus::Completed)
+            });
+            if !running {
+                exited.push(coproc.name.clone());
+            }
+            running
+        });
+
+        for name in exited {
+            for var in [
+                format!("{name}[0]"),
+                format!("{name}[1]"),
+                format!("{name}_PID"),
+            ] {
+                // variables may have been unset by the user in the meantime
+                let _ = rt.env.remove(&var);
+            }
+        }
+        Ok(())
+    }
+}
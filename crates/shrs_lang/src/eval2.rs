@@ -1,10 +1,35 @@
 // Lot of code based off of https://github.com/nuta/nsh/blob/main/src/eval.rs
 
-use std::process::ExitStatus;
+use std::{
+    ffi::CString,
+    fs::OpenOptions,
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+    time::Instant,
+};
 
-use shrs_job::{run_external_command, JobManager, Output, Process, ProcessGroup, Stdin};
+use nix::{
+    errno::Errno,
+    libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO},
+    sys::signal::Signal,
+    unistd,
+};
+use shrs_core::{env::Env, output_writer::OutputWriter, shell::Runtime};
+use shrs_job::{
+    dup_fd_above, exit_code, format_duration, inherit_fd, pipe, reset_job_control_signals,
+    restore_signal_handlers, run_external_command, run_subshell, save_tty_fds, stopped_status,
+    CpuTime, FdAction, JobId, JobManager, Output, Process, ProcessGroup, SavedFds, Stdin,
+    SHELL_FD_MIN,
+};
 
-use crate::ast;
+use crate::{
+    ast,
+    coproc::{Coproc, Coprocs, DEFAULT_COPROC_NAME},
+    PosixError,
+};
 
 pub struct Os {
     job_manager: JobManager,
@@ -12,43 +37,313 @@ pub struct Os {
     last_exit_status: ExitStatus,
 }
 
-pub fn run_job(
+/// Exit statuses of a pipeline that was run
+#[derive(Debug, Clone, Default)]
+pub struct PipelineStatus {
+    /// Status of each stage of the pipeline, in order
+    pub stages: Vec<ExitStatus>,
+    /// If the status of the pipeline should be negated (`! cmd`)
+    pub negate: bool,
+}
+
+impl PipelineStatus {
+    /// Status of a pipeline that has a single stage
+    pub fn single(status: ExitStatus) -> Self {
+        Self {
+            stages: vec![status],
+            negate: false,
+        }
+    }
+
+    /// Exit status of the entire pipeline
+    ///
+    /// This is the status of the last stage, or if `pipefail` is set, the status of the last
+    /// stage to exit with a non-zero status. An empty pipeline is considered successful.
+    pub fn status(&self, pipefail: bool) -> ExitStatus {
+        let status = if pipefail {
+            self.stages.iter().rev().find(|s| !s.success())
+        } else {
+            self.stages.last()
+        };
+        let status = status.copied().unwrap_or(ExitStatus::from_raw(0));
+
+        if self.negate {
+            let negated = if status.success() { 1 } else { 0 };
+            ExitStatus::from_raw(negated << 8)
+        } else {
+            status
+        }
+    }
+
+    /// Exit code of the entire pipeline, see [PipelineStatus::status]
+    pub fn code(&self, pipefail: bool) -> i32 {
+        exit_code(self.status(pipefail))
+    }
+
+    /// Exit codes of each stage of the pipeline
+    pub fn codes(&self) -> Vec<i32> {
+        self.stages.iter().map(|s| exit_code(*s)).collect()
+    }
+}
+
+/// State that is threaded through the evaluation of a command
+pub struct EvalCtx<'a> {
+    pub job_manager: &'a mut JobManager,
+    pub coprocs: &'a mut Coprocs,
+    pub rt: &'a mut Runtime,
+    pub out: &'a mut OutputWriter,
+    /// CPU time used by the processes of the foreground jobs that were waited on
+    pub cpu_time: CpuTime,
+    /// Process group of the subshell the command is evaluated in
+    ///
+    /// Jobs started by a subshell are put in its process group and don't take over the terminal.
+    pub pgid: Option<u32>,
+}
+
+/// Processes spawned by evaluating a command, which together make up a single job
+#[derive(Default)]
+pub struct JobProcs {
+    /// Stages of the pipeline
+    pub procs: Vec<Box<dyn Process>>,
+    /// Processes spawned for process substitution
+    pub substitutions: Vec<Box<dyn Process>>,
+    /// Process group the processes were put in
+    pub pgid: Option<u32>,
+}
+
+/// Hand processes over to the job manager as a new job
+pub fn start_job(
     job_manager: &mut JobManager,
-    procs: Vec<Box<dyn Process>>,
-    pgid: Option<u32>,
+    job: JobProcs,
     foreground: bool,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<JobId> {
     let proc_group = ProcessGroup {
-        id: pgid,
-        processes: procs,
+        id: job.pgid,
+        processes: job.procs,
+        substitutions: job.substitutions,
         foreground,
     };
 
-    let is_foreground = proc_group.foreground;
     let job_id = job_manager.create_job("", proc_group);
-
-    if is_foreground {
-        job_manager.put_job_in_foreground(Some(job_id), false)?;
-    } else {
+    if !foreground {
         job_manager.put_job_in_background(Some(job_id), false)?;
     }
-    Ok(())
+    Ok(job_id)
+}
+
+/// Run processes as a job and wait for it to finish if it is in the foreground
+///
+/// The returned statuses are empty for background jobs.
+pub fn run_job(
+    ctx: &mut EvalCtx,
+    job: JobProcs,
+    foreground: bool,
+) -> anyhow::Result<PipelineStatus> {
+    let job_id = start_job(ctx.job_manager, job, foreground)?;
+    if !foreground {
+        return Ok(PipelineStatus::default());
+    }
+
+    ctx.job_manager.put_job_in_foreground(Some(job_id), false)?;
+    let Some(job) = ctx.job_manager.get_job(job_id) else {
+        return Ok(PipelineStatus::default());
+    };
+    for cpu_time in job.processes().iter().filter_map(|p| p.cpu_time()) {
+        ctx.cpu_time = ctx.cpu_time + cpu_time;
+    }
+    // processes that were stopped haven't exited, but still have a stage in the status
+    let stages = job
+        .processes()
+        .iter()
+        .map(|p| {
+            p.status_code()
+                .unwrap_or_else(|| stopped_status(Signal::SIGTSTP as i32))
+        })
+        .collect();
+
+    Ok(PipelineStatus {
+        stages,
+        negate: false,
+    })
+}
+
+/// Run a command to completion, returning the status of the last pipeline that was run
+///
+/// Lists and logical operators are handled here, since they need to wait on the status of the
+/// previous command before deciding what to run next.
+pub fn run_command(ctx: &mut EvalCtx, cmd: &ast::Command) -> anyhow::Result<PipelineStatus> {
+    let pipefail = ctx.rt.options.pipefail;
+    match cmd {
+        ast::Command::SeqList(a_cmd, b_cmd) => {
+            let status = run_command(ctx, a_cmd)?;
+            match b_cmd {
+                Some(b_cmd) => run_command(ctx, b_cmd),
+                None => Ok(status),
+            }
+        },
+        ast::Command::AsyncList(a_cmd, b_cmd) => {
+            // TODO double check stdin and stdout
+            let job = eval_command(ctx, a_cmd, None, None, ctx.pgid, false)?;
+            run_job(ctx, job, false)?;
+
+            match b_cmd {
+                Some(b_cmd) => run_command(ctx, b_cmd),
+                None => Ok(PipelineStatus::default()),
+            }
+        },
+        ast::Command::And(a_cmd, b_cmd) => {
+            let status = run_command(ctx, a_cmd)?;
+            if status.status(pipefail).success() {
+                run_command(ctx, b_cmd)
+            } else {
+                Ok(status)
+            }
+        },
+        ast::Command::Or(a_cmd, b_cmd) => {
+            let status = run_command(ctx, a_cmd)?;
+            if status.status(pipefail).success() {
+                Ok(status)
+            } else {
+                run_command(ctx, b_cmd)
+            }
+        },
+        ast::Command::Not(cmd) => {
+            let mut status = run_command(ctx, cmd)?;
+            status.negate = !status.negate;
+            Ok(status)
+        },
+        ast::Command::Time(cmd) => {
+            // only the processes waited on while running the pipeline are counted, not background
+            // jobs that happen to finish at the same time
+            let start = Instant::now();
+            let outer_cpu = std::mem::take(&mut ctx.cpu_time);
+            let status = run_command(ctx, cmd);
+            let real = start.elapsed();
+            let cpu = ctx.cpu_time;
+            ctx.cpu_time = outer_cpu + cpu;
+            let status = status?;
+
+            ctx.out.eprintln(format!(
+                "\nreal\t{}\nuser\t{}\nsys\t{}",
+                format_duration(real),
+                format_duration(cpu.user),
+                format_duration(cpu.sys)
+            ))?;
+            Ok(status)
+        },
+        ast::Command::Coproc { name, cmd } => {
+            let name = name.as_deref().unwrap_or(DEFAULT_COPROC_NAME);
+            if ctx.coprocs.is_active(name) {
+                return Err(PosixError::CoprocActive(name.to_string()).into());
+            }
+
+            // the coprocess reads from `input` and writes to `output`
+            let (input_read, input_write) = pipe()?;
+            let (output_read, output_write) = pipe()?;
+            let job = eval_command(
+                ctx,
+                cmd,
+                Some(Stdin::FileDescriptor(input_read.as_raw_fd())),
+                Some(Output::FileDescriptor(output_write.as_raw_fd())),
+                ctx.pgid,
+                false,
+            )?;
+            let pid = job.pgid;
+            let job_id = start_job(ctx.job_manager, job, false)?;
+
+            // the ends used by the coprocess are closed when dropped here, so it sees EOF once the
+            // shell closes its end of the input pipe
+            let coproc = Coproc {
+                name: name.to_string(),
+                job_id,
+                pid,
+                read: dup_fd_above(&output_read, SHELL_FD_MIN)?,
+                write: dup_fd_above(&input_write, SHELL_FD_MIN)?,
+            };
+            ctx.coprocs.insert(coproc, ctx.rt)?;
+
+            Ok(PipelineStatus::default())
+        },
+        ast::Command::Simple {
+            redirects, args, ..
+        } if matches!(args.first(), Some(ast::Arg::Word(w)) if w == "exec") => {
+            exec(ctx, redirects, &args[1..])
+        },
+        ast::Command::None => Ok(PipelineStatus::default()),
+        _ => {
+            let job = eval_command(ctx, cmd, None, None, ctx.pgid, ctx.pgid.is_none())?;
+            run_job(ctx, job, true)
+        },
+    }
 }
 
-/// Returns group of processes and also the pgid if it has one
+/// Spawn the processes of a command, putting them in the process group `pgid`
+///
+/// If `pgid` is [None], a new process group is created with the first spawned process as its
+/// leader.
 pub fn eval_command(
-    job_manager: &mut JobManager,
+    ctx: &mut EvalCtx,
     cmd: &ast::Command,
     stdin: Option<Stdin>,
     stdout: Option<Output>,
-) -> anyhow::Result<(Vec<Box<dyn Process>>, Option<u32>)> {
+    pgid: Option<u32>,
+    foreground: bool,
+) -> anyhow::Result<JobProcs> {
     match cmd {
         ast::Command::Simple {
             assigns: _,
-            redirects: _,
+            redirects,
             args,
         } => {
-            let mut args_it = args.iter();
+            let (fd_actions, redirect_fds) = eval_redirects(ctx.rt, redirects)?;
+            let mut job = JobProcs {
+                pgid,
+                ..Default::default()
+            };
+
+            // spawn substituted processes first, so the pipes connected to them are open by the
+            // time the command itself runs
+            let mut words = vec![];
+            let mut subst_fds = vec![];
+            for arg in args {
+                match arg {
+                    ast::Arg::Word(word) => {
+                        words.push(expand_word(word, &ctx.rt.env, ctx.rt.exit_status))
+                    },
+                    ast::Arg::ProcessSubst { cmd, mode } => {
+                        let (read, write) = pipe()?;
+                        let (subst_stdin, subst_stdout, parent_fd) = match mode {
+                            ast::ProcessSubstMode::Read => {
+                                (None, Some(Output::FileDescriptor(write.as_raw_fd())), read)
+                            },
+                            ast::ProcessSubstMode::Write => {
+                                (Some(Stdin::FileDescriptor(read.as_raw_fd())), None, write)
+                            },
+                        };
+                        let subst = eval_command(
+                            ctx,
+                            cmd,
+                            subst_stdin,
+                            subst_stdout,
+                            job.pgid,
+                            foreground,
+                        )?;
+                        job.pgid = subst.pgid;
+                        job.substitutions.extend(subst.procs);
+                        job.substitutions.extend(subst.substitutions);
+
+                        words.push(String::new());
+                        subst_fds.push((words.len() - 1, parent_fd));
+                    },
+                }
+            }
+            // the pipes are only exposed once all substituted processes have been spawned, so
+            // they don't hold on to each other's pipes
+            for (i, fd) in subst_fds.iter() {
+                words[*i] = inherit_fd(fd)?;
+            }
+
+            let mut args_it = words.iter();
             let program = args_it.next().unwrap();
             let args = args_it.collect::<Vec<_>>();
 
@@ -61,34 +356,472 @@ pub fn eval_command(
                 proc_stdin,
                 proc_stdout,
                 Output::Inherit,
-                None,
-            )?;
-            Ok((vec![proc], pgid))
+                fd_actions,
+                job.pgid,
+                foreground,
+            )
+            .map_err(|e| match e.downcast::<std::io::Error>() {
+                Ok(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    PosixError::CommandNotFound(program.to_string()).into()
+                },
+                Ok(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    PosixError::PermissionDenied(program.to_string()).into()
+                },
+                Ok(e) => e.into(),
+                Err(e) => e,
+            })?;
+            // the shell's copies of the pipes are closed when `subst_fds` is dropped, so that the
+            // substituted processes see EOF once the command exits
+            drop(subst_fds);
+            drop(redirect_fds);
+
+            job.procs.push(proc);
+            job.pgid = pgid;
+            Ok(job)
         },
         ast::Command::Pipeline(a_cmd, b_cmd) => {
-            let (mut a_procs, _a_pgid) =
-                eval_command(job_manager, a_cmd, stdin, Some(Output::CreatePipe))?;
-            let (b_procs, b_pgid) = eval_command(
-                job_manager,
+            let mut a_job = eval_command(
+                ctx,
+                a_cmd,
+                stdin,
+                Some(Output::CreatePipe),
+                pgid,
+                foreground,
+            )?;
+            let b_job = eval_command(
+                ctx,
                 b_cmd,
-                a_procs.last_mut().unwrap().stdout(),
+                a_job.procs.last_mut().unwrap().stdout(),
                 stdout,
+                a_job.pgid,
+                foreground,
             )?;
-            a_procs.extend(b_procs);
-            Ok((a_procs, b_pgid))
+            a_job.procs.extend(b_job.procs);
+            a_job.substitutions.extend(b_job.substitutions);
+            a_job.pgid = b_job.pgid;
+            Ok(a_job)
         },
         ast::Command::AsyncList(a_cmd, b_cmd) => {
             // TODO double check stdin and stdout
-            let (procs, pgid) = eval_command(job_manager, a_cmd, None, None)?;
-            run_job(job_manager, procs, pgid, false)?;
+            let job = eval_command(ctx, a_cmd, None, None, ctx.pgid, false)?;
+            run_job(ctx, job, false)?;
 
             if let Some(b_cmd) = b_cmd {
-                eval_command(job_manager, b_cmd, None, None)
+                eval_command(ctx, b_cmd, None, None, pgid, foreground)
             } else {
-                Ok((vec![], None))
+                Ok(JobProcs::default())
             }
         },
-        ast::Command::None => Ok((vec![], None)),
-        _ => todo!(),
+        // a brace group with a single command, such as `{ cat; }`
+        ast::Command::SeqList(cmd, None) => eval_command(ctx, cmd, stdin, stdout, pgid, foreground),
+        // timing is only reported for pipelines run in the foreground
+        ast::Command::Time(cmd) => eval_command(ctx, cmd, stdin, stdout, pgid, foreground),
+        ast::Command::None => Ok(JobProcs::default()),
+        // lists and subshells need the whole shell to be evaluated, which is done by a copy of it
+        ast::Command::SeqList(..)
+        | ast::Command::And(..)
+        | ast::Command::Or(..)
+        | ast::Command::Not(_)
+        | ast::Command::Subshell(_) => {
+            let body = match cmd {
+                ast::Command::Subshell(body) => body,
+                cmd => cmd,
+            };
+            let (proc, pgid) = run_subshell(
+                || run_in_subshell(ctx, body),
+                stdin.unwrap_or(Stdin::Inherit),
+                stdout.unwrap_or(Output::Inherit),
+                pgid,
+                foreground,
+            )?;
+            Ok(JobProcs {
+                procs: vec![proc],
+                pgid,
+                ..Default::default()
+            })
+        },
+        ast::Command::Coproc { .. } => {
+            Err(PosixError::Unsupported("coprocesses in pipelines".into()).into())
+        },
+        ast::Command::If { .. } => Err(PosixError::Unsupported("if statements".into()).into()),
+        ast::Command::While { .. } | ast::Command::Until { .. } | ast::Command::For { .. } => {
+            Err(PosixError::Unsupported("loops".into()).into())
+        },
+        ast::Command::Case { .. } => Err(PosixError::Unsupported("case statements".into()).into()),
+        ast::Command::Fn { .. } => {
+            Err(PosixError::Unsupported("function definitions".into()).into())
+        },
+    }
+}
+
+/// Evaluate a command in a forked copy of the shell, returning the status to exit with
+fn run_in_subshell(ctx: &mut EvalCtx, cmd: &ast::Command) -> i32 {
+    // the jobs and coprocesses of the shell that forked belong to it, not to the subshell
+    let mut job_manager = JobManager::subshell();
+    let mut coprocs = Coprocs::default();
+    let mut ctx = EvalCtx {
+        job_manager: &mut job_manager,
+        coprocs: &mut coprocs,
+        rt: ctx.rt,
+        out: ctx.out,
+        cpu_time: CpuTime::default(),
+        pgid: Some(unistd::getpgrp().as_raw() as u32),
+    };
+    match run_command(&mut ctx, cmd) {
+        Ok(status) => status.code(ctx.rt.options.pipefail),
+        Err(e) => {
+            let _ = ctx.out.eprintln(&e);
+            e.downcast_ref::<PosixError>()
+                .and_then(PosixError::exit_code)
+                .unwrap_or(1)
+        },
+    }
+}
+
+/// Open the files used in redirections
+///
+/// Returns the actions to apply to the file descriptors of the process, along with the opened
+/// files, which need to be kept open until the process has been spawned.
+pub fn eval_redirects(
+    rt: &Runtime,
+    redirects: &[ast::Redirect],
+) -> anyhow::Result<(Vec<FdAction>, Vec<OwnedFd>)> {
+    let mut actions = vec![];
+    let mut fds = vec![];
+
+    for redirect in redirects {
+        let target = expand_word(&redirect.file, &rt.env, rt.exit_status);
+        let default_fd = match redirect.mode {
+            ast::RedirectMode::Read
+            | ast::RedirectMode::ReadAppend
+            | ast::RedirectMode::ReadDup
+            | ast::RedirectMode::ReadWrite => STDIN_FILENO,
+            _ => STDOUT_FILENO,
+        };
+        let fd = redirect.n.map(|n| n as RawFd).unwrap_or(default_fd);
+        if fd >= SHELL_FD_MIN {
+            return Err(PosixError::BadFileDescriptor(fd).into());
+        }
+
+        let mut options = OpenOptions::new();
+        match redirect.mode {
+            ast::RedirectMode::Read => options.read(true),
+            ast::RedirectMode::Write | ast::RedirectMode::WriteBoth => {
+                options.write(true).create(true).truncate(true)
+            },
+            ast::RedirectMode::WriteAppend | ast::RedirectMode::WriteBothAppend => {
+                options.append(true).create(true)
+            },
+            ast::RedirectMode::ReadWrite => options.read(true).write(true).create(true),
+            ast::RedirectMode::ReadAppend => {
+                return Err(PosixError::Unsupported("here-documents".into()).into());
+            },
+            ast::RedirectMode::ReadDup | ast::RedirectMode::WriteDup => {
+                if target == "-" {
+                    actions.push(FdAction::Close(fd));
+                } else if let Ok(src) = target.parse::<RawFd>() {
+                    actions.push(FdAction::Dup { fd, src });
+                } else {
+                    return Err(PosixError::AmbiguousRedirect(target).into());
+                }
+                continue;
+            },
+        };
+
+        let file = options
+            .open(rt.working_dir.join(&target))
+            .map_err(|e| PosixError::RedirectFile(target, e))?;
+        // move the file out of the way of file descriptors that are redirected to later on
+        let file = dup_fd_above(&file.into(), SHELL_FD_MIN)?;
+        actions.push(FdAction::Dup {
+            fd,
+            src: file.as_raw_fd(),
+        });
+        if matches!(
+            redirect.mode,
+            ast::RedirectMode::WriteBoth | ast::RedirectMode::WriteBothAppend
+        ) {
+            actions.push(FdAction::Dup {
+                fd: STDERR_FILENO,
+                src: fd,
+            });
+        }
+        fds.push(file);
+    }
+
+    Ok((actions, fds))
+}
+
+/// Run `exec`, which replaces the shell with a command
+///
+/// Without a command, the redirections are applied to the shell itself, so they stay in effect
+/// for the shell and all following commands, for example `exec 3>log` keeps `log` open as fd 3.
+fn exec(
+    ctx: &mut EvalCtx,
+    redirects: &[ast::Redirect],
+    args: &[ast::Arg],
+) -> anyhow::Result<PipelineStatus> {
+    let (redirect_actions, _redirect_fds) = eval_redirects(ctx.rt, redirects)?;
+
+    if args.is_empty() {
+        // the line editor and job control keep using the terminal
+        save_tty_fds()?;
+        for action in redirect_actions {
+            action.apply()?;
+        }
+        return Ok(PipelineStatus::default());
+    }
+
+    let mut argv = vec![];
+    for arg in args {
+        match arg {
+            ast::Arg::Word(word) => argv.push(CString::new(expand_word(
+                word,
+                &ctx.rt.env,
+                ctx.rt.exit_status,
+            ))?),
+            ast::Arg::ProcessSubst { .. } => {
+                return Err(PosixError::Unsupported("process substitutions in exec".into()).into());
+            },
+        }
+    }
+
+    // the shell keeps running if the program can't be executed, so everything changed for it
+    // is put back afterwards
+    let saved_fds = SavedFds::save(redirect_actions.iter().map(FdAction::fd));
+    // the signals ignored by the shell would otherwise stay ignored in the new program
+    let handlers = reset_job_control_signals()?;
+    // only returns if the program could not be executed
+    let result = redirect_actions
+        .iter()
+        .try_for_each(FdAction::apply)
+        .and_then(|_| unistd::execvp(&argv[0], &argv));
+    restore_signal_handlers(handlers)?;
+    saved_fds.restore();
+
+    let err = result.unwrap_err();
+    let program = argv[0].to_string_lossy().into_owned();
+    Err(match err {
+        Errno::ENOENT => PosixError::CommandNotFound(program).into(),
+        Errno::EACCES => PosixError::PermissionDenied(program).into(),
+        e => e.into(),
+    })
+}
+
+/// Expand parameters in a word
+///
+/// Supports `$NAME`, `${NAME}`, `${NAME[N]}` and `$?`. Words in single quotes are left as is.
+/// Variables that are not set expand to nothing.
+pub fn expand_word(word: &str, env: &Env, exit_status: i32) -> String {
+    if word.starts_with('\'') {
+        return word.to_string();
+    }
+
+    let lookup = |name: &str| -> String {
+        env.get(name)
+            // like in bash, referring to an array without an index gives its first element
+            .or_else(|_| env.get(&format!("{name}[0]")))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let mut expanded = String::new();
+    let mut chars = word.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            expanded.push(ch);
+            continue;
+        }
+        match chars.peek() {
+            Some('?') => {
+                chars.next();
+                expanded.push_str(&exit_status.to_string());
+            },
+            Some('{') => {
+                chars.next();
+                let name = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                expanded.push_str(&lookup(&name));
+            },
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                expanded.push_str(&lookup(&name));
+            },
+            _ => expanded.push('$'),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Read,
+        os::{fd::FromRawFd, unix::process::ExitStatusExt},
+        path::PathBuf,
+        process::ExitStatus,
+    };
+
+    use nix::{
+        errno::Errno,
+        fcntl::{fcntl, FcntlArg},
+        sys::signal::{self, SigHandler, Signal},
+        unistd,
+    };
+    use shrs_core::{env::Env, options::ShellOptions, output_writer::OutputWriter, shell::Runtime};
+    use shrs_job::{CpuTime, JobManager};
+
+    use super::{expand_word, run_command, EvalCtx, PipelineStatus};
+    use crate::{coproc::Coprocs, Lexer, Parser, PosixError};
+
+    /// Shell that runs commands without job control, like a subshell, so no terminal is needed
+    struct TestShell {
+        job_manager: JobManager,
+        coprocs: Coprocs,
+        rt: Runtime,
+        out: OutputWriter,
+    }
+
+    impl TestShell {
+        fn new(name: &str) -> Self {
+            let working_dir =
+                std::env::temp_dir().join(format!("shrs-eval-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&working_dir);
+            fs::create_dir_all(&working_dir).unwrap();
+            Self {
+                job_manager: JobManager::subshell(),
+                coprocs: Coprocs::default(),
+                rt: Runtime {
+                    working_dir,
+                    env: Env::new(),
+                    name: String::from("shrs"),
+                    args: vec![],
+                    exit_status: 0,
+                    pipe_status: vec![],
+                    options: ShellOptions::default(),
+                },
+                out: OutputWriter::default(),
+            }
+        }
+
+        fn run(&mut self, line: &str) -> anyhow::Result<PipelineStatus> {
+            let cmd = Parser::new().parse(Lexer::new(line)).unwrap();
+            let mut ctx = EvalCtx {
+                job_manager: &mut self.job_manager,
+                coprocs: &mut self.coprocs,
+                rt: &mut self.rt,
+                out: &mut self.out,
+                cpu_time: CpuTime::default(),
+                pgid: Some(unistd::getpgrp().as_raw() as u32),
+            };
+            let status = run_command(&mut ctx, &cmd)?;
+            // like the shell, expose the status of the pipeline to the next command
+            self.rt.set_pipe_status(status.codes());
+            Ok(status)
+        }
+
+        fn path(&self, file: &str) -> PathBuf {
+            self.rt.working_dir.join(file)
+        }
+    }
+
+    fn status(code: i32) -> ExitStatus {
+        ExitStatus::from_raw(code << 8)
+    }
+
+    #[test]
+    fn pipeline_status() {
+        let pipeline = PipelineStatus {
+            stages: vec![status(0), status(3), status(0)],
+            negate: false,
+        };
+        assert_eq!(pipeline.code(false), 0);
+        assert_eq!(pipeline.code(true), 3);
+        assert_eq!(pipeline.codes(), vec![0, 3, 0]);
+
+        let negated = PipelineStatus {
+            negate: true,
+            ..pipeline
+        };
+        assert_eq!(negated.code(false), 1);
+        assert_eq!(negated.code(true), 0);
+
+        assert_eq!(PipelineStatus::default().code(false), 0);
+    }
+
+    #[test]
+    fn expand() {
+        let env = Env::from_iter([("SHRS_TEST_VAR", "hello"), ("SHRS_TEST_FD[1]", "61")]);
+        assert_eq!(expand_word("$SHRS_TEST_VAR/x", &env, 0), "hello/x");
+        assert_eq!(expand_word("${SHRS_TEST_VAR}x", &env, 0), "hellox");
+        assert_eq!(expand_word("${SHRS_TEST_FD[1]}", &env, 0), "61");
+        assert_eq!(expand_word("$?", &env, 127), "127");
+        assert_eq!(expand_word("$SHRS_TEST_UNSET.", &env, 0), ".");
+        assert_eq!(expand_word("'$SHRS_TEST_VAR'", &env, 0), "'$SHRS_TEST_VAR'");
+        assert_eq!(expand_word("a$", &env, 0), "a$");
+    }
+
+    #[test]
+    fn process_substitution_lists() {
+        let mut sh = TestShell::new("subst");
+        let status = sh
+            .run("cat <(echo a; echo b) <(false || echo c) <(true && echo d) > out")
+            .unwrap();
+        assert_eq!(status.code(false), 0);
+        assert_eq!(fs::read_to_string(sh.path("out")).unwrap(), "a\nb\nc\nd\n");
+
+        sh.run("(echo e; false) | cat > piped").unwrap();
+        assert_eq!(fs::read_to_string(sh.path("piped")).unwrap(), "e\n");
+
+        // compound commands that the shell can't run yet are reported instead of panicking
+        assert!(sh.run("cat <(if true; then echo a; fi)").is_err());
+    }
+
+    #[test]
+    fn coproc_list() {
+        let mut sh = TestShell::new("coproc");
+        sh.run("coproc { echo a; echo b && echo c; }").unwrap();
+
+        let fd = sh.rt.env.get("COPROC[0]").unwrap().parse().unwrap();
+        // the shell keeps its end of the pipe open, so read from a copy of it
+        let fd = unistd::dup(fd).unwrap();
+        let mut output = String::new();
+        unsafe { File::from_raw_fd(fd) }
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "a\nb\nc\n");
+    }
+
+    #[test]
+    fn failed_exec() {
+        let mut sh = TestShell::new("exec");
+        unsafe { signal::signal(Signal::SIGTTOU, SigHandler::SigIgn) }.unwrap();
+
+        let err = sh.run("exec shrs-no-such-program 200>out").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PosixError>(),
+            Some(PosixError::CommandNotFound(_))
+        ));
+
+        // the shell keeps its file descriptors and signal handlers
+        assert_eq!(fcntl(200, FcntlArg::F_GETFD), Err(Errno::EBADF));
+        let handler = unsafe { signal::signal(Signal::SIGTTOU, SigHandler::SigIgn) }.unwrap();
+        assert_eq!(handler, SigHandler::SigIgn);
+    }
+
+    #[test]
+    fn pipe_status_variable() {
+        let mut sh = TestShell::new("pipestatus");
+        sh.run("false | true").unwrap();
+        sh.run("echo ${PIPESTATUS[0]} ${PIPESTATUS[1]} $PIPESTATUS > out")
+            .unwrap();
+        assert_eq!(fs::read_to_string(sh.path("out")).unwrap(), "1 0 1\n");
+
+        // the echo above was a single command
+        assert_eq!(sh.rt.env.get("PIPESTATUS[0]").unwrap(), "0");
+        assert!(sh.rt.env.get("PIPESTATUS[1]").is_err());
     }
 }
@@ -1,15 +1,20 @@
-use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+use std::{
+    cell::RefCell,
+    os::{fd::RawFd, unix::process::ExitStatusExt},
+};
 
+use nix::libc;
 use shrs_core::{
     lang::Lang,
     prelude::CmdOutput,
     shell::{Context, Runtime, Shell},
 };
-use shrs_job::{initialize_job_control, Output};
+use shrs_job::{initialize_job_control, signal_message, CpuTime};
 use thiserror::Error;
 
 use crate::{
-    eval2::{self, run_job},
+    coproc::Coprocs,
+    eval2::{self, EvalCtx},
     parser, Lexer, Parser, Token,
 };
 
@@ -27,15 +32,57 @@ pub enum PosixError {
     /// Issue evaluating command
     #[error("Failed evaluating command: {0}")]
     Eval(anyhow::Error),
+    /// Program to run does not exist
+    #[error("{0}: command not found")]
+    CommandNotFound(String),
+    /// Program to run is not executable
+    #[error("{0}: permission denied")]
+    PermissionDenied(String),
+    /// File used in redirection could not be opened
+    #[error("{0}: {1}")]
+    RedirectFile(String, std::io::Error),
+    /// Target of a redirection is not valid, such as `>&foo`
+    #[error("{0}: ambiguous redirect")]
+    AmbiguousRedirect(String),
+    /// Shell feature that is not implemented yet
+    #[error("{0} are not supported")]
+    Unsupported(String),
+    /// File descriptor in a redirection is reserved by the shell
+    #[error("{0}: bad file descriptor")]
+    BadFileDescriptor(RawFd),
+    /// Coprocess of the same name is still running
+    #[error("coproc {0}: still active")]
+    CoprocActive(String),
+}
+
+impl PosixError {
+    /// Exit status of a command that failed with this error, following the conventional exit
+    /// codes for programs that could not be run
+    ///
+    /// Returns [None] for errors that are not caused by the command itself.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            PosixError::CommandNotFound(_) => Some(127),
+            PosixError::PermissionDenied(_) => Some(126),
+            PosixError::RedirectFile(..)
+            | PosixError::AmbiguousRedirect(_)
+            | PosixError::Unsupported(_) => Some(1),
+            _ => None,
+        }
+    }
 }
 
 /// Posix implementation of shell command language
-pub struct PosixLang {}
+pub struct PosixLang {
+    coprocs: RefCell<Coprocs>,
+}
 
 impl PosixLang {
     pub fn new() -> Self {
         initialize_job_control().unwrap();
-        Self {}
+        Self {
+            coprocs: RefCell::new(Coprocs::default()),
+        }
     }
 }
 
@@ -60,11 +107,48 @@ impl Lang for PosixLang {
         };
 
         let mut job_manager = sh.job_manager.borrow_mut();
-        let (procs, pgid) = eval2::eval_command(&mut job_manager, &cmd, None, None)?;
+        let mut coprocs = self.coprocs.borrow_mut();
+        coprocs.reap(&mut job_manager, rt)?;
 
-        run_job(&mut job_manager, procs, pgid, true)?;
+        let pipefail = rt.options.pipefail;
+        let mut eval_ctx = EvalCtx {
+            job_manager: &mut job_manager,
+            coprocs: &mut coprocs,
+            rt,
+            out: &mut ctx.out,
+            cpu_time: CpuTime::default(),
+            pgid: None,
+        };
+        let status = match eval2::run_command(&mut eval_ctx, &cmd) {
+            Ok(status) => status,
+            Err(e) => {
+                // follow the conventional exit codes for programs that could not be run
+                let Some(code) = e
+                    .downcast_ref::<PosixError>()
+                    .and_then(PosixError::exit_code)
+                else {
+                    return Err(e);
+                };
+                ctx.out.eprintln(e)?;
+                rt.set_pipe_status(vec![code]);
+                return Ok(CmdOutput::new(code));
+            },
+        };
+
+        // report processes that were killed by a signal, except for the ones caused by the user
+        // or by a closed pipe, which are expected
+        if let Some(last) = status.stages.last() {
+            if let Some(sig) = last.signal() {
+                if sig != libc::SIGINT && sig != libc::SIGPIPE {
+                    if let Some(msg) = signal_message(*last) {
+                        ctx.out.eprintln(msg)?;
+                    }
+                }
+            }
+        }
 
-        Ok(CmdOutput::success())
+        rt.set_pipe_status(status.codes());
+        Ok(CmdOutput::from_status(status.status(pipefail)))
     }
 
     fn name(&self) -> String {
@@ -79,13 +163,57 @@ impl Lang for PosixLang {
             }
         };
 
+        // open brackets as well as compound commands that haven't been closed yet
         let mut brackets: Vec<Token> = vec![];
+        // keywords only start or end a compound command in command position
+        let mut is_cmd = true;
 
         let lexer = Lexer::new(command.as_str());
 
         for t in lexer {
             if let Ok(token) = t {
+                let was_cmd = is_cmd;
+                is_cmd = matches!(
+                    token.1,
+                    Token::NEWLINE
+                        | Token::SEMI
+                        | Token::DSEMI
+                        | Token::AMP
+                        | Token::AND_IF
+                        | Token::OR_IF
+                        | Token::PIPE
+                        | Token::LPAREN
+                        | Token::RPAREN
+                        | Token::LBRACE
+                        | Token::IF
+                        | Token::THEN
+                        | Token::ELSE
+                        | Token::ELIF
+                        | Token::WHILE
+                        | Token::UNTIL
+                        | Token::DO
+                );
+
                 match token.1 {
+                    Token::IF | Token::FOR | Token::WHILE | Token::UNTIL | Token::CASE
+                        if was_cmd =>
+                    {
+                        brackets.push(token.1)
+                    },
+                    Token::FI | Token::DONE | Token::ESAC if was_cmd => {
+                        let opened = match token.1 {
+                            Token::FI => brackets.last() == Some(&Token::IF),
+                            Token::ESAC => brackets.last() == Some(&Token::CASE),
+                            _ => matches!(
+                                brackets.last(),
+                                Some(Token::FOR | Token::WHILE | Token::UNTIL)
+                            ),
+                        };
+                        if !opened {
+                            return false;
+                        }
+                        brackets.pop();
+                    },
                     Token::LBRACE => brackets.push(token.1),
                     Token::LPAREN => brackets.push(token.1),
                     Token::RPAREN => {
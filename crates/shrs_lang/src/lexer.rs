@@ -9,8 +9,8 @@ use thiserror::Error;
 
 lazy_static! {
     pub static ref RESERVED_WORDS: Vec<&'static str> = vec![
-        "!", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "if", "in",
-        "then", "until", "while"
+        "!", "{", "}", "case", "coproc", "do", "done", "elif", "else", "esac", "fi", "for", "if",
+        "in", "then", "time", "until", "while"
     ];
 }
 
@@ -49,6 +49,16 @@ pub enum Token<'input> {
     DLESSDASH,
     CLOBBER,
 
+    /// Redirect both stdout and stderr, `&>`
+    ANDGREAT,
+    /// Append both stdout and stderr, `&>>`
+    ANDDGREAT,
+
+    /// Start of input process substitution, `<(`
+    LESSPAREN,
+    /// Start of output process substitution, `>(`
+    GREATPAREN,
+
     IF,
     THEN,
     ELSE,
@@ -64,6 +74,9 @@ pub enum Token<'input> {
     FOR,
     IN,
 
+    TIME,
+    COPROC,
+
     WORD(&'input str),
     ASSIGNMENT_WORD(&'input str),
     FNAME(&'input str),
@@ -118,7 +131,27 @@ impl<'input> Lexer<'input> {
         start: usize,
         end: usize,
     ) -> Result<(usize, Token<'input>, usize), Error> {
-        let (word, end) = self.take_until(start, end, |ch| !is_word_continue(ch));
+        // braces that are part of a parameter expansion such as `${NAME}` belong to the word
+        let mut prev = self.input[start..end].chars().last();
+        let mut in_param = false;
+        let (word, end) = self.take_until(start, end, |ch| {
+            let param_start = ch == '{' && prev == Some('$');
+            let terminate = !(in_param || param_start || is_word_continue(ch));
+            if param_start {
+                in_param = true;
+            } else if ch == '}' {
+                in_param = false;
+            }
+            prev = Some(ch);
+            terminate
+        });
+        // a number directly followed by a redirection operator is the fd being redirected
+        if word.chars().all(|c| c.is_ascii_digit())
+            && matches!(self.lookahead, Some((_, '<' | '>', _)))
+        {
+            return Ok((start, Token::IO_NUMBER(word), end));
+        }
+
         let token = match word {
             "if" => Token::IF,
             "then" => Token::THEN,
@@ -133,6 +166,8 @@ impl<'input> Lexer<'input> {
             "until" => Token::UNTIL,
             "for" => Token::FOR,
             "in" => Token::IN,
+            "time" => Token::TIME,
+            "coproc" => Token::COPROC,
             word => Token::WORD(word),
         };
         Ok((start, token, end))
@@ -219,6 +254,16 @@ impl<'input> Iterator for Lexer<'input> {
                         self.advance();
                         Some(Ok((start, Token::AND_IF, new_end)))
                     },
+                    Some((_, '>', new_end)) => {
+                        self.advance();
+                        match self.lookahead {
+                            Some((_, '>', new_end)) => {
+                                self.advance();
+                                Some(Ok((start, Token::ANDDGREAT, new_end)))
+                            },
+                            _ => Some(Ok((start, Token::ANDGREAT, new_end))),
+                        }
+                    },
                     _ => Some(Ok((start, Token::AMP, end))),
                 },
                 '|' => match self.lookahead {
@@ -245,6 +290,10 @@ impl<'input> Iterator for Lexer<'input> {
                         self.advance();
                         Some(Ok((start, Token::LESSGREAT, new_end)))
                     },
+                    Some((_, '(', new_end)) => {
+                        self.advance();
+                        Some(Ok((start, Token::LESSPAREN, new_end)))
+                    },
                     _ => Some(Ok((start, Token::LESS, end))),
                 },
                 '>' => match self.lookahead {
@@ -260,6 +309,10 @@ impl<'input> Iterator for Lexer<'input> {
                         self.advance();
                         Some(Ok((start, Token::CLOBBER, new_end)))
                     },
+                    Some((_, '(', new_end)) => {
+                        self.advance();
+                        Some(Ok((start, Token::GREATPAREN, new_end)))
+                    },
                     _ => Some(Ok((start, Token::GREAT, end))),
                 },
 
@@ -314,5 +367,66 @@ mod tests {
     fn keywords() {
         let mut lexer = Lexer::new("case");
         assert_eq!(lexer.next(), Some(Ok((0, Token::CASE, 4))));
+
+        let mut lexer = Lexer::new("time ls");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::TIME, 4))));
+        assert_eq!(lexer.next(), Some(Ok((5, Token::WORD("ls"), 7))));
+    }
+
+    #[test]
+    fn parameter_expansion() {
+        let tokens = Lexer::new("echo ${COPROC[1]} $HOME}")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("echo"),
+                Token::WORD("${COPROC[1]}"),
+                Token::WORD("$HOME"),
+                Token::RBRACE,
+            ]
+        );
+    }
+
+    #[test]
+    fn redirections() {
+        let tokens = Lexer::new("cmd 2>&1 3>file 10 &>> log")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("cmd"),
+                Token::IO_NUMBER("2"),
+                Token::GREATAND,
+                Token::WORD("1"),
+                Token::IO_NUMBER("3"),
+                Token::GREAT,
+                Token::WORD("file"),
+                Token::WORD("10"),
+                Token::ANDDGREAT,
+                Token::WORD("log"),
+            ]
+        );
+    }
+
+    #[test]
+    fn process_substitution() {
+        let tokens = Lexer::new("diff <(ls) >(cat)")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("diff"),
+                Token::LESSPAREN,
+                Token::WORD("ls"),
+                Token::RPAREN,
+                Token::GREATPAREN,
+                Token::WORD("cat"),
+                Token::RPAREN,
+            ]
+        );
     }
 }
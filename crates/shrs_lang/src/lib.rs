@@ -11,10 +11,12 @@ mod parser;
 pub use parser::{Error, Parser};
 
 mod lexer;
-pub use lexer::{Lexer, Token, RESERVED_WORDS};
+pub use lexer::{Error as LexerError, Lexer, Token, RESERVED_WORDS};
 
 pub mod ast;
 
+pub mod coproc;
+
 pub mod eval2;
 
 // pub mod process;
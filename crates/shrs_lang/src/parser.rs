@@ -25,22 +25,68 @@ impl Parser {
     }
 }
 
-/*
 #[cfg(test)]
 mod tests {
+    use super::Parser;
+    use crate::{ast, lexer::Lexer};
 
-    use super::grammar;
+    fn parse(input: &str) -> ast::Command {
+        Parser::new().parse(Lexer::new(input)).unwrap()
+    }
+
+    /// Words of a simple command
+    fn words(cmd: &ast::Command) -> Vec<String> {
+        let ast::Command::Simple { args, .. } = cmd else {
+            panic!("not a simple command: {cmd:?}");
+        };
+        args.iter()
+            .map(|arg| match arg {
+                ast::Arg::Word(word) => word.clone(),
+                _ => panic!("not a word: {arg:?}"),
+            })
+            .collect()
+    }
 
     #[test]
-    fn parse() {
-        let res = grammar::ProgramParser::new().parse("ls home | grep downloads");
-        println!("{:?}", res);
+    fn pipelines() {
+        assert!(matches!(
+            parse("ls home | grep downloads"),
+            ast::Command::Pipeline(..)
+        ));
+        assert!(matches!(
+            parse("ls home || grep downloads"),
+            ast::Command::Or(..)
+        ));
     }
 
     #[test]
-    fn and_or() {
-        let res = grammar::ProgramParser::new().parse("ls home || grep downloads");
-        println!("{:?}", res);
+    fn time() {
+        let ast::Command::Time(cmd) = parse("time ls -l") else {
+            panic!("time was not parsed as a reserved word");
+        };
+        assert_eq!(words(&cmd), ["ls", "-l"]);
+
+        assert_eq!(words(&parse("echo time")), ["echo", "time"]);
+        assert_eq!(words(&parse("which time > out")), ["which", "time"]);
+    }
+
+    #[test]
+    fn coproc() {
+        let ast::Command::Coproc { name, cmd } = parse("coproc cat -u") else {
+            panic!("coproc was not parsed as a reserved word");
+        };
+        assert_eq!(name, None);
+        assert_eq!(words(&cmd), ["cat", "-u"]);
+
+        let ast::Command::Coproc { name, .. } = parse("coproc { cat; }") else {
+            panic!("coproc with a brace group was not parsed");
+        };
+        assert_eq!(name, None);
+
+        assert_eq!(words(&parse("man coproc")), ["man", "coproc"]);
+        assert_eq!(
+            words(&parse("echo coproc time")),
+            ["echo", "coproc", "time"]
+        );
     }
 }
-*/
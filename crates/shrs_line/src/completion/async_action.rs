@@ -0,0 +1,379 @@
+//! Completion actions that run in the background
+//!
+//! Slow actions, such as listing the branches of a large repository or querying a remote
+//! service, would freeze the line while they run. An [AsyncAction] runs them on a separate thread
+//! and only waits briefly for the result. If it takes longer the menu is shown with a loading
+//! entry, and is updated as results arrive. Results are cached so that completing the same word
+//! again is instant.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{Action, Completion, CompletionCtx};
+
+/// Actions still running in the background, by key and generation
+static LOADING: Mutex<Vec<(Key, usize)>> = Mutex::new(Vec::new());
+/// Incremented whenever an action running in the background produces results or finishes
+static UPDATES: AtomicUsize = AtomicUsize::new(0);
+/// Source of the generation of each run of an action
+static GENERATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Check if an action for the completion described by `ctx` is still running in the background
+pub fn completions_loading(ctx: &CompletionCtx) -> bool {
+    let key = key(ctx);
+    LOADING.lock().unwrap().iter().any(|(k, _)| *k == key)
+}
+
+/// Counter that changes whenever background actions have new results
+///
+/// The completions should be computed again when this changes, which will pick up the new
+/// results from the cache.
+pub fn completion_updates() -> usize {
+    UPDATES.load(Ordering::SeqCst)
+}
+
+/// Used by streaming actions to send completions as they are found
+pub struct CompletionSender {
+    cache: Cache,
+    key: Key,
+    generation: usize,
+}
+
+impl CompletionSender {
+    /// Add completions to the results, returns false if they are no longer wanted
+    pub fn send(&self, mut completions: Vec<Completion>) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get_mut(&self.key) {
+            Some(entry) if entry.is_running(self.generation) => {
+                entry.completions.append(&mut completions);
+                UPDATES.fetch_add(1, Ordering::SeqCst);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Check if the action timed out or was replaced, in which case it should stop
+    pub fn is_cancelled(&self) -> bool {
+        let cache = self.cache.lock().unwrap();
+        !cache
+            .get(&self.key)
+            .is_some_and(|entry| entry.is_running(self.generation))
+    }
+
+    /// Mark the run as finished, unless it was already cancelled
+    fn finish(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(entry) = cache.get_mut(&self.key) {
+            if entry.is_running(self.generation) {
+                entry.stop(&self.key);
+            }
+        }
+    }
+}
+
+type StreamingFn = dyn Fn(&CompletionCtx, &CompletionSender) + Send + Sync;
+
+/// Command name, arguments before the word being completed, the word and working directory
+type Key = (String, Vec<String>, String, PathBuf);
+
+type Cache = Arc<Mutex<HashMap<Key, Entry>>>;
+
+fn key(ctx: &CompletionCtx) -> Key {
+    (
+        ctx.cmd_name().cloned().unwrap_or_default(),
+        ctx.words()
+            .get(1..ctx.arg_num())
+            .unwrap_or_default()
+            .to_vec(),
+        ctx.cur_word().cloned().unwrap_or_default(),
+        ctx.working_dir().clone(),
+    )
+}
+
+/// Cached results of the action for a [Key]
+struct Entry {
+    completions: Vec<Completion>,
+    loading: bool,
+    /// Run of the action the results come from, results of older runs are dropped
+    generation: usize,
+    created: Instant,
+}
+
+impl Entry {
+    fn is_running(&self, generation: usize) -> bool {
+        self.loading && self.generation == generation
+    }
+
+    /// Stop accepting results, the ones found so far are kept until they expire
+    fn stop(&mut self, key: &Key) {
+        self.loading = false;
+        let mut loading = LOADING.lock().unwrap();
+        if let Some(i) = loading
+            .iter()
+            .position(|(k, generation)| k == key && *generation == self.generation)
+        {
+            loading.swap_remove(i);
+        }
+        UPDATES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Action that runs on a background thread and caches its results
+///
+/// Results are cached per command line up to the word being completed, in each working
+/// directory.
+#[derive(Clone)]
+pub struct AsyncAction {
+    action: Arc<StreamingFn>,
+    cache: Cache,
+    /// Threads running the action, joined once they finish
+    threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// How long to wait for the results before showing the menu
+    wait: Duration,
+    /// How long to wait for the results in the background before giving up
+    timeout: Duration,
+    /// How long results are cached for
+    ttl: Duration,
+}
+
+impl AsyncAction {
+    /// Run an action that returns all of its completions at once
+    pub fn new(action: impl Fn(&CompletionCtx) -> Vec<Completion> + Send + Sync + 'static) -> Self {
+        Self::streaming(move |ctx, sender| {
+            sender.send(action(ctx));
+        })
+    }
+
+    /// Run an action that sends completions as it finds them
+    pub fn streaming(
+        action: impl Fn(&CompletionCtx, &CompletionSender) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            action: Arc::new(action),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            threads: Arc::new(Mutex::new(vec![])),
+            wait: Duration::from_millis(100),
+            timeout: Duration::from_secs(5),
+            ttl: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Results sent after the timeout are dropped, the action can check
+    /// [CompletionSender::is_cancelled] to stop early
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Convert into an action that can be used in a [Rule](super::Rule)
+    pub fn action(self) -> Action {
+        Box::new(move |ctx: &CompletionCtx| self.complete(ctx))
+    }
+
+    /// Completions found so far, starting the action if there are none cached
+    pub fn complete(&self, ctx: &CompletionCtx) -> Vec<Completion> {
+        let key = key(ctx);
+        self.join_finished();
+
+        let done = {
+            let mut cache = self.cache.lock().unwrap();
+            for (key, entry) in cache.iter_mut() {
+                if entry.loading && entry.created.elapsed() >= self.timeout {
+                    entry.stop(key);
+                }
+            }
+            match cache.get(&key) {
+                Some(entry) if entry.loading || entry.created.elapsed() < self.ttl => {
+                    return entry.completions.clone();
+                },
+                _ => {},
+            }
+            cache.retain(|_, entry| entry.loading || entry.created.elapsed() < self.ttl);
+
+            let generation = GENERATIONS.fetch_add(1, Ordering::SeqCst);
+            cache.insert(
+                key.clone(),
+                Entry {
+                    completions: vec![],
+                    loading: true,
+                    generation,
+                    created: Instant::now(),
+                },
+            );
+            self.spawn(ctx.clone(), key.clone(), generation)
+        };
+
+        // give fast actions the chance to finish before the menu is shown
+        let _ = done.recv_timeout(self.wait);
+        self.cache.lock().unwrap()[&key].completions.clone()
+    }
+
+    /// Run the action on a new thread, the returned channel is closed once it is done
+    fn spawn(&self, ctx: CompletionCtx, key: Key, generation: usize) -> Receiver<()> {
+        LOADING.lock().unwrap().push((key.clone(), generation));
+        let sender = CompletionSender {
+            cache: self.cache.clone(),
+            key,
+            generation,
+        };
+        let action = self.action.clone();
+        let (done_sender, done) = channel::<()>();
+        let thread = thread::spawn(move || {
+            action(&ctx, &sender);
+            sender.finish();
+            drop(done_sender);
+        });
+        self.threads.lock().unwrap().push(thread);
+        done
+    }
+
+    /// Join the threads of actions that finished
+    fn join_finished(&self) {
+        let mut threads = self.threads.lock().unwrap();
+        let (finished, running): (Vec<_>, Vec<_>) =
+            threads.drain(..).partition(|thread| thread.is_finished());
+        *threads = running;
+        drop(threads);
+        for thread in finished {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::channel,
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use super::{completions_loading, AsyncAction};
+    use crate::completion::{default_format, CompletionCtx};
+
+    /// Long enough for any action in the tests to finish, `complete` returns as soon as it does
+    const FOREVER: Duration = Duration::from_secs(60);
+
+    fn completions(action: &AsyncAction, ctx: &CompletionCtx) -> Vec<String> {
+        action
+            .complete(ctx)
+            .into_iter()
+            .map(|c| c.completion)
+            .collect()
+    }
+
+    impl AsyncAction {
+        fn join_all(&self) {
+            for thread in self.threads.lock().unwrap().drain(..) {
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn cached_and_streamed() {
+        let ctx = CompletionCtx::new(vec!["git".into(), "checkout".into(), "".into()]);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let fast = AsyncAction::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            default_format(vec!["main".into()])
+        })
+        .with_wait(FOREVER);
+        assert_eq!(completions(&fast, &ctx), vec!["main"]);
+        assert_eq!(completions(&fast, &ctx), vec!["main"]);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // the action sends its first result, then waits for the test to let it continue
+        let (sent, first_sent) = channel();
+        let (resume, resumed) = channel::<()>();
+        let resumed = Mutex::new(resumed);
+        let slow = AsyncAction::streaming(move |_, sender| {
+            sender.send(default_format(vec!["first".into()]));
+            sent.send(()).unwrap();
+            resumed.lock().unwrap().recv().unwrap();
+            sender.send(default_format(vec!["second".into()]));
+        })
+        .with_wait(Duration::ZERO);
+        completions(&slow, &ctx);
+        first_sent.recv().unwrap();
+        assert_eq!(completions(&slow, &ctx), vec!["first"]);
+        // only the word being loaded is reported as loading
+        let other = CompletionCtx::new(vec!["git".into(), "checkout".into(), "m".into()]);
+        assert!(completions_loading(&ctx));
+        assert!(!completions_loading(&other));
+
+        resume.send(()).unwrap();
+        slow.join_all();
+        assert!(!completions_loading(&ctx));
+        assert_eq!(completions(&slow, &ctx), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn timed_out_results_dropped() {
+        let ctx = CompletionCtx::new(vec!["ssh".into(), "host".into()]);
+
+        let (sent, partial_sent) = channel();
+        let (resume, resumed) = channel::<()>();
+        let resumed = Mutex::new(resumed);
+        let stuck = AsyncAction::streaming(move |_, sender| {
+            sender.send(default_format(vec!["partial".into()]));
+            sent.send(()).unwrap();
+            resumed.lock().unwrap().recv().unwrap();
+            let cancelled = sender.is_cancelled();
+            let accepted = sender.send(default_format(vec!["late".into()]));
+            sent.send(()).unwrap();
+            assert!(cancelled && !accepted);
+        })
+        .with_wait(Duration::ZERO)
+        .with_timeout(Duration::ZERO);
+        completions(&stuck, &ctx);
+        partial_sent.recv().unwrap();
+
+        // the timeout is noticed the next time completions are requested, the partial results
+        // are kept until they expire
+        assert_eq!(completions(&stuck, &ctx), vec!["partial"]);
+        assert!(!completions_loading(&ctx));
+
+        resume.send(()).unwrap();
+        partial_sent.recv().unwrap();
+        stuck.join_all();
+        assert_eq!(completions(&stuck, &ctx), vec!["partial"]);
+    }
+
+    #[test]
+    fn expired_entries_pruned() {
+        let action = AsyncAction::new(|ctx| default_format(vec![ctx.cur_word().unwrap().clone()]))
+            .with_wait(FOREVER)
+            .with_ttl(Duration::ZERO);
+        for word in ["a", "b", "c"] {
+            let ctx = CompletionCtx::new(vec!["cmd".into(), word.into()]);
+            assert_eq!(completions(&action, &ctx), vec![word]);
+        }
+        assert_eq!(action.cache.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,500 @@
+//! Completions from a description of a command line interface
+//!
+//! A [CliCommand] describes the flags, positional arguments and subcommands of a program. It can
+//! be written by hand, generated with `#[derive(Completion)]` from `shrs_derive_completion` or
+//! converted from a [clap::Command].
+
+use clap::ArgAction;
+
+use super::{
+    arg_pred, cmdname_eq_pred, default_format, filename_action, find_executables_in_path, home_dir,
+    ssh_hosts, unquote, with_kind, Completion, CompletionCtx, CompletionKind, Pred, ReplaceMethod,
+    Rule,
+};
+
+/// What kind of value a flag or positional argument takes
+#[derive(Clone, Debug, Default)]
+pub enum ValueHint {
+    /// Any value, nothing is completed
+    #[default]
+    Any,
+    /// Path to a file
+    File,
+    /// Path to a directory
+    Dir,
+    /// Name of a program in `PATH`
+    Executable,
+    /// Hostname from the hosts file and ssh config
+    Host,
+    /// One of a list of values with optional descriptions
+    Values(Vec<(String, Option<String>)>),
+    /// Values returned by a function
+    Custom(fn(&CompletionCtx) -> Vec<Completion>),
+}
+
+impl ValueHint {
+    /// Complete the value under the cursor
+    pub fn complete(&self, ctx: &CompletionCtx) -> Vec<Completion> {
+        match self {
+            ValueHint::Any => vec![],
+            ValueHint::File => filename_action(ctx),
+            ValueHint::Dir => filename_action(ctx)
+                .into_iter()
+                .filter(|completion| completion.kind == CompletionKind::Directory)
+                .collect(),
+            ValueHint::Executable => {
+                // a path to a program is completed like any other path
+                if ctx.unquoted_word().contains('/') {
+                    return filename_action(ctx);
+                }
+                let path = ctx.env().get("PATH").cloned().unwrap_or_default();
+                with_kind(
+                    default_format(find_executables_in_path(&path)),
+                    CompletionKind::Executable,
+                )
+            },
+            ValueHint::Host => with_kind(
+                default_format(ssh_hosts(&home_dir(ctx))),
+                CompletionKind::Host,
+            ),
+            ValueHint::Values(values) => values
+                .iter()
+                .map(|(value, description)| Completion {
+                    add_space: true,
+                    display: None,
+                    completion: value.clone(),
+                    replace_method: ReplaceMethod::Replace,
+                    comment: description.clone(),
+                    score: 0,
+                    matched: vec![],
+                    kind: CompletionKind::Other,
+                })
+                .collect(),
+            ValueHint::Custom(action) => action(ctx),
+        }
+    }
+}
+
+/// A flag such as `-v` or `--verbose`
+#[derive(Clone, Debug, Default)]
+pub struct CliFlag {
+    /// Long name, passed with a double dash
+    pub long: Option<String>,
+    /// Short name, passed with a single dash
+    pub short: Option<char>,
+    /// Shown next to the flag in the completion menu
+    pub description: Option<String>,
+    /// Value the flag takes, if any
+    pub value: Option<ValueHint>,
+    /// If the flag may be passed more than once
+    pub multiple: bool,
+}
+
+impl CliFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_long(mut self, long: impl ToString) -> Self {
+        self.long = Some(long.to_string());
+        self
+    }
+    pub fn with_short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+    pub fn with_description(mut self, description: impl ToString) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+    /// Make the flag take a value
+    pub fn with_value(mut self, value: ValueHint) -> Self {
+        self.value = Some(value);
+        self
+    }
+    pub fn with_multiple(mut self, multiple: bool) -> Self {
+        self.multiple = multiple;
+        self
+    }
+
+    fn completions(&self) -> Vec<Completion> {
+        let long = self.long.iter().map(|long| format!("--{long}"));
+        let short = self.short.iter().map(|short| format!("-{short}"));
+        long.chain(short)
+            .map(|flag| Completion {
+                add_space: true,
+                display: None,
+                completion: flag,
+                replace_method: ReplaceMethod::Replace,
+                comment: self.description.clone(),
+                score: 0,
+                matched: vec![],
+                kind: CompletionKind::Flag,
+            })
+            .collect()
+    }
+}
+
+/// A positional argument
+#[derive(Clone, Debug, Default)]
+pub struct CliArg {
+    /// Name of the argument, used for documentation
+    pub name: String,
+    /// Value the argument takes
+    pub value: ValueHint,
+    /// If the argument takes every remaining positional value
+    pub multiple: bool,
+}
+
+impl CliArg {
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn with_value(mut self, value: ValueHint) -> Self {
+        self.value = value;
+        self
+    }
+    pub fn with_multiple(mut self, multiple: bool) -> Self {
+        self.multiple = multiple;
+        self
+    }
+}
+
+/// A command, or a subcommand of another command
+#[derive(Clone, Debug, Default)]
+pub struct CliCommand {
+    /// Name the command is invoked with
+    pub name: String,
+    /// Shown next to the command in the completion menu when it is a subcommand
+    pub description: Option<String>,
+    pub flags: Vec<CliFlag>,
+    /// Positional arguments in order
+    pub args: Vec<CliArg>,
+    pub subcommands: Vec<CliCommand>,
+}
+
+impl CliCommand {
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn with_name(mut self, name: impl ToString) -> Self {
+        self.name = name.to_string();
+        self
+    }
+    pub fn with_description(mut self, description: impl ToString) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+    pub fn with_flag(mut self, flag: CliFlag) -> Self {
+        self.flags.push(flag);
+        self
+    }
+    pub fn with_arg(mut self, arg: CliArg) -> Self {
+        self.args.push(arg);
+        self
+    }
+    pub fn with_subcommand(mut self, subcommand: CliCommand) -> Self {
+        self.subcommands.push(subcommand);
+        self
+    }
+    pub fn with_subcommands(mut self, subcommands: impl IntoIterator<Item = CliCommand>) -> Self {
+        self.subcommands.extend(subcommands);
+        self
+    }
+
+    /// Rule that completes the arguments of this command
+    pub fn rule(self) -> Rule {
+        let name = self.name.clone();
+        Rule::new(
+            Pred::new(cmdname_eq_pred(name)).and(arg_pred),
+            move |ctx: &CompletionCtx| self.complete(ctx),
+        )
+    }
+
+    /// Complete the word under the cursor given the arguments before it
+    pub fn complete(&self, ctx: &CompletionCtx) -> Vec<Completion> {
+        let args = ctx
+            .words()
+            .get(1..ctx.arg_num())
+            .unwrap_or_default()
+            .iter()
+            .map(|word| unquote(word));
+
+        // walk the arguments to find the subcommand, flags and positional argument we are at
+        let mut cmd = self;
+        let mut seen: Vec<&CliFlag> = vec![];
+        let mut pending: Option<&CliFlag> = None;
+        let mut positional = 0;
+        let mut only_positional = false;
+        for arg in args {
+            if pending.take().is_some() {
+                continue;
+            }
+            if !only_positional && arg == "--" {
+                only_positional = true;
+                continue;
+            }
+            if !only_positional && arg.starts_with('-') && arg.len() > 1 {
+                let (flags, needs_value) = cmd.parse_flags(&arg);
+                seen.extend(flags);
+                pending = needs_value;
+                continue;
+            }
+            if positional == 0 {
+                if let Some(sub) = cmd.subcommands.iter().find(|sub| sub.name == arg) {
+                    cmd = sub;
+                    seen.clear();
+                    continue;
+                }
+            }
+            positional += 1;
+        }
+
+        if let Some(flag) = pending {
+            return flag
+                .value
+                .as_ref()
+                .map(|value| value.complete(ctx))
+                .unwrap_or_default();
+        }
+
+        if !only_positional && ctx.unquoted_word().starts_with('-') {
+            return cmd
+                .flags
+                .iter()
+                .filter(|flag| flag.multiple || !seen.iter().any(|seen| std::ptr::eq(*seen, *flag)))
+                .flat_map(|flag| flag.completions())
+                .collect();
+        }
+
+        let mut completions = vec![];
+        if positional == 0 {
+            completions.extend(cmd.subcommands.iter().map(|sub| Completion {
+                add_space: true,
+                display: None,
+                completion: sub.name.clone(),
+                replace_method: ReplaceMethod::Replace,
+                comment: sub.description.clone(),
+                score: 0,
+                matched: vec![],
+                kind: CompletionKind::Command,
+            }));
+        }
+        let arg = cmd
+            .args
+            .get(positional)
+            .or_else(|| cmd.args.last().filter(|arg| arg.multiple));
+        if let Some(arg) = arg {
+            completions.extend(arg.value.complete(ctx));
+        }
+        completions
+    }
+
+    /// Flags passed in a word such as `--output=file` or `-vo`, along with the flag still waiting
+    /// for its value in the next word
+    fn parse_flags(&self, arg: &str) -> (Vec<&CliFlag>, Option<&CliFlag>) {
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let Some(flag) = self
+                .flags
+                .iter()
+                .find(|flag| flag.long.as_deref() == Some(name))
+            else {
+                return (vec![], None);
+            };
+            let needs_value = flag.value.is_some() && value.is_none();
+            return (vec![flag], needs_value.then_some(flag));
+        }
+
+        let mut flags = vec![];
+        let shorts = &arg[1..];
+        for (i, c) in shorts.char_indices() {
+            let Some(flag) = self.flags.iter().find(|flag| flag.short == Some(c)) else {
+                break;
+            };
+            flags.push(flag);
+            // the rest of the word is the value, such as `-ofile`
+            if flag.value.is_some() {
+                let needs_value = i + c.len_utf8() == shorts.len();
+                return (flags, needs_value.then_some(flag));
+            }
+        }
+        (flags, None)
+    }
+}
+
+impl From<&clap::Command> for CliCommand {
+    fn from(cmd: &clap::Command) -> Self {
+        // building adds the generated help and version flags and subcommands
+        let mut cmd = cmd.clone();
+        cmd.build();
+        from_built(&cmd)
+    }
+}
+
+fn from_built(cmd: &clap::Command) -> CliCommand {
+    let flags = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(|arg| CliFlag {
+            long: arg.get_long().map(String::from),
+            short: arg.get_short(),
+            description: arg.get_help().map(|help| help.to_string()),
+            value: arg.get_action().takes_values().then(|| clap_value(arg)),
+            multiple: matches!(arg.get_action(), ArgAction::Append | ArgAction::Count),
+        })
+        .collect();
+
+    let args = cmd
+        .get_positionals()
+        .filter(|arg| !arg.is_hide_set())
+        .map(|arg| CliArg {
+            name: arg.get_id().to_string(),
+            value: clap_value(arg),
+            multiple: matches!(arg.get_action(), ArgAction::Append)
+                || arg
+                    .get_num_args()
+                    .is_some_and(|range| range.max_values() > 1),
+        })
+        .collect();
+
+    CliCommand {
+        name: cmd.get_name().to_string(),
+        description: cmd.get_about().map(|about| about.to_string()),
+        flags,
+        args,
+        subcommands: cmd
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .map(from_built)
+            .collect(),
+    }
+}
+
+fn clap_value(arg: &clap::Arg) -> ValueHint {
+    let values = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| {
+            (
+                value.get_name().to_string(),
+                value.get_help().map(|help| help.to_string()),
+            )
+        })
+        .collect::<Vec<_>>();
+    if !values.is_empty() {
+        return ValueHint::Values(values);
+    }
+
+    match arg.get_value_hint() {
+        clap::ValueHint::AnyPath | clap::ValueHint::FilePath => ValueHint::File,
+        clap::ValueHint::DirPath => ValueHint::Dir,
+        clap::ValueHint::ExecutablePath | clap::ValueHint::CommandName => ValueHint::Executable,
+        clap::ValueHint::Hostname => ValueHint::Host,
+        _ => ValueHint::Any,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CliArg, CliCommand, CliFlag, ValueHint};
+    use crate::completion::CompletionCtx;
+
+    fn complete(cmd: &CliCommand, words: &[&str]) -> Vec<String> {
+        let ctx = CompletionCtx::new(words.iter().map(|s| s.to_string()).collect());
+        let mut completions = cmd
+            .complete(&ctx)
+            .into_iter()
+            .map(|c| c.completion)
+            .collect::<Vec<_>>();
+        completions.sort();
+        completions
+    }
+
+    #[test]
+    fn command_tree() {
+        let level = ValueHint::Values(vec![("debug".into(), None), ("release".into(), None)]);
+        let cmd = CliCommand::new("tool")
+            .with_flag(
+                CliFlag::new()
+                    .with_long("verbose")
+                    .with_short('v')
+                    .with_multiple(true),
+            )
+            .with_flag(CliFlag::new().with_long("quiet"))
+            .with_subcommand(
+                CliCommand::new("build")
+                    .with_description("Build it")
+                    .with_flag(CliFlag::new().with_short('o').with_value(level.clone())),
+            )
+            .with_subcommand(
+                CliCommand::new("run").with_arg(CliArg::new("mode").with_value(level)),
+            );
+
+        assert_eq!(complete(&cmd, &["tool", ""]), vec!["build", "run"]);
+        assert_eq!(
+            complete(&cmd, &["tool", "-"]),
+            vec!["--quiet", "--verbose", "-v"]
+        );
+        // repeated flags are still offered, the others only once
+        assert_eq!(
+            complete(&cmd, &["tool", "--quiet", "-v", "-"]),
+            vec!["--verbose", "-v"]
+        );
+        assert_eq!(complete(&cmd, &["tool", "build", "-"]), vec!["-o"]);
+        assert_eq!(
+            complete(&cmd, &["tool", "build", "-o", ""]),
+            vec!["debug", "release"]
+        );
+        assert!(complete(&cmd, &["tool", "build", "-orelease", ""]).is_empty());
+        assert_eq!(
+            complete(&cmd, &["tool", "-v", "run", ""]),
+            vec!["debug", "release"]
+        );
+        assert!(complete(&cmd, &["tool", "run", "debug", ""]).is_empty());
+    }
+
+    #[test]
+    fn from_clap() {
+        use clap::{Arg, ArgAction, Command};
+
+        let cmd = Command::new("tool")
+            .arg(Arg::new("file").value_hint(clap::ValueHint::FilePath))
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .value_parser(["auto", "never"]),
+            )
+            .arg(
+                Arg::new("verbose")
+                    .short('v')
+                    .action(ArgAction::Count)
+                    .help("More output"),
+            )
+            .subcommand(Command::new("init").about("Create a project"));
+        let cmd = CliCommand::from(&cmd);
+
+        assert!(matches!(cmd.args[0].value, ValueHint::File));
+        let verbose = cmd.flags.iter().find(|f| f.short == Some('v')).unwrap();
+        assert!(verbose.multiple && verbose.value.is_none());
+        assert_eq!(verbose.description.as_deref(), Some("More output"));
+        assert_eq!(
+            complete(&cmd, &["tool", "--color", ""]),
+            vec!["auto", "never"]
+        );
+        assert!(cmd.flags.iter().any(|f| f.long.as_deref() == Some("help")));
+        assert_eq!(
+            cmd.subcommands[0].description.as_deref(),
+            Some("Create a project")
+        );
+    }
+}
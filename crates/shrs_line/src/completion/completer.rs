@@ -1,12 +1,15 @@
 //! Implementation of default rule based completer
 
-use std::path::{Path, PathBuf};
+use std::{collections::HashSet, path::PathBuf};
 
 use shrs_core::builtin::Builtins;
+use shrs_utils::fuzzy::{fuzzy_match, FuzzyMatch};
 
 use super::{
-    data::*, drop_path_end, filepaths, find_executables_in_path, Completer, Completion,
-    CompletionCtx, ReplaceMethod,
+    data::*, drop_path_end, file_kind, filepaths, find_executables_in_path, help_flags_action,
+    help_flags_pred, spec_action, spec_external_action, spec_external_pred, spec_files_pred,
+    spec_pred, ssh_hosts, users, AsyncAction, Completer, Completion, CompletionCtx, CompletionKind,
+    HelpFlags, ReplaceMethod, WordKind,
 };
 
 // TODO make this FnMut?
@@ -39,6 +42,47 @@ impl Pred {
     }
 }
 
+/// Strategy used to filter the completions by the word being completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// Completion starts with the word
+    #[default]
+    Prefix,
+    /// Completion starts with the word, ignoring case. Matches with the same case score higher
+    CaseInsensitivePrefix,
+    /// Completion contains the word anywhere, earlier occurrences score higher
+    Substring,
+    /// Characters of the word appear in order in the completion, see [fuzzy_match]
+    Fuzzy,
+}
+
+impl MatchMode {
+    /// Match a completion against the word, returning the score and the matched char indices
+    pub fn matches(&self, word: &str, completion: &str) -> Option<FuzzyMatch> {
+        let len = word.chars().count();
+        let at = |start: usize, score: i64| FuzzyMatch {
+            score,
+            positions: (start..start + len).collect(),
+        };
+        match self {
+            MatchMode::Prefix => completion.starts_with(word).then(|| at(0, 0)),
+            MatchMode::CaseInsensitivePrefix => {
+                if completion.starts_with(word) {
+                    Some(at(0, 1))
+                } else {
+                    let prefix = completion.chars().take(len).collect::<String>();
+                    (prefix.to_lowercase() == word.to_lowercase()).then(|| at(0, 0))
+                }
+            },
+            MatchMode::Substring => completion.find(word).map(|i| {
+                let start = completion[..i].chars().count();
+                at(start, -(start as i64))
+            }),
+            MatchMode::Fuzzy => fuzzy_match(word, completion),
+        }
+    }
+}
+
 pub type Filter = Box<dyn Fn(&String) -> bool>;
 pub type Format = Box<dyn Fn(String) -> Completion>;
 
@@ -48,6 +92,8 @@ pub struct Rule {
     pub pred: Pred,
     /// Action to execute if predicate is satisfied
     pub action: Action,
+    /// Overrides the match mode of the completer for this rule
+    pub match_mode: Option<MatchMode>,
     // pub filter: Filter,
     // pub format: Format,
 }
@@ -57,22 +103,40 @@ impl Rule {
         Self {
             pred,
             action: Box::new(action),
+            match_mode: None,
             // filter:
             // format: Box::new(default_format),
         }
     }
 
+    /// Filter the completions of this rule with a different [MatchMode]
+    pub fn with_match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = Some(match_mode);
+        self
+    }
+
     // TODO this could maybe be rewritten as a builder pattern
 }
 
 /// Default rule-based completion system
 pub struct DefaultCompleter {
     rules: Vec<Rule>,
+    /// How completions are matched against the current word, can be overridden per [Rule]
+    match_mode: MatchMode,
 }
 
 impl DefaultCompleter {
     pub fn new() -> Self {
-        Self { rules: vec![] }
+        Self {
+            rules: vec![],
+            match_mode: MatchMode::default(),
+        }
+    }
+
+    /// Set the [MatchMode] used by rules that don't specify one
+    pub fn with_match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
     }
 
     /// Register a new rule to use
@@ -83,15 +147,22 @@ impl DefaultCompleter {
     fn complete_helper(&self, ctx: &CompletionCtx) -> Vec<Completion> {
         let rules = self.rules.iter().filter(|p| (p.pred).test(ctx));
 
+        let cur_word = ctx.cur_word().cloned().unwrap_or_default();
         let mut output = vec![];
+        let mut seen = HashSet::new();
         for rule in rules {
-            // if rule was matched, run the corresponding action
-            // also do prefix search (could make if prefix search is used a config option)
+            // if rule was matched, run the corresponding action and keep the completions that
+            // match the current word
+            let match_mode = rule.match_mode.unwrap_or(self.match_mode);
             let mut comps = (rule.action)(ctx)
                 .into_iter()
-                .filter(|s| {
-                    s.accept()
-                        .starts_with(ctx.cur_word().unwrap_or(&String::new()))
+                // the first rule to return a completion wins, so later rules can fill in the gaps
+                .filter(|s| seen.insert(s.completion.clone()))
+                .filter_map(|mut s| {
+                    let m = match_mode.matches(&cur_word, &s.accept())?;
+                    s.score = m.score;
+                    s.matched = m.positions;
+                    Some(s)
                 })
                 // .map(|s| (rule.format)(s))
                 .collect::<Vec<_>>();
@@ -114,28 +185,101 @@ impl Default for DefaultCompleter {
         // collection of predefined rules
 
         let mut comp = DefaultCompleter::new();
+        comp.register(Rule::new(Pred::new(cmdname_pred), Box::new(command_action)));
         comp.register(Rule::new(
             Pred::new(git_pred).and(flag_pred),
             Box::new(git_flag_action),
         ));
         comp.register(Rule::new(Pred::new(git_pred), Box::new(git_action)));
         comp.register(Rule::new(
-            Pred::new(ls_pred).and(short_flag_pred),
-            Box::new(ls_short_flag_action),
+            Pred::new(git_branch_pred),
+            AsyncAction::new(git_branch_action).action(),
+        ));
+        comp.register(Rule::new(
+            Pred::new(help_flags_pred),
+            help_flags_action(HelpFlags::default()).action(),
+        ));
+        comp.register(Rule::new(Pred::new(spec_pred), Box::new(spec_action)));
+        comp.register(Rule::new(
+            Pred::new(spec_external_pred),
+            spec_external_action().action(),
         ));
         comp.register(Rule::new(
-            Pred::new(ls_pred).and(long_flag_pred),
-            Box::new(ls_long_flag_action),
+            Pred::new(arg_pred).and(spec_files_pred),
+            Box::new(filename_action),
+        ));
+        comp.register(Rule::new(
+            Pred::new(redirect_pred),
+            Box::new(filename_action),
+        ));
+        comp.register(Rule::new(
+            Pred::new(assignment_pred),
+            Box::new(filename_action),
+        ));
+        comp.register(Rule::new(
+            Pred::new(variable_pred),
+            Box::new(variable_action),
+        ));
+        comp.register(Rule::new(Pred::new(tilde_pred), Box::new(user_action)));
+        comp.register(Rule::new(
+            Pred::new(hostname_pred),
+            Box::new(hostname_action),
         ));
-        comp.register(Rule::new(Pred::new(arg_pred), Box::new(filename_action)));
         comp
     }
 }
 
+/// Complete the command name from the aliases, functions and builtins of the shell and the
+/// executables in `PATH`
+///
+/// Each name is completed once, as what the shell would run: aliases are used over functions, then
+/// builtins and finally executables.
+pub fn command_action(ctx: &CompletionCtx) -> Vec<Completion> {
+    let commands = ctx.commands();
+    let path = ctx.env().get("PATH").cloned().unwrap_or_default();
+
+    let aliases = commands
+        .aliases
+        .iter()
+        .map(|(name, expansion)| (name.clone(), Some(expansion.clone()), CompletionKind::Alias));
+    let functions = commands
+        .functions
+        .iter()
+        .map(|name| (name.clone(), None, CompletionKind::Function));
+    let builtins = commands
+        .builtins
+        .iter()
+        .map(|name| (name.clone(), None, CompletionKind::Builtin));
+    let executables = find_executables_in_path(&path)
+        .into_iter()
+        .map(|name| (name, None, CompletionKind::Executable));
+
+    let mut seen = HashSet::new();
+    aliases
+        .chain(functions)
+        .chain(builtins)
+        .chain(executables)
+        .filter(|(name, _, _)| seen.insert(name.clone()))
+        .map(|(name, comment, kind)| Completion {
+            add_space: true,
+            display: None,
+            completion: name,
+            replace_method: ReplaceMethod::Replace,
+            comment,
+            score: 0,
+            matched: vec![],
+            kind,
+        })
+        .collect()
+}
+
 /// Return all the executables in PATH
 pub fn cmdname_action(path_str: String) -> impl Fn(&CompletionCtx) -> Vec<Completion> {
     move |_ctx: &CompletionCtx| -> Vec<Completion> {
-        default_format(find_executables_in_path(&path_str))
+        with_kind(
+            default_format(find_executables_in_path(&path_str)),
+            CompletionKind::Executable,
+        )
     }
 }
 
@@ -145,71 +289,212 @@ pub fn builtin_cmdname_action(builtin: &Builtins) -> impl Fn(&CompletionCtx) ->
         .iter()
         .map(|(name, _)| name.to_owned().to_string())
         .collect::<Vec<_>>();
-    move |_ctx: &CompletionCtx| -> Vec<Completion> { default_format(builtin_names.clone()) }
+    move |_ctx: &CompletionCtx| -> Vec<Completion> {
+        with_kind(
+            default_format(builtin_names.clone()),
+            CompletionKind::Builtin,
+        )
+    }
 }
 
 /// Look in current directory for potential filenames to complete
 pub fn filename_action(ctx: &CompletionCtx) -> Vec<Completion> {
-    let cur_word = ctx.cur_word().unwrap();
-    let drop_end = drop_path_end(cur_word);
-    let cur_path = to_absolute(&drop_end, &dirs::home_dir().unwrap());
+    let cur_word = ctx.unquoted_word();
+    let drop_end = drop_path_end(&cur_word);
+    let cur_path = to_absolute(&drop_end, ctx);
 
     let output = filepaths(&cur_path).unwrap_or(vec![]);
     output
         .iter()
         .map(|x| {
             let filename = x.file_name().unwrap().to_str().unwrap().to_string();
-
-            let mut filename = sanitize_file_name(filename);
-
-            // escape special characters in filename
-
-            // append slash if directory name
-            let is_dir = x.is_dir();
-            if is_dir {
-                filename += "/";
+            let kind = file_kind(x);
+
+            // inside quotes the filename is completed as is and the quote is closed after a file,
+            // otherwise special characters are escaped
+            let (mut completion, display) = match ctx.quote() {
+                Some(quote) => (format!("{quote}{drop_end}{filename}"), filename),
+                None => {
+                    let filename = sanitize_file_name(filename);
+                    (sanitize_file_name(drop_end.clone()) + &filename, filename)
+                },
+            };
+
+            // directories are completed with a slash instead
+            if kind != CompletionKind::Directory {
+                if let Some(quote) = ctx.quote() {
+                    completion.push(quote);
+                }
             }
             Completion {
-                add_space: !is_dir,
-                display: Some(filename.to_owned()),
-                completion: drop_end.to_owned() + &filename,
+                add_space: true,
+                display: Some(display),
+                completion,
                 replace_method: ReplaceMethod::Replace,
                 comment: None,
+                score: 0,
+                matched: vec![],
+                kind,
             }
         })
         .collect::<Vec<_>>()
 }
 
+/// Complete the names of the variables in the environment, such as `$HOME` or `${HOME}`
+pub fn variable_action(ctx: &CompletionCtx) -> Vec<Completion> {
+    let Some(prefix) = ctx.var_prefix() else {
+        return vec![];
+    };
+    let cur_word = ctx.cur_word().cloned().unwrap_or_default();
+    // everything up to and including the `$` or `${`
+    let before = &cur_word[..cur_word.len() - prefix.len()];
+    let brace = before.ends_with('{');
+
+    ctx.env()
+        .iter()
+        .map(|(name, value)| Completion {
+            add_space: !brace && ctx.quote().is_none(),
+            display: Some(name.to_owned()),
+            completion: if brace {
+                format!("{before}{name}}}")
+            } else {
+                format!("{before}{name}")
+            },
+            replace_method: ReplaceMethod::Replace,
+            comment: value.lines().next().map(String::from),
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Variable,
+        })
+        .collect()
+}
+
+/// Complete the home directories of users, such as `~root/`
+pub fn user_action(_ctx: &CompletionCtx) -> Vec<Completion> {
+    users()
+        .into_iter()
+        .map(|(name, home)| Completion {
+            add_space: false,
+            display: Some(format!("~{name}")),
+            completion: format!("~{name}/"),
+            replace_method: ReplaceMethod::Replace,
+            comment: Some(home.to_string_lossy().to_string()),
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Directory,
+        })
+        .collect()
+}
+
+/// Complete hostnames after an `@`, such as `user@host`
+pub fn hostname_action(ctx: &CompletionCtx) -> Vec<Completion> {
+    let cur_word = ctx.cur_word().cloned().unwrap_or_default();
+    let Some(at) = cur_word.rfind('@') else {
+        return vec![];
+    };
+    let before = &cur_word[..=at];
+
+    ssh_hosts(&home_dir(ctx))
+        .into_iter()
+        .map(|host| Completion {
+            add_space: true,
+            display: Some(host.clone()),
+            completion: format!("{before}{host}"),
+            replace_method: ReplaceMethod::Replace,
+            comment: None,
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Host,
+        })
+        .collect()
+}
+
 /// Takes in an arbitrary path that user enters and convert it into an absolute path
-fn to_absolute(path_str: &str, home_dir: &Path) -> PathBuf {
+fn to_absolute(path_str: &str, ctx: &CompletionCtx) -> PathBuf {
     let path_buf = PathBuf::from(path_str);
 
     let absolute = if path_buf.has_root() {
         path_buf
     } else {
         // handle home directory tilde
-        // TODO ~username/ not yet handled
         if let Ok(stripped) = path_buf.strip_prefix("~/") {
-            home_dir.join(stripped)
+            home_dir(ctx).join(stripped)
+        } else if let Some(path) = user_home(path_str) {
+            path
         } else {
-            std::env::current_dir().unwrap().join(path_buf)
+            ctx.working_dir().join(path_buf)
         }
     };
 
     absolute
 }
 
+/// Expand a path in another user's home directory, such as `~root/.bashrc`
+fn user_home(path_str: &str) -> Option<PathBuf> {
+    let (user, rest) = path_str.strip_prefix('~')?.split_once('/')?;
+    let (_, home) = users().into_iter().find(|(name, _)| name == user)?;
+    Some(home.join(rest))
+}
+
+pub(crate) fn home_dir(ctx: &CompletionCtx) -> PathBuf {
+    ctx.env()
+        .get("HOME")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+}
+
 /// Check if we are completing the command name
 pub fn cmdname_pred(ctx: &CompletionCtx) -> bool {
-    ctx.arg_num() == 0
+    ctx.kind() == &WordKind::Command
 }
 pub fn git_pred(ctx: &CompletionCtx) -> bool {
     cmdname_eq_pred("git".into())(ctx)
 }
+/// Check if we are completing the branch of a git subcommand, such as `git checkout ma`
+pub fn git_branch_pred(ctx: &CompletionCtx) -> bool {
+    git_pred(ctx)
+        && ctx.arg_num() == 2
+        && ctx
+            .words()
+            .get(1)
+            .is_some_and(|cmd| ["checkout", "switch", "merge", "rebase"].contains(&cmd.as_str()))
+}
 
 /// Check if we are attempting to complete an argument
 pub fn arg_pred(ctx: &CompletionCtx) -> bool {
-    ctx.arg_num() != 0
+    ctx.kind() == &WordKind::Argument
+}
+
+/// Check if we are completing the target of a redirection
+pub fn redirect_pred(ctx: &CompletionCtx) -> bool {
+    ctx.kind() == &WordKind::Redirect
+}
+
+/// Check if we are completing the value of a variable assignment
+pub fn assignment_pred(ctx: &CompletionCtx) -> bool {
+    matches!(ctx.kind(), WordKind::Assignment(_))
+}
+
+/// Check if we are completing the name of a variable
+pub fn variable_pred(ctx: &CompletionCtx) -> bool {
+    ctx.kind() == &WordKind::Variable
+}
+
+/// Check if we are completing a user's home directory, such as `~ro`
+pub fn tilde_pred(ctx: &CompletionCtx) -> bool {
+    let cur_word = ctx.cur_word().unwrap_or(&String::new()).to_owned();
+    ctx.kind() != &WordKind::Variable && cur_word.starts_with('~') && !cur_word.contains('/')
+}
+
+/// Check if we are completing a hostname after an `@`, such as `user@ho`
+pub fn hostname_pred(ctx: &CompletionCtx) -> bool {
+    let cur_word = ctx.cur_word().unwrap_or(&String::new()).to_owned();
+    ctx.kind() == &WordKind::Argument
+        && cur_word
+            .rsplit_once('@')
+            .is_some_and(|(_, host)| !host.contains(['/', ':']))
 }
 
 /// Check if name of current command equals a given command name
@@ -233,9 +518,7 @@ pub fn long_flag_pred(ctx: &CompletionCtx) -> bool {
 /// Check if we are completing a (real) path
 pub fn path_pred(ctx: &CompletionCtx) -> bool {
     // strip part after slash
-    let cur_word = ctx.cur_word().unwrap();
-    // TODO should technically be using HOME env variable?
-    let cur_path = to_absolute(&drop_path_end(cur_word), &dirs::home_dir().unwrap());
+    let cur_path = to_absolute(&drop_path_end(&ctx.unquoted_word()), ctx);
 
     cur_path.is_dir()
 }
@@ -250,6 +533,9 @@ pub fn default_format(s: Vec<String>) -> Vec<Completion> {
             completion: x.to_owned(),
             replace_method: ReplaceMethod::Replace,
             comment: None,
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Other,
         })
         .collect::<Vec<_>>()
 }
@@ -262,10 +548,21 @@ pub fn default_format_with_comment(s: Vec<(&'static str, &'static str)>) -> Vec<
             completion: x.0.to_string(),
             replace_method: ReplaceMethod::Replace,
             comment: Some(x.1.to_string()),
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Other,
         })
         .collect::<Vec<_>>()
 }
 
+/// Set the kind of every completion
+pub fn with_kind(completions: Vec<Completion>, kind: CompletionKind) -> Vec<Completion> {
+    completions
+        .into_iter()
+        .map(|completion| Completion { kind, ..completion })
+        .collect()
+}
+
 // pub fn path_format(s: String) -> Completion {
 //     Completion { add_space: false, display: Some(path_end(&s)), completion: s }
 // }
@@ -280,8 +577,10 @@ fn sanitize_file_name(filename: String) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{flag_pred, DefaultCompleter};
-    use crate::completion::CompletionCtx;
+    use super::{
+        command_action, default_format, flag_pred, DefaultCompleter, MatchMode, Pred, Rule,
+    };
+    use crate::completion::{Completer, CompletionCtx, CompletionKind, ShellCommands};
 
     #[test]
     fn simple() {
@@ -296,4 +595,74 @@ mod tests {
         let ctx = CompletionCtx::new(vec![]);
         assert!(!flag_pred(&ctx));
     }
+
+    #[test]
+    fn command_names() {
+        let ctx = CompletionCtx::new(vec!["".into()]).with_commands(ShellCommands {
+            builtins: vec!["cd".into(), "ls".into()],
+            aliases: vec![("ls".into(), "ls --color".into())],
+            functions: vec!["greet".into()],
+        });
+        let completions = command_action(&ctx)
+            .into_iter()
+            .map(|c| (c.completion, c.comment, c.kind))
+            .collect::<Vec<_>>();
+
+        // the alias shadows the builtin of the same name
+        assert_eq!(
+            completions,
+            vec![
+                (
+                    "ls".into(),
+                    Some("ls --color".into()),
+                    CompletionKind::Alias
+                ),
+                ("greet".into(), None, CompletionKind::Function),
+                ("cd".into(), None, CompletionKind::Builtin),
+            ]
+        );
+    }
+
+    #[test]
+    fn match_modes() {
+        let completions = |match_mode: MatchMode| {
+            let mut comp = DefaultCompleter::new().with_match_mode(match_mode);
+            comp.register(Rule::new(Pred::new(|_| true), |_| {
+                default_format(vec![
+                    "Makefile".into(),
+                    "main.rs".into(),
+                    "mod.rs".into(),
+                    "format.rs".into(),
+                ])
+            }));
+            let ctx = CompletionCtx::new(vec!["cat".into(), "ma".into()]);
+            comp.complete(&ctx)
+                .into_iter()
+                .map(|c| (c.completion, c.score, c.matched))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            completions(MatchMode::Prefix),
+            vec![("main.rs".into(), 0, vec![0, 1])]
+        );
+        assert_eq!(
+            completions(MatchMode::CaseInsensitivePrefix),
+            vec![
+                ("Makefile".into(), 0, vec![0, 1]),
+                ("main.rs".into(), 1, vec![0, 1])
+            ]
+        );
+        assert_eq!(
+            completions(MatchMode::Substring),
+            vec![
+                ("main.rs".into(), 0, vec![0, 1]),
+                ("format.rs".into(), -3, vec![3, 4])
+            ]
+        );
+
+        let fuzzy = completions(MatchMode::Fuzzy);
+        assert_eq!(fuzzy.len(), 3);
+        assert!(fuzzy[1].1 > fuzzy[2].1, "main.rs ranks above format.rs");
+    }
 }
@@ -0,0 +1,619 @@
+//! Context about the command being completed
+//!
+//! The line is split into words with the shell's lexer, so that the words of the simple command
+//! the cursor is in are found even after pipes, `&&` or inside quotes.
+
+use std::{ops::Range, path::PathBuf};
+
+use shrs_core::{
+    alias::AliasRuleCtx,
+    complete::CommandSpec,
+    env::Env,
+    shell::{Context, Runtime, Shell},
+};
+use shrs_lang::{Lexer, LexerError, Token};
+
+/// What the word under the cursor is part of
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum WordKind {
+    /// Name of the command
+    Command,
+    /// Argument to the command
+    Argument,
+    /// Target of a redirection, such as the file in `> file`
+    Redirect,
+    /// Value of a variable assignment before the command, such as `bar` in `FOO=bar cmd`
+    Assignment(String),
+    /// Name of a variable being expanded, such as `$HO` or `${HO`
+    Variable,
+}
+
+/// Commands defined in the shell, which are completed along with the executables in `PATH`
+#[derive(Debug, Clone, Default)]
+pub struct ShellCommands {
+    /// Names of the registered builtins
+    pub builtins: Vec<String>,
+    /// Names of the aliases and what they expand to
+    pub aliases: Vec<(String, String)>,
+    /// Names of the shell functions
+    pub functions: Vec<String>,
+}
+
+impl ShellCommands {
+    /// Collect the builtins and aliases of the shell
+    pub fn from_shell(sh: &Shell, ctx: &Context, rt: &Runtime) -> Self {
+        let builtins = sh
+            .builtins
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        // only aliases whose rule currently applies, expanded like the shell would
+        let aliases = ctx
+            .alias
+            .names()
+            .filter_map(|name| {
+                let alias_ctx = AliasRuleCtx {
+                    alias_name: name,
+                    sh,
+                    ctx,
+                    rt,
+                };
+                let expansion = ctx.alias.get(&alias_ctx).last()?.to_string();
+                Some((name.to_string(), expansion))
+            })
+            .collect();
+
+        Self {
+            builtins,
+            aliases,
+            // TODO functions are currently disabled in the command language
+            functions: vec![],
+        }
+    }
+}
+
+/// Context on the current state of the input that completions are generated from
+#[derive(Debug, Clone)]
+pub struct CompletionCtx {
+    /// The entire input
+    line: String,
+    /// Byte offset of the cursor into the input
+    cursor: usize,
+    /// Name of the command and its arguments, not including assignments and redirections
+    words: Vec<String>,
+    /// Number of words before the word under the cursor
+    arg_num: usize,
+    cmd_name: Option<String>,
+    /// Part of the word under the cursor that is before the cursor
+    cur_word: String,
+    /// Byte range of the entire word under the cursor
+    word_range: Range<usize>,
+    /// Quote that is still open at the cursor
+    quote: Option<char>,
+    kind: WordKind,
+    working_dir: PathBuf,
+    env: Env,
+    /// Completion spec of the command, see [CompletionSpecs](shrs_core::complete::CompletionSpecs)
+    spec: Option<CommandSpec>,
+    commands: ShellCommands,
+}
+
+impl CompletionCtx {
+    /// Construct context from a line split by arguments, where the cursor is after the very last
+    /// argument
+    pub fn new(line: Vec<String>) -> Self {
+        let text = line.join(" ");
+        let cur_word = line.last().cloned().unwrap_or_default();
+        let kind = if line.len() > 1 {
+            WordKind::Argument
+        } else {
+            WordKind::Command
+        };
+        Self {
+            cursor: text.len(),
+            word_range: text.len() - cur_word.len()..text.len(),
+            line: text,
+            arg_num: line.len().saturating_sub(1),
+            cmd_name: line.first().cloned(),
+            words: line,
+            cur_word,
+            quote: None,
+            kind,
+            working_dir: std::env::current_dir().unwrap_or_default(),
+            env: Env::new(),
+            spec: None,
+            commands: ShellCommands::default(),
+        }
+    }
+
+    /// Construct context from the input and the byte offset of the cursor into it
+    pub fn from_line(line: &str, cursor: usize, rt: &Runtime) -> Self {
+        Self {
+            working_dir: rt.working_dir.clone(),
+            env: rt.env.clone(),
+            ..Self::parse(line, cursor)
+        }
+    }
+
+    /// Attach the completion spec of the command
+    pub fn with_spec(mut self, spec: Option<CommandSpec>) -> Self {
+        self.spec = spec;
+        self
+    }
+
+    /// Attach the builtins, aliases and functions of the shell
+    pub fn with_commands(mut self, commands: ShellCommands) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// Complete the arguments of an alias like those of the command it expands to
+    ///
+    /// The alias is replaced by its expansion in [Self::words], so with `alias ll='ls -l'` the line
+    /// `ll -` is completed like `ls -l -`. The word under the cursor and its range are unchanged.
+    pub fn expand_alias(mut self) -> Self {
+        if self.kind == WordKind::Command {
+            return self;
+        }
+        let Some(cmd_name) = &self.cmd_name else {
+            return self;
+        };
+        let Some((_, expansion)) = self
+            .commands
+            .aliases
+            .iter()
+            .find(|(name, _)| name == cmd_name)
+        else {
+            return self;
+        };
+        let expansion = expansion
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let Some(first) = expansion.first().cloned() else {
+            return self;
+        };
+
+        self.arg_num += expansion.len() - 1;
+        self.words.splice(0..1, expansion);
+        self.cmd_name = Some(first);
+        self
+    }
+
+    fn parse(line: &str, cursor: usize) -> Self {
+        let cursor = cursor.min(line.len());
+        let words = command_words(line, cursor);
+
+        let cur_index = words
+            .iter()
+            .position(|word| word.range.start <= cursor && cursor <= word.range.end);
+        let word_range = cur_index.map_or(cursor..cursor, |i| words[i].range.clone());
+        let prefix = &line[word_range.start..cursor];
+        let quote = open_quote(prefix);
+        let cur_role = cur_index.map_or(Role::Argument, |i| words[i].role.clone());
+
+        let args = words
+            .iter()
+            .filter(|word| matches!(word.role, Role::Command | Role::Argument))
+            .collect::<Vec<_>>();
+        let arg_num = args
+            .iter()
+            .filter(|word| word.range.end < word_range.start)
+            .count();
+
+        let (kind, cur_word) = match cur_role {
+            _ if quote != Some('\'') && variable_start(prefix).is_some() => {
+                (WordKind::Variable, prefix.to_string())
+            },
+            Role::Command => (WordKind::Command, prefix.to_string()),
+            Role::Argument => (WordKind::Argument, prefix.to_string()),
+            Role::Redirect => (WordKind::Redirect, prefix.to_string()),
+            // only the value of an assignment is completed
+            Role::Assignment => {
+                let (name, value) = prefix.split_once('=').unwrap_or((prefix, ""));
+                (WordKind::Assignment(name.to_string()), value.to_string())
+            },
+        };
+        let cmd_name = if kind == WordKind::Command {
+            Some(cur_word.clone())
+        } else {
+            args.iter()
+                .find(|word| word.role == Role::Command)
+                .map(|word| line[word.range.clone()].to_string())
+        };
+
+        Self {
+            line: line.to_string(),
+            cursor,
+            words: args
+                .iter()
+                .map(|word| line[word.range.clone()].to_string())
+                .collect(),
+            arg_num,
+            cmd_name,
+            cur_word,
+            word_range,
+            quote,
+            kind,
+            working_dir: std::env::current_dir().unwrap_or_default(),
+            env: Env::new(),
+            spec: None,
+            commands: ShellCommands::default(),
+        }
+    }
+
+    /// Get the name of the command
+    pub fn cmd_name(&self) -> Option<&String> {
+        self.cmd_name.as_ref()
+    }
+
+    /// Get the word that the user is currently typing, up to the cursor
+    ///
+    /// For an assignment this is only the value being assigned.
+    pub fn cur_word(&self) -> Option<&String> {
+        Some(&self.cur_word)
+    }
+
+    /// Which argument are we currently on
+    pub fn arg_num(&self) -> usize {
+        self.arg_num
+    }
+
+    /// Name of the command and all of its arguments, including any after the cursor
+    ///
+    /// When completing the command name or an argument the word at [Self::arg_num] is the word
+    /// under the cursor, which is empty if the cursor isn't on a word.
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// The entire input
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Byte offset of the cursor into the input
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Byte range of the entire word under the cursor, including the part after the cursor
+    pub fn word_range(&self) -> Range<usize> {
+        self.word_range.clone()
+    }
+
+    /// Quote character that is still open at the cursor
+    pub fn quote(&self) -> Option<char> {
+        self.quote
+    }
+
+    /// What the word under the cursor is part of
+    pub fn kind(&self) -> &WordKind {
+        &self.kind
+    }
+
+    /// The word under the cursor with quotes and backslash escapes removed
+    pub fn unquoted_word(&self) -> String {
+        unquote(&self.cur_word)
+    }
+
+    /// Partial name of the variable being expanded when completing a [WordKind::Variable]
+    pub fn var_prefix(&self) -> Option<&str> {
+        let start = variable_start(&self.cur_word)?;
+        Some(self.cur_word[start..].trim_start_matches(['$', '{']))
+    }
+
+    /// Working directory of the shell
+    pub fn working_dir(&self) -> &PathBuf {
+        &self.working_dir
+    }
+
+    /// Environment variables of the shell
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Completion spec of the command, if one was defined
+    pub fn spec(&self) -> Option<&CommandSpec> {
+        self.spec.as_ref()
+    }
+
+    /// Builtins, aliases and functions defined in the shell
+    pub fn commands(&self) -> &ShellCommands {
+        &self.commands
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Role {
+    Command,
+    Argument,
+    Redirect,
+    Assignment,
+}
+
+struct Word {
+    range: Range<usize>,
+    role: Role,
+}
+
+/// Find the words of the simple command that the cursor is in
+fn command_words(line: &str, cursor: usize) -> Vec<Word> {
+    // pieces of words along with the range they cover, separated by the tokens in between
+    let mut pieces = vec![];
+    for token in Lexer::new(line) {
+        let piece = match token {
+            Ok((start, Token::BACKSLASH, end)) => {
+                // the escaped character is skipped by the lexer if it is whitespace
+                let escaped = line[end..].chars().next().map_or(0, |ch| ch.len_utf8());
+                Some((start, end + escaped, None))
+            },
+            Ok((start, token, end)) => {
+                let token = (!is_word_piece(&token)).then_some(token);
+                Some((start, end, token))
+            },
+            // characters like `*` aren't recognized by the lexer but are part of a word
+            Err(LexerError::UnrecognizedChar(start, _, end)) => Some((start, end, None)),
+        };
+        pieces.extend(piece);
+    }
+    // an empty word where the cursor is if it isn't touching any word
+    let touching = pieces
+        .iter()
+        .any(|(start, end, token)| token.is_none() && *start <= cursor && cursor <= *end);
+    if !touching {
+        let i = pieces
+            .iter()
+            .position(|(start, _, _)| *start >= cursor)
+            .unwrap_or(pieces.len());
+        pieces.insert(i, (cursor, cursor, None));
+    }
+
+    let mut words: Vec<Word> = vec![];
+    let mut current: Option<(Range<usize>, bool)> = None;
+    let mut has_cmd_name = false;
+    let mut redirect = false;
+
+    let finish = |current: &mut Option<(Range<usize>, bool)>,
+                  words: &mut Vec<Word>,
+                  has_cmd_name: &mut bool| {
+        let Some((range, redirect)) = current.take() else {
+            return;
+        };
+        let role = if redirect {
+            Role::Redirect
+        } else if *has_cmd_name {
+            Role::Argument
+        } else if is_assignment(&line[range.clone()]) {
+            Role::Assignment
+        } else {
+            *has_cmd_name = true;
+            Role::Command
+        };
+        words.push(Word { range, role });
+    };
+
+    for (start, end, token) in pieces {
+        let Some(token) = token else {
+            match current.as_mut() {
+                // pieces with nothing in between are part of the same word
+                Some((range, _)) if start <= range.end => range.end = range.end.max(end),
+                _ => {
+                    finish(&mut current, &mut words, &mut has_cmd_name);
+                    current = Some((start..end, redirect));
+                    redirect = false;
+                },
+            }
+            continue;
+        };
+
+        finish(&mut current, &mut words, &mut has_cmd_name);
+        if is_redirect(&token) {
+            redirect = true;
+        } else if matches!(token, Token::IO_NUMBER(_)) {
+        } else if is_separator(&token) || !has_cmd_name {
+            // keywords only separate commands where a command name is expected
+            if start >= cursor {
+                break;
+            }
+            words.clear();
+            has_cmd_name = false;
+            redirect = false;
+        } else {
+            // keywords that are arguments, such as `done` in `echo done`
+            current = Some((start..end, redirect));
+            redirect = false;
+        }
+    }
+    finish(&mut current, &mut words, &mut has_cmd_name);
+    words
+}
+
+/// Tokens that can be part of a word
+fn is_word_piece(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::WORD(_)
+            | Token::ASSIGNMENT_WORD(_)
+            | Token::NAME(_)
+            | Token::FNAME(_)
+            | Token::EQUAL
+            | Token::SINGLEQUOTE
+            | Token::DOUBLEQUOTE
+    )
+}
+
+fn is_redirect(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::LESS
+            | Token::GREAT
+            | Token::DLESS
+            | Token::DGREAT
+            | Token::LESSAND
+            | Token::GREATAND
+            | Token::LESSGREAT
+            | Token::DLESSDASH
+            | Token::CLOBBER
+            | Token::ANDGREAT
+            | Token::ANDDGREAT
+    )
+}
+
+fn is_separator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::NEWLINE
+            | Token::SEMI
+            | Token::DSEMI
+            | Token::AMP
+            | Token::PIPE
+            | Token::AND_IF
+            | Token::OR_IF
+            | Token::LPAREN
+            | Token::RPAREN
+            | Token::BACKTICK
+            | Token::LESSPAREN
+            | Token::GREATPAREN
+    )
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|ch: char| ch.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        },
+        None => false,
+    }
+}
+
+/// Quote that is open at the end of a word
+fn open_quote(word: &str) -> Option<char> {
+    let mut quote = None;
+    let mut escaped = false;
+    for ch in word.chars() {
+        match (quote, ch) {
+            (_, _) if escaped => escaped = false,
+            (None | Some('"'), '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some(q), ch) if q == ch => quote = None,
+            _ => {},
+        }
+    }
+    quote
+}
+
+/// Remove quotes and backslash escapes from a word
+pub(crate) fn unquote(word: &str) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for ch in word.chars() {
+        match (quote, ch) {
+            (_, _) if escaped => {
+                out.push(ch);
+                escaped = false;
+            },
+            (None | Some('"'), '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some(q), ch) if q == ch => quote = None,
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Start of the variable expansion at the end of a word, such as `$HO` in `foo$HO`
+fn variable_start(word: &str) -> Option<usize> {
+    let start = word.rfind('$')?;
+    let name = &word[start + 1..];
+    let name = name.strip_prefix('{').unwrap_or(name);
+    name.chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        .then_some(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompletionCtx, ShellCommands, WordKind};
+
+    fn ctx(line: &str) -> CompletionCtx {
+        // the cursor is marked with `█`
+        let cursor = line.find('█').unwrap();
+        let line = line.replacen('█', "", 1);
+        CompletionCtx::parse(&line, cursor)
+    }
+
+    #[test]
+    fn simple_commands() {
+        let c = ctx("ls -la && git ch█ foo");
+        assert_eq!(c.cmd_name().map(|s| s.as_str()), Some("git"));
+        assert_eq!(c.cur_word().map(|s| s.as_str()), Some("ch"));
+        assert_eq!(c.arg_num(), 1);
+        assert_eq!(c.words(), ["git", "ch", "foo"]);
+        assert_eq!(c.word_range(), 14..16);
+        assert_eq!(c.kind(), &WordKind::Argument);
+
+        let c = ctx("cat foo | gr█");
+        assert_eq!(c.kind(), &WordKind::Command);
+        assert_eq!(c.arg_num(), 0);
+
+        let c = ctx("echo done; █");
+        assert_eq!(c.kind(), &WordKind::Command);
+        assert_eq!(ctx("echo done █").words(), ["echo", "done", ""]);
+    }
+
+    #[test]
+    fn quotes_and_escapes() {
+        let c = ctx("ls \"My Doc█");
+        assert_eq!(c.quote(), Some('"'));
+        assert_eq!(c.unquoted_word(), "My Doc");
+        assert_eq!(c.arg_num(), 1);
+
+        let c = ctx("ls My\\ Do█ foo");
+        assert_eq!(c.cur_word().map(|s| s.as_str()), Some("My\\ Do"));
+        assert_eq!(c.unquoted_word(), "My Do");
+        assert_eq!(c.quote(), None);
+    }
+
+    #[test]
+    fn redirects_assignments_and_variables() {
+        let c = ctx("sort < fi█ -r");
+        assert_eq!(c.kind(), &WordKind::Redirect);
+        assert_eq!(c.cmd_name().map(|s| s.as_str()), Some("sort"));
+        assert_eq!(c.words(), ["sort", "-r"]);
+
+        let c = ctx("EDITOR=vi█ git commit");
+        assert_eq!(c.kind(), &WordKind::Assignment("EDITOR".into()));
+        assert_eq!(c.cur_word().map(|s| s.as_str()), Some("vi"));
+        assert_eq!(c.cmd_name().map(|s| s.as_str()), Some("git"));
+
+        let c = ctx("echo \"${HO█");
+        assert_eq!(c.kind(), &WordKind::Variable);
+        assert_eq!(c.var_prefix(), Some("HO"));
+        assert_eq!(ctx("echo '$HO█").kind(), &WordKind::Argument);
+    }
+
+    #[test]
+    fn alias_expansion() {
+        let commands = ShellCommands {
+            aliases: vec![("gco".into(), "git checkout".into())],
+            ..Default::default()
+        };
+        let expand = |line| ctx(line).with_commands(commands.clone()).expand_alias();
+
+        let c = expand("gco ma█");
+        assert_eq!(c.cmd_name().map(|s| s.as_str()), Some("git"));
+        assert_eq!(c.words(), ["git", "checkout", "ma"]);
+        assert_eq!(c.arg_num(), 2);
+        assert_eq!(c.cur_word().map(|s| s.as_str()), Some("ma"));
+
+        // the alias itself is still completed as a command
+        assert_eq!(expand("gco█").words(), ["gco"]);
+        assert_eq!(expand("ls █").words(), ["ls", ""]);
+    }
+}
@@ -0,0 +1,651 @@
+//! Flags of commands parsed from their man page or `--help` output
+//!
+//! The first time the flags of a command are completed, its man page is rendered with `man`, or
+//! read from the raw roff file if `man` is not installed. If there is no man page, the command is
+//! run with `--help`, as long as it was found in PATH. This happens in the background, so the line
+//! doesn't freeze in the meantime. The parsed flags are cached on disk, keyed by the path and
+//! modification time of the binary, so this only happens again when the command is updated.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    flag_pred, output_with_timeout, AsyncAction, Completion, CompletionCtx, CompletionKind,
+    ReplaceMethod, WordKind,
+};
+
+/// How long `man` or a command printing its `--help` is given to finish
+const HELP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Flag of a command along with its description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flag {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Parses and caches the flags of commands, see the [module level docs](self)
+pub struct HelpFlags {
+    /// Directory the parsed flags are stored in, disk caching is disabled if [None]
+    cache_dir: Option<PathBuf>,
+    /// Flags that were already loaded, along with the modification time of the binary
+    memory: Mutex<HashMap<PathBuf, (SystemTime, Vec<Flag>)>>,
+}
+
+impl HelpFlags {
+    pub fn new(cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            cache_dir,
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the flags of the command at the given path
+    pub fn flags(&self, binary: &Path) -> Vec<Flag> {
+        let Ok(mtime) = binary.metadata().and_then(|metadata| metadata.modified()) else {
+            return vec![];
+        };
+        if let Some((cached_mtime, flags)) = self.memory.lock().unwrap().get(binary) {
+            if *cached_mtime == mtime {
+                return flags.clone();
+            }
+        }
+
+        let flags = match self.read_cache(binary, mtime) {
+            Some(flags) => flags,
+            None => {
+                let flags = parse_help(&help_text(binary).unwrap_or_default());
+                // a failure to cache only means the flags are parsed again next time
+                let _ = self.write_cache(binary, mtime, &flags);
+                flags
+            },
+        };
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(binary.to_path_buf(), (mtime, flags.clone()));
+        flags
+    }
+
+    fn cache_file(&self, binary: &Path) -> Option<PathBuf> {
+        let name = binary.to_string_lossy().replace('/', "%");
+        self.cache_dir.as_ref().map(|dir| dir.join(name))
+    }
+
+    /// Cached flags, if the cache is for the same version of the binary
+    fn read_cache(&self, binary: &Path, mtime: SystemTime) -> Option<Vec<Flag>> {
+        let contents = std::fs::read_to_string(self.cache_file(binary)?).ok()?;
+        let mut lines = contents.lines();
+        if lines.next()? != format_mtime(mtime) {
+            return None;
+        }
+        let flags = lines
+            .map(|line| match line.split_once('\t') {
+                Some((name, description)) => Flag {
+                    name: name.to_string(),
+                    description: Some(description.to_string()),
+                },
+                None => Flag {
+                    name: line.to_string(),
+                    description: None,
+                },
+            })
+            .collect();
+        Some(flags)
+    }
+
+    fn write_cache(&self, binary: &Path, mtime: SystemTime, flags: &[Flag]) -> std::io::Result<()> {
+        let Some(file) = self.cache_file(binary) else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut contents = format_mtime(mtime) + "\n";
+        for flag in flags {
+            contents += &flag.name;
+            if let Some(description) = &flag.description {
+                contents += "\t";
+                contents += description;
+            }
+            contents += "\n";
+        }
+        std::fs::write(file, contents)
+    }
+}
+
+impl Default for HelpFlags {
+    /// Cache flags in the user's cache directory
+    fn default() -> Self {
+        Self::new(dirs::cache_dir().map(|dir| dir.join("shrs/flags")))
+    }
+}
+
+fn format_mtime(mtime: SystemTime) -> String {
+    let since_epoch = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "{}.{:09}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    )
+}
+
+/// Check if we are completing a flag of a command that has no completion spec
+pub fn help_flags_pred(ctx: &CompletionCtx) -> bool {
+    ctx.kind() == &WordKind::Argument && ctx.spec().is_none() && flag_pred(ctx)
+}
+
+/// Complete flags parsed from the man page or `--help` output of the command
+///
+/// Parsing the flags for the first time runs `man` or the command itself, which is done in the
+/// background.
+pub fn help_flags_action(help_flags: HelpFlags) -> AsyncAction {
+    AsyncAction::new(move |ctx: &CompletionCtx| -> Vec<Completion> {
+        let Some(binary) = ctx
+            .cmd_name()
+            .and_then(|cmd_name| find_binary(cmd_name, ctx))
+        else {
+            return vec![];
+        };
+        help_flags
+            .flags(&binary)
+            .into_iter()
+            .map(|flag| Completion {
+                add_space: true,
+                display: None,
+                completion: flag.name,
+                replace_method: ReplaceMethod::Replace,
+                comment: flag.description,
+                score: 0,
+                matched: vec![],
+                kind: CompletionKind::Flag,
+            })
+            .collect()
+    })
+}
+
+/// Resolve a command name to the binary in PATH that would be run
+///
+/// Commands given as a path, such as `./script`, are skipped so that completing them never runs
+/// a program that wasn't installed.
+fn find_binary(cmd_name: &str, ctx: &CompletionCtx) -> Option<PathBuf> {
+    if cmd_name.contains('/') {
+        return None;
+    }
+    let path = ctx
+        .env()
+        .get("PATH")
+        .cloned()
+        .or_else(|_| std::env::var("PATH"))
+        .ok()?;
+    path.split(':')
+        .map(|dir| Path::new(dir).join(cmd_name))
+        .find(|path| path.is_file())
+}
+
+/// Text describing the flags of a command, from its man page or `--help`
+fn help_text(binary: &Path) -> Option<String> {
+    let name = binary.file_name()?.to_str()?;
+    rendered_man_page(name)
+        .or_else(|| roff_man_page(name).map(|roff| roff_to_text(&roff)))
+        .or_else(|| help_output(binary))
+}
+
+/// Man page rendered as plain text by `man`
+fn rendered_man_page(name: &str) -> Option<String> {
+    let output = output_with_timeout(
+        Command::new("man")
+            .args(["-P", "cat", name])
+            .env("MANWIDTH", "200"),
+        HELP_TIMEOUT,
+    )?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return None;
+    }
+    Some(strip_formatting(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Remove overstrikes such as `a\x08a` and escape sequences that make text bold or underlined
+fn strip_formatting(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x08' => {
+                out.pop();
+            },
+            '\x1b' => {
+                // skip until the final byte of the sequence
+                for ch in chars.by_ref() {
+                    if ch.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            },
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Raw roff source of the man page, searched for in `MANPATH` and the usual directories
+fn roff_man_page(name: &str) -> Option<String> {
+    let manpath = std::env::var("MANPATH").unwrap_or_default();
+    let dirs = manpath
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .chain(["/usr/local/share/man", "/usr/share/man"]);
+
+    for dir in dirs {
+        for section in ["1", "8"] {
+            let file = Path::new(dir).join(format!("man{section}/{name}.{section}"));
+            if file.is_file() {
+                return std::fs::read_to_string(file).ok();
+            }
+            let gz = file.with_extension(format!("{section}.gz"));
+            if gz.is_file() {
+                let output = Command::new("gzip")
+                    .arg("-dc")
+                    .arg(gz)
+                    .stderr(Stdio::null())
+                    .output()
+                    .ok()?;
+                return Some(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+        }
+    }
+    None
+}
+
+/// Output of running the command with `--help`, which is killed if it takes too long
+fn help_output(binary: &Path) -> Option<String> {
+    let output = output_with_timeout(Command::new(binary).arg("--help"), HELP_TIMEOUT)?;
+
+    // some commands print their help to stderr
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Some(if stdout.trim().is_empty() {
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    } else {
+        stdout.into_owned()
+    })
+}
+
+/// Indent of the tags and bodies of lists when converting roff to text
+const TAG_INDENT: usize = 7;
+const BODY_INDENT: usize = 14;
+
+/// Convert the roff source of a man page to text laid out like the rendered page
+///
+/// Only the parts that matter for finding flags are handled: lists started with `.TP`, `.IP` or
+/// mdoc's `.It`, font changes and common escapes.
+pub fn roff_to_text(roff: &str) -> String {
+    let mut out = String::new();
+    let mut indent = TAG_INDENT;
+    let mut tag_next = false;
+
+    for line in roff.lines() {
+        let (text, tag) = if let Some(request) = line.strip_prefix(['.', '\'']) {
+            let (name, args) = request
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .unwrap_or((request.trim_start(), ""));
+            match name {
+                // next text line is the tag of a list item
+                "TP" => {
+                    tag_next = true;
+                    continue;
+                },
+                "IP" => {
+                    let tag = roff_args(args).into_iter().next().unwrap_or_default();
+                    indent = BODY_INDENT;
+                    if tag.is_empty() {
+                        continue;
+                    }
+                    (tag, true)
+                },
+                "It" => {
+                    indent = BODY_INDENT;
+                    (mdoc_words(args), true)
+                },
+                // alternating fonts join their arguments without spaces
+                "BR" | "RB" | "BI" | "IB" | "IR" | "RI" => (roff_args(args).concat(), false),
+                "B" | "I" | "SM" => (roff_args(args).join(" "), false),
+                "Fl" | "Op" | "Ar" | "Nm" | "Pa" | "Cm" => {
+                    (mdoc_words(line.trim_start_matches('.')), false)
+                },
+                "SH" | "SS" | "Sh" | "Ss" | "PP" | "LP" | "P" | "Pp" | "HP" => {
+                    indent = TAG_INDENT;
+                    out.push('\n');
+                    continue;
+                },
+                // other requests don't produce text
+                _ => continue,
+            }
+        } else {
+            (roff_escapes(line), false)
+        };
+
+        let tag = tag || std::mem::take(&mut tag_next);
+        let line_indent = if tag {
+            indent = BODY_INDENT;
+            TAG_INDENT
+        } else {
+            indent
+        };
+        out += &" ".repeat(line_indent);
+        out += text.trim();
+        out.push('\n');
+    }
+    out
+}
+
+/// Split the arguments of a roff request, which may be quoted
+fn roff_args(args: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    for ch in roff_escapes(args).chars() {
+        match ch {
+            '"' => quoted = !quoted,
+            ch if ch.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            ch => word.push(ch),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Convert mdoc macros such as `Fl a Ar file` to text such as `-a file`
+fn mdoc_words(args: &str) -> String {
+    let mut out = vec![];
+    let mut flag = false;
+    for word in roff_args(args) {
+        match word.as_str() {
+            "Fl" => flag = true,
+            "Ar" | "Op" | "Oo" | "Oc" | "Cm" | "Pa" | "Nm" | "Ns" | "Li" | "Ic" => {},
+            word if flag => {
+                out.push(format!("-{word}"));
+                flag = false;
+            },
+            word => out.push(word.to_string()),
+        }
+    }
+    // a lone `Fl` is a single dash
+    if flag {
+        out.push("-".into());
+    }
+    out.join(" ")
+}
+
+/// Replace the escapes of roff text with the characters they stand for
+fn roff_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('-') => out.push('-'),
+            Some('e') | Some('\\') => out.push('\\'),
+            Some(' ') | Some('~') => out.push(' '),
+            // comment
+            Some('"') => break,
+            // font changes such as \fB or \f(CW
+            Some('f') => match chars.next() {
+                Some('(') => {
+                    chars.next();
+                    chars.next();
+                },
+                Some('[') => for _ in chars.by_ref().take_while(|ch| *ch != ']') {},
+                _ => {},
+            },
+            // special characters such as \(em
+            Some('(') => {
+                let special = chars.by_ref().take(2).collect::<String>();
+                out.push_str(match special.as_str() {
+                    "em" | "en" | "hy" | "mi" => "-",
+                    "aq" | "cq" | "oq" => "'",
+                    "dq" | "lq" | "rq" => "\"",
+                    "bu" => "*",
+                    _ => "",
+                });
+            },
+            // strings such as \*(lq
+            Some('*') => match chars.next() {
+                Some('(') => {
+                    chars.next();
+                    chars.next();
+                },
+                Some('[') => for _ in chars.by_ref().take_while(|ch| *ch != ']') {},
+                _ => {},
+            },
+            // zero width characters
+            Some('&') | Some('|') | Some('^') | Some('c') | Some(')') => {},
+            Some(ch) => out.push(ch),
+            None => {},
+        }
+    }
+    out
+}
+
+/// Find the flags and their descriptions in `--help` output or a rendered man page
+///
+/// Flags are expected at the start of an indented line, such as `  -a, --all  do not ignore`.
+/// The description either follows on the same line after two or more spaces, or on the next
+/// line with more indentation, which is then shared by all the flags of the lines above.
+pub fn parse_help(text: &str) -> Vec<Flag> {
+    let mut flags = vec![];
+    // flags that are waiting for a description on a later line, and the indent of their line
+    let mut pending: Vec<String> = vec![];
+    let mut pending_indent = 0;
+
+    for line in text.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some((names, description)) = parse_flag_line(trimmed) {
+            if !pending.is_empty() && indent != pending_indent {
+                flush(&mut flags, &mut pending, None);
+            }
+            pending.extend(names);
+            pending_indent = indent;
+            if description.is_some() {
+                flush(&mut flags, &mut pending, description);
+            }
+        } else if !pending.is_empty() {
+            let description = (indent > pending_indent).then(|| clean(trimmed));
+            flush(&mut flags, &mut pending, description);
+        }
+    }
+    flush(&mut flags, &mut pending, None);
+
+    // keep the first description of flags that are documented more than once
+    let mut seen = std::collections::HashSet::new();
+    flags.retain(|flag| seen.insert(flag.name.clone()));
+    flags
+}
+
+fn flush(flags: &mut Vec<Flag>, pending: &mut Vec<String>, description: Option<String>) {
+    for name in pending.drain(..) {
+        flags.push(Flag {
+            name,
+            description: description.clone(),
+        });
+    }
+}
+
+/// Split a line into its flags and the description after them, if the line starts with flags
+fn parse_flag_line(line: &str) -> Option<(Vec<String>, Option<String>)> {
+    if !line.starts_with('-') {
+        return None;
+    }
+    // the description is separated from the flags by a gap of spaces or a tab
+    let (tag, description) = match line.find("  ").into_iter().chain(line.find('\t')).min() {
+        Some(i) => (&line[..i], Some(clean(&line[i..]))),
+        None => (line, None),
+    };
+
+    let mut names = vec![];
+    for word in tag.split([',', ' ', '|']).filter(|word| !word.is_empty()) {
+        if !word.starts_with('-') {
+            // the argument of the flag such as `FILE`
+            continue;
+        }
+        let name = word
+            .split(['=', '[', '<'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(['.', ':', ')']);
+        let valid = name.len() > 1
+            && name != "--"
+            && name
+                .trim_start_matches('-')
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '?');
+        if !valid {
+            return None;
+        }
+        names.push(name.to_string());
+    }
+    if names.is_empty() {
+        return None;
+    }
+    Some((
+        names,
+        description.filter(|description| !description.is_empty()),
+    ))
+}
+
+/// Collapse the whitespace in a description
+fn clean(description: &str) -> String {
+    description.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::{parse_help, roff_to_text, strip_formatting, Flag, HelpFlags};
+
+    fn flags(text: &str) -> Vec<(String, Option<String>)> {
+        parse_help(text)
+            .into_iter()
+            .map(|flag| (flag.name, flag.description))
+            .collect()
+    }
+
+    fn flag(name: &str, description: &str) -> (String, Option<String>) {
+        (name.into(), Some(description.into()))
+    }
+
+    #[test]
+    fn help_output() {
+        let gnu = "Usage: ls [OPTION]... [FILE]...
+  -a, --all                  do not ignore entries starting with .
+      --block-size=SIZE      with -l, scale sizes by SIZE when printing sizes;
+                               e.g., '--block-size=M'; see SIZE format below
+      --color[=WHEN]         color the output WHEN
+  -w, --width=COLS           set output width to COLS.  0 means no limit
+";
+        assert_eq!(
+            flags(gnu),
+            vec![
+                flag("-a", "do not ignore entries starting with ."),
+                flag("--all", "do not ignore entries starting with ."),
+                flag(
+                    "--block-size",
+                    "with -l, scale sizes by SIZE when printing sizes;"
+                ),
+                flag("--color", "color the output WHEN"),
+                flag("-w", "set output width to COLS. 0 means no limit"),
+                flag("--width", "set output width to COLS. 0 means no limit"),
+            ]
+        );
+
+        let clap = "Options:
+  -v, --verbose
+          Use verbose output
+
+  -h, --help
+          Print help
+";
+        assert_eq!(
+            flags(clap),
+            vec![
+                flag("-v", "Use verbose output"),
+                flag("--verbose", "Use verbose output"),
+                flag("-h", "Print help"),
+                flag("--help", "Print help"),
+            ]
+        );
+    }
+
+    #[test]
+    fn man_pages() {
+        let rendered = "OPTIONS\n       \x1b[1m-a\x1b[0m, --all\n              do not ignore\n";
+        assert_eq!(
+            flags(&strip_formatting(rendered)),
+            vec![flag("-a", "do not ignore"), flag("--all", "do not ignore")]
+        );
+        assert_eq!(strip_formatting("-\x08-a\x08ab\x08b"), "-ab");
+
+        let roff = r#".SH OPTIONS
+.TP
+\fB\-a\fR, \fB\-\-all\fR
+do not ignore entries starting with .
+.IP "\fB\-e\fR \fIfilename\fR" 4
+.PD 0
+.IP "\fB\-\-exe=\fR\fIfilename\fR" 4
+.PD
+Specify the name of the executable
+.Bl -tag -width Ds
+.It Fl p Ar port
+Port to connect to
+"#;
+        assert_eq!(
+            flags(&roff_to_text(roff)),
+            vec![
+                flag("-a", "do not ignore entries starting with ."),
+                flag("--all", "do not ignore entries starting with ."),
+                flag("-e", "Specify the name of the executable"),
+                flag("--exe", "Specify the name of the executable"),
+                flag("-p", "Port to connect to"),
+            ]
+        );
+    }
+
+    #[test]
+    fn disk_cache() {
+        let dir = std::env::temp_dir().join(format!("shrs_help_flags_{}", std::process::id()));
+        let help_flags = HelpFlags::new(Some(dir.clone()));
+        let binary = std::path::Path::new("/bin/sh");
+        let cached = vec![Flag {
+            name: "--cached".into(),
+            description: Some("from the cache".into()),
+        }];
+
+        help_flags.write_cache(binary, UNIX_EPOCH, &cached).unwrap();
+        assert_eq!(help_flags.read_cache(binary, UNIX_EPOCH), Some(cached));
+        // a different modification time means the binary changed
+        let later = UNIX_EPOCH + std::time::Duration::from_secs(1);
+        assert_eq!(help_flags.read_cache(binary, later), None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
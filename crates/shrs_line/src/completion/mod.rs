@@ -3,21 +3,63 @@
 mod completer;
 pub use completer::*;
 
+mod ctx;
+pub use ctx::*;
+
+mod spec;
+pub use spec::*;
+
+mod help_flags;
+pub use help_flags::*;
+
+mod cli;
+pub use cli::*;
+
+mod async_action;
+pub use async_action::*;
+
 mod utils;
 pub use utils::*;
 
 mod data;
 
 /// How should the completion be substituted
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum ReplaceMethod {
     /// Append the returned value after the cursor
     Append,
     /// Replace the last word
+    #[default]
     Replace,
 }
 
-#[derive(Clone)]
+/// What a completion is, used by the menu to group, color and add icons to completions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CompletionKind {
+    /// Subcommand or other word that runs something
+    Command,
+    File,
+    /// Directory, which is completed with a trailing `/` and no space after it
+    Directory,
+    Symlink,
+    /// Executable file or program in `PATH`
+    Executable,
+    Builtin,
+    Alias,
+    /// Shell function
+    Function,
+    /// Option such as `--verbose`
+    Flag,
+    /// Environment variable
+    Variable,
+    /// Git branch, tag or other reference
+    GitRef,
+    Host,
+    #[default]
+    Other,
+}
+
+#[derive(Clone, Default)]
 pub struct Completion {
     /// If space should be added after completion
     pub add_space: bool,
@@ -29,21 +71,59 @@ pub struct Completion {
     pub replace_method: ReplaceMethod,
     /// Additional helpful information about the completion
     pub comment: Option<String>,
+    /// How well the completion matched the current word, higher is better. Set by the completer
+    pub score: i64,
+    /// Char indices into the completion value of the characters matched by the current word
+    pub matched: Vec<usize>,
+    /// What the completion is
+    pub kind: CompletionKind,
 }
 
 impl Completion {
     /// Used to provide user friendly preview of what value will be completed
     pub fn display(&self) -> String {
-        self.display.clone().unwrap_or(self.completion.clone())
+        let display = self.display.clone().unwrap_or(self.completion.clone());
+        if self.kind == CompletionKind::Directory && !display.ends_with('/') {
+            return display + "/";
+        }
+        display
     }
     /// Get actual value to be used when accepting completion
+    ///
+    /// Directories always end with a `/` and are not followed by a space, so that the path can be
+    /// completed further.
     pub fn accept(&self) -> String {
         let mut output = self.completion.clone();
+        if self.kind == CompletionKind::Directory {
+            if !output.ends_with('/') {
+                output.push('/');
+            }
+            return output;
+        }
         if self.add_space {
             output += " ";
         }
         output
     }
+    /// Char indices into the display value of the characters matched by the current word
+    ///
+    /// Only available if the display value is contained in the completion value, like a filename
+    /// is in its path.
+    pub fn display_matched(&self) -> Vec<usize> {
+        let display = self.display();
+        // the slash added after directories isn't part of the completion
+        let display = display.strip_suffix('/').unwrap_or(&display);
+        let Some(offset) = self.completion.rfind(display) else {
+            return vec![];
+        };
+        let offset = self.completion[..offset].chars().count();
+        let len = display.chars().count();
+        self.matched
+            .iter()
+            .filter(|i| (offset..offset + len).contains(i))
+            .map(|i| i - offset)
+            .collect()
+    }
 }
 
 /// Implement this trait to define your own tab completion system
@@ -52,34 +132,6 @@ pub trait Completer {
     fn complete(&self, ctx: &CompletionCtx) -> Vec<Completion>;
 }
 
-pub struct CompletionCtx {
-    /// The currently entered line split by arguments
-    ///
-    /// The cursor position is after the very last argument
-    line: Vec<String>,
-}
-
-impl CompletionCtx {
-    pub fn new(line: Vec<String>) -> Self {
-        Self { line }
-    }
-
-    /// Get the name of the command
-    pub fn cmd_name(&self) -> Option<&String> {
-        self.line.get(0)
-    }
-
-    /// Get the word that the user is currently typing
-    pub fn cur_word(&self) -> Option<&String> {
-        self.line.last()
-    }
-
-    /// Which argument are we currently on
-    pub fn arg_num(&self) -> usize {
-        self.line.len().saturating_sub(1)
-    }
-}
-
 #[cfg(test)]
 mod tests {
 
@@ -0,0 +1,348 @@
+//! Completions from completion specs and from programs that complete themselves
+//!
+//! Specs are defined with the `complete` builtin or loaded from spec files, see
+//! [CompletionSpecs](shrs_core::complete::CompletionSpecs).
+
+use std::{process::Command, time::Duration};
+
+use shrs_core::complete::{CompleteSpec, ExternalCompleter};
+
+use super::{
+    filename_action, output_with_timeout, unquote, AsyncAction, Completion, CompletionCtx,
+    CompletionKind, ReplaceMethod, WordKind,
+};
+
+/// How long a program is given to print its completions
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Cobra directive to not complete files if there are no completions
+const COBRA_NO_FILE_COMP: u32 = 4;
+/// Cobra directive to not add a space after the completion
+const COBRA_NO_SPACE: u32 = 2;
+/// Cobra directive that an error occurred
+const COBRA_ERROR: u32 = 1;
+
+/// Check if we are completing an argument of a command that has a completion spec
+pub fn spec_pred(ctx: &CompletionCtx) -> bool {
+    ctx.kind() == &WordKind::Argument && ctx.spec().is_some()
+}
+
+/// Check if we are completing an argument of a command that completes its arguments itself
+pub fn spec_external_pred(ctx: &CompletionCtx) -> bool {
+    spec_pred(ctx) && ctx.spec().is_some_and(|spec| spec.external.is_some())
+}
+
+/// Check if files should be completed, which completion specs can turn off
+pub fn spec_files_pred(ctx: &CompletionCtx) -> bool {
+    let Some(spec) = ctx.spec() else {
+        return true;
+    };
+    // external completers complete files themselves
+    if spec.external.is_some() {
+        return false;
+    }
+
+    let args = args(ctx);
+    let specs = applicable(&spec.specs, &args);
+    if let Some(option) = param_option(&specs, &args) {
+        return !option.no_files || option.force_files;
+    }
+    let positional = specs.iter().filter(|spec| !spec.has_options());
+    positional.clone().any(|spec| spec.force_files) || !positional.clone().any(|spec| spec.no_files)
+}
+
+/// Complete options and arguments from the completion spec of the command
+pub fn spec_action(ctx: &CompletionCtx) -> Vec<Completion> {
+    let Some(spec) = ctx.spec() else {
+        return vec![];
+    };
+    // completed by spec_external_action
+    if spec.external.is_some() {
+        return vec![];
+    }
+
+    let args = args(ctx);
+    let specs = applicable(&spec.specs, &args);
+
+    // parameter of the previous option
+    if let Some(option) = param_option(&specs, &args) {
+        return arguments(option);
+    }
+
+    if ctx.cur_word().is_some_and(|word| word.starts_with('-')) {
+        specs.iter().flat_map(|spec| options(spec)).collect()
+    } else {
+        specs
+            .iter()
+            .filter(|spec| !spec.has_options())
+            .flat_map(|spec| arguments(spec))
+            .collect()
+    }
+}
+
+/// Words before the one being completed, not including the command name
+fn args(ctx: &CompletionCtx) -> Vec<String> {
+    ctx.words()
+        .get(1..ctx.arg_num())
+        .unwrap_or_default()
+        .iter()
+        .map(|word| unquote(word))
+        .collect()
+}
+
+/// Specs whose condition holds
+fn applicable<'a>(specs: &'a [CompleteSpec], args: &[String]) -> Vec<&'a CompleteSpec> {
+    specs
+        .iter()
+        .filter(|spec| {
+            spec.condition
+                .as_ref()
+                .is_none_or(|condition| condition.test(args))
+        })
+        .collect()
+}
+
+/// Spec of the previous word if it is an option that takes a parameter
+fn param_option<'a>(specs: &[&'a CompleteSpec], args: &[String]) -> Option<&'a CompleteSpec> {
+    let prev = args.last()?;
+    specs
+        .iter()
+        .find(|spec| spec.requires_param && spec.matches_option(prev))
+        .copied()
+}
+
+fn options(spec: &CompleteSpec) -> Vec<Completion> {
+    let long = spec.long.iter().map(|long| format!("--{long}"));
+    let short = spec.short.iter().map(|short| format!("-{short}"));
+    let old = spec.old.iter().map(|old| format!("-{old}"));
+    long.chain(short)
+        .chain(old)
+        .map(|option| Completion {
+            add_space: true,
+            display: None,
+            completion: option,
+            replace_method: ReplaceMethod::Replace,
+            comment: spec.description.clone(),
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Flag,
+        })
+        .collect()
+}
+
+fn arguments(spec: &CompleteSpec) -> Vec<Completion> {
+    spec.arguments
+        .iter()
+        .map(|arg| Completion {
+            add_space: true,
+            display: None,
+            completion: arg.value.clone(),
+            replace_method: ReplaceMethod::Replace,
+            comment: arg.description.clone().or(spec.description.clone()),
+            score: 0,
+            matched: vec![],
+            kind: CompletionKind::Other,
+        })
+        .collect()
+}
+
+/// Ask the program for its completions
+///
+/// Runs in the background, so a slow program doesn't freeze the line. It is killed if it takes
+/// longer than two seconds.
+pub fn spec_external_action() -> AsyncAction {
+    AsyncAction::new(external_action).with_timeout(EXTERNAL_TIMEOUT)
+}
+
+fn external_action(ctx: &CompletionCtx) -> Vec<Completion> {
+    let (Some(cmd_name), Some(external)) = (ctx.cmd_name(), ctx.spec().and_then(|s| s.external))
+    else {
+        return vec![];
+    };
+    let args = args(ctx);
+    let cur_word = ctx.unquoted_word();
+
+    let mut command = Command::new(cmd_name);
+    match external {
+        ExternalCompleter::Clap => {
+            command
+                .env("COMPLETE", "fish")
+                .arg("--")
+                .arg(cmd_name)
+                .args(&args)
+                .arg(&cur_word);
+        },
+        ExternalCompleter::Cobra => {
+            command.arg("__complete").args(&args).arg(&cur_word);
+        },
+    }
+    let Some(output) =
+        output_with_timeout(command.current_dir(ctx.working_dir()), EXTERNAL_TIMEOUT)
+    else {
+        return vec![];
+    };
+    if !output.status.success() {
+        return vec![];
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+
+    // cobra ends the output with a line such as `:4` holding flags for the shell
+    let directive = match external {
+        ExternalCompleter::Cobra => lines
+            .pop()
+            .and_then(|line| line.strip_prefix(':')?.parse::<u32>().ok())
+            .unwrap_or(0),
+        ExternalCompleter::Clap => 0,
+    };
+    if directive & COBRA_ERROR != 0 {
+        return vec![];
+    }
+
+    let completions = parse_external(&lines, directive & COBRA_NO_SPACE == 0);
+    if completions.is_empty()
+        && external == ExternalCompleter::Cobra
+        && directive & COBRA_NO_FILE_COMP == 0
+    {
+        return filename_action(ctx);
+    }
+    completions
+}
+
+/// Parse lines of a value optionally followed by a tab and its description
+fn parse_external(lines: &[&str], add_space: bool) -> Vec<Completion> {
+    lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (value, description) = match line.split_once('\t') {
+                Some((value, description)) => (value, Some(description.to_string())),
+                None => (*line, None),
+            };
+            Completion {
+                // paths are completed one directory at a time
+                add_space: add_space && !value.ends_with('/'),
+                display: None,
+                completion: value.to_string(),
+                replace_method: ReplaceMethod::Replace,
+                comment: description.filter(|description| !description.is_empty()),
+                score: 0,
+                matched: vec![],
+                kind: if value.starts_with('-') {
+                    CompletionKind::Flag
+                } else if value.ends_with('/') {
+                    CompletionKind::Directory
+                } else {
+                    CompletionKind::Other
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, thread, time::Duration};
+
+    use shrs_core::complete::{CommandSpec, CompletionSpecs, ExternalCompleter};
+
+    use super::{parse_external, spec_action, spec_external_action, spec_files_pred};
+    use crate::completion::{completions_loading, CompletionCtx};
+
+    #[test]
+    fn specs() {
+        let mut specs = CompletionSpecs::new();
+        for line in [
+            "-c tool -s v -l verbose -d 'More output'",
+            "-c tool -n __fish_use_subcommand -f -a 'build\\tBuild\\ it run'",
+            "-c tool -n '__fish_seen_subcommand_from run' -l target -x -a 'debug release'",
+        ] {
+            specs
+                .complete(&shrs_core::complete::split_words(line))
+                .unwrap();
+        }
+        let spec = specs.get("tool").cloned();
+
+        let complete = |words: &[&str]| {
+            let ctx = CompletionCtx::new(words.iter().map(|s| s.to_string()).collect())
+                .with_spec(spec.clone());
+            let mut completions = spec_action(&ctx)
+                .into_iter()
+                .map(|c| c.completion)
+                .collect::<Vec<_>>();
+            completions.sort();
+            (completions, spec_files_pred(&ctx))
+        };
+
+        assert_eq!(
+            complete(&["tool", ""]),
+            (vec!["build".into(), "run".into()], false)
+        );
+        assert_eq!(
+            complete(&["tool", "-"]),
+            (vec!["--verbose".into(), "-v".into()], false)
+        );
+        assert_eq!(
+            complete(&["tool", "run", "-"]),
+            (
+                vec!["--target".into(), "--verbose".into(), "-v".into()],
+                true
+            )
+        );
+        assert_eq!(
+            complete(&["tool", "run", "--target", ""]),
+            (vec!["debug".into(), "release".into()], false)
+        );
+    }
+
+    #[test]
+    fn external_output() {
+        let completions = parse_external(&["--help\tPrint help", "src/", "plain"], true);
+        assert_eq!(completions[0].comment.as_deref(), Some("Print help"));
+        assert!(!completions[1].add_space);
+        assert!(completions[2].add_space);
+        assert_eq!(completions[2].comment, None);
+    }
+
+    #[test]
+    fn external_in_background() {
+        let dir = std::env::temp_dir().join(format!("shrs-external-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let go = dir.join("go");
+        let program = dir.join("tool");
+        // the program only answers once the test lets it
+        fs::write(
+            &program,
+            format!(
+                "#!/bin/sh\nwhile [ ! -e {} ]; do sleep 0.01; done\necho 'main\tBranch'\necho :4\n",
+                go.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let ctx = CompletionCtx::new(vec![program.display().to_string(), "m".into()]).with_spec(
+            Some(CommandSpec {
+                external: Some(ExternalCompleter::Cobra),
+                ..Default::default()
+            }),
+        );
+        let action = spec_external_action().with_wait(Duration::ZERO);
+        assert!(action.complete(&ctx).is_empty());
+        assert!(completions_loading(&ctx));
+
+        fs::write(&go, "").unwrap();
+        for _ in 0..1000 {
+            if !completions_loading(&ctx) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let completions = action.complete(&ctx);
+        assert_eq!(completions[0].completion, "main");
+        assert_eq!(completions[0].comment.as_deref(), Some("Branch"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
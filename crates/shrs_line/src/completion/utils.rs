@@ -1,6 +1,14 @@
 //! Collection of completion functions
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    time::{Duration, Instant},
+};
+
+use super::CompletionKind;
 
 // also provide some commonly used completion lists
 // - directories
@@ -33,36 +41,169 @@ pub(crate) fn filepaths(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
     filepaths_p(dir, |_| true)
 }
 
-/// Generate list of all executables in PATH
-fn executables(_dir: &Path) -> std::io::Result<Vec<String>> {
-    todo!()
+/// Generate list of all executables in a directory
+fn executables(dir: &Path) -> std::io::Result<Vec<String>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let execs = filepaths_p(dir, |dir_entry| {
+        // check if file is executable
+        dir_entry
+            .metadata()
+            .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    })?
+    .iter()
+    .filter_map(|path| path.file_name()?.to_str().map(String::from))
+    .collect();
+
+    Ok(execs)
+}
+
+/// Kind of completion for a file, directories include symlinks to directories
+pub(crate) fn file_kind(path: &Path) -> CompletionKind {
+    if path.is_dir() {
+        return CompletionKind::Directory;
+    }
+    let Ok(metadata) = path.symlink_metadata() else {
+        return CompletionKind::File;
+    };
+    if metadata.is_symlink() {
+        CompletionKind::Symlink
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        CompletionKind::Executable
+    } else {
+        CompletionKind::File
+    }
 }
 
-/// Generate list of all ssh hosts
-fn ssh_hosts(_dir: &Path) -> std::io::Result<Vec<String>> {
-    todo!()
+/// Run a command and collect its output, killing it if it hasn't exited after `timeout`
+///
+/// Used to run programs while completing, where a program that hangs would freeze the line.
+pub(crate) fn output_with_timeout(command: &mut Command, timeout: Duration) -> Option<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    // read on other threads so that a lot of output can't block the command
+    let read = |mut pipe: Box<dyn Read + Send>| {
+        std::thread::spawn(move || {
+            let mut buf = vec![];
+            let _ = pipe.read_to_end(&mut buf);
+            buf
+        })
+    };
+    let stdout = read(Box::new(child.stdout.take()?));
+    let stderr = read(Box::new(child.stderr.take()?));
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().ok()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    Some(Output {
+        status,
+        stdout: stdout.join().ok()?,
+        stderr: stderr.join().ok()?,
+    })
 }
 
 /// Looks through each directory in path and finds executables
 pub(crate) fn find_executables_in_path(path_str: &str) -> Vec<String> {
-    use std::{fs, os::unix::fs::PermissionsExt};
-
-    let mut execs = vec![];
-    for path in path_str.split(':') {
-        let dir = match fs::read_dir(path) {
-            Ok(dir) => dir,
-            Err(_) => continue,
-        };
-        for file in dir {
-            if let Ok(dir_entry) = file {
-                // check if file is executable
-                if dir_entry.metadata().unwrap().permissions().mode() & 0o111 != 0 {
-                    execs.push(dir_entry.file_name().to_str().unwrap().into());
-                }
-            }
-        }
-    }
-    execs
+    path_str
+        .split(':')
+        .flat_map(|path| executables(Path::new(path)).unwrap_or_default())
+        .collect()
+}
+
+/// Generate list of all users and their home directories from `/etc/passwd`
+pub(crate) fn users() -> Vec<(String, PathBuf)> {
+    std::fs::read_to_string("/etc/passwd")
+        .map(|contents| parse_passwd(&contents))
+        .unwrap_or_default()
+}
+
+fn parse_passwd(contents: &str) -> Vec<(String, PathBuf)> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            // name:password:uid:gid:gecos:home:shell
+            let fields = line.split(':').collect::<Vec<_>>();
+            let home = fields.get(5)?;
+            Some((fields[0].to_string(), PathBuf::from(home)))
+        })
+        .collect()
+}
+
+/// Generate list of all hosts from `/etc/hosts` and the ssh known hosts and config in the home
+/// directory, without duplicates
+pub(crate) fn ssh_hosts(home: &Path) -> Vec<String> {
+    let read = |path: &Path| std::fs::read_to_string(path).unwrap_or_default();
+
+    let mut hosts = parse_etc_hosts(&read(Path::new("/etc/hosts")));
+    hosts.append(&mut parse_known_hosts(&read(
+        &home.join(".ssh/known_hosts"),
+    )));
+    hosts.append(&mut parse_ssh_config(&read(&home.join(".ssh/config"))));
+
+    let mut seen = std::collections::HashSet::new();
+    hosts.retain(|host| seen.insert(host.clone()));
+    hosts
+}
+
+fn parse_etc_hosts(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        // first field is the address, the rest are names
+        .flat_map(|line| line.split_whitespace().skip(1))
+        .map(String::from)
+        .collect()
+}
+
+fn parse_known_hosts(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('@'))
+        .filter_map(|line| line.split_whitespace().next())
+        .flat_map(|hosts| hosts.split(','))
+        // hashed hosts can't be completed
+        .filter(|host| !host.starts_with('|'))
+        .map(|host| {
+            // [host]:port
+            host.strip_prefix('[')
+                .and_then(|host| host.split_once(']'))
+                .map_or(host, |(host, _)| host)
+                .to_string()
+        })
+        .collect()
+}
+
+fn parse_ssh_config(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line
+                .trim()
+                .split_once(|c: char| c.is_whitespace() || c == '=')?;
+            key.eq_ignore_ascii_case("host").then_some(value)
+        })
+        .flat_map(|value| value.split_whitespace())
+        // patterns don't name a single host
+        .filter(|host| !host.contains(['*', '?', '!']))
+        .map(String::from)
+        .collect()
 }
 
 /// Drop everything after the last / character
@@ -86,7 +227,33 @@ pub(crate) fn path_end(path: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::drop_path_end;
+    use std::{
+        path::PathBuf,
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    use super::{
+        drop_path_end, output_with_timeout, parse_etc_hosts, parse_known_hosts, parse_passwd,
+        parse_ssh_config,
+    };
+
+    #[test]
+    fn timeout() {
+        let output = output_with_timeout(
+            Command::new("sh").args(["-c", "echo out; echo err >&2"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        let start = Instant::now();
+        let output =
+            output_with_timeout(Command::new("sleep").arg("10"), Duration::from_millis(100));
+        assert!(output.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
 
     #[test]
     fn test_drop_path_end() {
@@ -94,4 +261,28 @@ mod tests {
         assert_eq!(drop_path_end("Downloads/"), "Downloads/".to_owned());
         assert_eq!(drop_path_end("Downloads"), "".to_owned());
     }
+
+    #[test]
+    fn users_and_hosts() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\nbin:x:1:1::/bin:/sbin/nologin\n";
+        assert_eq!(
+            parse_passwd(passwd),
+            vec![
+                ("root".into(), PathBuf::from("/root")),
+                ("bin".into(), PathBuf::from("/bin"))
+            ]
+        );
+
+        let hosts = "127.0.0.1 localhost # loopback\n# comment\n10.0.0.2\tnas nas.lan\n";
+        assert_eq!(parse_etc_hosts(hosts), vec!["localhost", "nas", "nas.lan"]);
+
+        let known_hosts = "github.com,140.82.112.3 ssh-ed25519 AAAA\n[git.lan]:2222 ssh-rsa AAAA\n|1|abc= ssh-rsa AAAA\n";
+        assert_eq!(
+            parse_known_hosts(known_hosts),
+            vec!["github.com", "140.82.112.3", "git.lan"]
+        );
+
+        let config = "Host work build\n  HostName 10.0.0.3\nHost *.lan !bad\nhost=pi\n";
+        assert_eq!(parse_ssh_config(config), vec!["work", "build", "pi"]);
+    }
 }
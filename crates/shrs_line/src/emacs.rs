@@ -0,0 +1,250 @@
+//! Emacs mode for shrs_line
+use std::collections::VecDeque;
+
+use shrs_utils::cursor_buffer::{CursorBuffer, Location, Result};
+
+/// Case conversion applied to a word by [EmacsCursorBuffer::change_word_case]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordCase {
+    /// Uppercase the whole word (`M-u`)
+    Upper,
+    /// Lowercase the whole word (`M-l`)
+    Lower,
+    /// Uppercase the first character and lowercase the rest (`M-c`)
+    Capitalize,
+}
+
+/// Emacs words are made of alphanumeric characters, unlike vi words which are split on whitespace
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric()
+}
+
+/// Extension trait to [CursorBuffer] that implements emacs style editing commands
+pub trait EmacsCursorBuffer {
+    /// Location of the end of the current or next word (`M-f`)
+    fn forward_word(&self) -> Location;
+    /// Location of the start of the current or previous word (`M-b`)
+    fn backward_word(&self) -> Location;
+    /// Location of the start of the previous whitespace delimited word (`C-w`)
+    fn backward_unix_word(&self) -> Location;
+    /// Remove the text between the cursor and a location and return it
+    fn kill(&mut self, loc: Location) -> Result<String>;
+    /// Drag the character before the cursor forward over the character at the cursor (`C-t`)
+    fn transpose_chars(&mut self) -> Result<()>;
+    /// Change the case of the next word and move the cursor past it
+    fn change_word_case(&mut self, case: WordCase) -> Result<()>;
+}
+
+impl EmacsCursorBuffer for CursorBuffer {
+    fn forward_word(&self) -> Location {
+        let chars = self.as_str().chars().collect::<Vec<_>>();
+        let mut i = self.cursor();
+        while i < chars.len() && !is_word_char(chars[i]) {
+            i += 1;
+        }
+        while i < chars.len() && is_word_char(chars[i]) {
+            i += 1;
+        }
+        Location::Abs(i)
+    }
+
+    fn backward_word(&self) -> Location {
+        let chars = self.as_str().chars().collect::<Vec<_>>();
+        let mut i = self.cursor();
+        while i > 0 && !is_word_char(chars[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word_char(chars[i - 1]) {
+            i -= 1;
+        }
+        Location::Abs(i)
+    }
+
+    fn backward_unix_word(&self) -> Location {
+        let chars = self.as_str().chars().collect::<Vec<_>>();
+        let mut i = self.cursor();
+        while i > 0 && chars[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        while i > 0 && !chars[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        Location::Abs(i)
+    }
+
+    fn kill(&mut self, loc: Location) -> Result<String> {
+        let killed = self.location_slice(Location::Cursor(), loc)?.to_string();
+        self.delete(Location::Cursor(), loc)?;
+        Ok(killed)
+    }
+
+    fn transpose_chars(&mut self) -> Result<()> {
+        // at the end of the line the two characters before the cursor are swapped instead
+        let cursor = self.cursor().min(self.len().saturating_sub(1));
+        if cursor == 0 {
+            return Ok(());
+        }
+        let swapped = self
+            .slice(cursor - 1..cursor + 1)
+            .to_string()
+            .chars()
+            .rev()
+            .collect::<String>();
+        self.delete(Location::Abs(cursor - 1), Location::Abs(cursor + 1))?;
+        self.insert(Location::Abs(cursor - 1), &swapped)?;
+        Ok(())
+    }
+
+    fn change_word_case(&mut self, case: WordCase) -> Result<()> {
+        let end = self.to_absolute(self.forward_word())?;
+        let start = self.cursor();
+        let word = self.slice(start..end).to_string();
+
+        let converted = match case {
+            WordCase::Upper => word.to_uppercase(),
+            WordCase::Lower => word.to_lowercase(),
+            WordCase::Capitalize => {
+                // the first alphanumeric character may be preceded by punctuation or whitespace
+                let mut capitalized = String::new();
+                let mut seen_word = false;
+                for ch in word.chars() {
+                    if !seen_word && is_word_char(ch) {
+                        seen_word = true;
+                        capitalized.extend(ch.to_uppercase());
+                    } else {
+                        capitalized.extend(ch.to_lowercase());
+                    }
+                }
+                capitalized
+            },
+        };
+
+        self.delete(Location::Abs(start), Location::Abs(end))?;
+        self.insert(Location::Abs(start), &converted)?;
+        Ok(())
+    }
+}
+
+/// Ring of killed text that can be yanked back into the buffer
+///
+/// Consecutive kills are merged into a single entry, and `M-y` directly after a yank cycles
+/// through older entries.
+pub struct KillRing {
+    entries: VecDeque<String>,
+    capacity: usize,
+    /// Entry that was last yanked
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn new(capacity: usize) -> Self {
+        KillRing {
+            entries: VecDeque::new(),
+            capacity,
+            yank_index: 0,
+        }
+    }
+
+    /// Add newly killed text as the most recent entry
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        self.entries.push_front(text);
+        self.entries.truncate(self.capacity);
+    }
+
+    /// Extend the most recent entry, placing the text before it if `prepend` is set (used by
+    /// backward kills)
+    pub fn extend(&mut self, text: String, prepend: bool) {
+        match self.entries.front_mut() {
+            Some(front) if prepend => front.insert_str(0, &text),
+            Some(front) => front.push_str(&text),
+            None => self.push(text),
+        }
+    }
+
+    /// Most recently killed text
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.entries.front().map(|s| s.as_str())
+    }
+
+    /// Rotate to the next older entry, wrapping around to the most recent one
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.entries.len();
+        self.entries.get(self.yank_index).map(|s| s.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for KillRing {
+    fn default() -> Self {
+        KillRing::new(16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shrs_utils::cursor_buffer::{CursorBuffer, Location, Result};
+
+    use super::{EmacsCursorBuffer, KillRing, WordCase};
+
+    #[test]
+    fn word_motions() -> Result<()> {
+        let mut cb = CursorBuffer::from_str("git commit --amend");
+
+        cb.move_cursor(cb.forward_word())?;
+        assert_eq!(cb.cursor(), 3);
+        cb.move_cursor(cb.forward_word())?;
+        assert_eq!(cb.cursor(), 10);
+        cb.move_cursor(cb.forward_word())?;
+        assert_eq!(cb.cursor(), 18);
+
+        cb.move_cursor(cb.backward_word())?;
+        assert_eq!(cb.cursor(), 13);
+        assert_eq!(cb.to_absolute(cb.backward_unix_word())?, 11);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transpose_and_case() -> Result<()> {
+        let mut cb = CursorBuffer::from_str("sl foo bar");
+        cb.move_cursor(Location::Abs(1))?;
+        cb.transpose_chars()?;
+        assert_eq!(cb.as_str(), "ls foo bar");
+        assert_eq!(cb.cursor(), 2);
+
+        cb.change_word_case(WordCase::Upper)?;
+        assert_eq!(cb.as_str(), "ls FOO bar");
+        cb.change_word_case(WordCase::Capitalize)?;
+        assert_eq!(cb.as_str(), "ls FOO Bar");
+        assert_eq!(cb.cursor(), 10);
+
+        // at end of line the last two characters are swapped
+        cb.transpose_chars()?;
+        assert_eq!(cb.as_str(), "ls FOO Bra");
+
+        Ok(())
+    }
+
+    #[test]
+    fn kill_ring() {
+        let mut ring = KillRing::new(2);
+        ring.push("one".into());
+        ring.push("two".into());
+        ring.extend(" three".into(), false);
+        ring.push("four".into());
+
+        assert_eq!(ring.yank(), Some("four"));
+        assert_eq!(ring.yank_pop(), Some("two three"));
+        assert_eq!(ring.yank_pop(), Some("four"));
+    }
+}
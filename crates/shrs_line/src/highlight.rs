@@ -133,7 +133,8 @@ pub fn shrs_rule(buf: &str) -> HashMap<usize, ContentStyle> {
                 | Token::SEMI
                 | Token::DSEMI
                 | Token::AMP
-                | Token::PIPE => {
+                | Token::PIPE
+                | Token::NEWLINE => {
                     is_cmd = true;
                 },
                 _ => (),
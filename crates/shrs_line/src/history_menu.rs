@@ -0,0 +1,281 @@
+//! Fuzzy finder over shell history
+//!
+//! The history menu is opened by a keybinding, for example
+//! ```ignore
+//! let keybinding = keybindings! {
+//!     |sh, ctx, rt|
+//!     "A-r" => { open_history_menu(sh, ctx, rt) },
+//! };
+//! ```
+//! The current line is used as the initial query, typing narrows down the entries and enter places
+//! the selected entry in the line.
+
+use std::{
+    cmp::Reverse,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use crossterm::{
+    cursor::{MoveDown, MoveToColumn, MoveUp},
+    style::{Color, ContentStyle, Print, PrintStyledContent, ResetColor, SetForegroundColor},
+    QueueableCommand,
+};
+use shrs_core::{
+    shell::{Context, Runtime, Shell},
+    theme::Theme,
+};
+use shrs_utils::fuzzy::fuzzy_match;
+
+use crate::{
+    history::HistoryMetadata,
+    menu::{Menu, Out},
+    painter::Painter,
+};
+
+/// Marker placed in the shell state to ask the line editor to open the history menu
+pub struct OpenHistoryMenu;
+
+/// Keybinding function that opens the history menu
+pub fn open_history_menu(_sh: &Shell, ctx: &mut Context, _rt: &mut Runtime) {
+    ctx.state.insert(OpenHistoryMenu);
+}
+
+/// Entry of the history menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryMenuItem {
+    pub command: String,
+    pub metadata: Option<HistoryMetadata>,
+}
+
+/// Menu that fuzzy filters history entries
+pub struct HistoryMenu {
+    /// All entries, most recent first
+    entries: Vec<(String, HistoryMenuItem)>,
+    /// Entries that match the query along with the positions of the matched characters, best
+    /// match first
+    matches: Vec<(usize, Vec<usize>)>,
+    query: String,
+    /// Currently selected match
+    cursor: u32,
+    active: bool,
+    /// Max number of entries to show when rendering the menu
+    limit: usize,
+}
+
+impl HistoryMenu {
+    pub fn new() -> Self {
+        HistoryMenu {
+            entries: vec![],
+            matches: vec![],
+            query: String::new(),
+            cursor: 0,
+            active: false,
+            limit: 10,
+        }
+    }
+
+    pub fn new_with_limit(limit: usize) -> Self {
+        let mut menu = Self::new();
+        menu.limit = limit;
+        menu
+    }
+
+    /// Filter the entries by a new query
+    pub fn set_query(&mut self, query: &str) {
+        self.query = query.to_string();
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        let mut scored = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, item))| {
+                fuzzy_match(&self.query, &item.command).map(|m| (m.score, i, m.positions))
+            })
+            .collect::<Vec<_>>();
+        // stable sort keeps more recent entries first among equal scores
+        scored.sort_by_key(|(score, ..)| Reverse(*score));
+
+        self.matches = scored
+            .into_iter()
+            .map(|(_, i, positions)| (i, positions))
+            .collect();
+        self.cursor = 0;
+    }
+
+    /// Range of matches that fit on screen, scrolled so that the cursor is visible
+    fn visible(&self) -> std::ops::Range<usize> {
+        let start = (self.cursor as usize + 1).saturating_sub(self.limit);
+        start..self.matches.len().min(start + self.limit)
+    }
+}
+
+impl Default for HistoryMenu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Menu for HistoryMenu {
+    type MenuItem = HistoryMenuItem;
+    type PreviewItem = String;
+
+    fn next(&mut self) {
+        if self.cursor as usize >= self.matches.len().saturating_sub(1) {
+            self.cursor = 0;
+        } else {
+            self.cursor += 1;
+        }
+    }
+    fn previous(&mut self) {
+        if self.cursor == 0 {
+            self.cursor = self.matches.len().saturating_sub(1) as u32;
+        } else {
+            self.cursor -= 1;
+        }
+    }
+    fn accept(&mut self) -> Option<&Self::MenuItem> {
+        self.disactivate();
+        self.current_selection()
+    }
+    fn current_selection(&self) -> Option<&Self::MenuItem> {
+        self.matches
+            .get(self.cursor as usize)
+            .map(|(i, _)| &self.entries[*i].1)
+    }
+    fn cursor(&self) -> u32 {
+        self.cursor
+    }
+    fn is_active(&self) -> bool {
+        self.active
+    }
+    fn activate(&mut self) {
+        self.active = true;
+    }
+    fn disactivate(&mut self) {
+        self.active = false;
+    }
+    fn items(&self) -> Vec<&(Self::PreviewItem, Self::MenuItem)> {
+        self.matches
+            .iter()
+            .map(|(i, _)| &self.entries[*i])
+            .collect()
+    }
+    fn set_items(&mut self, items: Vec<(Self::PreviewItem, Self::MenuItem)>) {
+        self.entries = items;
+        self.refilter();
+    }
+
+    fn render(&self, out: &mut Out, painter: &Painter, theme: &Theme) -> anyhow::Result<()> {
+        let width = painter.get_term_size().0 as usize;
+        let now = SystemTime::now();
+        let visible = self.visible();
+
+        for row in visible.clone() {
+            let (i, positions) = &self.matches[row];
+            let (preview, item) = &self.entries[*i];
+            let selected = row == self.cursor as usize;
+
+            out.queue(MoveDown(1))?;
+            out.queue(MoveToColumn(0))?;
+
+            // time since the command was run and its exit status
+            let metadata = item.metadata.clone().unwrap_or_default();
+            let age = metadata
+                .timestamp
+                .and_then(|timestamp| now.duration_since(timestamp).ok())
+                .map(format_relative)
+                .unwrap_or_default();
+            out.queue(SetForegroundColor(Color::DarkGrey))?;
+            out.queue(Print(format!("{age:>4} ")))?;
+            match metadata.exit_status {
+                Some(0) => {
+                    out.queue(SetForegroundColor(Color::Green))?;
+                    out.queue(Print(format!("{:>3} ", 0)))?;
+                },
+                Some(status) => {
+                    out.queue(SetForegroundColor(Color::Red))?;
+                    out.queue(Print(format!("{status:>3} ")))?;
+                },
+                None => {
+                    out.queue(Print("    "))?;
+                },
+            }
+            out.queue(ResetColor)?;
+
+            // command with the characters matched by the query highlighted
+            let text_width = width.saturating_sub(9);
+            for (ci, ch) in preview.chars().take(text_width).enumerate() {
+                let mut style = if selected {
+                    theme.menu_selected
+                } else {
+                    ContentStyle::new()
+                };
+                if positions.contains(&ci) {
+                    let matched = theme.menu_matched;
+                    style.foreground_color = matched.foreground_color.or(style.foreground_color);
+                    style.background_color = matched.background_color.or(style.background_color);
+                    style.attributes.extend(matched.attributes);
+                }
+                out.queue(PrintStyledContent(style.apply(ch)))?;
+            }
+        }
+
+        out.queue(MoveUp(visible.len() as u16))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn required_lines(&self, _painter: &Painter) -> usize {
+        self.visible().len() + 1
+    }
+}
+
+/// Short human readable representation of how long ago something happened
+fn format_relative(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        86400..=604799 => format!("{}d", secs / 86400),
+        _ => format!("{}w", secs / 604800),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryMenu, HistoryMenuItem};
+    use crate::menu::Menu;
+
+    #[test]
+    fn filter() {
+        let mut menu = HistoryMenu::new();
+        let items = ["git status", "cargo test", "git commit", "ls"]
+            .into_iter()
+            .map(|cmd| {
+                let item = HistoryMenuItem {
+                    command: cmd.to_string(),
+                    metadata: None,
+                };
+                (cmd.to_string(), item)
+            })
+            .collect();
+        menu.set_items(items);
+        assert_eq!(menu.items().len(), 4);
+
+        menu.set_query("gco");
+        let commands = menu
+            .items()
+            .iter()
+            .map(|(preview, _)| preview.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["git commit"]);
+
+        menu.set_query("gt");
+        assert_eq!(menu.current_selection().unwrap().command, "git status");
+    }
+}
@@ -1,8 +1,8 @@
 //! Readline implementation for shrs
 //!
 //! Readline is the part of the shell that is responsible for taking in user input. It handles a
-//! variety of things like keeping track of history, syntax highlighting, tab completion, vi mode,
-//! and many more.
+//! variety of things like keeping track of history, syntax highlighting, tab completion, vi and
+//! emacs editing modes, and many more.
 //!
 //! shrs_line has a similar design philosophy to the rest of shrs in that it is also highly
 //! configurable and extensible. Simply construct your own readline and give it to shrs to use.
@@ -20,14 +20,18 @@ extern crate derive_builder;
 pub mod buffer_history;
 pub mod completion;
 pub mod cursor;
+pub mod emacs;
 pub mod highlight;
 pub mod history;
+pub mod history_menu;
 pub mod hooks;
 pub mod keybinding;
 pub mod line;
+pub mod ls_colors;
 pub mod menu;
 pub mod painter;
 pub mod prompt;
+pub mod suggestion;
 pub mod vi;
 
 // TODO kinda ugly rexporting shrs_core here
@@ -40,14 +44,21 @@ pub mod prelude {
         buffer_history::{BufferHistory, DefaultBufferHistory},
         completion::*,
         cursor::CursorStyle,
+        emacs::*,
         highlight::{DefaultHighlighter, Highlighter, RuleFn, SyntaxHighlighter, SyntaxTheme},
-        history::{DefaultHistory, FileBackedHistory, History},
+        history::{
+            DefaultHistory, FileBackedHistory, History, HistoryDedup, HistoryMetadata,
+            SearchDirection,
+        },
+        history_menu::{open_history_menu, HistoryMenu, HistoryMenuItem},
         hooks::*,
         keybinding::{parse_keybinding, BindingFn, DefaultKeybinding, Keybinding},
         line::{Line, LineBuilder, LineBuilderError, LineCtx, LineMode, Readline},
-        menu::{DefaultMenu, Menu},
+        ls_colors::LsColors,
+        menu::{group_by_kind, no_groups, sort_alphabetical, sort_by_score, DefaultMenu, Menu},
         painter::StyledBuf,
         prompt::{DefaultPrompt, Prompt, *},
+        suggestion::{HistorySuggester, Suggester},
         vi::*,
     };
     // Macros
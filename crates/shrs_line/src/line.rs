@@ -1,6 +1,13 @@
 //! Core readline configuration
 
-use std::{borrow::BorrowMut, io::Write, iter::repeat, time::Duration, vec};
+use std::{
+    borrow::BorrowMut,
+    collections::{HashMap, HashSet},
+    io::Write,
+    iter::repeat,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+    vec,
+};
 
 use crossterm::{
     cursor::SetCursorStyle,
@@ -9,10 +16,15 @@ use crossterm::{
         KeyModifiers,
     },
     execute,
-    style::{Color, ContentStyle, StyledContent},
+    style::{Color, ContentStyle, StyledContent, Stylize},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
-use shrs_core::shell::{Context, Runtime, Shell};
+use shrs_core::{
+    complete::CompletionSpecs,
+    editor::{edit_text, editor},
+    options::EditMode,
+    shell::{Context, Runtime, Shell},
+};
 use shrs_lang::{Lexer, Token};
 use shrs_utils::{
     algo::longest_common_prefix,
@@ -21,7 +33,13 @@ use shrs_utils::{
 use shrs_vi::{Action, Command, Motion, Parser};
 use trie_rs::TrieBuilder;
 
-use crate::{painter::Painter, prelude::*};
+use crate::{
+    emacs::{EmacsCursorBuffer, KillRing, WordCase},
+    history_menu::OpenHistoryMenu,
+    painter::Painter,
+    prelude::*,
+    suggestion::next_word,
+};
 
 pub trait Readline {
     fn read_line(&mut self, sh: &Shell, ctx: &mut Context, rt: &mut Runtime) -> String;
@@ -34,8 +52,27 @@ pub enum LineMode {
     Insert,
     /// Vi normal mode
     Normal,
+    /// Vi visual mode, where text is selected before applying an operator to it
+    Visual,
+    /// Emacs mode
+    Emacs,
 }
 
+/// Last command run in emacs mode, used to merge consecutive kills and to allow `M-y` after a
+/// yank
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum EmacsCommand {
+    Kill,
+    /// Yank along with the range of the yanked text
+    Yank(usize, usize),
+    /// Start of a two key sequence such as `C-x C-e`, which is also available in vi normal mode
+    CtrlX,
+    Other,
+}
+
+/// Frames of the spinner shown in the completion menu while completions are loading
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
 /// Configuration for readline
 #[derive(Builder)]
 #[builder(pattern = "owned")]
@@ -46,16 +83,16 @@ pub struct Line {
     #[builder(setter(custom))]
     menu: Box<dyn Menu<MenuItem = Completion, PreviewItem = String>>,
 
+    /// Source of autosuggestions, see [Suggester]
+    #[builder(default = "Box::new(HistorySuggester::default())")]
+    #[builder(setter(custom))]
+    suggester: Box<dyn Suggester>,
+
     /// Completion system, see [Completer]
     #[builder(default = "Box::new(DefaultCompleter::default())")]
     #[builder(setter(custom))]
     completer: Box<dyn Completer>,
 
-    /// History, see [History]
-    #[builder(default = "Box::new(DefaultHistory::new())")]
-    #[builder(setter(custom))]
-    history: Box<dyn History<HistoryItem = String>>,
-
     #[builder(default = "Box::new(DefaultBufferHistory::new())")]
     #[builder(setter(custom))]
     buffer_history: Box<dyn BufferHistory>,
@@ -70,11 +107,29 @@ pub struct Line {
     #[builder(setter(custom))]
     keybinding: Box<dyn Keybinding>,
 
+    /// Fuzzy finder over history, see [HistoryMenu]
+    #[builder(default = "HistoryMenu::new()")]
+    history_menu: HistoryMenu,
+
     /// Custom prompt, see [Prompt]
     #[builder(default = "Box::new(DefaultPrompt::new())")]
     #[builder(setter(custom))]
     prompt: Box<dyn Prompt>,
 
+    /// Editing mode to start in, can be changed at runtime with `set -o vi` or `set -o emacs`
+    #[builder(default = "EditMode::Vi")]
+    edit_mode: EditMode,
+
+    /// Run the command as soon as the external editor opened with `v` or `C-x C-e` is closed,
+    /// instead of loading it back into the line
+    #[builder(default = "false")]
+    run_after_edit: bool,
+
+    /// Start visual mode with `v` in vi normal mode like vim does, instead of opening the command
+    /// in the external editor like bash does
+    #[builder(default = "false")]
+    visual_on_v: bool,
+
     // ignored fields
     #[builder(default = "Painter::new()")]
     #[builder(setter(skip))]
@@ -84,6 +139,30 @@ pub struct Line {
     #[builder(default = "String::new()")]
     #[builder(setter(skip))]
     normal_keys: String,
+
+    /// Killed text in emacs mode
+    #[builder(default = "KillRing::default()")]
+    #[builder(setter(skip))]
+    kill_ring: KillRing,
+
+    #[builder(default = "EmacsCommand::Other")]
+    #[builder(setter(skip))]
+    last_emacs_command: EmacsCommand,
+
+    /// Yanked and deleted text in vi mode
+    #[builder(default = "Registers::default()")]
+    #[builder(setter(skip))]
+    registers: Registers,
+
+    /// Last vi command that changed the line along with the text typed after it, repeated by `.`
+    #[builder(default = "None")]
+    #[builder(setter(skip))]
+    last_change: Option<(Command, String)>,
+
+    /// Whether more completions for the word in the menu are loading in the background
+    #[builder(default = "false")]
+    #[builder(setter(skip))]
+    completions_loading: bool,
 }
 
 impl Default for Line {
@@ -131,6 +210,45 @@ impl HistoryInd {
     }
 }
 
+/// State of an incremental history search
+struct HistorySearch {
+    query: String,
+    direction: SearchDirection,
+    /// History index of the current match
+    matched: Option<usize>,
+    /// No history entry contains the query
+    failed: bool,
+    /// Match state before each character of the query was typed, restored on backspace
+    prev_states: Vec<(Option<usize>, bool)>,
+    /// Line contents and cursor that are restored if the search is cancelled
+    saved_line: String,
+    saved_cursor: usize,
+}
+
+impl HistorySearch {
+    fn prompt_text(&self) -> String {
+        let failed = if self.failed { "failed " } else { "" };
+        let kind = match self.direction {
+            SearchDirection::Backward => "reverse-i-search",
+            SearchDirection::Forward => "i-search",
+        };
+        format!("({failed}{kind})`{}': ", self.query)
+    }
+}
+
+/// Prompt displayed in place of the user's prompt while searching history
+struct SearchPrompt(String);
+
+impl Prompt for SearchPrompt {
+    fn prompt_left(&self, _line_ctx: &mut LineCtx) -> StyledBuf {
+        StyledBuf::new(&self.0, ContentStyle::new())
+    }
+
+    fn prompt_right(&self, _line_ctx: &mut LineCtx) -> StyledBuf {
+        StyledBuf::empty()
+    }
+}
+
 /// Context that is passed to [Line]
 pub struct LineCtx<'a> {
     cb: CursorBuffer,
@@ -140,9 +258,21 @@ pub struct LineCtx<'a> {
     history_ind: HistoryInd,
     // line contents that were present before entering history mode
     saved_line: String,
+    // state of incremental history search, if one is in progress
+    search: Option<HistorySearch>,
+    // line contents and cursor from before the history menu was opened
+    history_menu_saved: Option<(String, usize)>,
+    // rest of the suggested command that is shown after the cursor
+    suggestion: Option<String>,
+    // run the line without waiting for enter
+    submit: bool,
+    // where the text typed after a vi change command such as `cw` starts, so that it can be
+    // repeated with `.`
+    insert_start: Option<usize>,
+    // other end of the selection in visual mode and whether whole lines are selected
+    visual_start: usize,
+    visual_line: bool,
     mode: LineMode,
-    // stored lines in a multiprompt command
-    pub lines: String,
 
     pub sh: &'a Shell,
     pub ctx: &'a mut Context,
@@ -156,8 +286,14 @@ impl<'a> LineCtx<'a> {
             current_word: String::new(),
             history_ind: HistoryInd::Prompt,
             saved_line: String::new(),
+            search: None,
+            history_menu_saved: None,
+            suggestion: None,
+            submit: false,
+            insert_start: None,
+            visual_start: 0,
+            visual_line: false,
             mode: LineMode::Insert,
-            lines: String::new(),
             sh,
             ctx,
             rt,
@@ -166,12 +302,9 @@ impl<'a> LineCtx<'a> {
     pub fn mode(&self) -> LineMode {
         self.mode
     }
-    fn get_full_command(&self) -> String {
-        let mut res: String = self.lines.clone();
-        let cur_line: String = self.cb.as_str().into();
-        res += cur_line.as_str();
-
-        res
+    /// Command typed so far, including all lines of a multiline command
+    pub fn get_full_command(&self) -> String {
+        self.cb.as_str().into_owned()
     }
 }
 
@@ -184,12 +317,12 @@ impl LineBuilder {
         self.menu = Some(Box::new(menu));
         self
     }
-    pub fn with_completer(mut self, completer: impl Completer + 'static) -> Self {
-        self.completer = Some(Box::new(completer));
+    pub fn with_suggester(mut self, suggester: impl Suggester + 'static) -> Self {
+        self.suggester = Some(Box::new(suggester));
         self
     }
-    pub fn with_history(mut self, history: impl History<HistoryItem = String> + 'static) -> Self {
-        self.history = Some(Box::new(history));
+    pub fn with_completer(mut self, completer: impl Completer + 'static) -> Self {
+        self.completer = Some(Box::new(completer));
         self
     }
     pub fn with_highlighter(mut self, highlighter: impl Highlighter + 'static) -> Self {
@@ -209,7 +342,12 @@ impl LineBuilder {
 impl Readline for Line {
     /// Start readline and read one line of user input
     fn read_line(&mut self, sh: &Shell, ctx: &mut Context, rt: &mut Runtime) -> String {
+        // the editing mode set by the user takes precedence over the configured one
+        let edit_mode = *rt.options.edit_mode.get_or_insert(self.edit_mode);
         let mut line_ctx = LineCtx::new(sh, ctx, rt);
+        if edit_mode == EditMode::Emacs {
+            line_ctx.mode = LineMode::Emacs;
+        }
         self.read_events(&mut line_ctx).unwrap()
     }
 }
@@ -236,14 +374,32 @@ impl Line {
         self.painter.paint(
             line_ctx,
             &self.prompt,
-            &self.menu,
+            self.menu.as_ref(),
             &styled_buf,
             line_ctx.cb.cursor(),
         )?;
 
+        let mut seen_updates = completion_updates();
         loop {
+            // while completions load in the background, refresh the menu as results arrive
+            if self.menu.is_active()
+                && !poll(Duration::from_millis(80))?
+                && (self.completions_loading || completion_updates() != seen_updates)
+            {
+                seen_updates = completion_updates();
+                self.refresh_completions(line_ctx)?;
+                self.painter.paint(
+                    line_ctx,
+                    &self.prompt,
+                    self.menu.as_ref(),
+                    &styled_buf,
+                    line_ctx.cb.cursor(),
+                )?;
+                continue;
+            }
             let event = read()?;
 
+            let mut consumed = false;
             if let Event::Key(key_event) = event {
                 if self.keybinding.handle_key_event(
                     line_ctx.sh,
@@ -251,38 +407,67 @@ impl Line {
                     line_ctx.rt,
                     key_event,
                 ) {
-                    break;
+                    // bindings can ask to open the history menu instead of ending the line
+                    if line_ctx.ctx.state.remove::<OpenHistoryMenu>().is_none() {
+                        break;
+                    }
+                    self.open_history_menu(line_ctx)?;
+                    consumed = true;
                 }
             }
 
-            let should_break = self.handle_standard_keys(line_ctx, event.clone())?;
-            if should_break {
-                break;
-            }
+            // keys typed while searching history edit the search query instead of the line
+            let consumed = consumed
+                || line_ctx.search.is_some() && self.handle_search_keys(line_ctx, event.clone())?
+                || self.history_menu.is_active()
+                    && self.handle_history_menu_keys(line_ctx, event.clone())?;
 
-            // handle menu events
-            if self.menu.is_active() {
-                self.handle_menu_keys(line_ctx, event.clone())?;
-            } else {
-                match line_ctx.mode {
-                    LineMode::Insert => {
-                        self.handle_insert_keys(line_ctx, event)?;
-                    },
-                    LineMode::Normal => {
-                        self.handle_normal_keys(line_ctx, event)?;
-                    },
+            if !consumed {
+                let should_break = self.handle_standard_keys(line_ctx, event.clone())?;
+                if should_break {
+                    self.finish_line(line_ctx)?;
+                    break;
+                }
+
+                // handle menu events
+                if self.menu.is_active() {
+                    self.handle_menu_keys(line_ctx, event.clone())?;
+                } else if self.handle_suggestion_keys(line_ctx, &event)? {
+                    // part of the suggestion was accepted
+                } else {
+                    match line_ctx.mode {
+                        LineMode::Insert => {
+                            self.handle_insert_keys(line_ctx, event)?;
+                        },
+                        LineMode::Normal | LineMode::Visual => {
+                            self.handle_normal_keys(line_ctx, event)?;
+                        },
+                        LineMode::Emacs => {
+                            self.handle_emacs_keys(line_ctx, event)?;
+                        },
+                    }
+                }
+
+                if line_ctx.submit {
+                    self.finish_line(line_ctx)?;
+                    break;
                 }
             }
 
             let res = line_ctx.get_full_command();
 
             // syntax highlight
-            styled_buf = self.highlighter.highlight(&res, line_ctx.lines.len());
+            styled_buf = self.highlighter.highlight(&res, 0);
 
             // add currently selected completion to buf
             if self.menu.is_active() {
                 if let Some(selection) = self.menu.current_selection() {
-                    let trimmed_selection = &selection.accept()[line_ctx.current_word.len()..];
+                    let accepted = selection.accept();
+                    // only previewed if the selection extends the current word, which may not be
+                    // the case with fuzzy matching
+                    let trimmed_selection = accepted
+                        .strip_prefix(&line_ctx.current_word)
+                        .unwrap_or_default();
                     styled_buf.push(
                         trimmed_selection,
                         ContentStyle {
@@ -293,20 +478,137 @@ impl Line {
                 }
             }
 
-            self.painter.paint(
-                line_ctx,
-                &self.prompt,
-                &self.menu,
-                &styled_buf,
-                line_ctx.cb.cursor(),
-            )?;
+            // show the rest of the suggested command after the cursor
+            line_ctx.suggestion = self.suggest(line_ctx);
+            if let Some(suggestion) = &line_ctx.suggestion {
+                styled_buf.push(
+                    suggestion,
+                    ContentStyle {
+                        foreground_color: Some(Color::DarkGrey),
+                        ..Default::default()
+                    },
+                );
+            }
+
+            // highlight the selection in visual mode
+            if line_ctx.mode == LineMode::Visual {
+                let selection_styles = self
+                    .selection(line_ctx)
+                    .map(|i| (i, ContentStyle::new().reverse()))
+                    .collect::<HashMap<_, _>>();
+                styled_buf.change_style(selection_styles, 0);
+            }
+
+            // highlight the text matched by the history search
+            if let Some(search) = line_ctx.search.as_ref().filter(|search| !search.failed) {
+                let cursor = line_ctx.cb.cursor();
+                let match_styles = (cursor..cursor + search.query.chars().count())
+                    .map(|i| (i, ContentStyle::new().reverse()))
+                    .collect::<HashMap<_, _>>();
+                styled_buf.change_style(match_styles, 0);
+            }
+
+            // the prompt is replaced by the search query while searching history
+            let search_prompt = if let Some(search) = &line_ctx.search {
+                Some(search.prompt_text())
+            } else if self.history_menu.is_active() {
+                Some(String::from("(history) "))
+            } else {
+                None
+            };
+            let search_prompt =
+                search_prompt.map(|text| Box::new(SearchPrompt(text)) as Box<dyn Prompt>);
+            let prompt = search_prompt.as_ref().unwrap_or(&self.prompt);
+
+            if self.history_menu.is_active() {
+                self.painter.paint(
+                    line_ctx,
+                    prompt,
+                    &self.history_menu,
+                    &styled_buf,
+                    line_ctx.cb.cursor(),
+                )?;
+            } else {
+                self.painter.paint(
+                    line_ctx,
+                    prompt,
+                    self.menu.as_ref(),
+                    &styled_buf,
+                    line_ctx.cb.cursor(),
+                )?;
+            }
         }
 
-        let res = line_ctx.get_full_command();
-        if !res.is_empty() {
-            self.history.add(res.clone());
+        Ok(line_ctx.get_full_command())
+    }
+
+    // redraw the submitted line without the suggestion or search prompt and move below it, so
+    // that the output of the command doesn't overwrite any of its lines
+    fn finish_line(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        ctx.suggestion = None;
+        let styled_buf = self.highlighter.highlight(&ctx.get_full_command(), 0);
+        self.painter.paint(
+            ctx,
+            &self.prompt,
+            self.menu.as_ref(),
+            &styled_buf,
+            ctx.cb.cursor(),
+        )?;
+        self.painter.newline()?;
+        Ok(())
+    }
+
+    // get the rest of the command suggested for the current line, suggestions are only shown
+    // while typing at the end of the line
+    fn suggest(&self, ctx: &LineCtx) -> Option<String> {
+        let typing = matches!(ctx.mode, LineMode::Insert | LineMode::Emacs)
+            && ctx.search.is_none()
+            && !self.menu.is_active()
+            && !self.history_menu.is_active()
+            && ctx.history_ind == HistoryInd::Prompt
+            && ctx.cb.cursor() == ctx.cb.len();
+        if !typing {
+            return None;
         }
-        Ok(res)
+
+        let suggestion = self.suggester.suggest(ctx)?;
+        let rest = suggestion.strip_prefix(&ctx.get_full_command())?;
+        // only the current line of a multiline suggestion is shown
+        let rest = rest.split('\n').next().unwrap_or_default();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+
+    // accept the whole suggestion or its next word, returns false if the key doesn't accept it
+    fn handle_suggestion_keys(&mut self, ctx: &mut LineCtx, event: &Event) -> anyhow::Result<bool> {
+        let Some(suggestion) = ctx.suggestion.clone() else {
+            return Ok(false);
+        };
+        // C-e finishes the C-x C-e sequence rather than accepting the suggestion
+        if self.last_emacs_command == EmacsCommand::CtrlX {
+            return Ok(false);
+        }
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        else {
+            return Ok(false);
+        };
+
+        let emacs = ctx.mode == LineMode::Emacs;
+        let accepted = match (code, modifiers) {
+            (KeyCode::Right, &KeyModifiers::NONE)
+            | (KeyCode::End, _)
+            | (KeyCode::Char('e'), &KeyModifiers::CONTROL) => suggestion,
+            (KeyCode::Char('f'), &KeyModifiers::CONTROL) if emacs => suggestion,
+            (KeyCode::Char('f'), &KeyModifiers::ALT) | (KeyCode::Right, &KeyModifiers::CONTROL) => {
+                next_word(&suggestion)?
+            },
+            _ => return Ok(false),
+        };
+
+        ctx.cb.insert(Location::Cursor(), &accepted)?;
+        self.buffer_history.add(&ctx.cb);
+        Ok(true)
     }
 
     fn handle_menu_keys(&mut self, ctx: &mut LineCtx, event: Event) -> anyhow::Result<()> {
@@ -351,15 +653,59 @@ impl Line {
             }) => {
                 self.menu.next();
             },
+            Event::Key(KeyEvent {
+                code: KeyCode::Left,
+                modifiers: KeyModifiers::NONE,
+                ..
+            }) => {
+                self.menu.left();
+            },
+            Event::Key(KeyEvent {
+                code: KeyCode::Right,
+                modifiers: KeyModifiers::NONE,
+                ..
+            }) => {
+                self.menu.right();
+            },
+            Event::Key(KeyEvent {
+                code: KeyCode::PageUp,
+                ..
+            }) => {
+                self.menu.page_up();
+            },
+            Event::Key(KeyEvent {
+                code: KeyCode::PageDown,
+                ..
+            }) => {
+                self.menu.page_down();
+            },
+            // typing narrows down the completions instead of closing the menu
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            }) if !c.is_whitespace() && matches!(ctx.mode, LineMode::Insert | LineMode::Emacs) => {
+                self.filter_menu(ctx, event)?;
+            },
+            Event::Key(KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::NONE,
+                ..
+            }) if matches!(ctx.mode, LineMode::Insert | LineMode::Emacs) => {
+                self.filter_menu(ctx, event)?;
+            },
             _ => {
                 self.menu.disactivate();
                 match ctx.mode {
                     LineMode::Insert => {
                         self.handle_insert_keys(ctx, event)?;
                     },
-                    LineMode::Normal => {
+                    LineMode::Normal | LineMode::Visual => {
                         self.handle_normal_keys(ctx, event)?;
                     },
+                    LineMode::Emacs => {
+                        self.handle_emacs_keys(ctx, event)?;
+                    },
                 };
             },
         };
@@ -375,6 +721,15 @@ impl Line {
             Event::Paste(p) => {
                 ctx.cb.insert(Location::Cursor(), p.as_str())?;
             },
+            // insert a newline without running the command
+            Event::Key(KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::ALT,
+                ..
+            }) if !self.menu.is_active() => {
+                ctx.cb.insert(Location::Cursor(), "\n")?;
+                self.buffer_history.add(&ctx.cb);
+            },
 
             Event::Key(KeyEvent {
                 code: KeyCode::Enter,
@@ -389,17 +744,16 @@ impl Line {
                 if self.menu.is_active() {
                     return Ok(false);
                 }
-                self.buffer_history.clear();
-                self.painter.newline()?;
 
+                // keep editing commands that aren't complete yet, such as an unterminated loop,
+                // by starting a new line at the end of the command
                 if ctx.sh.lang.needs_line_check(ctx.get_full_command()) {
-                    ctx.lines += ctx.cb.as_str().into_owned().as_str();
-                    ctx.lines += "\n";
-                    ctx.cb.clear();
-
+                    ctx.cb.insert(Location::Back(&ctx.cb), "\n")?;
+                    self.buffer_history.add(&ctx.cb);
                     return Ok(false);
                 }
 
+                self.buffer_history.clear();
                 return Ok(true);
             },
             Event::Key(KeyEvent {
@@ -407,15 +761,15 @@ impl Line {
                 modifiers: KeyModifiers::CONTROL,
                 ..
             }) => {
-                // if current input is empty exit the shell, otherwise treat it as enter
+                // if current input is empty exit the shell, otherwise treat it as enter (emacs
+                // mode deletes the character under the cursor instead)
                 if ctx.cb.len() == 0 {
                     // TODO maybe unify exiting the shell
                     disable_raw_mode(); // TODO this is temp fix, should be more graceful way of
                                         // handling cleanup code
                     std::process::exit(0);
-                } else {
+                } else if ctx.mode != LineMode::Emacs {
                     self.buffer_history.clear();
-                    self.painter.newline()?;
                     return Ok(true);
                 }
             },
@@ -433,61 +787,7 @@ impl Line {
                 modifiers: KeyModifiers::NONE,
                 ..
             }) => {
-                self.populate_completions(ctx)?;
-                self.menu.activate();
-
-                let completion_len = self.menu.items().len();
-
-                // no-op if no completions
-                if completion_len == 0 {
-                    self.menu.disactivate();
-                    return Ok(());
-                }
-                // if completions only has one entry, automatically select it
-                if completion_len == 1 {
-                    // TODO stupid ownership stuff
-                    let item = self.menu.items().get(0).map(|x| (*x).clone()).unwrap();
-                    self.accept_completion(ctx, item.1)?;
-                    self.menu.disactivate();
-                    return Ok(());
-                }
-
-                // TODO make this feature toggable
-                // TODO this is broken
-                // Automatically accept the common prefix
-                /*
-                let completions: Vec<&str> = self
-                    .menu
-                    .items()
-                    .iter()
-                    .map(|(preview, _)| preview.as_str())
-                    .collect();
-                let prefix = longest_common_prefix(completions);
-                self.accept_completion(
-                    ctx,
-                    Completion {
-                        add_space: false,
-                        display: None,
-                        completion: prefix.clone(),
-                        replace_method: ReplaceMethod::Append,
-                    },
-                )?;
-
-                // recompute completions with prefix stripped
-                // TODO this code is horrifying
-                let items = self.menu.items();
-                let new_items = items
-                    .iter()
-                    .map(|(preview, complete)| {
-                        let mut complete = complete.clone();
-                        complete.completion = complete.completion[prefix.len()..].to_string();
-                        (preview.clone(), complete)
-                    })
-                    .collect();
-                self.menu.set_items(new_items);
-                */
-
-                self.menu.activate();
+                self.tab_complete(ctx)?;
             },
             Event::Key(KeyEvent {
                 code: KeyCode::Left,
@@ -512,18 +812,28 @@ impl Line {
                 modifiers: KeyModifiers::NONE,
                 ..
             }) => {
-                self.history_down(ctx)?;
+                self.line_down(ctx)?;
             },
             Event::Key(KeyEvent {
                 code: KeyCode::Up,
                 modifiers: KeyModifiers::NONE,
                 ..
             }) => {
-                self.history_up(ctx)?;
+                self.line_up(ctx)?;
             },
             Event::Key(KeyEvent {
                 code: KeyCode::Esc, ..
             }) => {
+                // remember what was typed after a change so that `.` can type it again
+                if let Some(start) = ctx.insert_start.take() {
+                    if let Some((_, text)) = self.last_change.as_mut() {
+                        *text = ctx.cb.slice(start..ctx.cb.cursor().max(start)).to_string();
+                    }
+                }
+                // like vi, leaving insert mode moves back onto the last character typed
+                if ctx.cb.cursor() > ctx.cb.line_range(ctx.cb.cursor_line()).start {
+                    ctx.cb.move_cursor(Location::Before())?;
+                }
                 self.to_normal_mode(ctx)?;
                 self.buffer_history.add(&ctx.cb);
             },
@@ -541,6 +851,20 @@ impl Line {
                     ctx.cb.delete(Location::Before(), Location::Cursor())?;
                 }
             },
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }) => {
+                self.start_search(ctx, SearchDirection::Backward);
+            },
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('s'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }) => {
+                self.start_search(ctx, SearchDirection::Forward);
+            },
             Event::Key(KeyEvent {
                 code: KeyCode::Char('w'),
                 modifiers: KeyModifiers::CONTROL,
@@ -556,16 +880,23 @@ impl Line {
                 code: KeyCode::Char('a'),
                 modifiers: KeyModifiers::CONTROL,
                 ..
+            })
+            | Event::Key(KeyEvent {
+                code: KeyCode::Home,
+                ..
             }) => {
-                ctx.cb.move_cursor(Location::Front())?;
+                ctx.cb.move_cursor(Location::LineStart(&ctx.cb))?;
             },
 
             Event::Key(KeyEvent {
                 code: KeyCode::Char('e'),
                 modifiers: KeyModifiers::CONTROL,
                 ..
+            })
+            | Event::Key(KeyEvent {
+                code: KeyCode::End, ..
             }) => {
-                ctx.cb.move_cursor(Location::Back(&ctx.cb))?;
+                ctx.cb.move_cursor(Location::LineEnd(&ctx.cb))?;
             },
 
             Event::Key(KeyEvent {
@@ -580,45 +911,220 @@ impl Line {
     }
 
     fn handle_normal_keys(&mut self, ctx: &mut LineCtx, event: Event) -> anyhow::Result<()> {
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        else {
+            return Ok(());
+        };
+
+        if self.last_emacs_command == EmacsCommand::CtrlX {
+            self.last_emacs_command = EmacsCommand::Other;
+            if (code, modifiers) == (KeyCode::Char('e'), KeyModifiers::CONTROL) {
+                self.open_editor(ctx)?;
+            }
+            return Ok(());
+        }
+
         // TODO write better system toString support key combinations
-        match event {
-            Event::Key(KeyEvent {
-                code: KeyCode::Esc, ..
-            }) => {
+        match (code, modifiers) {
+            (KeyCode::Esc, _) => {
                 self.normal_keys.clear();
+                if ctx.mode == LineMode::Visual {
+                    self.to_normal_mode(ctx)?;
+                }
             },
-            Event::Key(KeyEvent {
-                code: KeyCode::Char(c),
-                ..
-            }) => {
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.normal_keys.clear();
+                self.buffer_history.next(ctx.cb.borrow_mut());
+            },
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => {
+                self.normal_keys.clear();
+                self.last_emacs_command = EmacsCommand::CtrlX;
+            },
+            (KeyCode::Char(_), KeyModifiers::CONTROL) => {},
+            (KeyCode::Char(c), _) => {
                 self.normal_keys.push(c);
 
-                if let Ok(Command { repeat, action }) = Parser::new().parse(&self.normal_keys) {
-                    for _ in 0..repeat {
-                        // special cases (possibly consulidate with execute_vi somehow)
-
-                        if let Ok(mode) = ctx.cb.execute_vi(action.clone()) {
-                            match mode {
-                                LineMode::Insert => self.to_insert_mode(ctx)?,
-                                LineMode::Normal => self.to_normal_mode(ctx)?,
-                            };
-                        }
-                        match action {
-                            Action::Undo => self.buffer_history.prev(ctx.cb.borrow_mut()),
-
-                            Action::Redo => self.buffer_history.next(ctx.cb.borrow_mut()),
-                            Action::Move(motion) => match motion {
-                                Motion::Up => self.history_up(ctx)?,
-                                Motion::Down => self.history_down(ctx)?,
-                                _ => {},
-                            },
-                            _ => {
-                                self.buffer_history.add(&ctx.cb);
-                            },
+                let parsed = if ctx.mode == LineMode::Visual {
+                    Parser::new().parse_visual(&self.normal_keys)
+                } else {
+                    Parser::new().parse(&self.normal_keys)
+                };
+                match parsed {
+                    Ok(command) => {
+                        self.normal_keys.clear();
+                        if ctx.mode == LineMode::Visual {
+                            self.run_visual_command(ctx, command)?;
+                        } else {
+                            self.run_normal_command(ctx, command)?;
                         }
+                    },
+                    // wait for the rest of the command
+                    Err(shrs_vi::Error::Incomplete) => {},
+                    Err(_) => self.normal_keys.clear(),
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn run_normal_command(&mut self, ctx: &mut LineCtx, command: Command) -> anyhow::Result<()> {
+        let command = match command.action {
+            // the count given to `.` replaces the count of the repeated command
+            Action::Repeat => {
+                let Some((last, text)) = self.last_change.clone() else {
+                    return Ok(());
+                };
+                let repeat = if command.repeat > 1 {
+                    command.repeat
+                } else {
+                    last.repeat
+                };
+                self.execute_vi_command(ctx, Command { repeat, ..last })?;
+                if ctx.mode == LineMode::Insert {
+                    ctx.cb.insert(Location::Cursor(), &text)?;
+                    if ctx.cb.cursor() > ctx.cb.line_range(ctx.cb.cursor_line()).start {
+                        ctx.cb.move_cursor(Location::Before())?;
                     }
+                    self.to_normal_mode(ctx)?;
+                    self.buffer_history.add(&ctx.cb);
+                }
+                return Ok(());
+            },
+            _ => command,
+        };
+
+        let is_change = matches!(
+            command.action,
+            Action::Delete(_)
+                | Action::Change(_)
+                | Action::Replace(_)
+                | Action::ToggleCase
+                | Action::LowerCase(_)
+                | Action::UpperCase(_)
+                | Action::Paste(_)
+                | Action::Insert
+                | Action::Chain(..)
+        );
+        self.execute_vi_command(ctx, command.clone())?;
+        if is_change {
+            self.last_change = Some((command, String::new()));
+            ctx.insert_start = (ctx.mode == LineMode::Insert).then(|| ctx.cb.cursor());
+        }
+        Ok(())
+    }
+
+    fn execute_vi_command(&mut self, ctx: &mut LineCtx, command: Command) -> anyhow::Result<()> {
+        let Command {
+            repeat,
+            register,
+            action,
+        } = command;
+        self.registers.select(register);
+
+        for i in 0..repeat {
+            // special cases (possibly consulidate with execute_vi somehow)
+
+            // a count replaces that many characters
+            if i > 0 && matches!(action, Action::Replace(_)) {
+                ctx.cb.move_cursor(Location::After())?;
+            }
+
+            if let Ok(mode) = ctx.cb.execute_vi(action.clone(), &mut self.registers) {
+                match mode {
+                    LineMode::Insert => self.to_insert_mode(ctx)?,
+                    LineMode::Normal => self.to_normal_mode(ctx)?,
+                    LineMode::Visual | LineMode::Emacs => {
+                        unreachable!("vi actions only switch to insert or normal mode")
+                    },
+                };
+            }
+            match action {
+                Action::Undo => self.buffer_history.prev(ctx.cb.borrow_mut()),
+                Action::Search { back } => {
+                    let direction = if back {
+                        SearchDirection::Backward
+                    } else {
+                        SearchDirection::Forward
+                    };
+                    self.start_search(ctx, direction);
+                },
+
+                Action::Redo => self.buffer_history.next(ctx.cb.borrow_mut()),
+                Action::Editor if self.visual_on_v => self.to_visual_mode(ctx, false)?,
+                Action::Editor => self.open_editor(ctx)?,
+                Action::Visual { line } => self.to_visual_mode(ctx, line)?,
+                Action::Move(motion) => match motion {
+                    Motion::Up => self.line_up(ctx)?,
+                    Motion::Down => self.line_down(ctx)?,
+                    _ => {},
+                },
+                _ => {
+                    self.buffer_history.add(&ctx.cb);
+                },
+            }
+        }
+        self.registers.select(None);
+        Ok(())
+    }
 
-                    self.normal_keys.clear();
+    fn run_visual_command(&mut self, ctx: &mut LineCtx, command: Command) -> anyhow::Result<()> {
+        let Command {
+            repeat,
+            register,
+            action,
+        } = command;
+
+        match action {
+            // `v` and `V` switch between selecting characters and lines, or leave visual mode
+            Action::Visual { line } if line == ctx.visual_line => self.to_normal_mode(ctx)?,
+            Action::Visual { line } => ctx.visual_line = line,
+            Action::Move(motion @ Motion::TextObject { .. }) => {
+                if let Some(range) = ctx.cb.motion_to_range(motion).filter(|r| !r.is_empty()) {
+                    ctx.visual_start = range.start;
+                    ctx.cb.move_cursor(Location::Abs(range.end - 1))?;
+                }
+            },
+            Action::Move(motion) => {
+                for _ in 0..repeat {
+                    // unlike normal mode, moving past the first or last line doesn't go through
+                    // history
+                    let loc = match motion {
+                        Motion::Up => Location::Up(&ctx.cb),
+                        Motion::Down => Location::Down(&ctx.cb),
+                        motion => ctx.cb.motion_to_loc(motion).ok(),
+                    };
+                    if let Some(loc) = loc {
+                        ctx.cb.move_cursor(loc)?;
+                    }
+                }
+            },
+            Action::Delete(_)
+            | Action::Change(_)
+            | Action::Yank(_)
+            | Action::Paste(_)
+            | Action::LowerCase(_)
+            | Action::UpperCase(_)
+            | Action::ToggleCase => {
+                let mut range = self.selection(ctx);
+                // deleting whole lines also deletes the newline after them
+                if ctx.visual_line && matches!(action, Action::Delete(_)) {
+                    if range.end < ctx.cb.len() {
+                        range.end += 1;
+                    } else if range.start > 0 {
+                        range.start -= 1;
+                    }
+                }
+                self.registers.select(register);
+                let mode = ctx.cb.operate(action, range, &mut self.registers)?;
+                self.registers.select(None);
+                self.buffer_history.add(&ctx.cb);
+                if mode == LineMode::Insert {
+                    self.to_insert_mode(ctx)?;
+                } else {
+                    self.to_normal_mode(ctx)?;
                 }
             },
             _ => {},
@@ -626,26 +1132,386 @@ impl Line {
         Ok(())
     }
 
+    // text selected in visual mode
+    fn selection(&self, ctx: &LineCtx) -> std::ops::Range<usize> {
+        let cb = &ctx.cb;
+        let start = ctx.visual_start.min(cb.cursor()).min(cb.len());
+        let end = ctx.visual_start.max(cb.cursor()).min(cb.len());
+        if ctx.visual_line {
+            let first = cb.rope().char_to_line(start);
+            let last = cb.rope().char_to_line(end);
+            cb.line_range(first).start..cb.line_range(last).end
+        } else {
+            // the character under the cursor is part of the selection
+            start..(end + 1).min(cb.len())
+        }
+    }
+
+    fn handle_emacs_keys(&mut self, ctx: &mut LineCtx, event: Event) -> anyhow::Result<()> {
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        else {
+            return Ok(());
+        };
+
+        if self.last_emacs_command == EmacsCommand::CtrlX {
+            self.last_emacs_command = EmacsCommand::Other;
+            if (code, modifiers) == (KeyCode::Char('e'), KeyModifiers::CONTROL) {
+                self.open_editor(ctx)?;
+            }
+            return Ok(());
+        }
+
+        let command = match (code, modifiers) {
+            (KeyCode::Tab, KeyModifiers::NONE) => {
+                self.tab_complete(ctx)?;
+                EmacsCommand::Other
+            },
+
+            // movement
+            (KeyCode::Char('a'), KeyModifiers::CONTROL) | (KeyCode::Home, _) => {
+                ctx.cb.move_cursor(Location::LineStart(&ctx.cb))?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('e'), KeyModifiers::CONTROL) | (KeyCode::End, _) => {
+                ctx.cb.move_cursor(Location::LineEnd(&ctx.cb))?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('b'), KeyModifiers::CONTROL) | (KeyCode::Left, KeyModifiers::NONE) => {
+                if ctx.cb.cursor() > 0 {
+                    ctx.cb.move_cursor(Location::Before())?;
+                }
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('f'), KeyModifiers::CONTROL) | (KeyCode::Right, KeyModifiers::NONE) => {
+                if ctx.cb.cursor() < ctx.cb.len() {
+                    ctx.cb.move_cursor(Location::After())?;
+                }
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('b'), KeyModifiers::ALT) | (KeyCode::Left, KeyModifiers::CONTROL) => {
+                ctx.cb.move_cursor(ctx.cb.backward_word())?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('f'), KeyModifiers::ALT) | (KeyCode::Right, KeyModifiers::CONTROL) => {
+                ctx.cb.move_cursor(ctx.cb.forward_word())?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('p'), KeyModifiers::CONTROL) | (KeyCode::Up, KeyModifiers::NONE) => {
+                self.line_up(ctx)?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('n'), KeyModifiers::CONTROL) | (KeyCode::Down, KeyModifiers::NONE) => {
+                self.line_down(ctx)?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.start_search(ctx, SearchDirection::Backward);
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                self.start_search(ctx, SearchDirection::Forward);
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => {
+                self.last_emacs_command = EmacsCommand::CtrlX;
+                return Ok(());
+            },
+
+            // deletion
+            (KeyCode::Backspace, KeyModifiers::NONE)
+            | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
+                if ctx.cb.cursor() > 0 {
+                    ctx.cb.delete(Location::Before(), Location::Cursor())?;
+                }
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('d'), KeyModifiers::CONTROL) | (KeyCode::Delete, _) => {
+                if ctx.cb.cursor() < ctx.cb.len() {
+                    ctx.cb.delete(Location::Cursor(), Location::After())?;
+                }
+                EmacsCommand::Other
+            },
+
+            // killing and yanking
+            (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                // at the end of a line the newline is killed, joining it with the next line
+                let end = Location::LineEnd(&ctx.cb);
+                if ctx.cb.to_absolute(end)? == ctx.cb.cursor() && ctx.cb.cursor() < ctx.cb.len() {
+                    self.emacs_kill(ctx, Location::After())?
+                } else {
+                    self.emacs_kill(ctx, end)?
+                }
+            },
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                self.emacs_kill(ctx, Location::LineStart(&ctx.cb))?
+            },
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                self.emacs_kill(ctx, ctx.cb.backward_unix_word())?
+            },
+            (KeyCode::Char('d'), KeyModifiers::ALT) => {
+                self.emacs_kill(ctx, ctx.cb.forward_word())?
+            },
+            (KeyCode::Backspace, KeyModifiers::ALT) => {
+                self.emacs_kill(ctx, ctx.cb.backward_word())?
+            },
+            (KeyCode::Char('y'), KeyModifiers::CONTROL) => match self.kill_ring.yank() {
+                Some(text) => {
+                    let start = ctx.cb.cursor();
+                    ctx.cb.insert(Location::Cursor(), text)?;
+                    EmacsCommand::Yank(start, ctx.cb.cursor())
+                },
+                None => EmacsCommand::Other,
+            },
+            (KeyCode::Char('y'), KeyModifiers::ALT) => match self.last_emacs_command {
+                // yank-pop only makes sense directly after a yank, replace the yanked text with
+                // the next entry of the kill ring
+                EmacsCommand::Yank(start, end) => match self.kill_ring.yank_pop() {
+                    Some(text) => {
+                        ctx.cb.delete(Location::Abs(start), Location::Abs(end))?;
+                        ctx.cb.insert(Location::Abs(start), text)?;
+                        EmacsCommand::Yank(start, ctx.cb.cursor())
+                    },
+                    None => EmacsCommand::Other,
+                },
+                _ => EmacsCommand::Other,
+            },
+
+            // text transformations
+            (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
+                ctx.cb.transpose_chars()?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('u'), KeyModifiers::ALT) => {
+                ctx.cb.change_word_case(WordCase::Upper)?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('l'), KeyModifiers::ALT) => {
+                ctx.cb.change_word_case(WordCase::Lower)?;
+                EmacsCommand::Other
+            },
+            (KeyCode::Char('c'), KeyModifiers::ALT) => {
+                ctx.cb.change_word_case(WordCase::Capitalize)?;
+                EmacsCommand::Other
+            },
+
+            // terminals send C-_ and C-/ as the same control character, which crossterm reports
+            // as C-7
+            (KeyCode::Char('_' | '/' | '7'), KeyModifiers::CONTROL) => {
+                // record any pending typed text so that it is what gets undone
+                self.buffer_history.add(&ctx.cb);
+                self.buffer_history.prev(&mut ctx.cb);
+                self.last_emacs_command = EmacsCommand::Other;
+                return Ok(());
+            },
+
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                ctx.cb.insert(Location::Cursor(), &c.to_string())?;
+                self.last_emacs_command = EmacsCommand::Other;
+                // typed text is undone a word at a time
+                if c.is_whitespace() {
+                    self.buffer_history.add(&ctx.cb);
+                }
+                return Ok(());
+            },
+            _ => return Ok(()),
+        };
+
+        self.last_emacs_command = command;
+        self.buffer_history.add(&ctx.cb);
+        Ok(())
+    }
+
+    // edit the line in the user's editor, raw mode is turned off while the editor is running
+    fn open_editor(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        // leave the line on screen and draw the prompt again below it afterwards, since we
+        // can't know what the editor leaves on the screen
+        self.painter.newline()?;
+        disable_raw_mode()?;
+        execute!(std::io::stdout(), DisableBracketedPaste)?;
+
+        let edited = edit_text(ctx.rt, &editor(ctx.rt), &ctx.cb.as_str());
+
+        enable_raw_mode()?;
+        execute!(std::io::stdout(), EnableBracketedPaste)?;
+        self.painter.init()?;
+
+        match edited {
+            Ok(edited) => {
+                ctx.cb.clear();
+                ctx.cb.insert(Location::Cursor(), &edited)?;
+                self.buffer_history.add(&ctx.cb);
+                ctx.submit = self.run_after_edit && !edited.trim().is_empty();
+            },
+            Err(e) => self.painter.print_error(e)?,
+        }
+        Ok(())
+    }
+
+    // kill the text between the cursor and loc, merging it with the previous kill if the last
+    // command was also a kill
+    fn emacs_kill(&mut self, ctx: &mut LineCtx, loc: Location) -> anyhow::Result<EmacsCommand> {
+        let backward = ctx.cb.to_absolute(loc)? < ctx.cb.cursor();
+        let killed = ctx.cb.kill(loc)?;
+        if self.last_emacs_command == EmacsCommand::Kill {
+            self.kill_ring.extend(killed, backward);
+        } else {
+            self.kill_ring.push(killed);
+        }
+        Ok(EmacsCommand::Kill)
+    }
+
+    // open the completion menu, or accept the completion directly if there is only one
+    fn tab_complete(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        self.populate_completions(ctx)?;
+        self.menu.activate();
+
+        let completion_len = self.menu.items().len();
+
+        // no-op if no completions
+        if completion_len == 0 {
+            self.menu.disactivate();
+            return Ok(());
+        }
+        // if completions only has one entry, automatically select it, unless more are loading
+        if completion_len == 1 && !self.completions_loading {
+            // TODO stupid ownership stuff
+            let item = self.menu.items().first().map(|x| (*x).clone()).unwrap();
+            self.accept_completion(ctx, item.1)?;
+            self.menu.disactivate();
+            return Ok(());
+        }
+
+        // TODO make this feature toggable
+        // TODO this is broken
+        // Automatically accept the common prefix
+        /*
+        let completions: Vec<&str> = self
+            .menu
+            .items()
+            .iter()
+            .map(|(preview, _)| preview.as_str())
+            .collect();
+        let prefix = longest_common_prefix(completions);
+        self.accept_completion(
+            ctx,
+            Completion {
+                add_space: false,
+                display: None,
+                completion: prefix.clone(),
+                replace_method: ReplaceMethod::Append,
+            },
+        )?;
+
+        // recompute completions with prefix stripped
+        // TODO this code is horrifying
+        let items = self.menu.items();
+        let new_items = items
+            .iter()
+            .map(|(preview, complete)| {
+                let mut complete = complete.clone();
+                complete.completion = complete.completion[prefix.len()..].to_string();
+                (preview.clone(), complete)
+            })
+            .collect();
+        self.menu.set_items(new_items);
+        */
+
+        self.menu.activate();
+        Ok(())
+    }
+
     // recalculate the current completions
     fn populate_completions(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
-        // TODO IFS
-        let args = ctx.cb.slice(..ctx.cb.cursor()).as_str().unwrap().split(' ');
-        ctx.current_word = args.clone().last().unwrap_or("").to_string();
-
-        let comp_ctx = CompletionCtx::new(args.map(|s| s.to_owned()).collect::<Vec<_>>());
+        let line = ctx.cb.as_str().into_owned();
+        let cursor = ctx.cb.slice(..ctx.cb.cursor()).len_bytes();
+        let commands = ShellCommands::from_shell(ctx.sh, ctx.ctx, ctx.rt);
+        let mut comp_ctx = CompletionCtx::from_line(&line, cursor, ctx.rt)
+            .with_commands(commands)
+            .expand_alias();
+        // specs live in the state so that the complete builtin can define them
+        if let Some(cmd_name) = comp_ctx.cmd_name().cloned() {
+            let spec = ctx
+                .ctx
+                .state
+                .get_mut_or_default::<CompletionSpecs>()
+                .get(&cmd_name)
+                .cloned();
+            comp_ctx = comp_ctx.with_spec(spec);
+        }
+        ctx.current_word = comp_ctx.cur_word().cloned().unwrap_or_default();
 
         let completions = self.completer.complete(&comp_ctx);
         let completions = completions.iter().collect::<Vec<_>>();
+        self.completions_loading = completions_loading(&comp_ctx);
 
-        let menuitems = completions
+        let mut menuitems = completions
             .iter()
             .map(|c| (c.display(), (*c).clone()))
             .collect::<Vec<_>>();
+
+        // accepting the loading entry inserts nothing
+        if self.completions_loading {
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let frame = SPINNER[(millis / 80) as usize % SPINNER.len()];
+            let loading = format!("{frame} loading…");
+            menuitems.push((
+                loading.clone(),
+                Completion {
+                    add_space: false,
+                    display: Some(loading),
+                    completion: String::new(),
+                    replace_method: ReplaceMethod::Append,
+                    comment: None,
+                    score: i64::MIN,
+                    matched: vec![],
+                    kind: CompletionKind::Other,
+                },
+            ));
+        }
         self.menu.set_items(menuitems);
 
         Ok(())
     }
 
+    // edit the line with a key typed while the menu is open and complete the new word
+    fn filter_menu(&mut self, ctx: &mut LineCtx, event: Event) -> anyhow::Result<()> {
+        match ctx.mode {
+            LineMode::Emacs => self.handle_emacs_keys(ctx, event)?,
+            _ => self.handle_insert_keys(ctx, event)?,
+        }
+        self.populate_completions(ctx)?;
+        if self.menu.items().is_empty() {
+            self.menu.disactivate();
+        }
+        Ok(())
+    }
+
+    // compute the completions again with results that arrived in the background, keeping the
+    // same item selected
+    fn refresh_completions(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        let selected = self.menu.current_selection().map(|item| item.display());
+        self.populate_completions(ctx)?;
+
+        let items = self.menu.items();
+        if items.is_empty() {
+            self.menu.disactivate();
+            return Ok(());
+        }
+        let position = items
+            .iter()
+            .position(|(_, item)| Some(item.display()) == selected)
+            .unwrap_or(0);
+        for _ in 0..position {
+            self.menu.next();
+        }
+        Ok(())
+    }
+
     // replace word at cursor with accepted word (used in automcompletion)
     fn accept_completion(
         &mut self,
@@ -660,11 +1526,9 @@ impl Line {
                 // no-op
             },
             ReplaceMethod::Replace => {
+                let cur_word_len = ctx.current_word.chars().count() as isize;
                 ctx.cb
-                    .move_cursor(Location::Rel(-(ctx.current_word.len() as isize)))?;
-                let cur_word_len = unicode_width::UnicodeWidthStr::width(ctx.current_word.as_str());
-                ctx.cb
-                    .delete(Location::Cursor(), Location::Rel(cur_word_len as isize))?;
+                    .delete(Location::Rel(-cur_word_len), Location::Cursor())?;
                 ctx.current_word.clear();
             },
         }
@@ -675,13 +1539,281 @@ impl Line {
         Ok(())
     }
 
+    fn start_search(&mut self, ctx: &mut LineCtx, direction: SearchDirection) {
+        if HistoryInd::Prompt == ctx.history_ind {
+            ctx.ctx.history.sync();
+        }
+        ctx.search = Some(HistorySearch {
+            query: String::new(),
+            direction,
+            matched: None,
+            failed: false,
+            prev_states: vec![],
+            saved_line: ctx.cb.as_str().into_owned(),
+            saved_cursor: ctx.cb.cursor(),
+        });
+    }
+
+    // handle a key while a history search is in progress, returns false if the search was ended
+    // and the key should be handled as usual
+    fn handle_search_keys(&mut self, ctx: &mut LineCtx, event: Event) -> anyhow::Result<bool> {
+        let Some(search) = ctx.search.as_mut() else {
+            return Ok(false);
+        };
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        else {
+            return Ok(true);
+        };
+
+        match (code, modifiers) {
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                search.direction = SearchDirection::Backward;
+                self.search_step(ctx, true)?;
+            },
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                search.direction = SearchDirection::Forward;
+                self.search_step(ctx, true)?;
+            },
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                search.prev_states.push((search.matched, search.failed));
+                search.query.push(c);
+                self.search_step(ctx, false)?;
+            },
+            (KeyCode::Backspace, KeyModifiers::NONE)
+            | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
+                if let Some((matched, failed)) = search.prev_states.pop() {
+                    search.query.pop();
+                    search.matched = matched;
+                    search.failed = failed;
+                    self.show_search_match(ctx)?;
+                }
+            },
+            // cancel the search and restore the original line
+            (KeyCode::Char('g' | 'c'), KeyModifiers::CONTROL) => {
+                let search = ctx.search.take().unwrap();
+                ctx.cb.clear();
+                ctx.cb.insert(Location::Cursor(), &search.saved_line)?;
+                ctx.cb.move_cursor(Location::Abs(search.saved_cursor))?;
+            },
+            // accept the match for further editing
+            (KeyCode::Esc, _) => {
+                self.end_search(ctx);
+            },
+            // any other key accepts the match and is then handled as usual, so enter runs it
+            _ => {
+                self.end_search(ctx);
+                return Ok(false);
+            },
+        }
+        Ok(true)
+    }
+
+    // look for the next match of the search query, moving past the current match if `skip` is set
+    fn search_step(&mut self, ctx: &mut LineCtx, skip: bool) -> anyhow::Result<()> {
+        let search = ctx.search.as_mut().unwrap();
+        if search.query.is_empty() {
+            return Ok(());
+        }
+
+        let start = match (search.matched, search.direction) {
+            (None, SearchDirection::Backward) => Some(0),
+            (None, SearchDirection::Forward) => None,
+            (Some(i), _) if !skip => Some(i),
+            (Some(i), SearchDirection::Backward) => Some(i + 1),
+            (Some(i), SearchDirection::Forward) => i.checked_sub(1),
+        };
+        let found = start.and_then(|start| {
+            ctx.ctx
+                .history
+                .search_index(&search.query, start, search.direction)
+        });
+
+        // keep showing the last match if nothing else matches
+        match found {
+            Some(i) => {
+                search.matched = Some(i);
+                search.failed = false;
+            },
+            None => search.failed = true,
+        }
+        self.show_search_match(ctx)
+    }
+
+    // fill the buffer with the matched history entry and place the cursor at the match
+    fn show_search_match(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        let search = ctx.search.as_ref().unwrap();
+        let (line, cursor) = match search.matched.and_then(|i| ctx.ctx.history.get(i)) {
+            Some(line) => {
+                let pos = match search.direction {
+                    SearchDirection::Backward => line.rfind(&search.query),
+                    SearchDirection::Forward => line.find(&search.query),
+                };
+                (line, line[..pos.unwrap_or(0)].chars().count())
+            },
+            None => (&search.saved_line, search.saved_cursor),
+        };
+
+        ctx.cb.clear();
+        ctx.cb.insert(Location::Cursor(), line)?;
+        ctx.cb.move_cursor(Location::Abs(cursor))?;
+        Ok(())
+    }
+
+    // leave search, keeping the matched line and continuing history browsing from it
+    fn end_search(&mut self, ctx: &mut LineCtx) {
+        let Some(search) = ctx.search.take() else {
+            return;
+        };
+        if let Some(i) = search.matched {
+            if ctx.history_ind == HistoryInd::Prompt {
+                ctx.saved_line = search.saved_line;
+            }
+            ctx.history_ind = HistoryInd::Line(i);
+        }
+    }
+
+    // open the history menu using the current line as the query
+    fn open_history_menu(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        ctx.search = None;
+        self.menu.disactivate();
+        if HistoryInd::Prompt == ctx.history_ind {
+            ctx.ctx.history.sync();
+        }
+
+        // only the most recent run of each command is listed
+        let mut seen = HashSet::new();
+        let items = (0..ctx.ctx.history.len())
+            .filter_map(|i| {
+                let command = ctx.ctx.history.get(i)?;
+                if !seen.insert(command.clone()) {
+                    return None;
+                }
+                let item = HistoryMenuItem {
+                    command: command.clone(),
+                    metadata: ctx.ctx.history.metadata(i),
+                };
+                Some((command.replace('\n', " "), item))
+            })
+            .collect();
+        self.history_menu.set_items(items);
+        self.history_menu.set_query(&ctx.cb.as_str());
+        self.history_menu.activate();
+
+        ctx.history_menu_saved = Some((ctx.cb.as_str().into_owned(), ctx.cb.cursor()));
+        ctx.cb.move_cursor(Location::Back(&ctx.cb))?;
+        Ok(())
+    }
+
+    // handle a key while the history menu is open, the line is used to type the query
+    fn handle_history_menu_keys(
+        &mut self,
+        ctx: &mut LineCtx,
+        event: Event,
+    ) -> anyhow::Result<bool> {
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        else {
+            return Ok(true);
+        };
+
+        match (code, modifiers) {
+            (KeyCode::Enter, KeyModifiers::NONE) => {
+                if let Some(item) = self.history_menu.accept().cloned() {
+                    ctx.cb.clear();
+                    ctx.cb.insert(Location::Cursor(), &item.command)?;
+                    ctx.history_menu_saved = None;
+                    return Ok(true);
+                }
+                self.close_history_menu(ctx)?;
+            },
+            (KeyCode::Esc, _) | (KeyCode::Char('g' | 'c'), KeyModifiers::CONTROL) => {
+                self.close_history_menu(ctx)?;
+            },
+            (KeyCode::Up, KeyModifiers::NONE)
+            | (KeyCode::BackTab, _)
+            | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                self.history_menu.previous();
+            },
+            (KeyCode::Down, KeyModifiers::NONE)
+            | (KeyCode::Tab, KeyModifiers::NONE)
+            | (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                self.history_menu.next();
+            },
+            (KeyCode::Left, KeyModifiers::NONE) if ctx.cb.cursor() > 0 => {
+                ctx.cb.move_cursor(Location::Before())?;
+            },
+            (KeyCode::Right, KeyModifiers::NONE) if ctx.cb.cursor() < ctx.cb.len() => {
+                ctx.cb.move_cursor(Location::After())?;
+            },
+            (KeyCode::Backspace, KeyModifiers::NONE)
+            | (KeyCode::Char('h'), KeyModifiers::CONTROL)
+                if ctx.cb.cursor() > 0 =>
+            {
+                ctx.cb.delete(Location::Before(), Location::Cursor())?;
+                self.history_menu.set_query(&ctx.cb.as_str());
+            },
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                ctx.cb.clear();
+                self.history_menu.set_query("");
+            },
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                ctx.cb.insert(Location::Cursor(), &c.to_string())?;
+                self.history_menu.set_query(&ctx.cb.as_str());
+            },
+            _ => {},
+        }
+        Ok(true)
+    }
+
+    // close the history menu without selecting anything and restore the line
+    fn close_history_menu(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        self.history_menu.disactivate();
+        if let Some((line, cursor)) = ctx.history_menu_saved.take() {
+            ctx.cb.clear();
+            ctx.cb.insert(Location::Cursor(), &line)?;
+            ctx.cb.move_cursor(Location::Abs(cursor))?;
+        }
+        Ok(())
+    }
+
+    // move to the previous line of a multiline command, or to the previous history entry when on
+    // the first line
+    fn line_up(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        match Location::Up(&ctx.cb) {
+            Some(loc) => ctx.cb.move_cursor(loc)?,
+            None => self.history_up(ctx)?,
+        }
+        Ok(())
+    }
+
+    // move to the next line of a multiline command, or to the next history entry when on the
+    // last line
+    fn line_down(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
+        match Location::Down(&ctx.cb) {
+            Some(loc) => ctx.cb.move_cursor(loc)?,
+            None => {
+                self.history_down(ctx)?;
+                // continue moving down through the lines of the entry from its first line
+                if ctx.history_ind != HistoryInd::Prompt {
+                    ctx.cb
+                        .move_cursor(Location::Abs(ctx.cb.line_range(0).end))?;
+                }
+            },
+        }
+        Ok(())
+    }
+
     fn history_up(&mut self, ctx: &mut LineCtx) -> anyhow::Result<()> {
-        // save current prompt
+        // save current prompt and pick up commands run in other sessions
         if HistoryInd::Prompt == ctx.history_ind {
             ctx.saved_line = ctx.cb.slice(..).to_string();
+            ctx.ctx.history.sync();
         }
 
-        ctx.history_ind = ctx.history_ind.up(self.history.len());
+        ctx.history_ind = ctx.history_ind.up(ctx.ctx.history.len());
         self.update_history(ctx)?;
 
         Ok(())
@@ -703,7 +1835,7 @@ impl Line {
             },
             // fill prompt with history element
             HistoryInd::Line(i) => {
-                let history_item = self.history.get(i).unwrap();
+                let history_item = ctx.ctx.history.get(i).unwrap();
                 ctx.cb.clear();
                 ctx.cb.insert(Location::Cursor(), history_item)?;
             },
@@ -731,6 +1863,26 @@ impl Line {
         Ok(())
     }
 
+    fn to_visual_mode(&mut self, line_ctx: &mut LineCtx, line: bool) -> anyhow::Result<()> {
+        if let Some(cursor_style) = line_ctx.ctx.state.get_mut::<CursorStyle>() {
+            cursor_style.style = SetCursorStyle::BlinkingBlock;
+        }
+        line_ctx.mode = LineMode::Visual;
+        line_ctx.visual_start = line_ctx.cb.cursor();
+        line_ctx.visual_line = line;
+
+        let hook_ctx = LineModeSwitchCtx {
+            line_mode: LineMode::Visual,
+        };
+        line_ctx.sh.hooks.run::<LineModeSwitchCtx>(
+            line_ctx.sh,
+            line_ctx.ctx,
+            line_ctx.rt,
+            hook_ctx,
+        )?;
+        Ok(())
+    }
+
     fn to_insert_mode(&mut self, line_ctx: &mut LineCtx) -> anyhow::Result<()> {
         line_ctx
             .ctx
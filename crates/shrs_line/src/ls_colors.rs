@@ -0,0 +1,162 @@
+//! Colors for files following the `LS_COLORS` environment variable
+//!
+//! `LS_COLORS` is a colon separated list of `key=codes` entries, where the key is either a file
+//! type such as `di` for directories or a pattern such as `*.rs`, and the codes are SGR escape
+//! codes such as `01;34`. Only the file types that completions can have are used.
+
+use std::collections::HashMap;
+
+use crossterm::style::{Attribute, Color, ContentStyle};
+
+use crate::completion::CompletionKind;
+
+/// Colors used by GNU ls when `LS_COLORS` is not set
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:ex=01;32";
+
+#[derive(Clone, Debug, Default)]
+pub struct LsColors {
+    /// Styles of file types, keyed by their two letter code such as `di`
+    types: HashMap<String, ContentStyle>,
+    /// Styles of file name suffixes such as `.rs`, in lowercase
+    suffixes: Vec<(String, ContentStyle)>,
+}
+
+impl LsColors {
+    /// Read the colors from `LS_COLORS`, or use the defaults of GNU ls if it is not set
+    pub fn from_env() -> Self {
+        match std::env::var("LS_COLORS") {
+            Ok(ls_colors) if !ls_colors.is_empty() => Self::parse(&ls_colors),
+            _ => Self::parse(DEFAULT_LS_COLORS),
+        }
+    }
+
+    /// Parse a string in the format of `LS_COLORS`, invalid entries are ignored
+    pub fn parse(ls_colors: &str) -> Self {
+        let mut colors = Self::default();
+        for (key, codes) in ls_colors
+            .split(':')
+            .filter_map(|entry| entry.split_once('='))
+        {
+            let Some(style) = parse_sgr(codes) else {
+                continue;
+            };
+            match key.strip_prefix('*') {
+                Some(suffix) => colors.suffixes.push((suffix.to_lowercase(), style)),
+                None => {
+                    colors.types.insert(key.to_string(), style);
+                },
+            }
+        }
+        // later patterns take precedence, and longer suffixes are more specific
+        colors.suffixes.reverse();
+        colors
+            .suffixes
+            .sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        colors
+    }
+
+    /// Style of a file with the given name, if it is a kind of file
+    pub fn style(&self, kind: CompletionKind, name: &str) -> Option<ContentStyle> {
+        let code = match kind {
+            CompletionKind::Directory => "di",
+            CompletionKind::Symlink => "ln",
+            CompletionKind::Executable => "ex",
+            CompletionKind::File => {
+                let name = name.to_lowercase();
+                let by_suffix = self
+                    .suffixes
+                    .iter()
+                    .find(|(suffix, _)| name.ends_with(suffix.as_str()));
+                if let Some((_, style)) = by_suffix {
+                    return Some(*style);
+                }
+                "fi"
+            },
+            _ => return None,
+        };
+        self.types.get(code).copied()
+    }
+}
+
+/// Convert SGR codes such as `01;38;5;208` into a style
+fn parse_sgr(codes: &str) -> Option<ContentStyle> {
+    let codes = codes
+        .split(';')
+        .map(|code| code.parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    let mut style = ContentStyle::new();
+    let mut codes = codes.into_iter();
+    while let Some(code) = codes.next() {
+        match code {
+            0 => style = ContentStyle::new(),
+            1 => style.attributes.set(Attribute::Bold),
+            2 => style.attributes.set(Attribute::Dim),
+            3 => style.attributes.set(Attribute::Italic),
+            4 => style.attributes.set(Attribute::Underlined),
+            5 => style.attributes.set(Attribute::SlowBlink),
+            7 => style.attributes.set(Attribute::Reverse),
+            8 => style.attributes.set(Attribute::Hidden),
+            9 => style.attributes.set(Attribute::CrossedOut),
+            30..=37 => style.foreground_color = Some(Color::AnsiValue(code - 30)),
+            90..=97 => style.foreground_color = Some(Color::AnsiValue(code - 90 + 8)),
+            40..=47 => style.background_color = Some(Color::AnsiValue(code - 40)),
+            100..=107 => style.background_color = Some(Color::AnsiValue(code - 100 + 8)),
+            38 => style.foreground_color = Some(parse_extended_color(&mut codes)?),
+            48 => style.background_color = Some(parse_extended_color(&mut codes)?),
+            39 => style.foreground_color = None,
+            49 => style.background_color = None,
+            _ => {},
+        }
+    }
+    Some(style)
+}
+
+/// Color after a `38` or `48` code, either `5;n` or `2;r;g;b`
+fn parse_extended_color(codes: &mut impl Iterator<Item = u8>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(Color::AnsiValue(codes.next()?)),
+        2 => Some(Color::Rgb {
+            r: codes.next()?,
+            g: codes.next()?,
+            b: codes.next()?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::style::{Attribute, Color};
+
+    use super::LsColors;
+    use crate::completion::CompletionKind;
+
+    #[test]
+    fn parse() {
+        let colors =
+            LsColors::parse("di=01;34:ln=target:ex=38;5;208:*.tar=31:*.tar.gz=4;32:*.RS=1");
+
+        let dir = colors.style(CompletionKind::Directory, "src").unwrap();
+        assert_eq!(dir.foreground_color, Some(Color::AnsiValue(4)));
+        assert!(dir.attributes.has(Attribute::Bold));
+
+        // invalid entries are skipped
+        assert!(colors.style(CompletionKind::Symlink, "link").is_none());
+
+        let exe = colors.style(CompletionKind::Executable, "run").unwrap();
+        assert_eq!(exe.foreground_color, Some(Color::AnsiValue(208)));
+
+        let archive = colors.style(CompletionKind::File, "a.tar.gz").unwrap();
+        assert_eq!(archive.foreground_color, Some(Color::AnsiValue(2)));
+        assert!(archive.attributes.has(Attribute::Underlined));
+        let archive = colors.style(CompletionKind::File, "a.tar").unwrap();
+        assert_eq!(archive.foreground_color, Some(Color::AnsiValue(1)));
+
+        // suffixes are matched regardless of case
+        assert!(colors.style(CompletionKind::File, "main.rs").is_some());
+        assert!(colors.style(CompletionKind::File, "notes.txt").is_none());
+        assert!(colors.style(CompletionKind::Flag, "a.tar").is_none());
+    }
+}
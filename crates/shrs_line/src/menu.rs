@@ -1,15 +1,20 @@
 //! General purpose selection menu for shell
 
-use std::{cmp::Ordering, fmt::Display, io::Write};
+use std::{cell::Cell, cmp::Ordering, fmt::Display, ops::Range};
 
 use crossterm::{
-    cursor::{MoveDown, MoveToColumn, MoveUp},
-    execute,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    cursor::{MoveDown, MoveToColumn},
+    style::{ContentStyle, Print, PrintStyledContent, Stylize},
     QueueableCommand,
 };
+use shrs_core::theme::Theme;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{completion::Completion, painter::Painter};
+use crate::{
+    completion::{Completion, CompletionKind},
+    ls_colors::LsColors,
+    painter::Painter,
+};
 
 pub type Out = std::io::BufWriter<std::io::Stdout>;
 
@@ -22,6 +27,22 @@ pub trait Menu {
     fn next(&mut self);
     /// Go to the previous selection
     fn previous(&mut self);
+    /// Go to the selection on the left, for menus with several columns
+    fn left(&mut self) {
+        self.previous();
+    }
+    /// Go to the selection on the right, for menus with several columns
+    fn right(&mut self) {
+        self.next();
+    }
+    /// Go up by the number of rows shown at once
+    fn page_up(&mut self) {
+        self.previous();
+    }
+    /// Go down by the number of rows shown at once
+    fn page_down(&mut self) {
+        self.next();
+    }
     /// Accept the current selection
     fn accept(&mut self) -> Option<&Self::MenuItem>;
     /// Get the current selection
@@ -38,13 +59,143 @@ pub trait Menu {
     fn items(&self) -> Vec<&(Self::PreviewItem, Self::MenuItem)>;
     fn set_items(&mut self, items: Vec<(Self::PreviewItem, Self::MenuItem)>);
 
-    fn render(&self, out: &mut Out, painter: &Painter) -> anyhow::Result<()>;
+    fn render(&self, out: &mut Out, painter: &Painter, theme: &Theme) -> anyhow::Result<()>;
     fn required_lines(&self, painter: &Painter) -> usize;
 }
 
 pub type SortFn = fn(&(String, Completion), &(String, Completion)) -> Ordering;
 
+/// Sort alphabetically by display name
+pub fn sort_alphabetical(a: &(String, Completion), b: &(String, Completion)) -> Ordering {
+    a.0.to_lowercase().cmp(&b.0.to_lowercase())
+}
+
+/// Sort by match score, best first, breaking ties alphabetically
+pub fn sort_by_score(a: &(String, Completion), b: &(String, Completion)) -> Ordering {
+    b.1.score
+        .cmp(&a.1.score)
+        .then_with(|| sort_alphabetical(a, b))
+}
+
+/// Name of the group a completion is shown in
+pub type GroupFn = fn(&Completion) -> &'static str;
+
+/// Group completions by their [CompletionKind]
+pub fn group_by_kind(completion: &Completion) -> &'static str {
+    match completion.kind {
+        CompletionKind::Command
+        | CompletionKind::Executable
+        | CompletionKind::Builtin
+        | CompletionKind::Alias
+        | CompletionKind::Function => "commands",
+        CompletionKind::File | CompletionKind::Directory | CompletionKind::Symlink => "files",
+        CompletionKind::Flag => "flags",
+        CompletionKind::Variable => "variables",
+        CompletionKind::GitRef => "git refs",
+        CompletionKind::Host => "hosts",
+        CompletionKind::Other => "other",
+    }
+}
+
+/// Nerd Font icon shown before completions of a kind
+fn icon(kind: CompletionKind) -> char {
+    match kind {
+        CompletionKind::Command => '\u{f120}',
+        CompletionKind::File => '\u{f15b}',
+        CompletionKind::Directory => '\u{f07b}',
+        CompletionKind::Symlink => '\u{f0c1}',
+        CompletionKind::Executable => '\u{f489}',
+        CompletionKind::Builtin => '\u{f0ad}',
+        CompletionKind::Alias => '\u{f061}',
+        CompletionKind::Function => '\u{f121}',
+        CompletionKind::Flag => '\u{f024}',
+        CompletionKind::Variable => '\u{f0e7}',
+        CompletionKind::GitRef => '\u{e725}',
+        CompletionKind::Host => '\u{f233}',
+        CompletionKind::Other => ' ',
+    }
+}
+
+/// Show all completions together
+pub fn no_groups(_completion: &Completion) -> &'static str {
+    ""
+}
+
+/// Column of completions in a [Section]
+struct Column {
+    items: Range<usize>,
+    name_width: usize,
+    /// Width of the longest comment, 0 if there are none
+    comment_width: usize,
+}
+
+impl Column {
+    fn width(&self) -> usize {
+        // comments are put in parentheses two spaces after the name
+        if self.comment_width > 0 {
+            self.name_width + self.comment_width + 4
+        } else {
+            self.name_width
+        }
+    }
+}
+
+/// Completions of the same group laid out in columns, filled top to bottom
+struct Section {
+    items: Range<usize>,
+    rows: usize,
+    columns: Vec<Column>,
+}
+
+enum Row {
+    Header(&'static str),
+    Items { section: usize, row: usize },
+}
+
+struct Layout {
+    sections: Vec<Section>,
+    rows: Vec<Row>,
+}
+
+impl Layout {
+    /// Section, column and row of an item
+    fn locate(&self, index: usize) -> Option<(usize, usize, usize)> {
+        let (s, section) = self
+            .sections
+            .iter()
+            .enumerate()
+            .find(|(_, section)| section.items.contains(&index))?;
+        let offset = index - section.items.start;
+        Some((s, offset / section.rows, offset % section.rows))
+    }
+
+    /// Line of the menu an item is on
+    fn line(&self, index: usize) -> Option<usize> {
+        let (s, _, r) = self.locate(index)?;
+        self.rows.iter().position(
+            |row| matches!(row, Row::Items { section, row } if *section == s && *row == r),
+        )
+    }
+
+    /// Item in the given column of a line, or the closest one before it
+    fn item_at(&self, line: usize, column: usize) -> Option<usize> {
+        let Row::Items { section, row } = self.rows.get(line)? else {
+            return None;
+        };
+        self.sections[*section]
+            .columns
+            .iter()
+            .take(column.saturating_add(1))
+            .rfind(|column| column.items.len() > *row)
+            .map(|column| column.items.start + row)
+    }
+}
+
 /// Simple menu that prompts user for a selection
+///
+/// Completions are shown in as many columns as fit in the terminal, grouped by the function
+/// given to [DefaultMenu::with_group]. Once there are more rows than `limit`, the menu scrolls and
+/// shows how many entries are hidden.
 pub struct DefaultMenu {
     selections: Vec<(String, Completion)>,
     /// Currently selected item
@@ -53,12 +204,22 @@ pub struct DefaultMenu {
     column_padding: usize,
     /// Max length in characters that the comment message is allowed to take up
     comment_max_length: usize,
-    /// Max number of entries to show when rendering the menu
+    /// Max number of rows to show when rendering the menu
     limit: usize,
     /// Function to use to sort the entries
     // TODO can we make this vary depending on which completions are used? does sorting belong more
     // to completion?
     sort: SortFn,
+    /// Function to use to group the entries
+    group: GroupFn,
+    /// Width of the terminal when the menu was last rendered, used to move between columns
+    width: Cell<usize>,
+    /// First row that is shown
+    scroll: Cell<usize>,
+    /// Colors of files
+    ls_colors: LsColors,
+    /// Show an icon before each entry, requires a Nerd Font
+    icons: bool,
 }
 
 impl DefaultMenu {
@@ -69,9 +230,14 @@ impl DefaultMenu {
             active: false,
             comment_max_length: 30,
             column_padding: 2,
-            limit: 20,
-            // by default sort alphabetical by display name
-            sort: |a, b| -> Ordering { a.0.to_lowercase().cmp(&b.0.to_lowercase()) },
+            limit: 10,
+            // by default sort by relevance, which is alphabetical if all completions scored the same
+            sort: sort_by_score,
+            group: group_by_kind,
+            width: Cell::new(80),
+            scroll: Cell::new(0),
+            ls_colors: LsColors::from_env(),
+            icons: false,
         }
     }
     pub fn new_with_limit(limit: usize) -> Self {
@@ -80,43 +246,276 @@ impl DefaultMenu {
         menu
     }
 
-    // TODO make these configurable?
-    fn selected_style(&self, out: &mut Out) -> crossterm::Result<()> {
-        execute!(
-            out,
-            SetBackgroundColor(Color::White),
-            SetForegroundColor(Color::Black),
-        )?;
-        Ok(())
+    /// Set the function used to sort the entries, for example [sort_alphabetical]
+    pub fn with_sort(mut self, sort: SortFn) -> Self {
+        self.sort = sort;
+        self
     }
 
-    fn unselected_style(&self, out: &mut Out) -> crossterm::Result<()> {
-        execute!(out, ResetColor)?;
-        Ok(())
+    /// Set the function used to group the entries, for example [no_groups]
+    pub fn with_group(mut self, group: GroupFn) -> Self {
+        self.group = group;
+        self
     }
 
-    fn comment_style(&self, out: &mut Out) -> crossterm::Result<()> {
-        execute!(out, SetForegroundColor(Color::Yellow),)?;
-        Ok(())
+    /// Set the colors used for files, which are read from `LS_COLORS` by default
+    pub fn with_ls_colors(mut self, ls_colors: LsColors) -> Self {
+        self.ls_colors = ls_colors;
+        self
     }
 
-    fn max_width(&self) -> usize {
-        // first determine how many columns are needed to list all completions
-        let mut max_width = 0;
-        for menu_item in self.items() {
-            // extra +4 is for formatting characters around the comment
-            let comment_len = menu_item
-                .1
-                .comment
-                .as_ref()
-                .map(|comment| comment.len().min(self.comment_max_length) + 4)
-                .unwrap_or(0);
-            let menu_item_len = menu_item.0.len() + comment_len;
+    /// Show a Nerd Font icon for the kind of each entry
+    pub fn with_icons(mut self, icons: bool) -> Self {
+        self.icons = icons;
+        self
+    }
+
+    /// Style of an entry that isn't selected, files are colored like `ls` and other kinds use the
+    /// theme
+    fn kind_style(&self, completion: &Completion, preview: &str, theme: &Theme) -> ContentStyle {
+        let color = match completion.kind {
+            CompletionKind::File
+            | CompletionKind::Directory
+            | CompletionKind::Symlink
+            | CompletionKind::Executable => {
+                return self
+                    .ls_colors
+                    .style(completion.kind, preview.trim_end_matches('/'))
+                    .unwrap_or_default();
+            },
+            CompletionKind::Builtin | CompletionKind::Function => theme.green,
+            CompletionKind::Alias => theme.cyan,
+            CompletionKind::Variable => theme.magenta,
+            CompletionKind::GitRef => theme.yellow,
+            CompletionKind::Host => theme.blue,
+            CompletionKind::Command | CompletionKind::Flag | CompletionKind::Other => {
+                return ContentStyle::new();
+            },
+        };
+        ContentStyle::new().with(color)
+    }
 
-            max_width = max_width.max(menu_item_len);
+    /// Lay out the entries in columns that fit in the given width
+    fn layout(&self, width: usize) -> Layout {
+        let name_widths = self
+            .selections
+            .iter()
+            .map(|(preview, _)| {
+                // icons are followed by a space
+                UnicodeWidthStr::width(preview.as_str()) + if self.icons { 2 } else { 0 }
+            })
+            .collect::<Vec<_>>();
+        let comment_widths = self
+            .selections
+            .iter()
+            .map(|(_, completion)| {
+                completion.comment.as_ref().map_or(0, |comment| {
+                    comment.chars().count().min(self.comment_max_length)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // entries were sorted into their groups when they were set
+        let mut groups: Vec<(&'static str, Range<usize>)> = vec![];
+        for (i, (_, completion)) in self.selections.iter().enumerate() {
+            let group = (self.group)(completion);
+            match groups.last_mut() {
+                Some((last, range)) if *last == group => range.end = i + 1,
+                _ => groups.push((group, i..i + 1)),
+            }
+        }
+        let show_headers = groups.len() > 1;
+
+        let mut layout = Layout {
+            sections: vec![],
+            rows: vec![],
+        };
+        for (header, items) in groups {
+            let section = self.section(items, &name_widths, &comment_widths, width);
+            if show_headers {
+                layout.rows.push(Row::Header(header));
+            }
+            for row in 0..section.rows {
+                layout.rows.push(Row::Items {
+                    section: layout.sections.len(),
+                    row,
+                });
+            }
+            layout.sections.push(section);
         }
+        layout
+    }
 
-        max_width
+    /// Find the most columns the items can be split into while fitting in the width
+    fn section(
+        &self,
+        items: Range<usize>,
+        name_widths: &[usize],
+        comment_widths: &[usize],
+        width: usize,
+    ) -> Section {
+        let columns_for = |rows: usize| {
+            items
+                .clone()
+                .step_by(rows)
+                .map(|start| {
+                    let items = start..(start + rows).min(items.end);
+                    Column {
+                        name_width: name_widths[items.clone()]
+                            .iter()
+                            .copied()
+                            .max()
+                            .unwrap_or(0),
+                        comment_width: comment_widths[items.clone()]
+                            .iter()
+                            .copied()
+                            .max()
+                            .unwrap_or(0),
+                        items,
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // no more columns than would fit if every column was as narrow as the narrowest item
+        let narrowest = name_widths[items.clone()]
+            .iter()
+            .copied()
+            .min()
+            .unwrap_or(0);
+        let max_columns = (width + self.column_padding) / (narrowest + self.column_padding).max(1);
+        let max_columns = max_columns.clamp(1, items.len().max(1));
+
+        for columns in (2..=max_columns).rev() {
+            let rows = items.len().div_ceil(columns);
+            let section_columns = columns_for(rows);
+            let total = section_columns.iter().map(Column::width).sum::<usize>()
+                + self.column_padding * (section_columns.len() - 1);
+            if total <= width {
+                return Section {
+                    items,
+                    rows,
+                    columns: section_columns,
+                };
+            }
+        }
+
+        // a single column, with the comments cut short to fit
+        let mut columns = columns_for(items.len().max(1));
+        for column in columns.iter_mut() {
+            if column.width() > width {
+                column.comment_width = width.saturating_sub(column.name_width + 4);
+            }
+        }
+        Section {
+            items: items.clone(),
+            rows: items.len(),
+            columns,
+        }
+    }
+
+    /// Range of rows to show, scrolled so that the selection is visible
+    fn visible_rows(&self, layout: &Layout) -> Range<usize> {
+        let visible = layout.rows.len().min(self.limit);
+        let mut scroll = self.scroll.get();
+        if let Some(line) = layout.line(self.cursor as usize) {
+            if line < scroll {
+                // also show the header of the group when scrolling up to its first row
+                scroll = match line.checked_sub(1).map(|prev| &layout.rows[prev]) {
+                    Some(Row::Header(_)) => line - 1,
+                    _ => line,
+                };
+            } else if line >= scroll + visible {
+                scroll = line + 1 - visible;
+            }
+        }
+        let scroll = scroll.min(layout.rows.len() - visible);
+        self.scroll.set(scroll);
+        scroll..scroll + visible
+    }
+
+    /// Move the selection by a number of rows, staying in the same column
+    fn move_rows(&mut self, rows: isize) {
+        let layout = self.layout(self.width.get());
+        let (Some(line), Some((_, column, _))) = (
+            layout.line(self.cursor as usize),
+            layout.locate(self.cursor as usize),
+        ) else {
+            return;
+        };
+        let last = layout.rows.len() - 1;
+        let target = line.saturating_add_signed(rows).min(last);
+        // skip over headers in the direction of movement, or back if there is nothing after them
+        let item = if rows > 0 {
+            (target..=last)
+                .chain((line..target).rev())
+                .find_map(|line| layout.item_at(line, column))
+        } else {
+            (target..=line).find_map(|line| layout.item_at(line, column))
+        };
+        if let Some(item) = item {
+            self.cursor = item as u32;
+        }
+    }
+
+    fn render_item(
+        &self,
+        out: &mut Out,
+        theme: &Theme,
+        index: usize,
+        column: &Column,
+    ) -> anyhow::Result<()> {
+        let (preview, completion) = &self.selections[index];
+        let base = if self.cursor as usize == index {
+            theme.menu_selected
+        } else {
+            self.kind_style(completion, preview, theme)
+        };
+        let mut name_width = UnicodeWidthStr::width(preview.as_str());
+
+        if self.icons {
+            out.queue(PrintStyledContent(
+                base.apply(format!("{} ", icon(completion.kind))),
+            ))?;
+            name_width += 2;
+        }
+
+        // highlight the characters matched by the current word
+        let matched = completion.display_matched();
+        for (ci, ch) in preview.chars().enumerate() {
+            let mut style = base;
+            if matched.contains(&ci) {
+                let matched_style = theme.menu_matched;
+                style.foreground_color = matched_style.foreground_color.or(style.foreground_color);
+                style.background_color = matched_style.background_color.or(style.background_color);
+                style.attributes.extend(matched_style.attributes);
+            }
+            out.queue(PrintStyledContent(style.apply(ch)))?;
+        }
+        let padding = column.name_width.saturating_sub(name_width);
+        out.queue(PrintStyledContent(base.apply(" ".repeat(padding))))?;
+
+        // comments line up in each column
+        if let Some(comment) = completion
+            .comment
+            .as_ref()
+            .filter(|_| column.comment_width > 0)
+        {
+            out.queue(Print("  ("))?;
+            out.queue(PrintStyledContent(
+                theme
+                    .menu_comment
+                    .apply(truncate(comment, column.comment_width)),
+            ))?;
+            out.queue(Print(")"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for DefaultMenu {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -138,6 +537,46 @@ impl Menu for DefaultMenu {
             self.cursor = self.cursor.saturating_sub(1);
         }
     }
+    fn left(&mut self) {
+        let layout = self.layout(self.width.get());
+        let Some((s, c, r)) = layout.locate(self.cursor as usize) else {
+            return;
+        };
+        let section = &layout.sections[s];
+        self.cursor = if c > 0 {
+            section.columns[c - 1].items.start + r
+        } else if r > 0 {
+            // last column of the previous row
+            layout
+                .line(self.cursor as usize)
+                .and_then(|line| layout.item_at(line - 1, usize::MAX))
+                .unwrap_or(section.items.start)
+        } else if section.items.start > 0 {
+            section.items.start - 1
+        } else {
+            self.selections.len().saturating_sub(1)
+        } as u32;
+    }
+    fn right(&mut self) {
+        let layout = self.layout(self.width.get());
+        let Some((s, c, r)) = layout.locate(self.cursor as usize) else {
+            return;
+        };
+        let section = &layout.sections[s];
+        self.cursor = match section.columns.get(c + 1) {
+            Some(column) if column.items.len() > r => column.items.start + r,
+            // first column of the next row
+            _ if r + 1 < section.rows => section.items.start + r + 1,
+            _ if section.items.end < self.selections.len() => section.items.end,
+            _ => 0,
+        } as u32;
+    }
+    fn page_up(&mut self) {
+        self.move_rows(-(self.limit as isize));
+    }
+    fn page_down(&mut self) {
+        self.move_rows(self.limit as isize);
+    }
     fn accept(&mut self) -> Option<&Self::MenuItem> {
         self.disactivate();
         self.current_selection()
@@ -163,92 +602,167 @@ impl Menu for DefaultMenu {
         self.selections.iter().collect()
     }
     fn set_items(&mut self, mut items: Vec<(Self::PreviewItem, Self::MenuItem)>) {
-        self.selections.clear();
         items.sort_by(self.sort);
-        self.selections.append(&mut items);
+        // keep each group together, with the group of the best entry first
+        let mut groups = vec![];
+        for (_, completion) in items.iter() {
+            let group = (self.group)(completion);
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        items.sort_by_key(|(_, completion)| {
+            let group = (self.group)(completion);
+            groups.iter().position(|g| *g == group)
+        });
+
+        self.selections = items;
         self.cursor = 0;
+        self.scroll.set(0);
     }
 
-    fn render(&self, out: &mut Out, painter: &Painter) -> anyhow::Result<()> {
-        let mut i = 0;
-        let mut column_start: usize = 0;
-
-        let max_width = self.max_width();
-        let mut columns_needed = painter.get_term_size().0 as usize / max_width;
-
-        // terminal is not wide enough to render even one line
-        if columns_needed == 0 {
-            columns_needed = 1;
-        }
-
-        // ceil division
-        let rows_needed = (self.items().len() + columns_needed - 1) / columns_needed;
-
-        self.unselected_style(out)?;
-        for column in self.items().chunks(rows_needed) {
-            // length of the longest word in column
-            let mut longest_word = 0;
-
-            for menu_item in column.iter() {
-                longest_word = longest_word.max(menu_item.0.len());
-                out.queue(MoveDown(1))?;
-                out.queue(MoveToColumn(column_start as u16))?;
-                if self.cursor() as usize == i {
-                    self.selected_style(out)?;
-                }
-                out.queue(Print(&menu_item.0))?;
-                self.unselected_style(out)?;
-
-                if let Some(comment) = &menu_item.1.comment {
-                    let comment_len = comment.len().min(self.comment_max_length);
-                    out.queue(MoveToColumn(
-                        (column_start + max_width - comment_len - 2) as u16, // -2 for parentheses
-                    ))?;
-                    out.queue(Print("("))?;
-                    self.comment_style(out)?;
-                    out.queue(Print(truncate(comment, comment_len)))?;
-                    self.unselected_style(out)?;
-                    out.queue(Print(")"))?;
-                }
-
-                i += 1;
+    fn render(&self, out: &mut Out, painter: &Painter, theme: &Theme) -> anyhow::Result<()> {
+        let width = painter.get_term_size().0 as usize;
+        self.width.set(width);
+        let layout = self.layout(width);
+        let visible = self.visible_rows(&layout);
+
+        let mut shown = 0;
+        for row in &layout.rows[visible] {
+            out.queue(MoveDown(1))?;
+            out.queue(MoveToColumn(0))?;
+            match row {
+                Row::Header(header) => {
+                    out.queue(PrintStyledContent(theme.menu_header.apply(header)))?;
+                },
+                Row::Items { section, row } => {
+                    let mut column_start = 0;
+                    for column in layout.sections[*section].columns.iter() {
+                        if column.items.len() > *row {
+                            out.queue(MoveToColumn(column_start as u16))?;
+                            self.render_item(out, theme, column.items.start + row, column)?;
+                            shown += 1;
+                        }
+                        column_start += column.width() + self.column_padding;
+                    }
+                },
             }
-            column_start += max_width + self.column_padding;
+        }
 
-            // move back up
-            out.queue(MoveUp(column.len() as u16))?;
+        let hidden = self.selections.len() - shown;
+        if hidden > 0 {
+            out.queue(MoveDown(1))?;
+            out.queue(MoveToColumn(0))?;
+            out.queue(PrintStyledContent(
+                theme.menu_header.apply(format!("{hidden} more…")),
+            ))?;
         }
 
         Ok(())
     }
 
     fn required_lines(&self, painter: &Painter) -> usize {
-        // TODO a bit of duplicated code
+        let width = painter.get_term_size().0 as usize;
+        self.width.set(width);
+        let layout = self.layout(width);
+        let more = usize::from(layout.rows.len() > self.limit);
 
-        let max_width = self.max_width();
+        layout.rows.len().min(self.limit) + more + 1
+    }
+}
 
-        let mut columns_needed = painter.get_term_size().0 as usize / max_width;
+/// Utility to truncate string and insert ellipses at end
+fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().nth(max_chars).is_none() {
+        return s.to_string();
+    }
+    let mut truncated = s
+        .chars()
+        .take(max_chars.saturating_sub(3))
+        .collect::<String>();
+    truncated.push_str("...");
+    truncated
+}
 
-        // terminal is not wide enough to render even one line
-        if columns_needed == 0 {
-            columns_needed = 1;
-        }
+#[cfg(test)]
+mod tests {
+    use super::{truncate, DefaultMenu, Menu};
+    use crate::completion::{Completion, CompletionKind};
+
+    fn menu(names: &[&str], width: usize) -> DefaultMenu {
+        let mut menu = DefaultMenu::new_with_limit(3);
+        menu.width.set(width);
+        menu.set_items(
+            names
+                .iter()
+                .map(|name| {
+                    let kind = if name.starts_with('-') {
+                        CompletionKind::Flag
+                    } else {
+                        CompletionKind::File
+                    };
+                    (
+                        name.to_string(),
+                        Completion {
+                            completion: name.to_string(),
+                            kind,
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect(),
+        );
+        menu
+    }
 
-        // ceil division
-        let rows_needed = (self.items().len() + columns_needed - 1) / columns_needed;
+    fn selected(menu: &DefaultMenu) -> String {
+        menu.current_selection().unwrap().completion.clone()
+    }
 
-        rows_needed + 1
+    #[test]
+    fn grid() {
+        // four columns of two rows: a c e g / b d f
+        let mut menu = menu(&["a", "b", "c", "d", "e", "f", "g"], 10);
+        let layout = menu.layout(10);
+        assert_eq!(layout.rows.len(), 2);
+        assert_eq!(layout.sections[0].columns.len(), 4);
+
+        menu.right();
+        assert_eq!(selected(&menu), "c");
+        menu.next();
+        assert_eq!(selected(&menu), "d");
+        menu.right();
+        assert_eq!(selected(&menu), "f");
+        // past the last column the next row starts
+        menu.right();
+        assert_eq!(selected(&menu), "a");
+        menu.left();
+        assert_eq!(selected(&menu), "g");
     }
-}
 
-/// Utility to truncate string and insert ellipses at end
-fn truncate(s: &str, max_chars: usize) -> String {
-    match s.char_indices().nth(max_chars) {
-        None => s.to_string(),
-        Some((idx, _)) => {
-            let mut truncated = s[..idx.saturating_sub(3)].to_string();
-            truncated.push_str("...");
-            truncated
-        },
+    #[test]
+    fn groups_and_pages() {
+        let names = ["-a", "-b", "x", "y", "z"];
+        let mut menu = menu(&names, 1);
+        // each group has a header and one entry per row
+        let layout = menu.layout(1);
+        assert_eq!(layout.rows.len(), 7);
+
+        // headers are skipped
+        menu.page_down();
+        assert_eq!(selected(&menu), "x");
+        menu.page_down();
+        assert_eq!(selected(&menu), "z");
+        menu.page_up();
+        assert_eq!(selected(&menu), "x");
+        menu.page_up();
+        assert_eq!(selected(&menu), "-a");
+    }
+    #[test]
+    fn truncate_multibyte() {
+        let comment = format!("{}‘bbbb", "a".repeat(27));
+        assert_eq!(truncate(&comment, 30), format!("{}...", "a".repeat(27)));
+        assert_eq!(truncate(&comment, 32), comment);
+        assert_eq!(truncate("‘‘‘‘‘‘", 5), "‘‘...");
     }
 }
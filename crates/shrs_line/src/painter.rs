@@ -8,16 +8,14 @@ use std::{
 };
 
 use crossterm::{
-    cursor::{self, MoveToColumn, MoveToNextLine, MoveToPreviousLine},
+    cursor::{self, MoveToColumn, MoveToNextLine},
     style::{ContentStyle, Print, PrintStyledContent, StyledContent},
     terminal::{self, Clear, ScrollUp},
     QueueableCommand,
 };
 use unicode_width::UnicodeWidthStr;
 
-use crate::{
-    completion::Completion, cursor::CursorStyle, line::LineCtx, menu::Menu, prompt::Prompt,
-};
+use crate::{cursor::CursorStyle, line::LineCtx, menu::Menu, prompt::Prompt};
 /// Text to be rendered by painter
 #[derive(Clone)]
 pub struct StyledBuf {
@@ -89,7 +87,7 @@ impl StyledBuf {
         }
     }
 }
-pub fn line_content_len(line: Vec<StyledContent<String>>) -> u16 {
+pub fn line_content_len(line: &[StyledContent<String>]) -> u16 {
     let c = line
         .iter()
         .map(|x| x.content().as_str())
@@ -121,7 +119,8 @@ pub struct Painter {
     term_size: (u16, u16),
     /// Current line the prompt is on
     prompt_line: u16,
-    num_newlines: usize,
+    /// Number of rows the prompt and buffer took up when they were last painted
+    rows: u16,
 }
 
 impl Painter {
@@ -130,14 +129,14 @@ impl Painter {
             out: RefCell::new(BufWriter::new(stdout())),
             term_size: (0, 0),
             prompt_line: 0,
-            num_newlines: 0,
+            rows: 1,
         }
     }
 
     /// Clear screen and move prompt to the top
     pub fn init(&mut self) -> crossterm::Result<()> {
         self.prompt_line = 0;
-        self.num_newlines = 0;
+        self.rows = 1;
         self.term_size = terminal::size()?;
 
         // advance to next row if cursor in middle of line
@@ -157,151 +156,123 @@ impl Painter {
         self.term_size
     }
 
-    pub fn paint<T: Prompt + ?Sized>(
+    pub fn paint<T: Prompt + ?Sized, I, P: Display>(
         &mut self,
         line_ctx: &mut LineCtx,
         prompt: impl AsRef<T>,
-        menu: &Box<dyn Menu<MenuItem = Completion, PreviewItem = String>>,
+        menu: &dyn Menu<MenuItem = I, PreviewItem = P>,
         styled_buf: &StyledBuf,
         cursor_ind: usize,
     ) -> anyhow::Result<()> {
         self.out.borrow_mut().queue(cursor::Hide)?;
 
-        // scroll up if we need more lines
-        if menu.is_active() {
-            let required_lines = menu.required_lines(&self) as u16;
-            let remaining_lines = self.term_size.1.saturating_sub(self.prompt_line);
-            if required_lines > remaining_lines {
-                let extra_lines = required_lines.saturating_sub(remaining_lines);
-                self.out
-                    .borrow_mut()
-                    .queue(ScrollUp(extra_lines.try_into().unwrap()))?;
-                self.prompt_line = self.prompt_line.saturating_sub(extra_lines);
-            }
-        }
-        //newlines to account for when clearing and printing prompt
         let prompt_left = prompt.as_ref().prompt_left(line_ctx);
         let prompt_right = prompt.as_ref().prompt_right(line_ctx);
-        let prompt_left_lines = prompt_left.lines();
         let prompt_right_lines = prompt_right.lines();
-        let styled_buf_lines = styled_buf.lines();
-
-        //need to also take into account extra lines needed for prompt_right
-        let total_newlines = (prompt_right_lines.len() - 1)
-            .max(styled_buf_lines.len() - 1 + prompt_left_lines.len() - 1);
 
-        //make sure num_newlines never gets smaller, and adds newlines to adjust for prompt
-        if self.num_newlines < total_newlines {
-            self.out
-                .borrow_mut()
-                .queue(ScrollUp((total_newlines - self.num_newlines) as u16))?;
+        // the first line of the buffer goes after the last line of the left prompt, the rest of
+        // the lines of the buffer each get their own row
+        let mut rows = prompt_left.lines();
+        let prompt_rows = rows.len() - 1;
+        let mut buf_lines = styled_buf.lines().into_iter();
+        rows.last_mut()
+            .unwrap()
+            .extend(buf_lines.next().unwrap_or_default());
+        rows.extend(buf_lines);
+        // right prompt can also be taller than everything else
+        while rows.len() < prompt_right_lines.len() {
+            rows.push(vec![]);
+        }
 
-            self.num_newlines = total_newlines;
+        // rows longer than the terminal is wide wrap onto the rows below them, a row that exactly
+        // fills the terminal takes up an extra row so that the cursor can be placed after it
+        let term_width = self.term_size.0.max(1) as usize;
+        let row_heights = rows
+            .iter()
+            .map(|row| line_content_len(row) as usize / term_width + 1)
+            .collect::<Vec<_>>();
+        let total_rows = row_heights.iter().sum::<usize>();
+
+        // find the row and column the cursor is drawn at
+        let before_cursor = styled_buf
+            .content
+            .chars()
+            .take(cursor_ind)
+            .collect::<String>();
+        let cursor_line = before_cursor.matches('\n').count();
+        let mut cursor_width =
+            UnicodeWidthStr::width(before_cursor.rsplit('\n').next().unwrap_or_default());
+        if cursor_line == 0 {
+            cursor_width +=
+                UnicodeWidthStr::width(prompt_left.content.rsplit('\n').next().unwrap());
         }
+        let cursor_row = prompt_rows + cursor_line;
+        let cursor_row =
+            row_heights[..cursor_row].iter().sum::<usize>() + cursor_width / term_width;
+        let cursor_col = cursor_width % term_width;
+
+        // scroll up if the prompt, buffer and menu don't fit below the prompt line
+        let menu_rows = if menu.is_active() {
+            menu.required_lines(self)
+        } else {
+            0
+        };
+        let bottom = self.prompt_line as usize + total_rows + menu_rows;
+        let term_height = self.term_size.1 as usize;
+        if bottom > term_height {
+            let extra_lines = (bottom - term_height) as u16;
+            self.out.borrow_mut().queue(ScrollUp(extra_lines))?;
+            self.prompt_line = self.prompt_line.saturating_sub(extra_lines);
+        }
+        self.rows = total_rows as u16;
 
         // clean up current line first
         self.out
             .borrow_mut()
-            .queue(cursor::MoveTo(
-                0,
-                self.prompt_line.saturating_sub(self.num_newlines as u16),
-            ))?
+            .queue(cursor::MoveTo(0, self.prompt_line))?
             .queue(Clear(terminal::ClearType::FromCursorDown))?;
 
-        // cursor position from left side of terminal
-        let mut left_space = 0;
-
-        let mut ri = 0;
-        let mut li = 0;
-        let mut bi = 0;
         //RENDER PROMPT
-        //loop through lines rendering prompt left and prompt right and only start rendering of
-        //buffer when prompt_left is out of lines
-
-        loop {
-            if li < prompt_left_lines.len() {
-                for span in prompt_left_lines[li].iter() {
-                    self.out
-                        .borrow_mut()
-                        .queue(PrintStyledContent(span.clone()))?;
-                }
-
-                li += 1;
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                self.out.borrow_mut().queue(MoveToNextLine(1))?;
             }
-            if bi < styled_buf_lines.len() && li >= prompt_left_lines.len() {
-                for span in styled_buf_lines[bi].iter() {
-                    self.out
-                        .borrow_mut()
-                        .queue(PrintStyledContent(span.clone()))?;
-                }
-                bi += 1;
+            for span in row.iter() {
+                self.out
+                    .borrow_mut()
+                    .queue(PrintStyledContent(span.clone()))?;
             }
 
-            if ri < prompt_right_lines.len() {
-                let mut right_space = self.term_size.0;
-                right_space -= line_content_len(prompt_right_lines[ri].clone()) as u16;
-                self.out.borrow_mut().queue(MoveToColumn(right_space))?;
-                for span in prompt_right_lines[ri].iter() {
-                    self.out
-                        .borrow_mut()
-                        .queue(PrintStyledContent(span.clone()))?;
+            let row_width = line_content_len(row) as usize;
+            if let Some(right) = prompt_right_lines.get(i) {
+                // only draw the right prompt if it doesn't overlap the text on this row
+                let right_width = line_content_len(right) as usize;
+                if row_width + right_width < term_width {
+                    let right_space = (term_width - right_width) as u16;
+                    self.out.borrow_mut().queue(MoveToColumn(right_space))?;
+                    for span in right.iter() {
+                        self.out
+                            .borrow_mut()
+                            .queue(PrintStyledContent(span.clone()))?;
+                    }
                 }
-
-                ri += 1;
             }
-            //no lines left in any of the styledbufs
-            if li == prompt_left_lines.len()
-                && bi == styled_buf_lines.len()
-                && ri == prompt_right_lines.len()
-            {
-                break;
+            // the terminal doesn't wrap until something else is printed, move to the extra row
+            if row_width > 0 && row_width.is_multiple_of(term_width) {
+                self.out.borrow_mut().queue(Print("\r\n"))?;
             }
-            self.out.borrow_mut().queue(MoveToNextLine(1))?;
-        }
-        //account for right prompt being longest and set position for cursor
-        //-1 to account for inline
-        if ri > bi + li.saturating_sub(1) {
-            self.out
-                .borrow_mut()
-                .queue(MoveToPreviousLine((ri - (bi + li - 1)) as u16))?;
         }
 
-        //calculate left space
-        //prompt space is 0 if there is going to be a newline in the styled_buf
-        if styled_buf_lines.len().saturating_sub(1) == 0 {
-            //space is width of last line of prompt_left
-            left_space += UnicodeWidthStr::width(
-                prompt_left
-                    .content
-                    .split('\n')
-                    .last()
-                    .unwrap()
-                    .chars()
-                    .collect::<String>()
-                    .as_str(),
-            );
-        }
-
-        //take last line of buf to the cursor index and get length for cursor
-        let chars = styled_buf
-            .content
-            .split('\n')
-            .last()
-            .unwrap()
-            .chars()
-            .take(cursor_ind)
-            .collect::<String>();
-        left_space += UnicodeWidthStr::width(chars.as_str());
-
         // render menu
         if menu.is_active() {
-            menu.render(&mut self.out.borrow_mut(), &self)?;
+            menu.render(&mut self.out.borrow_mut(), self, &line_ctx.sh.theme)?;
         }
 
         //move cursor to correct position
-        self.out
-            .borrow_mut()
-            .queue(cursor::MoveToColumn(left_space as u16))?;
+        self.out.borrow_mut().queue(cursor::MoveTo(
+            cursor_col as u16,
+            self.prompt_line + cursor_row as u16,
+        ))?;
         self.out.borrow_mut().queue(cursor::Show)?;
 
         // set cursor style
@@ -313,9 +284,26 @@ impl Painter {
         Ok(())
     }
 
+    /// Move to the line after the last painted row
     pub fn newline(&mut self) -> crossterm::Result<()> {
-        self.out.borrow_mut().queue(Print("\r\n"))?;
+        self.out
+            .borrow_mut()
+            .queue(cursor::MoveTo(0, self.prompt_line + self.rows - 1))?
+            .queue(Print("\r\n"))?;
         self.out.borrow_mut().flush()?;
         Ok(())
     }
+
+    /// Print an error where the prompt is, the prompt is painted below it afterwards
+    pub fn print_error(&mut self, err: impl Display) -> crossterm::Result<()> {
+        // raw mode doesn't move back to the start of the line on a newline
+        let msg = err.to_string().replace('\n', "\r\n");
+        self.out
+            .borrow_mut()
+            .queue(cursor::MoveTo(0, self.prompt_line))?
+            .queue(Print(msg))?
+            .queue(Print("\r\n"))?;
+        self.out.borrow_mut().flush()?;
+        self.init()
+    }
 }
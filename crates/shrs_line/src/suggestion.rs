@@ -0,0 +1,168 @@
+//! Fish style autosuggestions
+//!
+//! While typing, the rest of a suggested command is shown dimmed after the cursor. Pressing right
+//! arrow or `C-e` accepts the whole suggestion and `M-f` accepts the next word of it.
+//!
+//! Suggestions are provided by a [Suggester], which by default is a [HistorySuggester]. Any
+//! closure taking a [LineCtx] can also be used as one, for example to turn suggestions off
+//! ```ignore
+//! let readline = LineBuilder::default()
+//!     .with_suggester(|_: &LineCtx| None)
+//!     .build()?;
+//! ```
+
+use std::path::Path;
+
+use shrs_core::history::History;
+use shrs_utils::cursor_buffer::{CursorBuffer, Result};
+
+use crate::{emacs::EmacsCursorBuffer, line::LineCtx};
+
+/// Source of autosuggestions
+pub trait Suggester {
+    /// Suggest a command for the line that has been typed so far
+    ///
+    /// The suggested command must start with the typed line, otherwise it is ignored.
+    fn suggest(&self, line_ctx: &LineCtx) -> Option<String>;
+}
+
+impl<F: Fn(&LineCtx) -> Option<String>> Suggester for F {
+    fn suggest(&self, line_ctx: &LineCtx) -> Option<String> {
+        self(line_ctx)
+    }
+}
+
+/// Suggest the most recent history entry that starts with the typed line
+pub struct HistorySuggester {
+    /// Prefer entries that were run in the current working directory
+    prefer_cwd: bool,
+}
+
+impl HistorySuggester {
+    pub fn new(prefer_cwd: bool) -> Self {
+        HistorySuggester { prefer_cwd }
+    }
+}
+
+impl Default for HistorySuggester {
+    fn default() -> Self {
+        HistorySuggester::new(true)
+    }
+}
+
+impl HistorySuggester {
+    /// Suggest an entry of `history` for `line`, typed in the directory `cwd`
+    fn suggest_from(
+        &self,
+        history: &dyn History<HistoryItem = String>,
+        line: &str,
+        cwd: &Path,
+    ) -> Option<String> {
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        let candidates = (0..history.len())
+            .filter_map(|i| Some((i, history.get(i)?)))
+            .filter(|(_, cmd)| cmd.len() > line.len() && cmd.starts_with(line));
+
+        let mut fallback = None;
+        for (i, cmd) in candidates {
+            if !self.prefer_cwd {
+                return Some(cmd.clone());
+            }
+            let in_cwd = history
+                .metadata(i)
+                .and_then(|metadata| metadata.cwd)
+                .is_some_and(|dir| dir == cwd);
+            if in_cwd {
+                return Some(cmd.clone());
+            }
+            fallback.get_or_insert_with(|| cmd.clone());
+        }
+        fallback
+    }
+}
+
+impl Suggester for HistorySuggester {
+    fn suggest(&self, line_ctx: &LineCtx) -> Option<String> {
+        self.suggest_from(
+            line_ctx.ctx.history.as_ref(),
+            &line_ctx.get_full_command(),
+            &line_ctx.rt.working_dir,
+        )
+    }
+}
+
+/// Part of the rest of a suggestion that is accepted by `M-f`, up to the end of its next word
+pub(crate) fn next_word(suggestion: &str) -> Result<String> {
+    let rest = CursorBuffer::from_str(suggestion);
+    let end = rest.to_absolute(rest.forward_word())?;
+    Ok(suggestion.chars().take(end).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use shrs_core::history::{DefaultHistory, History, HistoryMetadata};
+
+    use super::{next_word, HistorySuggester};
+
+    fn history(entries: &[(&str, &str)]) -> DefaultHistory {
+        let mut hist = DefaultHistory::new();
+        for (cmd, cwd) in entries {
+            let metadata = HistoryMetadata {
+                cwd: Some(PathBuf::from(cwd)),
+                ..Default::default()
+            };
+            hist.add_with_metadata(cmd.to_string(), metadata);
+        }
+        hist
+    }
+
+    #[test]
+    fn most_recent() {
+        let hist = history(&[("cargo build", "/a"), ("cargo test", "/b"), ("ls", "/b")]);
+        let suggester = HistorySuggester::new(false);
+
+        assert_eq!(
+            suggester.suggest_from(&hist, "cargo", Path::new("/a")),
+            Some("cargo test".into())
+        );
+        // an entry that is just the typed line has nothing left to suggest
+        assert_eq!(suggester.suggest_from(&hist, "ls", Path::new("/a")), None);
+    }
+
+    #[test]
+    fn prefer_cwd() {
+        let hist = history(&[("cargo build", "/a"), ("cargo test", "/b")]);
+        let suggester = HistorySuggester::default();
+
+        assert_eq!(
+            suggester.suggest_from(&hist, "cargo", Path::new("/a")),
+            Some("cargo build".into())
+        );
+        // without a match in the working directory the most recent one is used
+        assert_eq!(
+            suggester.suggest_from(&hist, "cargo", Path::new("/c")),
+            Some("cargo test".into())
+        );
+    }
+
+    #[test]
+    fn empty_line() {
+        let hist = history(&[("cargo build", "/a")]);
+        let suggester = HistorySuggester::default();
+
+        assert_eq!(suggester.suggest_from(&hist, "", Path::new("/a")), None);
+        assert_eq!(suggester.suggest_from(&hist, "  ", Path::new("/a")), None);
+    }
+
+    #[test]
+    fn accept_word() {
+        assert_eq!(next_word(" commit --amend").unwrap(), " commit");
+        assert_eq!(next_word("mit --amend").unwrap(), "mit");
+        assert_eq!(next_word(" --amend").unwrap(), " --amend");
+    }
+}
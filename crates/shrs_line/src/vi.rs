@@ -1,14 +1,117 @@
 /// Vi mode for shrs_line
+use std::{collections::HashMap, ops::Range};
+
 use arboard::Clipboard;
 use shrs_utils::cursor_buffer::{CursorBuffer, Location, Result};
-use shrs_vi::{Action, Motion};
+use shrs_vi::{Action, Motion, TextObject};
 
 use crate::{completion::CompletionCtx, line::LineMode};
 
+/// Registers that yanked and deleted text is stored in
+///
+/// A register is selected for the next command by typing `"` followed by its name. Naming a
+/// register in uppercase appends to it, `+` and `*` use the system clipboard and `_` discards the
+/// text. The unnamed register `"` always holds the last yanked or deleted text and is used when no
+/// register is selected.
+#[derive(Default)]
+pub struct Registers {
+    registers: HashMap<char, String>,
+    selected: Option<char>,
+}
+
+impl Registers {
+    /// Select the register used by the next command, `None` selects the unnamed register
+    pub fn select(&mut self, name: Option<char>) {
+        self.selected = name;
+    }
+
+    /// Contents of the selected register
+    pub fn get(&self) -> Option<String> {
+        match self.selected.unwrap_or('"') {
+            '+' | '*' => Clipboard::new().and_then(|mut c| c.get_text()).ok(),
+            name => self.registers.get(&name.to_ascii_lowercase()).cloned(),
+        }
+    }
+
+    /// Store text in the selected register
+    pub fn set(&mut self, text: String) {
+        let text = match self.selected.unwrap_or('"') {
+            '_' => return,
+            '+' | '*' => {
+                let _ = Clipboard::new().and_then(|mut c| c.set_text(text.clone()));
+                text
+            },
+            name if name.is_ascii_uppercase() => {
+                let register = self.registers.entry(name.to_ascii_lowercase()).or_default();
+                register.push_str(&text);
+                register.clone()
+            },
+            name => {
+                self.registers.insert(name, text.clone());
+                text
+            },
+        };
+        self.registers.insert('"', text);
+    }
+}
+
 /// Extension trait to [CursorBuffer] that enables the execution of vi motions
 pub trait ViCursorBuffer {
     fn motion_to_loc(&self, motion: Motion) -> Result<Location>;
-    fn execute_vi(&mut self, action: Action) -> Result<LineMode>;
+    /// Range of text that an operator such as `d` applies to when given a motion, `None` if the
+    /// motion doesn't select anything, such as a text object that isn't found
+    fn motion_to_range(&self, motion: Motion) -> Option<Range<usize>>;
+    fn execute_vi(&mut self, action: Action, registers: &mut Registers) -> Result<LineMode>;
+    /// Apply the operator of an action to a range of text, ignoring the motion of the action
+    ///
+    /// Used for the selection in visual mode.
+    fn operate(
+        &mut self,
+        action: Action,
+        range: Range<usize>,
+        registers: &mut Registers,
+    ) -> Result<LineMode>;
+}
+
+/// Kind of character, used to find the boundaries of words
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum CharClass {
+    Newline,
+    Whitespace,
+    Punctuation,
+    Word,
+}
+
+impl CharClass {
+    /// With `punc` punctuation is a separate kind of word, otherwise words are only delimited by
+    /// whitespace
+    fn of(ch: char, punc: bool) -> Self {
+        if ch == '\n' {
+            CharClass::Newline
+        } else if ch.is_whitespace() {
+            CharClass::Whitespace
+        } else if punc && is_punc(ch) {
+            CharClass::Punctuation
+        } else {
+            CharClass::Word
+        }
+    }
+}
+
+fn is_punc(ch: char) -> bool {
+    !ch.is_alphanumeric() && ch != '_' && !ch.is_whitespace()
+}
+
+/// Motions that include the character they end on when used with an operator
+fn is_inclusive(motion: Motion) -> bool {
+    matches!(
+        motion,
+        Motion::Find { back: false, .. }
+            | Motion::WordEnd
+            | Motion::WordPuncEnd
+            | Motion::BackWordEnd
+            | Motion::MatchBracket
+    )
 }
 
 impl ViCursorBuffer for CursorBuffer {
@@ -40,8 +143,8 @@ impl ViCursorBuffer for CursorBuffer {
             },
             Motion::Left => Ok(Location::Before()),
             Motion::Right => Ok(Location::After()),
-            Motion::Start => Ok(Location::Front()),
-            Motion::End => Ok(Location::Back(self)),
+            Motion::Start => Ok(Location::LineStart(self)),
+            Motion::End | Motion::All => Ok(Location::LineEnd(self)),
             Motion::Word => {
                 // check if at end of line
                 let cur_char = if let Some(ch) = self.char_at(Location::Cursor()) {
@@ -61,7 +164,6 @@ impl ViCursorBuffer for CursorBuffer {
                     .unwrap_or(Location::Back(self)))
             },
             Motion::WordPunc => {
-                let punc = "!-~*|\".?[]{}()";
                 //check if at end of line
                 let cur_char = if let Some(ch) = self.char_at(Location::Cursor()) {
                     ch
@@ -70,14 +172,14 @@ impl ViCursorBuffer for CursorBuffer {
                 };
                 let start = if cur_char.is_whitespace() {
                     Location::Cursor()
-                } else if punc.contains(cur_char) {
+                } else if is_punc(cur_char) {
                     //start at non punc
-                    Location::Find(self, Location::Cursor(), |ch| !punc.contains(ch))
+                    Location::Find(self, Location::Cursor(), |ch| !is_punc(ch))
                         .unwrap_or(Location::Back(self))
                 } else {
                     //if letter char
                     Location::Find(self, Location::Cursor(), |ch| {
-                        ch.is_whitespace() || punc.contains(ch)
+                        ch.is_whitespace() || is_punc(ch)
                     })
                     .unwrap_or(Location::Back(self))
                 };
@@ -111,11 +213,44 @@ impl ViCursorBuffer for CursorBuffer {
                 };
                 Ok(ret + Location::After())
             },
+            Motion::WordEnd => Ok(word_end(self, false)),
+            Motion::WordPuncEnd => Ok(word_end(self, true)),
+            Motion::BackWordEnd => Ok(back_word_end(self)),
+            Motion::MatchBracket => {
+                Ok(match_bracket(self).map_or(Location::Cursor(), Location::Abs))
+            },
             _ => Ok(Location::Cursor()),
         }
     }
 
-    fn execute_vi(&mut self, action: Action) -> Result<LineMode> {
+    fn motion_to_range(&self, motion: Motion) -> Option<Range<usize>> {
+        let cursor = self.cursor();
+        match motion {
+            Motion::None | Motion::Selection => None,
+            Motion::TextObject { object, inner } => text_object(self, object, inner),
+            Motion::All => Some(self.line_range(self.cursor_line())),
+            // whole lines from the cursor to the line above or below
+            Motion::Up | Motion::Down => {
+                let line = self.cursor_line();
+                let other = if motion == Motion::Up {
+                    line.checked_sub(1)?
+                } else {
+                    Some(line + 1).filter(|l| *l < self.line_count())?
+                };
+                Some(self.line_range(line.min(other)).start..self.line_range(line.max(other)).end)
+            },
+            motion => {
+                let loc = self.to_absolute(self.motion_to_loc(motion).ok()?).ok()?;
+                if is_inclusive(motion) {
+                    Some(loc.min(cursor)..(loc.max(cursor) + 1).min(self.len()))
+                } else {
+                    Some(loc.min(cursor)..loc.max(cursor))
+                }
+            },
+        }
+    }
+
+    fn execute_vi(&mut self, action: Action, registers: &mut Registers) -> Result<LineMode> {
         match action {
             Action::Insert => return Ok(LineMode::Insert),
             Action::Move(motion) => match motion {
@@ -123,118 +258,325 @@ impl ViCursorBuffer for CursorBuffer {
                 | Motion::Right
                 | Motion::Start
                 | Motion::End
+                | Motion::All
                 | Motion::Word
                 | Motion::WordPunc
                 | Motion::BackWord
+                | Motion::WordEnd
+                | Motion::WordPuncEnd
+                | Motion::BackWordEnd
+                | Motion::MatchBracket
                 | Motion::Find { .. } => {
                     self.move_cursor(self.motion_to_loc(motion)?)?;
                 },
                 _ => (),
             },
-            Action::Delete(motion) => match motion {
-                Motion::All => {
-                    self.clear();
-                },
-                Motion::Left
-                | Motion::Right
-                | Motion::Start
-                | Motion::End
-                | Motion::Word
-                | Motion::WordPunc
-                | Motion::BackWord
-                | Motion::Find { .. } => {
-                    self.delete(Location::Cursor(), self.motion_to_loc(motion)?)?;
-                },
-                _ => (),
+            Action::Delete(motion) => {
+                let Some(mut range) = self.motion_to_range(motion) else {
+                    return Ok(LineMode::Normal);
+                };
+                // deleting whole lines also deletes the newline between them and the next line
+                if matches!(motion, Motion::All | Motion::Up | Motion::Down) {
+                    if range.end < self.len() {
+                        range.end += 1;
+                    } else if range.start > 0 {
+                        range.start -= 1;
+                    }
+                }
+                return self.operate(Action::Delete(motion), range, registers);
+            },
+            Action::Change(motion) => {
+                // like vim, `cw` on a word only changes up to the end of the word
+                let motion = match (motion, self.char_at(Location::Cursor())) {
+                    (Motion::WordPunc, Some(ch)) if !ch.is_whitespace() => Motion::WordPuncEnd,
+                    (Motion::Word, Some(ch)) if !ch.is_whitespace() => Motion::WordEnd,
+                    _ => motion,
+                };
+                let Some(range) = self.motion_to_range(motion) else {
+                    return Ok(LineMode::Normal);
+                };
+                return self.operate(Action::Change(motion), range, registers);
+            },
+            action @ (Action::Yank(motion)
+            | Action::UpperCase(motion)
+            | Action::LowerCase(motion)) => {
+                if let Some(range) = self.motion_to_range(motion) {
+                    self.operate(action, range, registers)?;
+                }
             },
             //executed left to right
             Action::Chain(action1, action2) => {
-                self.execute_vi(*action1)?;
-                return self.execute_vi(*action2);
+                self.execute_vi(*action1, registers)?;
+                return self.execute_vi(*action2, registers);
             },
             Action::ToggleCase => {
-                let loc = Location::Rel(0);
-                if let Some(c) = self.char_at(loc) {
-                    let c = if c.is_uppercase() {
-                        c.to_lowercase().collect::<String>()
-                    } else {
-                        c.to_uppercase().collect::<String>()
-                    };
-                    self.insert_inplace(loc, &c)?;
+                let cursor = self.cursor();
+                if cursor < self.len() {
+                    self.operate(Action::ToggleCase, cursor..cursor + 1, registers)?;
+                    // move on to the next character so that `~` can be repeated
+                    if cursor + 1 < self.line_range(self.cursor_line()).end {
+                        self.move_cursor(Location::Abs(cursor + 1))?;
+                    }
+                }
+            },
+            Action::Replace(ch) => {
+                let cursor = self.cursor();
+                if cursor < self.line_range(self.cursor_line()).end {
+                    self.delete(Location::Abs(cursor), Location::Abs(cursor + 1))?;
+                    self.insert(Location::Abs(cursor), &ch.to_string())?;
+                    self.move_cursor(Location::Abs(cursor))?;
                 }
             },
             Action::Paste(motion) => {
-                let mut clipboard = Clipboard::new().unwrap();
                 let mut l = self.motion_to_loc(motion)?;
                 if self.to_absolute(l).is_err() {
                     l = Location::Rel(0);
                 }
 
-                self.insert(l, clipboard.get_text().unwrap().as_str())?;
+                if let Some(text) = registers.get() {
+                    self.insert(l, &text)?;
+                }
             },
-            Action::Yank(motion) => {
-                let s = self
-                    .location_slice(Location::Cursor(), self.motion_to_loc(motion)?)?
-                    .as_str();
-
-                if let Some(yanked) = s {
-                    let mut clipboard = Clipboard::new().unwrap();
-                    clipboard.set_text(yanked).unwrap();
+            _ => (),
+        }
+        Ok(LineMode::Normal)
+    }
+
+    fn operate(
+        &mut self,
+        action: Action,
+        range: Range<usize>,
+        registers: &mut Registers,
+    ) -> Result<LineMode> {
+        let text = self.slice(range.clone()).to_string();
+        let (start, end) = (Location::Abs(range.start), Location::Abs(range.end));
+        let replacement = match action {
+            Action::Delete(_) | Action::Change(_) => {
+                registers.set(text);
+                self.delete(start, end)?;
+                if matches!(action, Action::Change(_)) {
+                    return Ok(LineMode::Insert);
                 }
+                return Ok(LineMode::Normal);
             },
-            action @ (Action::UpperCase(_) | Action::LowerCase(_)) => {
-                let motion = match action {
-                    Action::UpperCase(motion) => motion,
-                    Action::LowerCase(motion) => motion,
-                    _ => unreachable!("This match expr is only for upper/lower case actions"),
+            Action::Yank(_) => {
+                registers.set(text);
+                self.move_cursor(start)?;
+                return Ok(LineMode::Normal);
+            },
+            // the replaced text is stored so that it can be pasted somewhere else
+            Action::Paste(_) => {
+                let Some(pasted) = registers.get() else {
+                    return Ok(LineMode::Normal);
                 };
-                let loc = self.motion_to_loc(motion)?;
-                let selected = self
-                    .location_slice(Location::Cursor(), loc)?
-                    .as_str()
-                    .map(|s| match action {
-                        Action::UpperCase(_) => s.to_uppercase(),
-                        Action::LowerCase(_) => s.to_lowercase(),
-                        _ => unreachable!("This match expr is only for upper/lower case actions"),
-                    });
-
-                if let Some(selected) = selected.as_ref() {
-                    let abs_loc = self.to_absolute(loc)?;
-                    let curr_loc = self.to_absolute(Location::Cursor())?;
-
-                    self.insert_inplace(
-                        if abs_loc > curr_loc {
-                            Location::Cursor()
-                        } else {
-                            loc
-                        },
-                        selected,
-                    )?;
-                }
+                registers.set(text);
+                pasted
             },
-            _ => (),
-        }
+            Action::UpperCase(_) => text.to_uppercase(),
+            Action::LowerCase(_) => text.to_lowercase(),
+            Action::ToggleCase => text
+                .chars()
+                .map(|c| {
+                    if c.is_uppercase() {
+                        c.to_lowercase().collect::<String>()
+                    } else {
+                        c.to_uppercase().collect::<String>()
+                    }
+                })
+                .collect(),
+            _ => return Ok(LineMode::Normal),
+        };
+        self.delete(start, end)?;
+        self.insert(start, &replacement)?;
+        self.move_cursor(start)?;
         Ok(LineMode::Normal)
     }
 }
 
+// end of the word under or after the cursor
+fn word_end(cb: &CursorBuffer, punc: bool) -> Location {
+    let chars = cb.as_str().chars().collect::<Vec<_>>();
+    let mut i = cb.cursor() + 1;
+    while i < chars.len() && chars[i].is_whitespace() {
+        i += 1;
+    }
+    if i >= chars.len() {
+        return Location::Cursor();
+    }
+    let class = CharClass::of(chars[i], punc);
+    while i + 1 < chars.len() && CharClass::of(chars[i + 1], punc) == class {
+        i += 1;
+    }
+    Location::Abs(i)
+}
+
+// end of the word before the one under the cursor
+fn back_word_end(cb: &CursorBuffer) -> Location {
+    let chars = cb.as_str().chars().collect::<Vec<_>>();
+    let mut i = cb.cursor();
+    // move to the start of the current word
+    if let Some(ch) = chars.get(i).filter(|ch| !ch.is_whitespace()) {
+        let class = CharClass::of(*ch, true);
+        while i > 0 && CharClass::of(chars[i - 1], true) == class {
+            i -= 1;
+        }
+    }
+    if i == 0 {
+        return Location::Front();
+    }
+    i -= 1;
+    while i > 0 && chars[i].is_whitespace() {
+        i -= 1;
+    }
+    Location::Abs(i)
+}
+
+// position of the bracket matching the first bracket at or after the cursor on the current line
+fn match_bracket(cb: &CursorBuffer) -> Option<usize> {
+    let line = cb.line_range(cb.cursor_line());
+    let (pos, ch) = cb
+        .slice(cb.cursor()..line.end)
+        .chars()
+        .enumerate()
+        .find(|(_, ch)| "()[]{}".contains(*ch))?;
+    let pos = cb.cursor() + pos;
+    match ch {
+        '(' => find_close(cb, pos, '(', ')'),
+        '[' => find_close(cb, pos, '[', ']'),
+        '{' => find_close(cb, pos, '{', '}'),
+        ')' => find_open(cb, pos, '(', ')'),
+        ']' => find_open(cb, pos, '[', ']'),
+        _ => find_open(cb, pos, '{', '}'),
+    }
+}
+
+// closing bracket that matches the opening one at `pos`
+fn find_close(cb: &CursorBuffer, pos: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, ch) in cb.slice(pos + 1..).chars().enumerate() {
+        if ch == open {
+            depth += 1;
+        } else if ch == close {
+            if depth == 0 {
+                return Some(pos + 1 + i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+// opening bracket that matches the closing one at `pos`, or that encloses `pos` if it isn't a
+// bracket
+fn find_open(cb: &CursorBuffer, pos: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let chars = cb.slice(..pos).chars().collect::<Vec<_>>();
+    for (i, ch) in chars.into_iter().enumerate().rev() {
+        if ch == close {
+            depth += 1;
+        } else if ch == open {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+fn text_object(cb: &CursorBuffer, object: TextObject, inner: bool) -> Option<Range<usize>> {
+    let cursor = cb.cursor();
+    match object {
+        TextObject::Word | TextObject::WordPunc => {
+            let punc = object == TextObject::WordPunc;
+            let chars = cb.as_str().chars().collect::<Vec<_>>();
+            let i = cursor.min(chars.len().checked_sub(1)?);
+            let class = CharClass::of(chars[i], punc);
+            let run_start = |mut i: usize, class| {
+                while i > 0 && CharClass::of(chars[i - 1], punc) == class {
+                    i -= 1;
+                }
+                i
+            };
+            let run_end = |mut i: usize, class| {
+                while i < chars.len() && CharClass::of(chars[i], punc) == class {
+                    i += 1;
+                }
+                i
+            };
+            let (start, end) = (run_start(i, class), run_end(i, class));
+            if inner {
+                return Some(start..end);
+            }
+            // include the whitespace after the word, or before it if there is none after
+            let next = chars.get(end).map(|ch| CharClass::of(*ch, punc));
+            if class == CharClass::Whitespace {
+                match next {
+                    Some(next) if next != CharClass::Newline => Some(start..run_end(end, next)),
+                    _ => Some(start..end),
+                }
+            } else if next == Some(CharClass::Whitespace) {
+                Some(start..run_end(end, CharClass::Whitespace))
+            } else if start > 0 && CharClass::of(chars[start - 1], punc) == CharClass::Whitespace {
+                Some(run_start(start - 1, CharClass::Whitespace)..end)
+            } else {
+                Some(start..end)
+            }
+        },
+        TextObject::Quote(quote) => {
+            // quotes are paired up from the start of the line
+            let line = cb.line_range(cb.cursor_line());
+            let quotes = cb
+                .slice(line.clone())
+                .chars()
+                .enumerate()
+                .filter(|(_, ch)| *ch == quote)
+                .map(|(i, _)| line.start + i)
+                .collect::<Vec<_>>();
+            let (open, close) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(_, close)| cursor <= *close)?;
+            if inner {
+                Some(open + 1..close)
+            } else {
+                Some(open..close + 1)
+            }
+        },
+        TextObject::Bracket(open, close) => {
+            let start = if cb.char_at(Location::Cursor()) == Some(open) {
+                cursor
+            } else {
+                find_open(cb, cursor, open, close)?
+            };
+            let end = find_close(cb, start, open, close)?;
+            if inner {
+                Some(start + 1..end)
+            } else {
+                Some(start..end + 1)
+            }
+        },
+    }
+}
+
 #[cfg(test)]
 mod test {
     use shrs_utils::cursor_buffer::{CursorBuffer, Result};
-    use shrs_vi::{Action, Motion};
+    use shrs_vi::{Action, Motion, TextObject};
 
-    use super::ViCursorBuffer;
+    use super::{Registers, ViCursorBuffer};
 
     #[test]
     fn move_next_word() -> Result<()> {
         let mut cb = CursorBuffer::from_str("hello world goodbye world");
+        let mut registers = Registers::default();
 
         assert_eq!(cb.cursor(), 0);
 
-        cb.execute_vi(Action::Move(Motion::Word))?;
+        cb.execute_vi(Action::Move(Motion::Word), &mut registers)?;
         assert_eq!(cb.cursor(), 6);
 
-        cb.execute_vi(Action::Move(Motion::Word))?;
+        cb.execute_vi(Action::Move(Motion::Word), &mut registers)?;
         assert_eq!(cb.cursor(), 12);
 
         Ok(())
@@ -243,16 +585,89 @@ mod test {
     #[test]
     fn move_back_word() -> Result<()> {
         let mut cb = CursorBuffer::from_str("hello world goodbye world");
-        cb.execute_vi(Action::Move(Motion::End))?;
-        cb.execute_vi(Action::Move(Motion::Left))?;
+        let mut registers = Registers::default();
+        cb.execute_vi(Action::Move(Motion::End), &mut registers)?;
+        cb.execute_vi(Action::Move(Motion::Left), &mut registers)?;
         assert_eq!(cb.cursor(), 24);
 
-        cb.execute_vi(Action::Move(Motion::BackWord))?;
+        cb.execute_vi(Action::Move(Motion::BackWord), &mut registers)?;
         assert_eq!(cb.cursor(), 20);
 
-        cb.execute_vi(Action::Move(Motion::BackWord))?;
+        cb.execute_vi(Action::Move(Motion::BackWord), &mut registers)?;
         assert_eq!(cb.cursor(), 12);
 
         Ok(())
     }
+
+    #[test]
+    fn word_ends_and_brackets() -> Result<()> {
+        let mut cb = CursorBuffer::from_str("echo $(foo.bar) baz");
+        let mut registers = Registers::default();
+
+        cb.execute_vi(Action::Move(Motion::WordPuncEnd), &mut registers)?;
+        assert_eq!(cb.cursor(), 3);
+        cb.execute_vi(Action::Move(Motion::WordPuncEnd), &mut registers)?;
+        assert_eq!(cb.cursor(), 6);
+        cb.execute_vi(Action::Move(Motion::WordEnd), &mut registers)?;
+        assert_eq!(cb.cursor(), 14);
+        cb.execute_vi(Action::Move(Motion::BackWordEnd), &mut registers)?;
+        assert_eq!(cb.cursor(), 13);
+
+        cb.execute_vi(Action::Move(Motion::MatchBracket), &mut registers)?;
+        assert_eq!(cb.cursor(), 6);
+        cb.execute_vi(Action::Move(Motion::MatchBracket), &mut registers)?;
+        assert_eq!(cb.cursor(), 14);
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_objects() -> Result<()> {
+        let mut cb = CursorBuffer::from_str("git commit -m \"fix (some) bug\" now");
+        let mut registers = Registers::default();
+        cb.move_cursor(shrs_utils::cursor_buffer::Location::Abs(22))?;
+
+        let object = |object, inner| Motion::TextObject { object, inner };
+        assert_eq!(
+            cb.motion_to_range(object(TextObject::WordPunc, true)),
+            Some(20..24)
+        );
+        assert_eq!(
+            cb.motion_to_range(object(TextObject::Word, false)),
+            Some(19..26)
+        );
+        assert_eq!(
+            cb.motion_to_range(object(TextObject::Bracket('(', ')'), false)),
+            Some(19..25)
+        );
+
+        cb.execute_vi(
+            Action::Change(object(TextObject::Quote('"'), true)),
+            &mut registers,
+        )?;
+        assert_eq!(cb.as_str(), "git commit -m \"\" now");
+        assert_eq!(cb.cursor(), 15);
+        assert_eq!(registers.get().as_deref(), Some("fix (some) bug"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn named_registers() -> Result<()> {
+        let mut cb = CursorBuffer::from_str("one two");
+        let mut registers = Registers::default();
+
+        registers.select(Some('a'));
+        cb.execute_vi(Action::Yank(Motion::WordPunc), &mut registers)?;
+        registers.select(Some('A'));
+        cb.execute_vi(Action::Delete(Motion::All), &mut registers)?;
+        registers.select(Some('a'));
+        assert_eq!(registers.get().as_deref(), Some("one one two"));
+
+        registers.select(None);
+        cb.execute_vi(Action::Paste(Motion::None), &mut registers)?;
+        assert_eq!(cb.as_str(), "one one two");
+
+        Ok(())
+    }
 }
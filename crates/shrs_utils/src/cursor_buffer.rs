@@ -63,6 +63,30 @@ impl Location {
         Location::Abs(cb.len())
     }
 
+    /// Location at the beginning of the line the cursor is on
+    pub fn LineStart(cb: &CursorBuffer) -> Location {
+        Location::Abs(cb.line_range(cb.cursor_line()).start)
+    }
+
+    /// Location at the end of the line the cursor is on, before the newline
+    pub fn LineEnd(cb: &CursorBuffer) -> Location {
+        Location::Abs(cb.line_range(cb.cursor_line()).end)
+    }
+
+    /// Location on the previous line at the same column as the cursor, clamped to the end of
+    /// that line. Returns `None` if the cursor is on the first line
+    pub fn Up(cb: &CursorBuffer) -> Option<Location> {
+        let line = cb.cursor_line().checked_sub(1)?;
+        Some(cb.line_column(line))
+    }
+
+    /// Location on the next line at the same column as the cursor, clamped to the end of that
+    /// line. Returns `None` if the cursor is on the last line
+    pub fn Down(cb: &CursorBuffer) -> Option<Location> {
+        let line = cb.cursor_line() + 1;
+        (line < cb.line_count()).then(|| cb.line_column(line))
+    }
+
     /// Location of the next occurrence of character
     pub fn FindChar(cb: &CursorBuffer, start: Location, c: char) -> Option<Location> {
         Location::Find(cb, start, |ch| ch == c)
@@ -246,6 +270,34 @@ impl CursorBuffer {
         self.data.len_chars()
     }
 
+    /// Number of lines in the buffer, an empty buffer has one line
+    pub fn line_count(&self) -> usize {
+        self.data.len_lines()
+    }
+
+    /// Index of the line the cursor is on
+    pub fn cursor_line(&self) -> usize {
+        self.data.char_to_line(self.cursor)
+    }
+
+    /// Range of characters on a line, not including the newline that ends it
+    pub fn line_range(&self, line: usize) -> std::ops::Range<usize> {
+        let start = self.data.line_to_char(line);
+        let end = if line + 1 < self.line_count() {
+            self.data.line_to_char(line + 1) - 1
+        } else {
+            self.len()
+        };
+        start..end
+    }
+
+    /// Location on another line at the same column as the cursor
+    fn line_column(&self, line: usize) -> Location {
+        let column = self.cursor - self.line_range(self.cursor_line()).start;
+        let range = self.line_range(line);
+        Location::Abs((range.start + column).min(range.end))
+    }
+
     /// Get char at position
     pub fn char_at(&self, loc: Location) -> Option<char> {
         self.to_absolute(loc)
@@ -369,6 +421,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn multiple_lines() -> Result<()> {
+        let mut cb = CursorBuffer::from_str("for i in a b\ndo\n  echo $i");
+        assert_eq!(cb.line_count(), 3);
+        assert_eq!(cb.line_range(1), 13..15);
+
+        cb.move_cursor(Location::Abs(10))?;
+        assert_eq!(Location::Up(&cb), None);
+        // column is clamped to the end of a shorter line
+        cb.move_cursor(Location::Down(&cb).unwrap())?;
+        assert_eq!((cb.cursor_line(), cb.cursor()), (1, 15));
+        cb.move_cursor(Location::Down(&cb).unwrap())?;
+        assert_eq!(cb.cursor(), 18);
+        assert_eq!(Location::Down(&cb), None);
+
+        assert_eq!(Location::LineStart(&cb), Location::Abs(16));
+        assert_eq!(Location::LineEnd(&cb), Location::Back(&cb));
+        Ok(())
+    }
+
     #[test]
     fn utf8_basic() -> Result<()> {
         let mut cb = CursorBuffer::from_str("こんにちは");
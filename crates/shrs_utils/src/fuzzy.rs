@@ -0,0 +1,194 @@
+//! Fuzzy matching in the style of fzf
+//!
+//! A pattern matches a text if all of its characters appear in the text in order. Matches are
+//! scored so that characters matched at the start of words and runs of consecutive characters rank
+//! higher than matches scattered across the text.
+
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+
+const BONUS_BOUNDARY_WHITE: i64 = 10;
+const BONUS_BOUNDARY_DELIMITER: i64 = 9;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// Result of a successful fuzzy match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Quality of the match, higher is better
+    pub score: i64,
+    /// Character indices into the text of the matched characters
+    pub positions: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Number,
+}
+
+fn char_class(ch: char) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::White
+    } else if "/,:;|=".contains(ch) {
+        CharClass::Delimiter
+    } else if ch.is_lowercase() {
+        CharClass::Lower
+    } else if ch.is_uppercase() {
+        CharClass::Upper
+    } else if ch.is_numeric() {
+        CharClass::Number
+    } else if ch.is_alphabetic() {
+        CharClass::Lower
+    } else {
+        CharClass::NonWord
+    }
+}
+
+/// Bonus for matching a character of class `cur` that follows a character of class `prev`
+fn bonus(prev: CharClass, cur: CharClass) -> i64 {
+    use CharClass::*;
+    match (prev, cur) {
+        (_, White | Delimiter | NonWord) => 0,
+        (White, _) => BONUS_BOUNDARY_WHITE,
+        (Delimiter, _) => BONUS_BOUNDARY_DELIMITER,
+        (NonWord, _) => BONUS_BOUNDARY,
+        (Lower, Upper) | (Lower | Upper, Number) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+/// Fuzzy match a pattern against a text
+///
+/// Matching is case insensitive unless the pattern contains an uppercase character. An empty
+/// pattern matches everything with a score of zero.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let case_sensitive = pattern.chars().any(|ch| ch.is_uppercase());
+    let normalize = |ch: char| {
+        if case_sensitive {
+            ch
+        } else {
+            ch.to_lowercase().next().unwrap_or(ch)
+        }
+    };
+    let pattern = pattern.chars().map(normalize).collect::<Vec<_>>();
+    let chars = text.chars().collect::<Vec<_>>();
+
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: vec![],
+        });
+    }
+
+    // find the first occurrence of the pattern as a subsequence
+    let mut pi = 0;
+    let mut end = None;
+    for (i, ch) in chars.iter().enumerate() {
+        if normalize(*ch) == pattern[pi] {
+            pi += 1;
+            if pi == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // walk backwards from the end to find the shortest window containing the pattern
+    let mut pi = pattern.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if normalize(chars[i]) == pattern[pi - 1] {
+            pi -= 1;
+            if pi == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    // score the window, greedily matching the pattern from its start
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut pi = 0;
+    let mut in_gap = false;
+    let mut consecutive = 0;
+    let mut first_bonus = 0;
+    let mut prev_class = if start == 0 {
+        CharClass::White
+    } else {
+        char_class(chars[start - 1])
+    };
+
+    for (i, ch) in chars.iter().enumerate().take(end + 1).skip(start) {
+        let class = char_class(*ch);
+        if pi < pattern.len() && normalize(*ch) == pattern[pi] {
+            let mut char_bonus = bonus(prev_class, class);
+            if consecutive == 0 {
+                first_bonus = char_bonus;
+            } else {
+                // a run of consecutive matches keeps the bonus of the character it started on
+                if char_bonus == BONUS_BOUNDARY {
+                    first_bonus = char_bonus;
+                }
+                char_bonus = char_bonus.max(first_bonus).max(BONUS_CONSECUTIVE);
+            }
+            if pi == 0 {
+                char_bonus *= BONUS_FIRST_CHAR_MULTIPLIER;
+            }
+
+            score += SCORE_MATCH + char_bonus;
+            positions.push(i);
+            consecutive += 1;
+            in_gap = false;
+            pi += 1;
+        } else {
+            score += if in_gap {
+                SCORE_GAP_EXTENSION
+            } else {
+                SCORE_GAP_START
+            };
+            consecutive = 0;
+            first_bonus = 0;
+            in_gap = true;
+        }
+        prev_class = class;
+    }
+
+    Some(FuzzyMatch { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_match;
+
+    #[test]
+    fn matching() {
+        assert!(fuzzy_match("gco", "git checkout").is_some());
+        assert!(fuzzy_match("gcx", "git checkout").is_none());
+        assert!(fuzzy_match("GIT", "git status").is_none());
+
+        let m = fuzzy_match("gs", "git status").unwrap();
+        assert_eq!(m.positions, vec![0, 4]);
+    }
+
+    #[test]
+    fn scoring() {
+        let score = |pattern, text| fuzzy_match(pattern, text).unwrap().score;
+
+        // word boundaries beat matches in the middle of words
+        assert!(score("gs", "git status") > score("gs", "dogs"));
+        // consecutive characters beat scattered ones
+        assert!(score("make", "make test") > score("make", "mv a/k e"));
+        // shorter gaps are preferred
+        assert!(score("ct", "cat") > score("ct", "coat"));
+    }
+}
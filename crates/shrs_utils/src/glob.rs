@@ -0,0 +1,97 @@
+//! Shell style pattern matching
+//!
+//! Supports `*` to match any string, `?` to match any single character, bracket expressions such
+//! as `[abc]`, `[a-z]` and `[!0-9]`, and `\` to escape special characters.
+
+/// Check if the whole text matches a shell pattern
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut pi, mut ti) = (0, 0);
+    // position to retry from after the last star, in case it needs to match more characters
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < text.len() {
+        let step = match pattern.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+                continue;
+            },
+            Some('?') => Some(1),
+            // an unterminated bracket is treated as a literal character
+            Some('[') => match_bracket(&pattern[pi..], text[ti])
+                .unwrap_or_else(|| ('[' == text[ti]).then_some(1)),
+            Some('\\') if pi + 1 < pattern.len() => (pattern[pi + 1] == text[ti]).then_some(2),
+            Some(ch) => (*ch == text[ti]).then_some(1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(len), _) => {
+                pi += len;
+                ti += 1;
+            },
+            (None, Some((star, start))) => {
+                pi = star + 1;
+                ti = start + 1;
+                backtrack = Some((star, start + 1));
+            },
+            (None, None) => return false,
+        }
+    }
+
+    pattern[pi..].iter().all(|ch| *ch == '*')
+}
+
+/// Match a character against a bracket expression at the start of the pattern, returning the
+/// length of the expression if it matches, or `None` if the bracket is never closed
+fn match_bracket(pattern: &[char], ch: char) -> Option<Option<usize>> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let cur = *pattern.get(i)?;
+        if cur == ']' && !first {
+            break;
+        }
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|end| *end != ']') {
+            matched |= (cur..=pattern[i + 2]).contains(&ch);
+            i += 3;
+        } else {
+            matched |= cur == ch;
+            i += 1;
+        }
+        first = false;
+    }
+
+    Some((matched != negated).then_some(i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn matching() {
+        assert!(glob_match("ls", "ls"));
+        assert!(!glob_match("ls", "ls -la"));
+        assert!(glob_match("ls *", "ls -la"));
+        assert!(glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("git ?", "git a"));
+        assert!(!glob_match("git ?", "git"));
+        assert!(glob_match("[bf]g", "fg"));
+        assert!(glob_match("[!a-c]d", "xd"));
+        assert!(!glob_match("[!a-c]d", "bd"));
+        assert!(glob_match("a\\*", "a*"));
+        assert!(!glob_match("a\\*", "ab"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(glob_match("[a", "[a"));
+    }
+}
@@ -1,3 +1,5 @@
 pub mod algo;
 pub mod cursor_buffer;
+pub mod fuzzy;
+pub mod glob;
 pub mod macros;
@@ -1,6 +1,8 @@
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Command {
     pub repeat: u32,
+    /// Register selected with `"`, the unnamed register is used if none is given
+    pub register: Option<char>,
     pub action: Action,
 }
 
@@ -10,6 +12,12 @@ pub enum Motion {
     BackWord,
     WordPunc,
     Word,
+    /// End of the word, treating punctuation as part of the word (`E`)
+    WordEnd,
+    /// End of the word, stopping at punctuation (`e`)
+    WordPuncEnd,
+    /// End of the previous word (`ge`)
+    BackWordEnd,
     Left,
     Right,
     Start,
@@ -18,6 +26,8 @@ pub enum Motion {
     End,
     /// Select entire line (for Move action this behaves same as End)
     All,
+    /// Bracket matching the one under or after the cursor (`%`)
+    MatchBracket,
     /// Search forward/backward for/to character
     ///
     /// Encapsulates `f`, `F`, `t`, and `T`
@@ -26,6 +36,27 @@ pub enum Motion {
         back: bool,
         to: bool,
     },
+    /// Text object such as `iw` or `a(`, only valid after an operator or in visual mode
+    TextObject {
+        object: TextObject,
+        /// `i` selects only the contents, `a` includes the surrounding quotes, brackets or
+        /// whitespace
+        inner: bool,
+    },
+    /// Text selected in visual mode
+    Selection,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TextObject {
+    /// Word, stopping at punctuation (`w`)
+    WordPunc,
+    /// Word delimited by whitespace (`W`)
+    Word,
+    /// Text between a pair of quotes
+    Quote(char),
+    /// Text between an opening and closing bracket
+    Bracket(char, char),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -33,6 +64,8 @@ pub enum Action {
     Undo,
     Redo,
     Delete(Motion),
+    /// Delete and enter insert mode
+    Change(Motion),
     Yank(Motion),
     Move(Motion),
     Insert,
@@ -41,4 +74,18 @@ pub enum Action {
     Paste(Motion),
     LowerCase(Motion),
     UpperCase(Motion),
+    /// Replace the character under the cursor
+    Replace(char),
+    /// Repeat the last change (`.`)
+    Repeat,
+    /// Start, switch or leave visual mode, `line` selects whole lines (`V`)
+    Visual {
+        line: bool,
+    },
+    /// Incrementally search history, `/` searches older entries and `?` more recent ones
+    Search {
+        back: bool,
+    },
+    /// Edit the line in an external editor (`v` or `C-x C-e`)
+    Editor,
 }
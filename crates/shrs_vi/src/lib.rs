@@ -15,7 +15,10 @@ pub use ast::*;
 mod tests {
 
     use super::grammar;
-    use crate::ast::{Action, Command, Motion};
+    use crate::{
+        ast::{Action, Command, Motion, TextObject},
+        Error, Parser,
+    };
 
     #[test]
     fn basic() -> anyhow::Result<()> {
@@ -24,6 +27,7 @@ mod tests {
             res,
             Command {
                 repeat: 1,
+                register: None,
                 action: Action::Delete(Motion::WordPunc)
             }
         );
@@ -33,6 +37,7 @@ mod tests {
             res,
             Command {
                 repeat: 42,
+                register: None,
                 action: Action::Delete(Motion::WordPunc)
             }
         );
@@ -47,9 +52,90 @@ mod tests {
             res,
             Command {
                 repeat: 1,
+                register: None,
                 action: Action::ToggleCase
             }
         );
         Ok(())
     }
+
+    #[test]
+    fn text_objects() -> anyhow::Result<()> {
+        let res = grammar::CommandParser::new().parse("ci\"")?;
+        assert_eq!(
+            res.action,
+            Action::Change(Motion::TextObject {
+                object: TextObject::Quote('"'),
+                inner: true
+            })
+        );
+
+        let res = grammar::CommandParser::new().parse("da(")?;
+        assert_eq!(
+            res.action,
+            Action::Delete(Motion::TextObject {
+                object: TextObject::Bracket('(', ')'),
+                inner: false
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn registers_and_char_args() -> anyhow::Result<()> {
+        let res = grammar::CommandParser::new().parse("\"a2yw")?;
+        assert_eq!(
+            res,
+            Command {
+                repeat: 2,
+                register: Some('a'),
+                action: Action::Yank(Motion::WordPunc)
+            }
+        );
+
+        // characters that are also commands can be searched for and replaced with
+        let res = grammar::CommandParser::new().parse("dtw")?;
+        assert_eq!(
+            res.action,
+            Action::Delete(Motion::Find {
+                ch: 'w',
+                back: false,
+                to: true
+            })
+        );
+        let res = grammar::CommandParser::new().parse("r)")?;
+        assert_eq!(res.action, Action::Replace(')'));
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete() {
+        let mut parser = Parser::new();
+        for input in ["d", "f", "\"", "\"a", "ya", "g"] {
+            assert!(
+                matches!(parser.parse(input), Err(Error::Incomplete)),
+                "{input}"
+            );
+        }
+        assert!(matches!(parser.parse("zj"), Err(Error::UnsuccessfulParse)));
+        assert_eq!(
+            parser.parse_visual("d").unwrap().action,
+            Action::Delete(Motion::Selection)
+        );
+    }
+
+    #[test]
+    fn visual() {
+        let mut parser = Parser::new();
+        // `v` opens the external editor like in bash, and only switches modes in visual mode
+        assert_eq!(parser.parse("v").unwrap().action, Action::Editor);
+        assert_eq!(
+            parser.parse("V").unwrap().action,
+            Action::Visual { line: true }
+        );
+        assert_eq!(
+            parser.parse_visual("v").unwrap().action,
+            Action::Visual { line: false }
+        );
+    }
 }
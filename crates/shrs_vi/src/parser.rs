@@ -1,28 +1,68 @@
+use lalrpop_util::ParseError;
 use thiserror::Error;
 
-use crate::{ast, grammar::CommandParser};
+use crate::{
+    ast,
+    grammar::{CommandParser, VisualCommandParser},
+};
 
 // TODO better errors for unsuccessful parses
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("unsuccessful parse")]
     UnsuccessfulParse,
+    /// More keys are needed to complete the command, such as after `d` or `f`
+    #[error("incomplete command")]
+    Incomplete,
 }
 
 pub struct Parser {
     parser: CommandParser,
+    visual_parser: VisualCommandParser,
 }
 
 impl Parser {
     pub fn new() -> Self {
         Parser {
             parser: CommandParser::new(),
+            visual_parser: VisualCommandParser::new(),
         }
     }
 
+    /// Parse a command typed in normal mode
     pub fn parse(&mut self, input: &str) -> Result<ast::Command, Error> {
-        self.parser
+        self.parser.parse(input).map_err(|e| parse_error(input, e))
+    }
+
+    /// Parse a command typed in visual mode
+    pub fn parse_visual(&mut self, input: &str) -> Result<ast::Command, Error> {
+        self.visual_parser
             .parse(input)
-            .map_err(|_| Error::UnsuccessfulParse)
+            .map_err(|e| parse_error(input, e))
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
+/// The input may still become a valid command if parsing only failed at its last key
+fn parse_error<T, E>(input: &str, error: ParseError<usize, T, E>) -> Error {
+    let last = input.char_indices().last().map_or(0, |(i, _)| i);
+    match error {
+        ParseError::UnrecognizedEOF { .. } => Error::Incomplete,
+        ParseError::InvalidToken { location } if location >= last => Error::Incomplete,
+        ParseError::UnrecognizedToken {
+            token: (start, _, _),
+            ..
+        } if start >= last => Error::Incomplete,
+        _ => Error::UnsuccessfulParse,
+    }
+}
+
+/// Character argument of a command lexed together with it, such as `x` in `fx`
+pub(crate) fn arg(token: &str) -> char {
+    token.chars().nth(1).unwrap()
+}
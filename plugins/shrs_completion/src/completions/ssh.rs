@@ -1,5 +1,6 @@
 use shrs::prelude::{
-    cmdname_eq_pred, Completion, CompletionCtx, DefaultCompleter, Pred, ReplaceMethod, Rule,
+    cmdname_eq_pred, Completion, CompletionCtx, CompletionKind, DefaultCompleter, Pred,
+    ReplaceMethod, Rule,
 };
 
 use crate::helpers::known_hosts;
@@ -31,6 +32,9 @@ fn known_hosts_action(ctx: &CompletionCtx) -> Vec<Completion> {
                     completion: pat.pattern.clone(),
                     replace_method: ReplaceMethod::Append,
                     comment: None,
+                    score: 0,
+                    matched: vec![],
+                    kind: CompletionKind::Other,
                 })
                 .collect::<Vec<_>>()
         })
@@ -1,12 +1,51 @@
 use shrs::prelude::*;
 use shrs_derive_completion::Completion;
 
+// only the completions are derived here, nothing is parsed into the types
+#[allow(dead_code)]
 #[derive(Completion)]
 struct MyCli {
+    /// Print help
     #[flag(long = "help", short)]
     help: bool,
-    #[flag(short = "v")]
+    /// More output, can be passed multiple times
+    #[flag(short = "v", multiple)]
     verbose: bool,
+    /// Read options from a file
+    #[flag(short, value = "file")]
+    config: Option<String>,
+    #[subcommand]
+    command: Option<Command>,
+}
+
+#[allow(dead_code)]
+#[derive(Completion)]
+enum Command {
+    /// Copy files to a remote machine
+    Upload {
+        /// Machine to copy to
+        #[flag(value = "host")]
+        remote: String,
+        #[positional(value = "file")]
+        files: Vec<String>,
+    },
+    /// Set how much is printed
+    Log {
+        #[positional(values = "error warn info debug")]
+        level: String,
+    },
+    /// Run a program
+    Exec(Exec),
+}
+
+#[allow(dead_code)]
+#[derive(Completion)]
+struct Exec {
+    #[positional(value = "executable")]
+    program: String,
+    /// Directory to run the program in
+    #[flag(value = "dir")]
+    cwd: Option<String>,
 }
 
 fn main() {
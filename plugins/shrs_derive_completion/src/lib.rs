@@ -1,14 +1,23 @@
 //! Specify shrs completions from a derive macro
 //!
+//! Deriving [Completion] on a struct describes a command, with its fields being flags,
+//! positional arguments or a subcommand. Deriving it on an enum describes a list of subcommands,
+//! one for each variant. See the `basic` example.
 //!
+//! Fields and variants are described by their doc comments, which are shown in the completion
+//! menu.
 
 #[macro_use]
 extern crate derive_builder;
 
 extern crate proc_macro;
 
+use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Item, ItemStruct, LitStr};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Expr, ExprLit, Field, Fields, Item, ItemEnum,
+    ItemStruct, Lit, LitStr, Meta, Type,
+};
 
 /// Information on the CLI itself
 #[derive(Builder)]
@@ -16,13 +25,15 @@ use syn::{parse_macro_input, Item, ItemStruct, LitStr};
 struct Cli {
     /// Name of the command
     name: String,
+    /// Description shown when the command is a subcommand
+    #[builder(default)]
+    description: Option<String>,
 }
 
 /// Information on a flag
 #[derive(Builder)]
 #[builder(pattern = "mutable")]
 struct Flag {
-    // desc: String,
     /// Long flag name
     ///
     /// Long names are passed with a double dash. For example `--verbose`.
@@ -32,134 +43,360 @@ struct Flag {
     /// Short flags can be passed with a single dash. For example `-v`.
     #[builder(default)]
     short: Option<char>,
+    #[builder(default)]
+    description: Option<String>,
+    /// Value hint if the flag takes a value
+    #[builder(default)]
+    value: Option<TokenStream>,
+    /// If the flag can be passed more than once
+    #[builder(default)]
+    multiple: bool,
+}
+
+/// Information on a positional argument
+#[derive(Builder)]
+#[builder(pattern = "mutable")]
+struct Positional {
+    name: String,
+    value: TokenStream,
+    /// If the argument takes every remaining value
+    #[builder(default)]
+    multiple: bool,
 }
 
 #[derive(thiserror::Error, Debug)]
 enum Error {
     #[error("Could not applied on unnamed field")]
     UnnamedField,
+    #[error("Variant must have named fields or a single unnamed field")]
+    UnsupportedVariant,
+    #[error("Unknown value hint, expected one of any, file, dir, executable or host")]
+    UnknownValueHint,
+    #[error("expected short flag with single character")]
+    EmptyShort,
 }
 
-#[proc_macro_derive(Completion, attributes(flag))]
+#[proc_macro_derive(Completion, attributes(completion, flag, positional, subcommand))]
 pub fn completion(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let parsed_input = parse_macro_input!(input as Item);
 
-    if let Item::Struct(item) = parsed_input {
-        impl_struct(item).unwrap().into()
-    } else {
-        quote! {
-            compile_error!("not used on struct or enum")
+    let output = match parsed_input {
+        Item::Struct(item) => impl_struct(item),
+        Item::Enum(item) => impl_enum(item),
+        _ => Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "not used on struct or enum",
+        )),
+    };
+    output.unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+fn impl_struct(item: ItemStruct) -> syn::Result<TokenStream> {
+    let struct_name = &item.ident;
+    let cli = parse_cli(&item.attrs, struct_name.to_string().to_ascii_lowercase())?;
+    let command = command(&cli, &item.fields)?;
+
+    let output = quote! {
+        impl #struct_name {
+            /// Description of the command used for completion
+            pub fn cli() -> ::shrs::line::completion::CliCommand {
+                #command
+            }
+
+            pub fn rules(comp: &mut ::shrs::line::completion::DefaultCompleter) {
+                comp.register(Self::cli().rule());
+            }
         }
-        .into()
+    };
+    Ok(output)
+}
+
+fn impl_enum(item: ItemEnum) -> syn::Result<TokenStream> {
+    let enum_name = &item.ident;
+
+    let mut subcommands = vec![];
+    for variant in item.variants.iter() {
+        let cli = parse_cli(&variant.attrs, kebab_case(&variant.ident.to_string()))?;
+
+        let subcommand = match &variant.fields {
+            // a variant wrapping another type that derives completion takes its arguments
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                let name = &cli.name;
+                let description = cli.description.iter();
+                quote! {
+                    <#ty>::cli()
+                        .with_name(#name)
+                        #(.with_description(#description))*
+                }
+            },
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new(variant.span(), Error::UnsupportedVariant))
+            },
+            fields => command(&cli, fields)?,
+        };
+        subcommands.push(subcommand);
     }
+
+    let output = quote! {
+        impl #enum_name {
+            /// Subcommands used for completion, one for each variant
+            pub fn subcommands() -> Vec<::shrs::line::completion::CliCommand> {
+                vec![#(#subcommands),*]
+            }
+        }
+    };
+    Ok(output)
 }
 
-fn impl_struct(item: ItemStruct) -> Result<proc_macro2::TokenStream, Error> {
+/// Read `#[completion(name = "...")]` and the doc comment of a command
+fn parse_cli(attrs: &[Attribute], default_name: String) -> syn::Result<Cli> {
     let mut cli = CliBuilder::default();
-    let mut flags: Vec<Flag> = vec![];
+    cli.name(default_name);
+    cli.description(doc_comment(attrs));
 
-    let struct_name = &item.ident;
-    cli.name(struct_name.to_string().to_ascii_lowercase());
+    for attr in attrs.iter() {
+        if attr.path().is_ident("completion") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    cli.name(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("description") {
+                    cli.description(Some(meta.value()?.parse::<LitStr>()?.value()));
+                } else {
+                    return Err(meta.error("unsupported attribute"));
+                }
+                Ok(())
+            })?;
+        }
+    }
+    Ok(cli.build().unwrap())
+}
+
+/// Generate the [CliCommand] for a command with the given fields
+fn command(cli: &Cli, fields: &Fields) -> syn::Result<TokenStream> {
+    let mut flags: Vec<Flag> = vec![];
+    let mut positionals: Vec<Positional> = vec![];
+    let mut subcommands: Vec<&Type> = vec![];
 
-    for field in item.fields.iter() {
-        let field_name = field.ident.clone().ok_or(Error::UnnamedField)?;
+    for field in fields.iter() {
+        let field_name = field
+            .ident
+            .clone()
+            .ok_or_else(|| syn::Error::new(field.span(), Error::UnnamedField))?;
 
-        // check if field is marked as flag
+        // check if field is marked as flag, positional or subcommand
         for attr in field.attrs.iter() {
             if attr.path().is_ident("flag") {
-                // Default long flag name is name of field
-                let mut flag = FlagBuilder::default();
-                flag.long(field_name.to_string());
-
-                attr.parse_nested_meta(|meta| {
-                    if meta.path.is_ident("long") {
-                        let value = meta.value()?;
-                        let s = value.parse::<LitStr>()?.value();
-                        flag.long(s);
-                    } else if meta.path.is_ident("short") {
-                        let c = if let Ok(value) = meta.value() {
-                            value.parse::<LitStr>()?.value()
-                        } else {
-                            // if no specific short flag is passed, use the first character of the
-                            // current field
-                            field_name.to_string()
-                        };
-                        let c = c
-                            .chars()
-                            .next()
-                            .ok_or(meta.error("expected short flag with single character"))?;
-                        flag.short(Some(c));
-                    } else {
-                        return Err(meta.error("unsupported attribute"));
-                    }
-
-                    Ok(())
-                })
-                .unwrap();
-                flags.push(flag.build().unwrap());
+                flags.push(parse_flag(attr, field, &field_name.to_string())?);
+            } else if attr.path().is_ident("positional") {
+                positionals.push(parse_positional(attr, field, &field_name.to_string())?);
+            } else if attr.path().is_ident("subcommand") {
+                subcommands.push(inner_type(&field.ty, "Option").unwrap_or(&field.ty));
             }
         }
     }
 
-    let cli = cli.build().unwrap();
+    let name = &cli.name;
+    let description = cli.description.iter();
+    let flags = flags.iter().map(|flag| {
+        let long = &flag.long;
+        let short = flag.short.iter();
+        let description = flag.description.iter();
+        let value = flag.value.iter();
+        let multiple = flag.multiple;
+        quote! {
+            ::shrs::line::completion::CliFlag::new()
+                .with_long(#long)
+                #(.with_short(#short))*
+                #(.with_description(#description))*
+                #(.with_value(#value))*
+                .with_multiple(#multiple)
+        }
+    });
+    let positionals = positionals.iter().map(|positional| {
+        let name = &positional.name;
+        let value = &positional.value;
+        let multiple = positional.multiple;
+        quote! {
+            ::shrs::line::completion::CliArg::new(#name)
+                .with_value(#value)
+                .with_multiple(#multiple)
+        }
+    });
 
-    let _flag_rules = flag_rules(cli, flags).unwrap();
+    Ok(quote! {
+        ::shrs::line::completion::CliCommand::new(#name)
+            #(.with_description(#description))*
+            #(.with_flag(#flags))*
+            #(.with_arg(#positionals))*
+            #(.with_subcommands(<#subcommands>::subcommands()))*
+    })
+}
 
-    let output = quote! {
-        // TODO might run into issues using a use statement here
-        use shrs::line::completion::{
-            cmdname_eq_pred, default_format, flag_pred, Action, Completion, CompletionCtx,
-            DefaultCompleter, Pred, Rule,
-        };
+/// Parse `#[flag(long = "...", short, value = "...", multiple)]`
+///
+/// Flags on `bool` fields are switches, other flags take a value and flags on `Vec` fields can
+/// be passed more than once.
+fn parse_flag(attr: &Attribute, field: &Field, field_name: &str) -> syn::Result<Flag> {
+    // Default long flag name is name of field
+    let mut flag = FlagBuilder::default();
+    flag.long(field_name.replace('_', "-"));
+    flag.description(doc_comment(&field.attrs));
 
-        impl #struct_name {
-            pub fn rules(comp: &mut DefaultCompleter) {
+    let is_vec = inner_type(&field.ty, "Vec").is_some();
+    if !is_type(&field.ty, "bool") {
+        flag.value(Some(value_hint("any")?));
+    }
+    flag.multiple(is_vec);
 
-                // Rules for flags
-                comp.register(#_flag_rules);
-            }
+    // allow a bare `#[flag]`
+    if !matches!(attr.meta, Meta::List(_)) {
+        return Ok(flag.build().unwrap());
+    }
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("long") {
+            let value = meta.value()?;
+            let s = value.parse::<LitStr>()?.value();
+            flag.long(s);
+        } else if meta.path.is_ident("short") {
+            let c = if let Ok(value) = meta.value() {
+                value.parse::<LitStr>()?.value()
+            } else {
+                // if no specific short flag is passed, use the first character of the
+                // current field
+                field_name.to_string()
+            };
+            let c = c.chars().next().ok_or(meta.error(Error::EmptyShort))?;
+            flag.short(Some(c));
+        } else if meta.path.is_ident("help") {
+            flag.description(Some(meta.value()?.parse::<LitStr>()?.value()));
+        } else if meta.path.is_ident("multiple") {
+            flag.multiple(true);
+        } else if meta.path.is_ident("switch") {
+            flag.value(None);
+        } else if let Some(value) = parse_value(&meta)? {
+            flag.value(Some(value));
+        } else {
+            return Err(meta.error("unsupported attribute"));
         }
+
+        Ok(())
+    })?;
+    Ok(flag.build().unwrap())
+}
+
+/// Parse `#[positional(value = "...")]`, positionals on `Vec` fields take every remaining value
+fn parse_positional(attr: &Attribute, field: &Field, field_name: &str) -> syn::Result<Positional> {
+    let mut positional = PositionalBuilder::default();
+    positional.name(field_name.to_string());
+    positional.value(value_hint("any")?);
+    positional.multiple(inner_type(&field.ty, "Vec").is_some());
+
+    // allow a bare `#[positional]`
+    if matches!(attr.meta, Meta::List(_)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("multiple") {
+                positional.multiple(true);
+            } else if let Some(value) = parse_value(&meta)? {
+                positional.value(value);
+            } else {
+                return Err(meta.error("unsupported attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(positional.build().unwrap())
+}
+
+/// Parse `value = "file"`, `value_fn = path::to::function` or `values = "a b c"`
+fn parse_value(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<TokenStream>> {
+    if meta.path.is_ident("value") {
+        let hint = meta.value()?.parse::<LitStr>()?;
+        let value =
+            value_hint(&hint.value()).map_err(|e| syn::Error::new(hint.span(), e.to_string()))?;
+        Ok(Some(value))
+    } else if meta.path.is_ident("value_fn") {
+        let function = meta.value()?.parse::<syn::Path>()?;
+        Ok(Some(quote! {
+            ::shrs::line::completion::ValueHint::Custom(#function)
+        }))
+    } else if meta.path.is_ident("values") {
+        let values = meta.value()?.parse::<LitStr>()?.value();
+        let values = values.split_whitespace();
+        Ok(Some(quote! {
+            ::shrs::line::completion::ValueHint::Values(vec![#((#values.into(), None)),*])
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+fn value_hint(hint: &str) -> syn::Result<TokenStream> {
+    let variant = match hint {
+        "any" => quote! { Any },
+        "file" => quote! { File },
+        "dir" => quote! { Dir },
+        "executable" => quote! { Executable },
+        "host" => quote! { Host },
+        _ => {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                Error::UnknownValueHint,
+            ))
+        },
     };
-    Ok(output)
+    Ok(quote! { ::shrs::line::completion::ValueHint::#variant })
 }
 
-/// Generate rules based off of flags that were parsed
-fn flag_rules(cli: Cli, flags: Vec<Flag>) -> Result<proc_macro2::TokenStream, Error> {
-    let cli_name = &cli.name;
-    let long_flags = flags
+/// First paragraph of the doc comment
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines = attrs
         .iter()
-        .map(|f| {
-            let f = format!("--{}", f.long);
-            quote! { #f.into() }
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
         })
+        .take_while(|line| !line.is_empty())
         .collect::<Vec<_>>();
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
 
-    let short_flags = flags
-        .iter()
-        .filter_map(|f| {
-            f.short.map(|f| {
-                let f = format!("-{f}");
-                quote! { #f.into() }
-            })
-        })
-        .collect::<Vec<_>>();
+fn is_type(ty: &Type, name: &str) -> bool {
+    matches!(ty, Type::Path(path) if path.path.is_ident(name))
+}
 
-    let output = quote! {
-        {
-            let flags_action = |ctx: &CompletionCtx| -> Vec<Completion> {
-                default_format(
-                    vec![
-                        #(#short_flags),*
-                        ,
-                        #(#long_flags),*
-                    ]
-                )
-            };
-            Rule::new(
-                Pred::new(cmdname_eq_pred(#cli_name.into())).and(flag_pred),
-                Box::new(flags_action)
-            )
-        }
+/// Type parameter of a type such as `Vec<T>`
+fn inner_type<'a>(ty: &'a Type, outer: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
     };
-    Ok(output)
+    let segment = path.path.segments.last()?;
+    if segment.ident != outer {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// Convert a variant name such as `RemoteAdd` to `remote-add`
+fn kebab_case(name: &str) -> String {
+    let mut output = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i != 0 {
+            output.push('-');
+        }
+        output.extend(c.to_lowercase());
+    }
+    output
 }
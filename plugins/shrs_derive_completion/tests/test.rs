@@ -1,5 +1,142 @@
+// only the completions are derived, nothing is parsed into the types
+#![allow(dead_code)]
+
+use shrs::line::completion::{CliCommand, CliFlag, Completion, CompletionCtx, ValueHint};
+use shrs_derive_completion::Completion;
+
+#[derive(Completion)]
+#[completion(name = "tool")]
+struct Tool {
+    /// Print help
+    ///
+    /// Only the first paragraph is used.
+    #[flag(long = "help", short)]
+    help: bool,
+    /// More output
+    #[flag(short = "v", multiple)]
+    verbose: bool,
+    /// Read options from a file
+    #[flag(short, value = "file")]
+    config: Option<String>,
+    #[flag(help = "Files to skip")]
+    exclude_from: Vec<String>,
+    #[positional(value = "dir")]
+    target: String,
+    #[subcommand]
+    command: Option<Command>,
+}
+
+#[derive(Completion)]
+enum Command {
+    /// Copy files to a remote machine
+    Upload {
+        #[flag(value = "host")]
+        remote: String,
+        #[positional(value = "file")]
+        files: Vec<String>,
+    },
+    #[completion(name = "log", description = "Set how much is printed")]
+    LogLevel {
+        #[positional(values = "error warn")]
+        level: String,
+    },
+    /// Run a program
+    RunProgram(Program),
+}
+
+#[derive(Completion)]
+struct Program {
+    #[positional(value_fn = programs)]
+    program: String,
+}
+
+fn programs(_ctx: &CompletionCtx) -> Vec<Completion> {
+    vec![]
+}
+
+fn flag<'a>(cmd: &'a CliCommand, long: &str) -> &'a CliFlag {
+    cmd.flags
+        .iter()
+        .find(|flag| flag.long.as_deref() == Some(long))
+        .unwrap_or_else(|| panic!("missing flag --{long}"))
+}
+
+#[test]
+fn derive_struct() {
+    let cli = Tool::cli();
+    assert_eq!(cli.name, "tool");
+    assert_eq!(cli.description, None);
+
+    let longs = cli
+        .flags
+        .iter()
+        .map(|flag| flag.long.as_deref().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(longs, ["help", "verbose", "config", "exclude-from"]);
+
+    let help = flag(&cli, "help");
+    assert_eq!(help.short, Some('h'));
+    assert_eq!(help.description.as_deref(), Some("Print help"));
+    assert!(help.value.is_none());
+
+    let verbose = flag(&cli, "verbose");
+    assert_eq!(verbose.short, Some('v'));
+    assert!(verbose.multiple);
+
+    let config = flag(&cli, "config");
+    assert_eq!(config.short, Some('c'));
+    assert!(matches!(config.value, Some(ValueHint::File)));
+    assert!(!config.multiple);
+
+    let exclude = flag(&cli, "exclude-from");
+    assert_eq!(exclude.short, None);
+    assert_eq!(exclude.description.as_deref(), Some("Files to skip"));
+    assert!(matches!(exclude.value, Some(ValueHint::Any)));
+    assert!(exclude.multiple);
+
+    assert_eq!(cli.args.len(), 1);
+    assert_eq!(cli.args[0].name, "target");
+    assert!(matches!(cli.args[0].value, ValueHint::Dir));
+
+    let subcommands = cli
+        .subcommands
+        .iter()
+        .map(|cmd| cmd.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(subcommands, ["upload", "log", "run-program"]);
+}
+
 #[test]
-fn tests() {
-    let _t = trybuild::TestCases::new();
-    // t.pass("tests/01-simple.rs");
+fn derive_enum() {
+    let subcommands = Command::subcommands();
+
+    let upload = &subcommands[0];
+    assert_eq!(
+        upload.description.as_deref(),
+        Some("Copy files to a remote machine")
+    );
+    assert!(matches!(
+        flag(upload, "remote").value,
+        Some(ValueHint::Host)
+    ));
+    assert_eq!(upload.args[0].name, "files");
+    assert!(matches!(upload.args[0].value, ValueHint::File));
+    assert!(upload.args[0].multiple);
+
+    let log = &subcommands[1];
+    assert_eq!(log.name, "log");
+    assert_eq!(log.description.as_deref(), Some("Set how much is printed"));
+    let ValueHint::Values(values) = &log.args[0].value else {
+        panic!("expected a list of values");
+    };
+    let values = values.iter().map(|(v, _)| v.as_str()).collect::<Vec<_>>();
+    assert_eq!(values, ["error", "warn"]);
+
+    // a variant wrapping a type takes the arguments of that type
+    let run = &subcommands[2];
+    assert_eq!(run.name, "run-program");
+    assert_eq!(run.description.as_deref(), Some("Run a program"));
+    assert_eq!(run.args[0].name, "program");
+    assert!(matches!(run.args[0].value, ValueHint::Custom(_)));
+    assert!(!run.args[0].multiple);
 }
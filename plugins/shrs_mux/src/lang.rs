@@ -232,7 +232,7 @@ impl Lang for BashLang {
             .write_all(cd_statement.as_bytes())
             .expect("unable to set var");
 
-        for (k, v) in rt.env.iter() {
+        for (k, v) in rt.env.exported() {
             let export_statement = format!("export {}={:?}\n", k, v);
             stdin
                 .write_all(export_statement.as_bytes())
@@ -21,13 +21,10 @@ struct MyPrompt;
 impl Prompt for MyPrompt {
     fn prompt_left(&self, line_ctx: &mut LineCtx) -> StyledBuf {
         let indicator = match line_ctx.mode() {
-            LineMode::Insert => String::from(">").cyan(),
+            LineMode::Insert | LineMode::Emacs => String::from(">").cyan(),
             LineMode::Normal => String::from(":").yellow(),
+            LineMode::Visual => String::from("v").magenta(),
         };
-        if !line_ctx.lines.is_empty() {
-            return styled! {" ", indicator, " "};
-        }
-
         styled! {" ", @(blue)username(), " ", @(white,bold)top_pwd(), " ", indicator, " "}
     }
     fn prompt_right(&self, line_ctx: &mut LineCtx) -> StyledBuf {
@@ -45,10 +42,6 @@ impl Prompt for MyPrompt {
             .map(|state| state.get_lang());
 
         let git_branch = git::branch().map(|s| format!("git:{s}"));
-        if !line_ctx.lines.is_empty() {
-            return styled! {""};
-        }
-
         styled! {@(bold,blue)git_branch, " ", time_str, " ", lang, " "}
     }
 }
@@ -76,34 +69,27 @@ fn main() {
     env.load();
     env.set("SHELL_NAME", "shrs_example");
 
-    let builtins = Builtins::default();
-
     // =-=-= Completion =-=-=
-    // Get list of binaries in path and initialize the completer to autocomplete command names
-    let path_string = env.get("PATH").unwrap().to_string();
-    let mut completer = DefaultCompleter::default();
-    completer.register(Rule::new(
-        Pred::new(cmdname_pred),
-        Box::new(cmdname_action(path_string)),
-    ));
-    completer.register(Rule::new(
-        Pred::new(cmdname_pred),
-        Box::new(builtin_cmdname_action(&builtins)),
-    ));
+    // The default completer completes command names from the aliases, builtins and PATH
+    let completer = DefaultCompleter::default();
 
     // =-=-= Menu =-=-=-=
     let menu = DefaultMenu::new();
 
     // =-=-= History =-=-=
-    // Use history that writes to file on disk
+    // Use history that writes to file on disk, shared with other open shells
     let history_file = config_dir.as_path().join("history");
-    let history = FileBackedHistory::new(history_file).expect("Could not open history file");
+    let history = FileBackedHistory::new(history_file)
+        .expect("Could not open history file")
+        .with_ignore(&std::env::var("HISTIGNORE").unwrap_or_default())
+        .with_share(true);
 
     // =-=-= Keybindings =-=-=
     // Add basic keybindings
     let keybinding = keybindings! {
         |sh, ctx, rt|
         "C-l" => { Command::new("clear").spawn() },
+        "A-r" => { open_history_menu(sh, ctx, rt) },
         "C-p" => {
             if let Some(state) = ctx.state.get_mut::<CdStackState>() {
                 if let Some(new_path) = state.down() {
@@ -129,7 +115,6 @@ fn main() {
     let readline = LineBuilder::default()
         .with_completer(completer)
         .with_menu(menu)
-        .with_history(history)
         .with_keybinding(keybinding)
         .with_prompt(prompt)
         .build()
@@ -170,6 +155,10 @@ a rusty POSIX shell | build {}"#,
     let mut hooks = Hooks::new();
     hooks.register(startup_msg);
 
+    // =-=-= Completion specs =-=-=
+    // Spec files of fish style complete commands, such as git.fish, are loaded from here
+    let completion_specs = CompletionSpecs::new().with_path(config_dir.join("completions"));
+
     // =-=-= Shell =-=-=
     // Construct the final shell
     let myshell = ShellBuilder::default()
@@ -177,6 +166,8 @@ a rusty POSIX shell | build {}"#,
         .with_env(env)
         .with_alias(alias)
         .with_readline(readline)
+        .with_history(history)
+        .with_state(completion_specs)
         .with_plugin(OutputCapturePlugin)
         .with_plugin(CommandTimerPlugin)
         .with_plugin(RunContextPlugin::new())